@@ -0,0 +1,92 @@
+// Which window has focus, for limiting hotkeys and clicking to one application.
+//
+// Only the title is available everywhere, so the filter is a case-insensitive substring of it.
+// Windows asks winapi for the foreground window and X11 reads _NET_ACTIVE_WINDOW from the root
+// window; elsewhere (macOS, Wayland) the title is unknown and the filter lets everything through.
+
+// Title of the focused window, if this platform can tell
+#[cfg(target_os = "windows")]
+pub fn foreground_title() -> Option<String> {
+    use winapi::um::winuser::{GetForegroundWindow, GetWindowTextW};
+
+    let mut buffer = [0u16; 512];
+    // SAFETY: the handle is only passed straight back to the API, which copies at most
+    // buffer.len() characters into the buffer
+    let len = unsafe {
+        let window = GetForegroundWindow();
+        if window.is_null() {
+            return None;
+        }
+        GetWindowTextW(window, buffer.as_mut_ptr(), buffer.len() as i32)
+    };
+    Some(String::from_utf16_lossy(&buffer[..len.max(0) as usize]))
+}
+
+#[cfg(target_os = "linux")]
+pub fn foreground_title() -> Option<String> {
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+    let (conn, screen) = x11rb::connect(None).ok()?;
+    let root = conn.setup().roots[screen].root;
+    let atom = |name: &[u8]| conn.intern_atom(false, name).ok()?.reply().ok().map(|reply| reply.atom);
+
+    let active = conn
+        .get_property(false, root, atom(b"_NET_ACTIVE_WINDOW")?, AtomEnum::WINDOW, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?;
+    let window = active.value32()?.next().filter(|&window| window != 0)?;
+
+    // Prefer the UTF-8 title and fall back to the legacy one
+    let title = conn
+        .get_property(false, window, atom(b"_NET_WM_NAME")?, atom(b"UTF8_STRING")?, 0, 1024)
+        .ok()?
+        .reply()
+        .ok()?
+        .value;
+    let title = if title.is_empty() {
+        conn.get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)
+            .ok()?
+            .reply()
+            .ok()?
+            .value
+    } else {
+        title
+    };
+    Some(String::from_utf8_lossy(&title).into_owned())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn foreground_title() -> Option<String> {
+    None
+}
+
+// An empty filter matches everything, and so does an unknown title so the filter can't lock
+// the user out on platforms without a foreground-window query
+pub fn title_matches(filter: &str, title: Option<&str>) -> bool {
+    let filter = filter.trim();
+    match title {
+        Some(title) if !filter.is_empty() => title.to_lowercase().contains(&filter.to_lowercase()),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_is_a_case_insensitive_substring() {
+        assert!(title_matches("minecraft", Some("Minecraft 1.20.4 - Singleplayer")));
+        assert!(title_matches("  Notepad ", Some("notes.txt - notepad")));
+        assert!(!title_matches("minecraft", Some("Mozilla Firefox")));
+    }
+
+    #[test]
+    fn empty_filter_or_unknown_title_matches() {
+        assert!(title_matches("", Some("Mozilla Firefox")));
+        assert!(title_matches("   ", None));
+        assert!(title_matches("minecraft", None));
+    }
+}
@@ -0,0 +1,518 @@
+// Where injected input goes.
+//
+// The action thread and scripts only talk to InputBackend, so the scheduler can be driven
+// against the recording MockBackend in tests and other ways of injecting input can be swapped in.
+
+use crate::keyboard_layout::{self, KeyboardLayout};
+use crate::keys::{map_key_str_to_enigo_key, single_char, ClickButton};
+use enigo::{Coordinate, Direction, Enigo, Keyboard, Mouse, Settings};
+use serde::{Deserialize, Serialize};
+#[cfg(test)]
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// A failing backend is logged at most this often, however many calls fail in between
+pub const INJECTION_ERROR_LOG_INTERVAL: Duration = Duration::from_secs(5);
+// Consecutive failures before the status line says injection is failing
+pub const INJECTION_FAILING_AFTER: u32 = 3;
+// Consecutive failures before the action thread pauses the mode and builds the backend again
+pub const INJECTION_REBUILD_AFTER: u32 = 20;
+// Rebuilds tried without an injection working in between before the run is stopped
+pub const BACKEND_REBUILD_ATTEMPTS: u32 = 3;
+// Wait before each rebuild, giving a reconnecting display session a moment to come back
+pub const BACKEND_REBUILD_DELAY: Duration = Duration::from_millis(500);
+
+// Kiosk builds can leave out mouse or keyboard input entirely with the `mouse` and `keyboard`
+// cargo features (both on by default). Every real backend checks before injecting, so macros
+// and scripts can't get around a missing one either
+pub const MOUSE_ENABLED: bool = cfg!(feature = "mouse");
+pub const KEYBOARD_ENABLED: bool = cfg!(feature = "keyboard");
+
+pub fn mouse_allowed() -> Result<(), String> {
+    if MOUSE_ENABLED { Ok(()) } else { Err("mouse input isn't part of this build".to_string()) }
+}
+
+pub fn keyboard_allowed() -> Result<(), String> {
+    if KEYBOARD_ENABLED { Ok(()) } else { Err("keyboard input isn't part of this build".to_string()) }
+}
+
+// Errors come back as text for the log; what went wrong differs too much between backends to act on
+pub trait InputBackend {
+    fn mouse_down(&mut self, button: ClickButton) -> Result<(), String>;
+    fn mouse_up(&mut self, button: ClickButton) -> Result<(), String>;
+    fn mouse_click(&mut self, button: ClickButton) -> Result<(), String>;
+    // Keys are named as in the Keys field
+    fn key_down(&mut self, key: &str) -> Result<(), String>;
+    fn key_up(&mut self, key: &str) -> Result<(), String>;
+    fn key_click(&mut self, key: &str) -> Result<(), String>;
+    fn move_to(&mut self, x: i32, y: i32) -> Result<(), String>;
+    // The layout single characters are typed with; backends that send key names as-is ignore it
+    fn set_layout(&mut self, _layout: KeyboardLayout) {}
+    // Switch to the backend picked in the settings; only SelectedBackend can
+    fn select(&mut self, _kind: BackendKind) -> Result<(), String> {
+        Ok(())
+    }
+    // The backend input goes through after the last select, which a failed switch leaves as it was
+    fn kind(&self) -> Option<BackendKind> {
+        None
+    }
+}
+
+// The ways of injecting input the settings offer
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Enigo,
+    // Win32 SendInput directly; Windows only
+    SendInput,
+    // A virtual device through /dev/uinput, which needs no X server; Linux only
+    Uinput,
+}
+
+impl BackendKind {
+    #[cfg(windows)]
+    pub const AVAILABLE: &'static [BackendKind] = &[BackendKind::Enigo, BackendKind::SendInput];
+    #[cfg(target_os = "linux")]
+    pub const AVAILABLE: &'static [BackendKind] = &[BackendKind::Enigo, BackendKind::Uinput];
+    #[cfg(not(any(windows, target_os = "linux")))]
+    pub const AVAILABLE: &'static [BackendKind] = &[BackendKind::Enigo];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            BackendKind::Enigo => "enigo",
+            BackendKind::SendInput => "SendInput",
+            BackendKind::Uinput => "uinput",
+        }
+    }
+}
+
+pub fn build_backend(kind: BackendKind) -> Result<Box<dyn InputBackend>, String> {
+    match kind {
+        BackendKind::Enigo => Ok(Box::new(EnigoBackend::new()?)),
+        #[cfg(windows)]
+        BackendKind::SendInput => Ok(Box::new(crate::sendinput::SendInputBackend::new()?)),
+        #[cfg(not(windows))]
+        BackendKind::SendInput => Err("SendInput is only available on Windows".to_string()),
+        #[cfg(target_os = "linux")]
+        BackendKind::Uinput => Ok(Box::new(crate::uinput::UinputBackend::new()?)),
+        #[cfg(not(target_os = "linux"))]
+        BackendKind::Uinput => Err("uinput is only available on Linux".to_string()),
+    }
+}
+
+// Whichever backend the settings pick, rebuilt when the pick changes. What the action thread runs on
+pub struct SelectedBackend {
+    kind: BackendKind,
+    inner: Box<dyn InputBackend>,
+}
+
+impl SelectedBackend {
+    pub fn new(kind: BackendKind) -> Result<Self, String> {
+        Ok(SelectedBackend { kind, inner: build_backend(kind)? })
+    }
+}
+
+impl InputBackend for SelectedBackend {
+    fn mouse_down(&mut self, button: ClickButton) -> Result<(), String> {
+        self.inner.mouse_down(button)
+    }
+
+    fn mouse_up(&mut self, button: ClickButton) -> Result<(), String> {
+        self.inner.mouse_up(button)
+    }
+
+    fn mouse_click(&mut self, button: ClickButton) -> Result<(), String> {
+        self.inner.mouse_click(button)
+    }
+
+    fn key_down(&mut self, key: &str) -> Result<(), String> {
+        self.inner.key_down(key)
+    }
+
+    fn key_up(&mut self, key: &str) -> Result<(), String> {
+        self.inner.key_up(key)
+    }
+
+    fn key_click(&mut self, key: &str) -> Result<(), String> {
+        self.inner.key_click(key)
+    }
+
+    fn move_to(&mut self, x: i32, y: i32) -> Result<(), String> {
+        self.inner.move_to(x, y)
+    }
+
+    fn set_layout(&mut self, layout: KeyboardLayout) {
+        self.inner.set_layout(layout);
+    }
+
+    // A backend that fails to build leaves the old one in place, and is tried again on each call
+    fn select(&mut self, kind: BackendKind) -> Result<(), String> {
+        if kind != self.kind {
+            self.inner = build_backend(kind)?;
+            self.kind = kind;
+        }
+        Ok(())
+    }
+
+    fn kind(&self) -> Option<BackendKind> {
+        Some(self.kind)
+    }
+}
+
+// How many inputs per second `backend` manages, sending releases of the middle button for
+// `duration`. A release of a button that isn't down does nothing anywhere, so this is safe to
+// run over any window. A click is a press and a release, so it comes out at about half of this
+pub fn benchmark(backend: &mut dyn InputBackend, duration: Duration) -> Result<f64, String> {
+    let start = Instant::now();
+    let mut count = 0u64;
+    while start.elapsed() < duration {
+        backend.mouse_up(ClickButton::Middle)?;
+        count += 1;
+    }
+    Ok(count as f64 / start.elapsed().as_secs_f64())
+}
+
+// Injects through enigo. Single characters go through the layout table so AltGr and dead keys work
+pub struct EnigoBackend {
+    enigo: Enigo,
+    layout: KeyboardLayout,
+}
+
+impl EnigoBackend {
+    pub fn new() -> Result<Self, String> {
+        let enigo = Enigo::new(&Settings::default()).map_err(|e| e.to_string())?;
+        Ok(EnigoBackend { enigo, layout: KeyboardLayout::default() })
+    }
+
+    fn button(&mut self, button: ClickButton, direction: Direction) -> Result<(), String> {
+        mouse_allowed()?;
+        self.enigo.button(button.to_enigo(), direction).map_err(|e| e.to_string())
+    }
+
+    fn key(&mut self, key: &str, direction: Direction) -> Result<(), String> {
+        keyboard_allowed()?;
+        let result = match (single_char(key), direction) {
+            (Some(c), Direction::Press) => keyboard_layout::press_char(&mut self.enigo, c, self.layout),
+            (Some(c), Direction::Release) => keyboard_layout::release_char(&mut self.enigo, c, self.layout),
+            (Some(c), Direction::Click) => keyboard_layout::tap_char(&mut self.enigo, c, self.layout),
+            (None, _) => match map_key_str_to_enigo_key(key) {
+                Some(enigo_key) => self.enigo.key(enigo_key, direction),
+                None => return Err(format!("unknown key '{}'", key)),
+            },
+        };
+        result.map_err(|e| e.to_string())
+    }
+}
+
+impl InputBackend for EnigoBackend {
+    fn mouse_down(&mut self, button: ClickButton) -> Result<(), String> {
+        self.button(button, Direction::Press)
+    }
+
+    fn mouse_up(&mut self, button: ClickButton) -> Result<(), String> {
+        self.button(button, Direction::Release)
+    }
+
+    fn mouse_click(&mut self, button: ClickButton) -> Result<(), String> {
+        self.button(button, Direction::Click)
+    }
+
+    fn key_down(&mut self, key: &str) -> Result<(), String> {
+        self.key(key, Direction::Press)
+    }
+
+    fn key_up(&mut self, key: &str) -> Result<(), String> {
+        self.key(key, Direction::Release)
+    }
+
+    fn key_click(&mut self, key: &str) -> Result<(), String> {
+        self.key(key, Direction::Click)
+    }
+
+    fn move_to(&mut self, x: i32, y: i32) -> Result<(), String> {
+        mouse_allowed()?;
+        self.enigo.move_mouse(x, y, Coordinate::Abs).map_err(|e| e.to_string())
+    }
+
+    fn set_layout(&mut self, layout: KeyboardLayout) {
+        self.layout = layout;
+    }
+}
+
+// Injection failures as the action thread sees them, so a backend that fails on every call
+// shows up in the log and the status without one log line per call
+#[derive(Clone, Debug, Default)]
+pub struct InjectionErrors {
+    consecutive: u32,
+    // Failures since the last logged one
+    unreported: u32,
+    last_logged: Option<Instant>,
+    // Backends built since an injection last worked
+    rebuilds: u32,
+}
+
+impl InjectionErrors {
+    pub fn succeeded(&mut self) {
+        self.consecutive = 0;
+        self.rebuilds = 0;
+    }
+
+    // Count a failure; returns a log line when one is due
+    pub fn failed(&mut self, error: &str, now: Instant) -> Option<String> {
+        self.consecutive += 1;
+        if self.last_logged.is_some_and(|at| now.saturating_duration_since(at) < INJECTION_ERROR_LOG_INTERVAL) {
+            self.unreported += 1;
+            return None;
+        }
+        self.last_logged = Some(now);
+        let line = match std::mem::take(&mut self.unreported) {
+            0 => format!("Input injection failed: {}", error),
+            more => format!("Input injection failed: {} ({} more failures since the last report)", error, more),
+        };
+        Some(line)
+    }
+
+    pub fn is_failing(&self) -> bool {
+        self.consecutive >= INJECTION_FAILING_AFTER
+    }
+
+    pub fn needs_rebuild(&self) -> bool {
+        self.consecutive >= INJECTION_REBUILD_AFTER
+    }
+
+    // Count a rebuild about to be tried, starting the failure count over for the new backend.
+    // False once BACKEND_REBUILD_ATTEMPTS have been used up without an injection working since,
+    // when it's time to give up instead
+    pub fn start_rebuild(&mut self) -> bool {
+        if self.rebuilds >= BACKEND_REBUILD_ATTEMPTS {
+            return false;
+        }
+        self.rebuilds += 1;
+        self.consecutive = 0;
+        true
+    }
+
+    pub fn rebuilds(&self) -> u32 {
+        self.rebuilds
+    }
+}
+
+// One call a MockBackend received
+#[cfg(test)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum MockCall {
+    MouseDown(ClickButton),
+    MouseUp(ClickButton),
+    MouseClick(ClickButton),
+    KeyDown(String),
+    KeyUp(String),
+    KeyClick(String),
+    MoveTo(i32, i32),
+}
+
+// Records every call with the time it arrived instead of injecting anything. Clones share the
+// record, so a test can keep one while the action thread owns another. Only built for tests
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct MockBackend {
+    calls: Arc<Mutex<Vec<(Instant, MockCall)>>>,
+    // Every call fails with this while it is set (and is still recorded)
+    failure: Arc<Mutex<Option<String>>>,
+    // The next call panics (after being recorded), standing in for a bug on the action thread
+    panic_next: Arc<AtomicBool>,
+}
+
+#[cfg(test)]
+impl MockBackend {
+    fn push(&self, call: MockCall) -> Result<(), String> {
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push((Instant::now(), call));
+        }
+        if self.panic_next.swap(false, Ordering::SeqCst) {
+            panic!("mock backend told to panic");
+        }
+        match self.failure.lock().ok().and_then(|failure| failure.clone()) {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    pub fn fail_with(&self, error: Option<&str>) {
+        if let Ok(mut failure) = self.failure.lock() {
+            *failure = error.map(str::to_string);
+        }
+    }
+
+    pub fn panic_next(&self) {
+        self.panic_next.store(true, Ordering::SeqCst);
+    }
+
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.timed_calls().into_iter().map(|(_, call)| call).collect()
+    }
+
+    pub fn timed_calls(&self) -> Vec<(Instant, MockCall)> {
+        self.calls.lock().map(|calls| calls.clone()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+impl InputBackend for MockBackend {
+    fn mouse_down(&mut self, button: ClickButton) -> Result<(), String> {
+        self.push(MockCall::MouseDown(button))
+    }
+
+    fn mouse_up(&mut self, button: ClickButton) -> Result<(), String> {
+        self.push(MockCall::MouseUp(button))
+    }
+
+    fn mouse_click(&mut self, button: ClickButton) -> Result<(), String> {
+        self.push(MockCall::MouseClick(button))
+    }
+
+    fn key_down(&mut self, key: &str) -> Result<(), String> {
+        self.push(MockCall::KeyDown(key.to_string()))
+    }
+
+    fn key_up(&mut self, key: &str) -> Result<(), String> {
+        self.push(MockCall::KeyUp(key.to_string()))
+    }
+
+    fn key_click(&mut self, key: &str) -> Result<(), String> {
+        self.push(MockCall::KeyClick(key.to_string()))
+    }
+
+    fn move_to(&mut self, x: i32, y: i32) -> Result<(), String> {
+        self.push(MockCall::MoveTo(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logs_failures_at_most_once_per_interval() {
+        let start = Instant::now();
+        let mut errors = InjectionErrors::default();
+        assert_eq!(errors.failed("denied", start).as_deref(), Some("Input injection failed: denied"));
+        assert_eq!(errors.failed("denied", start + Duration::from_secs(1)), None);
+        assert_eq!(errors.failed("denied", start + Duration::from_secs(2)), None);
+        assert_eq!(
+            errors.failed("denied", start + INJECTION_ERROR_LOG_INTERVAL).as_deref(),
+            Some("Input injection failed: denied (2 more failures since the last report)"),
+        );
+    }
+
+    #[test]
+    fn failing_after_consecutive_errors_only() {
+        let now = Instant::now();
+        let mut errors = InjectionErrors::default();
+        for _ in 1..INJECTION_FAILING_AFTER {
+            errors.failed("denied", now);
+        }
+        assert!(!errors.is_failing());
+        errors.succeeded();
+        for _ in 0..INJECTION_FAILING_AFTER {
+            errors.failed("denied", now);
+        }
+        assert!(errors.is_failing());
+        errors.succeeded();
+        assert!(!errors.is_failing());
+    }
+
+    #[test]
+    fn rebuilds_only_so_often_without_a_success() {
+        let now = Instant::now();
+        let mut errors = InjectionErrors::default();
+        for _ in 1..INJECTION_REBUILD_AFTER {
+            errors.failed("denied", now);
+        }
+        assert!(errors.is_failing());
+        assert!(!errors.needs_rebuild());
+        errors.failed("denied", now);
+        assert!(errors.needs_rebuild());
+
+        // Each rebuilt backend gets the full count again, but only so many of them
+        for attempt in 1..=BACKEND_REBUILD_ATTEMPTS {
+            assert!(errors.start_rebuild());
+            assert_eq!(errors.rebuilds(), attempt);
+            assert!(!errors.needs_rebuild());
+            for _ in 0..INJECTION_REBUILD_AFTER {
+                errors.failed("denied", now);
+            }
+        }
+        assert!(!errors.start_rebuild());
+
+        // One injection that works earns the next run its attempts back
+        errors.succeeded();
+        assert_eq!(errors.rebuilds(), 0);
+        assert!(errors.start_rebuild());
+    }
+
+    #[test]
+    fn failing_mock_backend_counts_towards_a_rebuild() {
+        let mut mock = MockBackend::default();
+        let mut errors = InjectionErrors::default();
+        mock.fail_with(Some("display gone"));
+        for _ in 0..INJECTION_REBUILD_AFTER {
+            if let Err(e) = mock.mouse_click(ClickButton::Left) {
+                errors.failed(&e, Instant::now());
+            }
+        }
+        assert!(errors.needs_rebuild());
+        mock.fail_with(None);
+        assert!(errors.start_rebuild());
+        mock.mouse_click(ClickButton::Left).unwrap();
+        errors.succeeded();
+        assert_eq!(errors.rebuilds(), 0);
+        assert!(!errors.is_failing());
+    }
+
+    #[test]
+    fn benchmark_counts_inputs() {
+        let mut mock = MockBackend::default();
+        let rate = benchmark(&mut mock, Duration::from_millis(20)).unwrap();
+        let calls = mock.calls();
+        assert!(!calls.is_empty());
+        assert!(calls.iter().all(|call| *call == MockCall::MouseUp(ClickButton::Middle)));
+        assert!(rate > 0.0);
+        mock.fail_with(Some("denied"));
+        assert_eq!(benchmark(&mut mock, Duration::from_millis(20)), Err("denied".to_string()));
+    }
+
+    #[test]
+    fn selecting_keeps_the_backend_until_the_setting_changes() {
+        let mock = MockBackend::default();
+        let mut selected = SelectedBackend { kind: BackendKind::SendInput, inner: Box::new(mock.clone()) };
+        assert_eq!(selected.select(BackendKind::SendInput), Ok(()));
+        selected.mouse_click(ClickButton::Left).unwrap();
+        assert_eq!(mock.calls(), [MockCall::MouseClick(ClickButton::Left)]);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn a_failed_switch_keeps_the_backend_in_use() {
+        let mock = MockBackend::default();
+        let mut selected = SelectedBackend { kind: BackendKind::Enigo, inner: Box::new(mock.clone()) };
+        assert!(selected.select(BackendKind::SendInput).is_err());
+        assert_eq!(selected.kind(), Some(BackendKind::Enigo));
+        // Asking again fails again rather than pretending the switch happened
+        assert!(selected.select(BackendKind::SendInput).is_err());
+        selected.mouse_click(ClickButton::Left).unwrap();
+        assert_eq!(mock.calls(), [MockCall::MouseClick(ClickButton::Left)]);
+    }
+
+    #[test]
+    fn mock_records_failed_calls() {
+        let mut mock = MockBackend::default();
+        mock.fail_with(Some("no display"));
+        assert_eq!(mock.mouse_click(ClickButton::Left), Err("no display".to_string()));
+        mock.fail_with(None);
+        assert_eq!(mock.key_click("a"), Ok(()));
+        assert_eq!(mock.calls(), [MockCall::MouseClick(ClickButton::Left), MockCall::KeyClick("a".to_string())]);
+    }
+}
@@ -0,0 +1,220 @@
+// The whole setup as one JSON file, for moving it to another machine: the settings with the
+// hotkey bindings, and every profile, under a schema version.
+//
+// An import is checked entry by entry, so one bad value only costs that value: each rejected
+// setting or profile is reported with the reason and everything else is still imported. A file
+// from a newer schema is refused outright rather than half understood.
+
+use crate::profiles::Profile;
+use crate::settings::SavedSettings;
+use crate::state::{AppState, INTERVAL_RANGE};
+use crate::timing;
+use serde::Serialize;
+use serde_json::Value;
+
+// Raised whenever a change to the file would make older versions misread it
+pub const SCHEMA_VERSION: u64 = 1;
+
+#[derive(Serialize)]
+struct Backup<'a> {
+    schema_version: u64,
+    settings: &'a SavedSettings,
+    profiles: &'a [Profile],
+}
+
+pub fn export(settings: &SavedSettings, profiles: &[Profile]) -> String {
+    let backup = Backup { schema_version: SCHEMA_VERSION, settings, profiles };
+    serde_json::to_string_pretty(&backup).unwrap_or_default()
+}
+
+// What an import file holds once checked
+#[derive(Debug)]
+pub struct Import {
+    // The current settings with each one accepted from the file in its place
+    pub settings: SavedSettings,
+    pub settings_accepted: usize,
+    pub profiles: Vec<Profile>,
+    // Each entry that was left out and why, e.g. "settings.start_delay_s: invalid type: ..."
+    pub rejected: Vec<String>,
+}
+
+// Why a profile from a file can't be used, beyond it being readable at all
+fn check_profile(profile: &Profile) -> Result<(), String> {
+    if profile.name.trim().is_empty() {
+        return Err("the profile has no name".to_string());
+    }
+    for (what, us) in [("click interval", profile.click_interval_us), ("key interval", profile.key_interval_us)] {
+        if !INTERVAL_RANGE.contains(&us) {
+            return Err(format!(
+                "the {} must be between {} and {}",
+                what,
+                timing::format_us(*INTERVAL_RANGE.start()),
+                timing::format_hms(*INTERVAL_RANGE.end()),
+            ));
+        }
+    }
+    let mut state = AppState { key_to_inject: profile.keys.clone(), ..AppState::default() };
+    state.parse_key_sequence();
+    match state.sequence_errors.first() {
+        Some(error) => Err(format!("key {} '{}' is invalid ({})", error.index + 1, error.token, error.message)),
+        None => Ok(()),
+    }
+}
+
+// Check an exported file against `current`, the settings here
+pub fn parse_import(json: &str, current: &SavedSettings) -> Result<Import, String> {
+    let document: Value = serde_json::from_str(json).map_err(|e| format!("not a JSON file: {}", e))?;
+    let version = document.get("schema_version").and_then(Value::as_u64);
+    match version {
+        None => return Err("no schema_version, so not an export from this app".to_string()),
+        Some(version) if version > SCHEMA_VERSION => {
+            return Err(format!(
+                "it was exported by a newer version (schema {}); this one reads up to schema {}",
+                version, SCHEMA_VERSION,
+            ));
+        },
+        Some(_) => {},
+    }
+    let mut rejected = Vec::new();
+
+    // Each setting is tried on its own on top of the current ones, so a bad one is named
+    let Value::Object(mut merged) = serde_json::to_value(current).map_err(|e| e.to_string())? else {
+        return Err("settings aren't an object".to_string());
+    };
+    let mut settings_accepted = 0;
+    match document.get("settings") {
+        None => {},
+        Some(Value::Object(entries)) => {
+            for (key, value) in entries {
+                if !merged.contains_key(key) {
+                    rejected.push(format!("settings.{}: not a setting this version knows", key));
+                    continue;
+                }
+                let mut candidate = merged.clone();
+                candidate.insert(key.clone(), value.clone());
+                match serde_json::from_value::<SavedSettings>(Value::Object(candidate)) {
+                    Ok(_) => {
+                        merged.insert(key.clone(), value.clone());
+                        settings_accepted += 1;
+                    },
+                    Err(e) => rejected.push(format!("settings.{}: {}", key, e)),
+                }
+            }
+        },
+        Some(_) => rejected.push("settings: not an object".to_string()),
+    }
+    let settings = serde_json::from_value(Value::Object(merged)).map_err(|e| e.to_string())?;
+
+    let mut profiles: Vec<Profile> = Vec::new();
+    match document.get("profiles") {
+        None => {},
+        Some(Value::Array(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                let label = match item.get("name").and_then(Value::as_str) {
+                    Some(name) => format!("profiles[{}] ('{}')", i, name),
+                    None => format!("profiles[{}]", i),
+                };
+                let profile = serde_json::from_value::<Profile>(item.clone())
+                    .map_err(|e| e.to_string())
+                    .and_then(|profile| check_profile(&profile).map(|()| profile));
+                match profile {
+                    Ok(profile) if profiles.iter().any(|p| p.name == profile.name) => {
+                        rejected.push(format!("{}: an earlier profile in the file has the same name", label));
+                    },
+                    Ok(profile) => profiles.push(profile),
+                    Err(e) => rejected.push(format!("{}: {}", label, e)),
+                }
+            }
+        },
+        Some(_) => rejected.push("profiles: not a list".to_string()),
+    }
+
+    Ok(Import { settings, settings_accepted, profiles, rejected })
+}
+
+// Split imported profiles into those new here and those whose name is taken by a different
+// profile here; ones identical to a profile here are dropped
+pub fn split_conflicts(existing: &[Profile], incoming: Vec<Profile>) -> (Vec<Profile>, Vec<Profile>) {
+    let mut new = Vec::new();
+    let mut conflicting = Vec::new();
+    for profile in incoming {
+        match existing.iter().find(|p| p.name == profile.name) {
+            None => new.push(profile),
+            Some(here) if *here != profile => conflicting.push(profile),
+            Some(_) => {},
+        }
+    }
+    (new, conflicting)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str, keys: &str) -> Profile {
+        let mut state = AppState { key_to_inject: keys.to_string(), ..AppState::default() };
+        state.click_interval.us = 50_000;
+        state.current_profile(name, false)
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let mut state = AppState { hold_mode: true, ..AppState::default() };
+        state.start_delay_s = 7;
+        let profiles = vec![profile("Farm", "a, b"), profile("Fish", "f")];
+        let json = export(&SavedSettings::of(&state), &profiles);
+
+        let import = parse_import(&json, &SavedSettings::default()).unwrap();
+        assert_eq!(import.settings, SavedSettings::of(&state));
+        assert_eq!(import.profiles, profiles);
+        assert!(import.rejected.is_empty());
+    }
+
+    #[test]
+    fn rejects_exactly_the_bad_entries() {
+        let json = r#"{
+            "schema_version": 1,
+            "settings": {"start_delay_s": 5, "hold_mode": "yes", "teleport": true},
+            "profiles": [
+                {"name": "Good", "keys": "w"},
+                {"name": "Typo", "keys": "w, bogus"},
+                {"name": "Slow", "click_interval_us": 0},
+                {"keys": "x"},
+                {"name": "Good", "keys": "s"},
+                {"name": 5}
+            ]
+        }"#;
+        let import = parse_import(json, &SavedSettings::default()).unwrap();
+        assert_eq!(import.settings_accepted, 1);
+        assert_eq!(import.settings.start_delay_s, 5);
+        assert!(!import.settings.hold_mode);
+        assert_eq!(import.profiles.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["Good"]);
+
+        let rejected = import.rejected;
+        assert_eq!(rejected.len(), 7, "{:#?}", rejected);
+        assert!(rejected.iter().any(|r| r.starts_with("settings.hold_mode: invalid type")));
+        assert!(rejected.contains(&"settings.teleport: not a setting this version knows".to_string()));
+        assert!(rejected.iter().any(|r| r.starts_with("profiles[1] ('Typo'): key 2 'bogus' is invalid")));
+        assert!(rejected.iter().any(|r| r.starts_with("profiles[2] ('Slow'): the click interval must be between")));
+        assert!(rejected.contains(&"profiles[3]: the profile has no name".to_string()));
+        assert!(rejected.contains(&"profiles[4] ('Good'): an earlier profile in the file has the same name".to_string()));
+        assert!(rejected.iter().any(|r| r.starts_with("profiles[5]: invalid type")));
+    }
+
+    #[test]
+    fn refuses_other_files_and_newer_schemas() {
+        assert!(parse_import("not json", &SavedSettings::default()).is_err());
+        assert!(parse_import("{\"settings\": {}}", &SavedSettings::default()).unwrap_err().contains("schema_version"));
+        let newer = format!("{{\"schema_version\": {}}}", SCHEMA_VERSION + 1);
+        assert!(parse_import(&newer, &SavedSettings::default()).unwrap_err().contains("newer version"));
+    }
+
+    #[test]
+    fn splits_out_conflicting_profiles() {
+        let here = vec![profile("Farm", "a"), profile("Fish", "f")];
+        let incoming = vec![profile("Farm", "a"), profile("Fish", "g"), profile("Mine", "m")];
+        let (new, conflicting) = split_conflicts(&here, incoming);
+        assert_eq!(new, vec![profile("Mine", "m")]);
+        assert_eq!(conflicting, vec![profile("Fish", "g")]);
+    }
+}
@@ -0,0 +1,384 @@
+// Command-line arguments: settings for a normal launch, and a headless mode for test rigs.
+//
+// A normal launch takes settings to start the window with, and optionally a mode to start, so
+// a desktop shortcut can drop straight into a running session. `--no-gui` skips egui entirely:
+// the arguments set up an AppState, the usual action thread runs it, and whatever it logs is
+// printed to stdout. Ctrl+C is Stop All. There are no hotkeys, so nothing there competes with
+// a copy that has a window.
+
+use crate::backend::InputBackend;
+use crate::elevation::RESTORE_FLAG;
+use crate::instance::ALLOW_MULTIPLE_FLAG;
+use crate::logging;
+use crate::{log_info, log_warn};
+use crate::safety;
+use crate::scheduler::start_action_thread;
+use crate::state::{lock_state, parse_interval_ms, ActiveMode, AppState};
+use crate::timing;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const NO_GUI_FLAG: &str = "--no-gui";
+
+// How often the log is printed and the run checked on
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+// How long the action thread gets to release what it holds on the way out
+const RELEASE_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub const USAGE: &str = "\
+Usage: ez_autoclicker --no-gui --mode click|keys [options]
+
+  --mode click|keys   click the mouse, or type the --keys sequence
+  --interval MS       milliseconds between actions (default 100)
+  --count N           stop after N actions; 0 runs until Ctrl+C (default 0)
+  --keys SEQUENCE     the key sequence for --mode keys, as typed in the Keys field
+
+Ctrl+C stops and releases anything held. Exits with 1 if input injection fails,
+2 for bad arguments.";
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_INJECTION_FAILED: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CliOptions {
+    pub mode: ActiveMode,
+    pub interval_us: u64,
+    pub count: u64,
+    pub keys: Option<String>,
+}
+
+// "click" or "keys", as --mode and --start take them
+fn parse_mode(flag: &str, text: &str) -> Result<ActiveMode, String> {
+    let mode = match text {
+        "click" => ActiveMode::Clicking,
+        "keys" => ActiveMode::KeystrokeInjection,
+        other => return Err(format!("unknown mode '{}' for {}; use click or keys", other, flag)),
+    };
+    if !mode.is_available() {
+        return Err(format!("{} isn't part of this build", mode.label().to_lowercase()));
+    }
+    Ok(mode)
+}
+
+fn parse_interval(text: &str) -> Result<u64, String> {
+    parse_interval_ms(text).map_err(|e| format!("--interval: {}", e))
+}
+
+// The options for a headless run; Ok(None) when --no-gui isn't among `args` (program name
+// excluded) and the window should open as usual
+pub fn parse_args(args: &[String]) -> Result<Option<CliOptions>, String> {
+    if !args.iter().any(|arg| arg == NO_GUI_FLAG) {
+        return Ok(None);
+    }
+    let mut mode = None;
+    let mut interval_us = 100_000;
+    let mut count = 0;
+    let mut keys = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            NO_GUI_FLAG => {},
+            "--mode" => mode = Some(parse_mode(arg, value()?)?),
+            "--interval" => interval_us = parse_interval(value()?)?,
+            "--count" => {
+                let text = value()?;
+                count = text.parse().map_err(|_| format!("--count takes a whole number, not '{}'", text))?;
+            },
+            "--keys" => keys = Some(value()?.clone()),
+            other => return Err(format!("unknown argument '{}'", other)),
+        }
+    }
+    let mode = mode.ok_or("--mode is required")?;
+    if mode == ActiveMode::KeystrokeInjection && keys.is_none() {
+        return Err("--mode keys needs --keys".to_string());
+    }
+    Ok(Some(CliOptions { mode, interval_us, count, keys }))
+}
+
+// The state a headless run starts from
+pub fn initial_state(options: &CliOptions) -> AppState {
+    let mut state = AppState::default();
+    state.click_interval.us = options.interval_us;
+    state.key_interval.us = options.interval_us;
+    state.action_limit = options.count;
+    if let Some(keys) = &options.keys {
+        state.key_to_inject = keys.clone();
+    }
+    state.parse_key_sequence();
+    state
+}
+
+// Run `options` to the end, printing the log to `out`; returns the exit code. Setting
+// `interrupted` stops the run as Stop All would. Whatever is left held at the end is released
+// through a backend from `make_backend` too, so it goes where the inputs went
+pub fn run<B, F>(options: &CliOptions, make_backend: F, interrupted: Arc<AtomicBool>, out: &mut dyn Write) -> i32
+where
+    B: InputBackend,
+    F: FnMut() -> Result<B, String> + Clone + Send + 'static,
+{
+    let state = Arc::new(Mutex::new(initial_state(options)));
+    let is_running = Arc::new(AtomicBool::new(true));
+    let hold_released = Arc::new(AtomicBool::new(false));
+    let release_backend = make_backend.clone();
+    let action_thread = start_action_thread(Arc::clone(&state), Arc::clone(&is_running), hold_released, make_backend);
+
+    // Starting can fail (an invalid key sequence), which leaves the mode off from the start. A
+    // short run can also be over before the first poll, so this is decided here
+    let started = {
+        let mut state = lock_state(&state);
+        let now = Instant::now();
+        match options.mode {
+            ActiveMode::KeystrokeInjection => state.start_keystroke_injection(now),
+            _ => state.start_clicking(now),
+        }
+        state.send_update();
+        state.active_mode != ActiveMode::None
+    };
+
+    let mut code = EXIT_OK;
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let mut state = lock_state(&state);
+        state.log.append(logging::take_pending());
+        let log = std::mem::take(&mut state.log);
+        let _ = out.write_all(log.render(state.log_level).as_bytes());
+        if interrupted.swap(false, Ordering::SeqCst) {
+            state.stop_all("Stopped by Ctrl+C\n", Instant::now());
+            state.send_update();
+            continue;
+        }
+        if state.injection_unavailable.is_some() || state.injection_failing {
+            code = EXIT_INJECTION_FAILED;
+            state.stop_all("Stopped: input injection failed\n", Instant::now());
+            state.send_update();
+            break;
+        }
+        if state.active_mode == ActiveMode::None {
+            if !started {
+                code = EXIT_USAGE;
+            }
+            break;
+        }
+    }
+
+    // As when the window closes: stop, let the action thread release what it holds, and
+    // release whatever it couldn't
+    is_running.store(false, Ordering::SeqCst);
+    lock_state(&state).send_update();
+    safety::join_with_timeout(&state, "action", action_thread, RELEASE_TIMEOUT);
+    safety::release_leftovers(release_backend);
+    let mut state = lock_state(&state);
+    let log = std::mem::take(&mut state.log);
+    let _ = out.write_all(log.render(state.log_level).as_bytes());
+    let _ = out.flush();
+    code
+}
+
+pub const LAUNCH_USAGE: &str = "\
+Usage: ez_autoclicker [--profile NAME] [--interval MS] [--keys SEQUENCE] [--hold] [--start click|keys]
+
+--profile is applied first and the other settings over it.";
+
+// How long an autostart waits at least, so the shortcut's window can be left for the target
+pub const AUTOSTART_DELAY: Duration = Duration::from_secs(3);
+
+// Settings given on the command line of a normal (windowed) launch. They're applied over the
+// defaults and anything restored at startup, --profile first and the single settings after it,
+// so `--profile farming --interval 50` is the farming profile at 50 ms
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LaunchOptions {
+    pub profile: Option<String>,
+    pub interval_us: Option<u64>,
+    pub keys: Option<String>,
+    pub hold: bool,
+    pub start: Option<ActiveMode>,
+}
+
+// The launch options in `args` (program name excluded), with a message for each one that
+// couldn't be used; those are left at their defaults rather than stopping the launch
+pub fn parse_launch_args(args: &[String]) -> (LaunchOptions, Vec<String>) {
+    let mut options = LaunchOptions::default();
+    let mut errors = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        let result = match arg.as_str() {
+            ALLOW_MULTIPLE_FLAG => Ok(()),
+            RESTORE_FLAG => value().map(|_| ()),
+            "--profile" => value().map(|name| options.profile = Some(name.clone())),
+            "--interval" => value().and_then(|text| parse_interval(text)).map(|us| options.interval_us = Some(us)),
+            "--keys" => value().map(|keys| options.keys = Some(keys.clone())),
+            "--hold" => {
+                options.hold = true;
+                Ok(())
+            },
+            "--start" => value().and_then(|text| parse_mode(arg, text)).map(|mode| options.start = Some(mode)),
+            other => Err(format!("unknown argument '{}'", other)),
+        };
+        if let Err(e) = result {
+            errors.push(e);
+        }
+    }
+    (options, errors)
+}
+
+// Apply `options` to the state the window starts with, logging what came from the command line
+pub fn apply_launch_options(state: &mut AppState, options: &LaunchOptions, now: Instant) {
+    if let Some(name) = &options.profile {
+        match state.profiles.iter().position(|profile| &profile.name == name) {
+            Some(index) => state.apply_profile(index, now),
+            None => log_warn!(state.log, "Command line: no profile called '{}'", name),
+        }
+    }
+    if let Some(us) = options.interval_us {
+        state.click_interval.us = us;
+        state.key_interval.us = us;
+        log_info!(state.log, "Command line: interval {}", timing::format_us(us));
+    }
+    if let Some(keys) = &options.keys {
+        state.key_to_inject = keys.clone();
+        state.parse_key_sequence();
+        log_info!(state.log, "Command line: keys '{}'", keys);
+    }
+    if options.hold {
+        state.hold_mode = true;
+        log_info!(state.log, "Command line: hold mode on");
+    }
+    if let Some(mode) = &options.start {
+        state.start_mode(mode, now);
+        // Starting can fail (an invalid sequence); the log already says why
+        if state.active_mode != ActiveMode::None {
+            let earliest = now + AUTOSTART_DELAY;
+            if state.start_pending_until.is_none_or(|until| until < earliest) {
+                state.start_pending_until = Some(earliest);
+            }
+            log_info!(state.log, "Command line: starting {} after the countdown", mode.label().to_lowercase());
+        }
+    }
+}
+
+// A windows_subsystem app has no console of its own; borrow the one it was started from, if
+// any, so the log shows up there
+#[cfg(windows)]
+pub fn attach_console() {
+    use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
+    // SAFETY: no pointers involved; failing just means there's no console to print to
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn attach_console() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, MockCall};
+    use crate::keys::ClickButton;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_a_headless_run() {
+        assert_eq!(parse_args(&args("--allow-multiple")), Ok(None));
+        assert_eq!(
+            parse_args(&args("--no-gui --mode click --interval 250 --count 1000")),
+            Ok(Some(CliOptions { mode: ActiveMode::Clicking, interval_us: 250_000, count: 1000, keys: None })),
+        );
+        assert_eq!(
+            parse_args(&args("--no-gui --mode keys --keys a --interval 0.5")).map(|o| o.map(|o| o.interval_us)),
+            Ok(Some(500)),
+        );
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse_args(&args("--no-gui")).is_err());
+        assert!(parse_args(&args("--no-gui --mode scroll")).is_err());
+        assert!(parse_args(&args("--no-gui --mode keys")).is_err());
+        assert!(parse_args(&args("--no-gui --mode click --interval")).is_err());
+        assert!(parse_args(&args("--no-gui --mode click --interval 0")).is_err());
+        assert!(parse_args(&args("--no-gui --mode click --count lots")).is_err());
+        assert!(parse_args(&args("--no-gui --mode click --colour red")).is_err());
+    }
+
+    #[test]
+    fn parses_launch_options_and_reports_bad_ones() {
+        let (options, errors) = parse_launch_args(&args("--interval 50 --keys w,s --hold --profile farming --start keys --allow-multiple"));
+        assert!(errors.is_empty());
+        assert_eq!(options, LaunchOptions {
+            profile: Some("farming".to_string()),
+            interval_us: Some(50_000),
+            keys: Some("w,s".to_string()),
+            hold: true,
+            start: Some(ActiveMode::KeystrokeInjection),
+        });
+
+        let (options, errors) = parse_launch_args(&args("--interval fast --hold --start dance --bogus"));
+        assert_eq!(options, LaunchOptions { hold: true, ..LaunchOptions::default() });
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn launch_options_override_the_profile_and_autostart_after_a_delay() {
+        let now = Instant::now();
+        let mut state = AppState::default();
+        state.click_interval.us = 1_000_000;
+        state.key_to_inject = "q".to_string();
+        state.profile_name = "farming".to_string();
+        state.save_profile();
+        let mut state = AppState { profiles: state.profiles, ..AppState::default() };
+
+        let (options, _) = parse_launch_args(&args("--profile farming --interval 50 --start click"));
+        apply_launch_options(&mut state, &options, now);
+        assert_eq!(state.key_to_inject, "q");
+        assert_eq!(state.click_interval.us, 50_000);
+        assert_eq!(state.active_mode, ActiveMode::Clicking);
+        assert_eq!(state.start_pending_until, Some(now + AUTOSTART_DELAY));
+        assert!(state.log.contains("Command line: interval"));
+    }
+
+    #[test]
+    fn clicks_the_count_and_exits() {
+        let backend = MockBackend::default();
+        let options = CliOptions { mode: ActiveMode::Clicking, interval_us: 1_000, count: 5, keys: None };
+        let mut out = Vec::new();
+        let thread_backend = backend.clone();
+        let code = run(&options, move || Ok(thread_backend.clone()), Arc::new(AtomicBool::new(false)), &mut out);
+        assert_eq!(code, EXIT_OK);
+        let calls = backend.calls();
+        assert_eq!(calls[..5], vec![MockCall::MouseClick(ClickButton::Left); 5]);
+        // Then the shutdown lets go of every button, as closing the window does
+        assert!(calls[5..].iter().all(|call| matches!(call, MockCall::MouseUp(_))), "{:?}", calls);
+        assert!(String::from_utf8(out).unwrap().contains("Started clicking"));
+    }
+
+    #[test]
+    fn injection_failure_exits_non_zero() {
+        let backend = MockBackend::default();
+        backend.fail_with(Some("denied"));
+        let options = CliOptions { mode: ActiveMode::Clicking, interval_us: 1_000, count: 0, keys: None };
+        let mut out = Vec::new();
+        let thread_backend = backend.clone();
+        let code = run(&options, move || Ok(thread_backend.clone()), Arc::new(AtomicBool::new(false)), &mut out);
+        assert_eq!(code, EXIT_INJECTION_FAILED);
+        assert!(String::from_utf8(out).unwrap().contains("Input injection failed: denied"));
+    }
+
+    #[test]
+    fn interrupting_stops_the_run() {
+        let options = CliOptions { mode: ActiveMode::Clicking, interval_us: 1_000, count: 0, keys: None };
+        let mut out = Vec::new();
+        let code = run(&options, || Ok(MockBackend::default()), Arc::new(AtomicBool::new(true)), &mut out);
+        assert_eq!(code, EXIT_OK);
+        assert!(String::from_utf8(out).unwrap().contains("Stopped by Ctrl+C"));
+    }
+}
@@ -0,0 +1,79 @@
+// Where the action thread gets the time from, so tests can step through a run without waiting.
+//
+// Only the scheduling side reads the clock: how long the thread actually sleeps and spins is
+// still measured in real time, so a MockClock nobody advances just means nothing comes due.
+
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+#[cfg(test)]
+use std::time::Duration;
+use std::time::Instant;
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// A clock that only moves when told to. Clones share the same time
+#[cfg(test)]
+#[derive(Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new() -> Self {
+        MockClock { now: Arc::new(Mutex::new(Instant::now())) }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += by;
+    }
+
+    // Never moves backwards; an Instant earlier than now is ignored
+    pub fn set(&self, to: Instant) {
+        let mut now = self.now.lock().unwrap_or_else(|e| e.into_inner());
+        *now = (*now).max(to);
+    }
+}
+
+#[cfg(test)]
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_moves_only_forward_and_only_when_told() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+        let shared = clock.clone();
+        shared.advance(Duration::from_millis(250));
+        assert_eq!(clock.now(), start + Duration::from_millis(250));
+        clock.set(start);
+        assert_eq!(clock.now(), start + Duration::from_millis(250));
+        clock.set(start + Duration::from_secs(2));
+        assert_eq!(shared.now(), start + Duration::from_secs(2));
+    }
+}
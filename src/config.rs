@@ -0,0 +1,242 @@
+// Where the app keeps files between runs, and the config file there.
+//
+// config.toml holds the same settings as eframe's storage in a form people can edit. It is read
+// at startup, after the storage, so an edit made while the app was closed wins, and written back
+// once the settings have stopped changing for CONFIG_WRITE_DELAY and on exit. Keys this version
+// doesn't know are kept when the file is written; a file that doesn't parse is never
+// overwritten.
+
+use crate::settings::SavedSettings;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+// How long the settings have to stay the same before they're written, so typing in a field
+// doesn't write the file on every key
+pub const CONFIG_WRITE_DELAY: Duration = Duration::from_secs(1);
+
+// The per-user config directory, e.g. ~/.config/ez_autoclicker or %APPDATA%\ez_autoclicker;
+// the temp directory where there's no such thing. Not created here
+pub fn config_dir() -> PathBuf {
+    dirs::config_dir().unwrap_or_else(std::env::temp_dir).join("ez_autoclicker")
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+// Show the config directory in the file manager, creating it first so there's something to show
+pub fn open_config_dir() -> Result<(), String> {
+    let dir = config_dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?;
+    let opener = if cfg!(windows) {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    std::process::Command::new(opener)
+        .arg(&dir)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("couldn't open {}: {}", dir.display(), e))
+}
+
+// A parse error with the line it's on, e.g. "config.toml, line 3: invalid string" followed by
+// that line
+fn parse_error(path: &Path, text: &str, error: &toml::de::Error) -> String {
+    let Some(span) = error.span() else {
+        return format!("{}: {}", path.display(), error.message());
+    };
+    let line = text[..span.start.min(text.len())].matches('\n').count();
+    format!(
+        "{}, line {}: {}\n\n    {}",
+        path.display(),
+        line + 1,
+        error.message().trim_end(),
+        text.lines().nth(line).unwrap_or("").trim_end(),
+    )
+}
+
+// The settings in the config file; None when there's no file yet
+pub fn read_config(path: &Path) -> Result<Option<SavedSettings>, String> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+    };
+    toml::from_str(&text).map(Some).map_err(|e| parse_error(path, &text, &e))
+}
+
+// Put `ours` into `table`, merging nested tables too so the unknown keys in them are kept
+fn merge_tables(table: &mut toml::Table, ours: toml::Table) {
+    for (key, value) in ours {
+        if let toml::Value::Table(nested) = value {
+            if let Some(toml::Value::Table(existing)) = table.get_mut(&key) {
+                merge_tables(existing, nested);
+                continue;
+            }
+            table.insert(key, toml::Value::Table(nested));
+        } else {
+            table.insert(key, value);
+        }
+    }
+}
+
+// Write `settings` to the config file, keeping any keys in it that aren't ours. A file that
+// can't be read is left alone like one that doesn't parse
+pub fn write_config(path: &Path, settings: &SavedSettings) -> Result<(), String> {
+    let mut table = match fs::read_to_string(path) {
+        Ok(text) => text.parse::<toml::Table>().map_err(|e| {
+            format!("not overwriting it until it's fixed: {}", parse_error(path, &text, &e))
+        })?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+        Err(e) => return Err(format!("couldn't read {}: {}", path.display(), e)),
+    };
+    match toml::Value::try_from(settings).map_err(|e| e.to_string())? {
+        toml::Value::Table(ours) => merge_tables(&mut table, ours),
+        _ => return Err("settings aren't a table".to_string()),
+    }
+    let text = toml::to_string(&table).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?;
+    }
+    fs::write(path, text).map_err(|e| format!("couldn't write {}: {}", path.display(), e))
+}
+
+// Decides when the settings are due to be written to the config file
+#[derive(Default)]
+pub struct ConfigWriter {
+    // What the file holds as far as we know
+    written: Option<SavedSettings>,
+    // When the settings were first seen to differ from that
+    changed_at: Option<Instant>,
+}
+
+impl ConfigWriter {
+    // Settings just read from the file, or already written
+    pub fn new(written: SavedSettings) -> Self {
+        ConfigWriter { written: Some(written), changed_at: None }
+    }
+
+    // Whether `current` should be written now. Counts it as written, so a write that fails
+    // isn't retried until the settings change again
+    pub fn due(&mut self, current: &SavedSettings, now: Instant) -> bool {
+        if self.written.as_ref() == Some(current) {
+            self.changed_at = None;
+            return false;
+        }
+        let changed_at = *self.changed_at.get_or_insert(now);
+        if now.saturating_duration_since(changed_at) < CONFIG_WRITE_DELAY {
+            return false;
+        }
+        self.written = Some(current.clone());
+        self.changed_at = None;
+        true
+    }
+
+    // On exit: whether anything is left unwritten, however recently it changed
+    pub fn flush(&mut self, current: &SavedSettings) -> bool {
+        if self.written.as_ref() == Some(current) {
+            return false;
+        }
+        self.written = Some(current.clone());
+        self.changed_at = None;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::AppState;
+
+    fn test_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("ez_autoclicker_config_test_{}_{}", std::process::id(), name))
+            .join("config.toml")
+    }
+
+    #[test]
+    fn writes_and_reads_back_keeping_unknown_keys() {
+        let path = test_path("round_trip");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        assert_eq!(read_config(&path), Ok(None));
+
+        let mut state = AppState::default();
+        state.click_interval.us = 40_000;
+        state.key_to_inject = "a, b".to_string();
+        let settings = SavedSettings::of(&state);
+        write_config(&path, &settings).unwrap();
+        assert_eq!(read_config(&path), Ok(Some(settings.clone())));
+
+        let text = fs::read_to_string(&path).unwrap();
+        fs::write(&path, format!("from_a_newer_version = 3\n{}", text)).unwrap();
+        assert_eq!(read_config(&path), Ok(Some(settings.clone())));
+        write_config(&path, &settings).unwrap();
+        assert!(fs::read_to_string(&path).unwrap().contains("from_a_newer_version = 3"));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn keeps_unknown_keys_inside_our_tables() {
+        let path = test_path("nested");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let settings = SavedSettings::default();
+        write_config(&path, &settings).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("[hotkeys"), "{}", text);
+        fs::write(&path, text.replacen("[hotkeys", "[hotkeys.from_a_newer_version]\nx = 1\n\n[hotkeys", 1)).unwrap();
+        write_config(&path, &settings).unwrap();
+        let table: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+        assert_eq!(table["hotkeys"]["from_a_newer_version"]["x"].as_integer(), Some(1));
+        assert_eq!(read_config(&path), Ok(Some(settings)));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn does_not_overwrite_a_file_it_cannot_read() {
+        // A directory where the file should be can't be read as one
+        let path = test_path("unreadable");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        fs::create_dir_all(&path).unwrap();
+        assert!(write_config(&path, &SavedSettings::default()).is_err());
+        assert!(path.is_dir());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn parse_errors_name_the_line_and_block_writing() {
+        let path = test_path("broken");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "hold_mode = true\nkeys = \"w, s\nstart_delay_s = 3\n").unwrap();
+
+        let error = read_config(&path).unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+        assert!(error.ends_with("    keys = \"w, s"), "{}", error);
+        assert!(write_config(&path, &SavedSettings::default()).is_err());
+        assert!(fs::read_to_string(&path).unwrap().starts_with("hold_mode = true\nkeys = \"w, s\n"));
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn writes_once_the_settings_settle() {
+        let start = Instant::now();
+        let mut writer = ConfigWriter::new(SavedSettings::default());
+        let mut changed = SavedSettings::default();
+        assert!(!writer.due(&changed, start));
+        changed.hold_mode = true;
+        assert!(!writer.due(&changed, start));
+        changed.start_delay_s = 4;
+        assert!(!writer.due(&changed, start + CONFIG_WRITE_DELAY / 2));
+        assert!(writer.due(&changed, start + CONFIG_WRITE_DELAY));
+        assert!(!writer.due(&changed, start + CONFIG_WRITE_DELAY * 3));
+
+        changed.action_limit = 10;
+        assert!(writer.flush(&changed));
+        assert!(!writer.flush(&changed));
+    }
+}
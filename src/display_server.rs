@@ -0,0 +1,65 @@
+// Which kind of desktop session the app runs in.
+//
+// Under Wayland, enigo's injection and rdev's global listening mostly see nothing, so the app
+// switches to a uinput device for injection and reads /dev/input directly for hotkeys instead.
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DisplayServer {
+    X11,
+    Wayland,
+    // Windows, macOS, or a session that says nothing about itself
+    #[default]
+    Other,
+}
+
+impl DisplayServer {
+    pub fn detect() -> Self {
+        if !cfg!(target_os = "linux") {
+            return DisplayServer::Other;
+        }
+        Self::from_env(
+            std::env::var("XDG_SESSION_TYPE").ok().as_deref(),
+            std::env::var_os("WAYLAND_DISPLAY").is_some(),
+            std::env::var_os("DISPLAY").is_some(),
+        )
+    }
+
+    // XDG_SESSION_TYPE is what the login manager set up, so it wins; otherwise whichever
+    // display the environment points at. XWayland sets DISPLAY too, so Wayland is checked first
+    pub fn from_env(session_type: Option<&str>, wayland_display: bool, x_display: bool) -> Self {
+        match session_type.map(str::to_lowercase).as_deref() {
+            Some("wayland") => DisplayServer::Wayland,
+            Some("x11") => DisplayServer::X11,
+            _ if wayland_display => DisplayServer::Wayland,
+            _ if x_display => DisplayServer::X11,
+            _ => DisplayServer::Other,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DisplayServer::X11 => "X11",
+            DisplayServer::Wayland => "Wayland",
+            DisplayServer::Other => "native",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_type_wins_over_the_displays() {
+        assert_eq!(DisplayServer::from_env(Some("wayland"), false, true), DisplayServer::Wayland);
+        assert_eq!(DisplayServer::from_env(Some("x11"), true, true), DisplayServer::X11);
+        assert_eq!(DisplayServer::from_env(Some("Wayland"), false, false), DisplayServer::Wayland);
+    }
+
+    #[test]
+    fn falls_back_to_the_displays() {
+        assert_eq!(DisplayServer::from_env(Some("tty"), true, true), DisplayServer::Wayland);
+        assert_eq!(DisplayServer::from_env(None, false, true), DisplayServer::X11);
+        assert_eq!(DisplayServer::from_env(None, false, false), DisplayServer::Other);
+    }
+}
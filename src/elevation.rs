@@ -0,0 +1,241 @@
+// Spotting target windows that run as administrator, which Windows won't let us send input to.
+//
+// UIPI drops input sent to a process with a higher integrity level than ours without reporting
+// an error, so clicking an elevated window looks like the clicker doing nothing. While a mode
+// runs, the foreground window's process is compared with ours every few seconds; when it
+// outranks us the log says so and the UI offers to restart as administrator. The current
+// settings are carried across in a temporary profile the new copy loads at startup.
+// Elsewhere nothing is ever elevated.
+
+use crate::log_warn;
+use crate::profiles::Profile;
+use crate::state::{lock_state, ActiveMode, AppState};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How often the foreground window is checked while a mode runs
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+// Followed by the path of the settings to start with
+pub const RESTORE_FLAG: &str = "--restore-profile";
+
+// Everything a restarted copy needs to carry on where this one stopped
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RestartProfile {
+    pub settings: Profile,
+    pub profiles: Vec<Profile>,
+    pub active_profile: Option<String>,
+}
+
+impl RestartProfile {
+    pub fn of(state: &AppState) -> Self {
+        RestartProfile {
+            settings: state.current_profile("Before restart", true),
+            profiles: state.profiles.clone(),
+            active_profile: state.active_profile.clone(),
+        }
+    }
+
+    pub fn apply_to(self, state: &mut AppState) {
+        state.load_settings(&self.settings);
+        state.profiles = self.profiles;
+        state.active_profile = self.active_profile;
+    }
+}
+
+pub fn restart_profile_path() -> PathBuf {
+    std::env::temp_dir().join("ez_autoclicker_restart.json")
+}
+
+pub fn write_restart_profile(profile: &RestartProfile, path: &Path) -> Result<(), String> {
+    let json = serde_json::to_string(profile).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("couldn't save the settings for the restart: {}", e))
+}
+
+// Read the settings left by the copy that restarted us, removing the file since it's single-use
+pub fn take_restart_profile(path: &Path) -> Result<RestartProfile, String> {
+    let json = fs::read_to_string(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))?;
+    let _ = fs::remove_file(path);
+    serde_json::from_str(&json).map_err(|e| format!("couldn't read {}: {}", path.display(), e))
+}
+
+#[cfg(windows)]
+mod win {
+    use std::ptr;
+    use winapi::shared::minwindef::{DWORD, FALSE};
+    use winapi::shared::winerror::ERROR_ACCESS_DENIED;
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::processthreadsapi::{GetCurrentProcess, OpenProcess, OpenProcessToken};
+    use winapi::um::securitybaseapi::{GetSidSubAuthority, GetSidSubAuthorityCount, GetTokenInformation};
+    use winapi::um::winnt::{TokenIntegrityLevel, HANDLE, PROCESS_QUERY_LIMITED_INFORMATION, TOKEN_MANDATORY_LABEL, TOKEN_QUERY};
+    use winapi::um::winuser::{GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId};
+
+    pub enum Integrity {
+        Level(DWORD),
+        // The token couldn't be opened. A process of the same user and level always lets us,
+        // so a refusal means it outranks us
+        Denied,
+    }
+
+    // The mandatory integrity level of `process`' token
+    pub fn integrity_level(process: HANDLE) -> Option<Integrity> {
+        // SAFETY: the token handle is closed before returning, and the buffer is sized by the
+        // first GetTokenInformation call and holds the TOKEN_MANDATORY_LABEL the SID points into
+        unsafe {
+            let mut token: HANDLE = ptr::null_mut();
+            if OpenProcessToken(process, TOKEN_QUERY, &mut token) == 0 {
+                return (GetLastError() == ERROR_ACCESS_DENIED).then_some(Integrity::Denied);
+            }
+            let mut len: DWORD = 0;
+            GetTokenInformation(token, TokenIntegrityLevel, ptr::null_mut(), 0, &mut len);
+            let mut buffer = vec![0u64; (len as usize).div_ceil(8).max(1)];
+            let ok = GetTokenInformation(token, TokenIntegrityLevel, buffer.as_mut_ptr().cast(), len, &mut len);
+            CloseHandle(token);
+            if ok == 0 {
+                return None;
+            }
+            let label = &*(buffer.as_ptr() as *const TOKEN_MANDATORY_LABEL);
+            let count = *GetSidSubAuthorityCount(label.Label.Sid);
+            Some(Integrity::Level(*GetSidSubAuthority(label.Label.Sid, count.saturating_sub(1) as DWORD)))
+        }
+    }
+
+    pub fn own_level() -> Option<DWORD> {
+        // SAFETY: the pseudo-handle of our own process needs no closing
+        match integrity_level(unsafe { GetCurrentProcess() })? {
+            Integrity::Level(level) => Some(level),
+            Integrity::Denied => None,
+        }
+    }
+
+    // The foreground window's title and its process' integrity
+    pub fn foreground() -> Option<(String, Integrity)> {
+        // SAFETY: the window handle is only passed back to the API; the process handle is
+        // closed before returning
+        unsafe {
+            let window = GetForegroundWindow();
+            if window.is_null() {
+                return None;
+            }
+            let mut pid: DWORD = 0;
+            GetWindowThreadProcessId(window, &mut pid);
+            let mut buffer = [0u16; 512];
+            let len = GetWindowTextW(window, buffer.as_mut_ptr(), buffer.len() as i32);
+            let title = String::from_utf16_lossy(&buffer[..len.max(0) as usize]);
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+            if process.is_null() {
+                return (GetLastError() == ERROR_ACCESS_DENIED).then_some((title, Integrity::Denied));
+            }
+            let integrity = integrity_level(process);
+            CloseHandle(process);
+            Some((title, integrity?))
+        }
+    }
+}
+
+// Title of the foreground window if its process outranks ours
+#[cfg(windows)]
+pub fn elevated_foreground() -> Option<String> {
+    let (title, integrity) = win::foreground()?;
+    match integrity {
+        win::Integrity::Level(level) if level <= win::own_level()? => None,
+        _ => Some(title),
+    }
+}
+
+#[cfg(not(windows))]
+pub fn elevated_foreground() -> Option<String> {
+    None
+}
+
+// Start a copy of the app as administrator, which loads the settings at `profile_path`. Returns
+// once the user has answered the UAC prompt
+#[cfg(windows)]
+pub fn relaunch_as_admin(profile_path: &Path) -> Result<(), String> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::ptr;
+    use winapi::um::shellapi::ShellExecuteW;
+    use winapi::um::winuser::SW_SHOWNORMAL;
+
+    let wide = |s: &std::ffi::OsStr| s.encode_wide().chain(Some(0)).collect::<Vec<u16>>();
+    let exe = std::env::current_exe().map_err(|e| format!("couldn't find the app's executable: {}", e))?;
+    let args = format!("{} \"{}\"", RESTORE_FLAG, profile_path.display());
+    let (verb, exe, args) = (wide("runas".as_ref()), wide(exe.as_os_str()), wide(args.as_ref()));
+    // SAFETY: every string is NUL-terminated UTF-16 that outlives the call
+    let result = unsafe { ShellExecuteW(ptr::null_mut(), verb.as_ptr(), exe.as_ptr(), args.as_ptr(), ptr::null(), SW_SHOWNORMAL) };
+    // Anything up to 32 is an error code; the commonest is the user declining the prompt
+    if result as usize > 32 {
+        Ok(())
+    } else {
+        Err(format!("couldn't restart as administrator (error {}); the UAC prompt may have been declined", result as usize))
+    }
+}
+
+#[cfg(not(windows))]
+pub fn relaunch_as_admin(_profile_path: &Path) -> Result<(), String> {
+    Err("restarting as administrator is only possible on Windows".to_string())
+}
+
+// While a mode runs, keep state.elevated_target naming the foreground window if it outranks us,
+// logging each time a new one is found
+pub fn start_elevation_watch(state: Arc<Mutex<AppState>>, is_running: Arc<AtomicBool>) {
+    if !cfg!(windows) {
+        return;
+    }
+    thread::spawn(move || {
+        while is_running.load(Ordering::SeqCst) {
+            thread::sleep(CHECK_INTERVAL);
+            let active = lock_state(&state).active_mode != ActiveMode::None;
+            let elevated = if active { elevated_foreground() } else { None };
+            let mut state = lock_state(&state);
+            if state.elevated_target == elevated {
+                continue;
+            }
+            if let Some(title) = &elevated {
+                log_warn!(
+                    state.log,
+                    "'{}' runs as administrator, so Windows drops the input sent to it; restart as administrator to reach it",
+                    title,
+                );
+            }
+            state.elevated_target = elevated;
+            state.request_repaint();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_profile_round_trips_and_is_used_once() {
+        let mut state = AppState::default();
+        state.click_interval.us = 12_345;
+        state.key_to_inject = "a, b".to_string();
+        state.profile_name = "Farm".to_string();
+        state.save_profile();
+        state.click_interval.us = 50_000;
+
+        let path = std::env::temp_dir().join(format!("ez_autoclicker_restart_test_{}.json", std::process::id()));
+        write_restart_profile(&RestartProfile::of(&state), &path).unwrap();
+        let mut restarted = AppState::default();
+        take_restart_profile(&path).unwrap().apply_to(&mut restarted);
+        assert_eq!(restarted.click_interval.us, 50_000);
+        assert_eq!(restarted.key_to_inject, "a, b");
+        assert_eq!(restarted.profiles, state.profiles);
+        assert_eq!(restarted.active_profile.as_deref(), Some("Farm"));
+        assert!(take_restart_profile(&path).is_err());
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn nothing_is_elevated_elsewhere() {
+        assert_eq!(elevated_foreground(), None);
+    }
+}
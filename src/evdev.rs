@@ -0,0 +1,182 @@
+// Hotkeys read straight from the kernel's input devices, for Wayland sessions.
+//
+// Wayland doesn't let one app see another's input, so rdev's listener only hears keys while our
+// own window has focus. The devices under /dev/input see everything, but reading them needs
+// membership of the input group. Events are turned into rdev events and handed to the same
+// HotkeyListener the rdev listener uses.
+
+use crate::hotkeys::SIDE_BUTTONS;
+use crate::keys::map_evdev_key_to_rdev_key;
+use crate::listener::{HotkeyListener, ListenerStatus};
+use crate::logging::{self, Category};
+use crate::{log_error, log_info, log_warn};
+use crate::state::{lock_state, AppState};
+use rdev::{Button, Event, EventType, Key as RdevKey};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+const INPUT_DIR: &str = "/dev/input";
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+// How long a poll waits, which is also how quickly a shutdown is noticed
+const POLL_TIMEOUT_MS: i32 = 100;
+// Plugged-in devices are picked up this often
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+pub const PERMISSION_HINT: &str = "no permission to read /dev/input; add yourself to the input group (sudo usermod -aG input $USER) and log in again";
+
+// The rdev event for one evdev event, if it is one hotkeys care about. Auto-repeats (value 2)
+// come through as presses, as they do from rdev
+pub fn to_rdev_event(kind: u16, code: u16, value: i32) -> Option<EventType> {
+    match kind {
+        EV_KEY => {
+            let button = match code {
+                0x110 => Some(Button::Left),
+                0x111 => Some(Button::Right),
+                0x112 => Some(Button::Middle),
+                0x113 => Some(Button::Unknown(SIDE_BUTTONS.0)),
+                0x114 => Some(Button::Unknown(SIDE_BUTTONS.1)),
+                _ => None,
+            };
+            let pressed = value != 0;
+            Some(match (button, pressed) {
+                (Some(button), true) => EventType::ButtonPress(button),
+                (Some(button), false) => EventType::ButtonRelease(button),
+                (None, true) => EventType::KeyPress(map_evdev_key_to_rdev_key(code).unwrap_or(RdevKey::Unknown(code as u32))),
+                (None, false) => EventType::KeyRelease(map_evdev_key_to_rdev_key(code).unwrap_or(RdevKey::Unknown(code as u32))),
+            })
+        },
+        // Only counts as user input; nothing reads the position
+        EV_REL => Some(EventType::MouseMove { x: 0.0, y: 0.0 }),
+        _ => None,
+    }
+}
+
+// Open every event device not open yet; returns whether any was refused for lack of permission
+fn open_devices(devices: &mut Vec<(PathBuf, File)>) -> bool {
+    let Ok(entries) = fs::read_dir(INPUT_DIR) else {
+        return false;
+    };
+    let mut denied = false;
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let is_event = path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with("event"));
+        if !is_event || devices.iter().any(|(open, _)| *open == path) {
+            continue;
+        }
+        match OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(&path) {
+            Ok(file) => devices.push((path, file)),
+            Err(e) => denied |= e.kind() == io::ErrorKind::PermissionDenied,
+        }
+    }
+    denied
+}
+
+// Read whatever events `device` has waiting; Err once the device is gone
+fn read_events(device: &mut File, out: &mut Vec<(u16, u16, i32)>) -> io::Result<()> {
+    let size = mem::size_of::<libc::input_event>();
+    let mut buffer = vec![0u8; size * 64];
+    loop {
+        let read = match device.read(&mut buffer) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for chunk in buffer[..read].chunks_exact(size) {
+            // SAFETY: the kernel writes whole input_event structs, and read_unaligned copes with
+            // the byte buffer's alignment
+            let event: libc::input_event = unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const libc::input_event) };
+            out.push((event.type_, event.code, event.value));
+        }
+    }
+}
+
+// Listen for hotkeys on every input device until `is_running` goes false
+pub fn start_evdev_hotkey_thread(
+    state: Arc<Mutex<AppState>>,
+    is_running: Arc<AtomicBool>,
+    hold_released: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        logging::set_thread_category(Category::Hotkey);
+        let mut devices = Vec::new();
+        let denied = open_devices(&mut devices);
+        {
+            let mut state = lock_state(&state);
+            if devices.is_empty() {
+                let reason = if denied { PERMISSION_HINT.to_string() } else { format!("no input devices found in {}", INPUT_DIR) };
+                log_error!(state.log, "Hotkey listener error: {}", reason);
+                state.hotkey_listener = ListenerStatus::Failed;
+                state.hotkeys_unavailable = Some(reason);
+                state.request_repaint();
+                return;
+            }
+            log_info!(state.log, "Listening for hotkeys on {} input devices", devices.len());
+            if denied {
+                log_warn!(state.log, "Some input devices couldn't be read: {}", PERMISSION_HINT);
+            }
+        }
+
+        let mut listener = HotkeyListener::new(Arc::clone(&state), hold_released, false);
+        let mut last_scan = Instant::now();
+        let mut events = Vec::new();
+        while is_running.load(Ordering::SeqCst) {
+            if last_scan.elapsed() >= RESCAN_INTERVAL {
+                open_devices(&mut devices);
+                last_scan = Instant::now();
+            }
+            let mut fds: Vec<libc::pollfd> = devices
+                .iter()
+                .map(|(_, file)| libc::pollfd { fd: file.as_raw_fd(), events: libc::POLLIN, revents: 0 })
+                .collect();
+            // SAFETY: the pointer and length describe `fds`, which outlives the call
+            if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, POLL_TIMEOUT_MS) } <= 0 {
+                continue;
+            }
+            let mut gone = Vec::new();
+            for (index, fd) in fds.iter().enumerate() {
+                if fd.revents == 0 {
+                    continue;
+                }
+                if read_events(&mut devices[index].1, &mut events).is_err() {
+                    gone.push(index);
+                }
+            }
+            // Unplugged devices are dropped; a rescan finds them again if they come back
+            for index in gone.into_iter().rev() {
+                devices.remove(index);
+            }
+            for (kind, code, value) in events.drain(..) {
+                if let Some(event_type) = to_rdev_event(kind, code, value) {
+                    listener.handle(&Event { time: SystemTime::now(), name: None, event_type });
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_evdev_events_into_rdev_events() {
+        assert_eq!(to_rdev_event(EV_KEY, 64, 1), Some(EventType::KeyPress(RdevKey::F6)));
+        assert_eq!(to_rdev_event(EV_KEY, 64, 2), Some(EventType::KeyPress(RdevKey::F6)));
+        assert_eq!(to_rdev_event(EV_KEY, 64, 0), Some(EventType::KeyRelease(RdevKey::F6)));
+        assert_eq!(to_rdev_event(EV_KEY, 0x110, 1), Some(EventType::ButtonPress(Button::Left)));
+        assert_eq!(to_rdev_event(EV_KEY, 0x114, 0), Some(EventType::ButtonRelease(Button::Unknown(SIDE_BUTTONS.1))));
+        assert_eq!(to_rdev_event(EV_KEY, 240, 1), Some(EventType::KeyPress(RdevKey::Unknown(240))));
+        assert_eq!(to_rdev_event(EV_REL, 0, 5), Some(EventType::MouseMove { x: 0.0, y: 0.0 }));
+        // Sync reports and the like
+        assert_eq!(to_rdev_event(0, 0, 0), None);
+    }
+}
@@ -0,0 +1,91 @@
+// Heartbeats from the background threads, so the footer can show one that has died or hung.
+//
+// Each thread stores the time of its latest sign of life in its Heartbeat without taking any
+// lock, so a thread stuck holding the state lock still shows up as stuck. The action thread
+// and HTTP server beat every pass of their loops; the hotkey listener can only beat when an
+// input event arrives, so it gets far longer before it counts as unresponsive.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub struct Heartbeat {
+    pub name: &'static str,
+    // Silence longer than this counts as unresponsive
+    pub stale_after: Duration,
+    // Milliseconds since the epoch of the latest beat; 0 before the first
+    last_ms: AtomicU64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Health {
+    NotStarted,
+    Alive,
+    Unresponsive(Duration),
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64)
+}
+
+impl Heartbeat {
+    pub const fn new(name: &'static str, stale_after: Duration) -> Self {
+        Heartbeat { name, stale_after, last_ms: AtomicU64::new(0) }
+    }
+
+    pub fn beat(&self) {
+        self.beat_at(now_ms());
+    }
+
+    pub fn beat_at(&self, ms: u64) {
+        self.last_ms.store(ms, Ordering::Relaxed);
+    }
+
+    pub fn health(&self, now_ms: u64) -> Health {
+        match self.last_ms.load(Ordering::Relaxed) {
+            0 => Health::NotStarted,
+            last => {
+                let silent = Duration::from_millis(now_ms.saturating_sub(last));
+                if silent > self.stale_after { Health::Unresponsive(silent) } else { Health::Alive }
+            },
+        }
+    }
+
+    pub fn is_unresponsive(&self) -> bool {
+        matches!(self.health(now_ms()), Health::Unresponsive(_))
+    }
+
+    // For the dot's tooltip, e.g. "action thread unresponsive for 12s"
+    pub fn describe(&self, health: Health) -> String {
+        match health {
+            Health::NotStarted => format!("no sign of the {} thread yet", self.name),
+            Health::Alive => format!("{} thread running", self.name),
+            Health::Unresponsive(silent) => format!("{} thread unresponsive for {}s", self.name, silent.as_secs()),
+        }
+    }
+}
+
+pub static ACTION: Heartbeat = Heartbeat::new("action", Duration::from_secs(3));
+pub static HOTKEY: Heartbeat = Heartbeat::new("hotkey", Duration::from_secs(300));
+pub static HTTP: Heartbeat = Heartbeat::new("HTTP", Duration::from_secs(3));
+
+// In the order the footer shows them
+pub static THREADS: [&Heartbeat; 3] = [&ACTION, &HOTKEY, &HTTP];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goes_stale_after_its_limit() {
+        let heartbeat = Heartbeat::new("test", Duration::from_secs(3));
+        assert_eq!(heartbeat.health(10_000), Health::NotStarted);
+        heartbeat.beat_at(10_000);
+        assert_eq!(heartbeat.health(12_000), Health::Alive);
+        assert_eq!(heartbeat.health(13_000), Health::Alive);
+        let stale = heartbeat.health(22_500);
+        assert_eq!(stale, Health::Unresponsive(Duration::from_millis(12_500)));
+        assert_eq!(heartbeat.describe(stale), "test thread unresponsive for 12s");
+        // A clock that went backwards isn't silence
+        assert_eq!(heartbeat.health(5_000), Health::Alive);
+    }
+}
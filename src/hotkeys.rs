@@ -0,0 +1,588 @@
+// Global hotkey bindings and modifier tracking.
+//
+// rdev reports one key at a time, so chords like Ctrl+F6 are matched by keeping track of
+// which modifier keys are down and comparing that with the binding when the key arrives.
+
+use crate::state::ActiveMode;
+use rdev::{Button, Key as RdevKey};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::time::{Duration, Instant};
+
+// A physical input as the rdev listener sees it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PhysicalInput {
+    Key(RdevKey),
+    Button(Button),
+}
+
+impl PhysicalInput {
+    // Left and right clicks are what the clicker itself sends, so binding them needs confirming
+    pub fn is_primary_button(self) -> bool {
+        matches!(self, PhysicalInput::Button(Button::Left | Button::Right))
+    }
+
+    // Inverse of input_name, for reading bindings back from a file
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(key) = key_from_name(name) {
+            return Some(PhysicalInput::Key(key));
+        }
+        let button = match name.to_lowercase().as_str() {
+            "mouse1" => Button::Left,
+            "mouse2" => Button::Right,
+            "mouse3" => Button::Middle,
+            "mouse4" => Button::Unknown(SIDE_BUTTONS.0),
+            "mouse5" => Button::Unknown(SIDE_BUTTONS.1),
+            other => Button::Unknown(other.strip_prefix("mousebutton")?.parse().ok()?),
+        };
+        Some(PhysicalInput::Button(button))
+    }
+}
+
+// Stored by name so config files stay readable and don't depend on rdev's enum layout
+impl Serialize for PhysicalInput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&input_name(*self))
+    }
+}
+
+impl<'de> Deserialize<'de> for PhysicalInput {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        PhysicalInput::from_name(&name).ok_or_else(|| D::Error::custom(format!("unknown key or button '{}'", name)))
+    }
+}
+
+// The side buttons' codes: Windows reports them as 1 and 2, X11 as 8 and 9
+#[cfg(target_os = "windows")]
+pub const SIDE_BUTTONS: (u8, u8) = (1, 2);
+#[cfg(not(target_os = "windows"))]
+pub const SIDE_BUTTONS: (u8, u8) = (8, 9);
+
+// Display name of every rdev key; top-row digits are plain digits and the keypad is NumpadN
+const KEY_NAMES: [(RdevKey, &str); 105] = [
+    (RdevKey::Alt, "Alt"), (RdevKey::AltGr, "AltGr"), (RdevKey::ControlLeft, "Ctrl"),
+    (RdevKey::ControlRight, "RightCtrl"), (RdevKey::ShiftLeft, "Shift"),
+    (RdevKey::ShiftRight, "RightShift"), (RdevKey::MetaLeft, "Meta"),
+    (RdevKey::MetaRight, "RightMeta"), (RdevKey::Function, "Fn"), (RdevKey::Backspace, "Backspace"),
+    (RdevKey::CapsLock, "CapsLock"), (RdevKey::Delete, "Delete"), (RdevKey::End, "End"),
+    (RdevKey::Escape, "Esc"), (RdevKey::Home, "Home"), (RdevKey::Insert, "Insert"),
+    (RdevKey::PageDown, "PageDown"), (RdevKey::PageUp, "PageUp"), (RdevKey::Return, "Enter"),
+    (RdevKey::Space, "Space"), (RdevKey::Tab, "Tab"), (RdevKey::UpArrow, "Up"),
+    (RdevKey::DownArrow, "Down"), (RdevKey::LeftArrow, "Left"), (RdevKey::RightArrow, "Right"),
+    (RdevKey::PrintScreen, "PrintScreen"), (RdevKey::ScrollLock, "ScrollLock"),
+    (RdevKey::Pause, "Pause"), (RdevKey::NumLock, "NumLock"), (RdevKey::F1, "F1"),
+    (RdevKey::F2, "F2"), (RdevKey::F3, "F3"), (RdevKey::F4, "F4"), (RdevKey::F5, "F5"),
+    (RdevKey::F6, "F6"), (RdevKey::F7, "F7"), (RdevKey::F8, "F8"), (RdevKey::F9, "F9"),
+    (RdevKey::F10, "F10"), (RdevKey::F11, "F11"), (RdevKey::F12, "F12"), (RdevKey::Num0, "0"),
+    (RdevKey::Num1, "1"), (RdevKey::Num2, "2"), (RdevKey::Num3, "3"), (RdevKey::Num4, "4"),
+    (RdevKey::Num5, "5"), (RdevKey::Num6, "6"), (RdevKey::Num7, "7"), (RdevKey::Num8, "8"),
+    (RdevKey::Num9, "9"), (RdevKey::KeyA, "A"), (RdevKey::KeyB, "B"), (RdevKey::KeyC, "C"),
+    (RdevKey::KeyD, "D"), (RdevKey::KeyE, "E"), (RdevKey::KeyF, "F"), (RdevKey::KeyG, "G"),
+    (RdevKey::KeyH, "H"), (RdevKey::KeyI, "I"), (RdevKey::KeyJ, "J"), (RdevKey::KeyK, "K"),
+    (RdevKey::KeyL, "L"), (RdevKey::KeyM, "M"), (RdevKey::KeyN, "N"), (RdevKey::KeyO, "O"),
+    (RdevKey::KeyP, "P"), (RdevKey::KeyQ, "Q"), (RdevKey::KeyR, "R"), (RdevKey::KeyS, "S"),
+    (RdevKey::KeyT, "T"), (RdevKey::KeyU, "U"), (RdevKey::KeyV, "V"), (RdevKey::KeyW, "W"),
+    (RdevKey::KeyX, "X"), (RdevKey::KeyY, "Y"), (RdevKey::KeyZ, "Z"), (RdevKey::BackQuote, "`"),
+    (RdevKey::Minus, "-"), (RdevKey::Equal, "="), (RdevKey::LeftBracket, "["),
+    (RdevKey::RightBracket, "]"), (RdevKey::SemiColon, ";"), (RdevKey::Quote, "'"),
+    (RdevKey::BackSlash, "\\"), (RdevKey::IntlBackslash, "IntlBackslash"), (RdevKey::Comma, ","),
+    (RdevKey::Dot, "."), (RdevKey::Slash, "/"), (RdevKey::Kp0, "Numpad0"),
+    (RdevKey::Kp1, "Numpad1"), (RdevKey::Kp2, "Numpad2"), (RdevKey::Kp3, "Numpad3"),
+    (RdevKey::Kp4, "Numpad4"), (RdevKey::Kp5, "Numpad5"), (RdevKey::Kp6, "Numpad6"),
+    (RdevKey::Kp7, "Numpad7"), (RdevKey::Kp8, "Numpad8"), (RdevKey::Kp9, "Numpad9"),
+    (RdevKey::KpReturn, "NumpadEnter"), (RdevKey::KpMinus, "NumpadMinus"),
+    (RdevKey::KpPlus, "NumpadPlus"), (RdevKey::KpMultiply, "NumpadMultiply"),
+    (RdevKey::KpDivide, "NumpadDivide"), (RdevKey::KpDelete, "NumpadDelete"),
+];
+
+// Name of a key for labels and config files; keys rdev doesn't know show their raw code
+pub fn key_name(key: RdevKey) -> String {
+    match KEY_NAMES.iter().find(|(k, _)| *k == key) {
+        Some((_, name)) => name.to_string(),
+        None => match key {
+            RdevKey::Unknown(code) => format!("Unknown(0x{:x})", code),
+            other => format!("{:?}", other),
+        },
+    }
+}
+
+// Case-insensitive inverse of key_name
+pub fn key_from_name(name: &str) -> Option<RdevKey> {
+    if let Some(&(key, _)) = KEY_NAMES.iter().find(|(_, n)| n.eq_ignore_ascii_case(name)) {
+        return Some(key);
+    }
+    let code = name.strip_prefix("Unknown(0x")?.strip_suffix(')')?;
+    u32::from_str_radix(code, 16).ok().map(RdevKey::Unknown)
+}
+
+// Actions that can be triggered by a global hotkey
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HotkeyAction {
+    StartKeys,
+    StartClicking,
+    Stop,
+    PauseResume,
+    ShorterInterval,
+    LongerInterval,
+    NextProfile,
+    OneShot,
+    Panic,
+}
+
+impl HotkeyAction {
+    pub const ALL: [HotkeyAction; 9] = [
+        HotkeyAction::StartKeys,
+        HotkeyAction::StartClicking,
+        HotkeyAction::Stop,
+        HotkeyAction::PauseResume,
+        HotkeyAction::ShorterInterval,
+        HotkeyAction::LongerInterval,
+        HotkeyAction::NextProfile,
+        HotkeyAction::OneShot,
+        HotkeyAction::Panic,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            HotkeyAction::StartKeys => "start keystroke injection",
+            HotkeyAction::StartClicking => "start clicking",
+            HotkeyAction::Stop => "stop",
+            HotkeyAction::PauseResume => "pause/resume",
+            HotkeyAction::ShorterInterval => "shorten interval",
+            HotkeyAction::LongerInterval => "lengthen interval",
+            HotkeyAction::NextProfile => "next profile",
+            HotkeyAction::OneShot => "one-shot action",
+            HotkeyAction::Panic => "panic release",
+        }
+    }
+
+    // The mode a start hotkey switches on
+    pub fn mode(self) -> Option<ActiveMode> {
+        match self {
+            HotkeyAction::StartKeys => Some(ActiveMode::KeystrokeInjection),
+            HotkeyAction::StartClicking => Some(ActiveMode::Clicking),
+            _ => None,
+        }
+    }
+
+    // False for the start hotkey of a mode this build leaves out; such a hotkey is never
+    // bound, so its key reaches other apps as usual
+    pub fn is_available(self) -> bool {
+        self.mode().is_none_or(|mode| mode.is_available())
+    }
+}
+
+// What pressing a start hotkey does when its mode is already running
+#[derive(Clone, Copy, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Activation {
+    // Always (re)start, the original behaviour
+    #[default]
+    Restart,
+    // Stop the mode if it is already running
+    Toggle,
+    // Run only while the key is physically held down
+    Hold,
+}
+
+impl Activation {
+    pub const ALL: [Activation; 3] = [Activation::Restart, Activation::Toggle, Activation::Hold];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Activation::Restart => "Press starts",
+            Activation::Toggle => "Press toggles",
+            Activation::Hold => "Hold to run",
+        }
+    }
+}
+
+// Modifier keys that must be down for a hotkey; left and right count the same
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Modifiers {
+    pub const NONE: Modifiers = Modifiers { ctrl: false, shift: false, alt: false };
+
+    pub fn is_modifier(key: RdevKey) -> bool {
+        matches!(
+            key,
+            RdevKey::ControlLeft | RdevKey::ControlRight | RdevKey::ShiftLeft | RdevKey::ShiftRight | RdevKey::Alt | RdevKey::AltGr
+        )
+    }
+}
+
+// Which modifier keys are down, built from individual press and release events
+#[derive(Clone, Debug, Default)]
+pub struct ModifierTracker {
+    down: Vec<RdevKey>,
+}
+
+impl ModifierTracker {
+    // Returns true when the key was a modifier, which never triggers a hotkey by itself
+    pub fn handle(&mut self, key: RdevKey, pressed: bool) -> bool {
+        if !Modifiers::is_modifier(key) {
+            return false;
+        }
+        self.down.retain(|&k| k != key);
+        if pressed {
+            self.down.push(key);
+        }
+        true
+    }
+
+    pub fn current(&self) -> Modifiers {
+        self.held_besides(None)
+    }
+
+    // The modifiers down apart from `key`, for when the modifier itself is bound as a hotkey
+    pub fn current_without(&self, key: RdevKey) -> Modifiers {
+        self.held_besides(Some(key))
+    }
+
+    fn held_besides(&self, except: Option<RdevKey>) -> Modifiers {
+        let held = |keys: &[RdevKey]| self.down.iter().any(|k| Some(*k) != except && keys.contains(k));
+        Modifiers {
+            ctrl: held(&[RdevKey::ControlLeft, RdevKey::ControlRight]),
+            shift: held(&[RdevKey::ShiftLeft, RdevKey::ShiftRight]),
+            alt: held(&[RdevKey::Alt, RdevKey::AltGr]),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Hotkey {
+    pub input: PhysicalInput,
+    #[serde(default)]
+    pub modifiers: Modifiers,
+    #[serde(default)]
+    pub activation: Activation,
+    // Only fire on the second of two quick presses
+    #[serde(default)]
+    pub double_tap: bool,
+}
+
+impl Hotkey {
+    pub fn new(input: PhysicalInput) -> Self {
+        Self { input, modifiers: Modifiers::NONE, activation: Activation::default(), double_tap: false }
+    }
+
+    // Modifiers have to match exactly, so Ctrl+F6 and F6 can be bound to different actions
+    pub fn matches(&self, input: PhysicalInput, modifiers: Modifiers) -> bool {
+        self.input == input && self.modifiers == modifiers
+    }
+
+    pub fn name(&self) -> String {
+        let mut name = String::new();
+        for (down, label) in [(self.modifiers.ctrl, "Ctrl+"), (self.modifiers.shift, "Shift+"), (self.modifiers.alt, "Alt+")] {
+            if down {
+                name.push_str(label);
+            }
+        }
+        name.push_str(&input_name(self.input));
+        if self.double_tap {
+            name.push_str(" ×2");
+        }
+        name
+    }
+}
+
+// First presses of double-tap hotkeys still waiting for their second press
+#[derive(Clone, Debug, Default)]
+pub struct DoubleTapTracker {
+    first: Vec<(HotkeyAction, Instant)>,
+}
+
+impl DoubleTapTracker {
+    // Returns true when this press completes a double tap; otherwise it becomes the first tap
+    pub fn tap(&mut self, action: HotkeyAction, now: Instant, window: Duration) -> bool {
+        self.first.retain(|(_, at)| now.saturating_duration_since(*at) <= window);
+        if let Some(index) = self.first.iter().position(|(a, _)| *a == action) {
+            self.first.remove(index);
+            true
+        } else {
+            self.first.push((action, now));
+            false
+        }
+    }
+}
+
+// The key bound to each hotkey action; no two actions share a key combination
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotkeyBindings {
+    pub start_keys: Hotkey,
+    pub start_clicking: Hotkey,
+    pub stop: Hotkey,
+    pub pause: Hotkey,
+    pub shorter_interval: Hotkey,
+    pub longer_interval: Hotkey,
+    pub next_profile: Hotkey,
+    pub one_shot: Hotkey,
+    pub panic: Hotkey,
+}
+
+impl Default for HotkeyBindings {
+    fn default() -> Self {
+        Self {
+            start_keys: Hotkey::new(PhysicalInput::Key(RdevKey::F5)),
+            start_clicking: Hotkey::new(PhysicalInput::Key(RdevKey::F6)),
+            stop: Hotkey::new(PhysicalInput::Key(RdevKey::F7)),
+            pause: Hotkey::new(PhysicalInput::Key(RdevKey::F8)),
+            shorter_interval: Hotkey::new(PhysicalInput::Key(RdevKey::F9)),
+            longer_interval: Hotkey::new(PhysicalInput::Key(RdevKey::F10)),
+            next_profile: Hotkey::new(PhysicalInput::Key(RdevKey::F4)),
+            one_shot: Hotkey::new(PhysicalInput::Key(RdevKey::F11)),
+            panic: Hotkey::new(PhysicalInput::Key(RdevKey::F12)),
+        }
+    }
+}
+
+impl HotkeyBindings {
+    pub fn get(&self, action: HotkeyAction) -> &Hotkey {
+        match action {
+            HotkeyAction::StartKeys => &self.start_keys,
+            HotkeyAction::StartClicking => &self.start_clicking,
+            HotkeyAction::Stop => &self.stop,
+            HotkeyAction::PauseResume => &self.pause,
+            HotkeyAction::ShorterInterval => &self.shorter_interval,
+            HotkeyAction::LongerInterval => &self.longer_interval,
+            HotkeyAction::NextProfile => &self.next_profile,
+            HotkeyAction::OneShot => &self.one_shot,
+            HotkeyAction::Panic => &self.panic,
+        }
+    }
+
+    pub fn get_mut(&mut self, action: HotkeyAction) -> &mut Hotkey {
+        match action {
+            HotkeyAction::StartKeys => &mut self.start_keys,
+            HotkeyAction::StartClicking => &mut self.start_clicking,
+            HotkeyAction::Stop => &mut self.stop,
+            HotkeyAction::PauseResume => &mut self.pause,
+            HotkeyAction::ShorterInterval => &mut self.shorter_interval,
+            HotkeyAction::LongerInterval => &mut self.longer_interval,
+            HotkeyAction::NextProfile => &mut self.next_profile,
+            HotkeyAction::OneShot => &mut self.one_shot,
+            HotkeyAction::Panic => &mut self.panic,
+        }
+    }
+
+    // The action an input triggers given the modifiers currently down
+    pub fn action_for(&self, input: PhysicalInput, modifiers: Modifiers) -> Option<HotkeyAction> {
+        HotkeyAction::ALL
+            .into_iter()
+            .find(|&action| action.is_available() && self.get(action).matches(input, modifiers))
+    }
+
+    // Any action bound to the input, whatever its modifiers; injecting it could still trigger it
+    pub fn action_using_input(&self, input: PhysicalInput) -> Option<HotkeyAction> {
+        HotkeyAction::ALL.into_iter().find(|&action| action.is_available() && self.get(action).input == input)
+    }
+
+    // Pairs of actions bound to the same combination. Rebinding refuses these, but a profile
+    // can still bring them in
+    pub fn duplicates(&self) -> Vec<(HotkeyAction, HotkeyAction)> {
+        let mut pairs = Vec::new();
+        for (i, &first) in HotkeyAction::ALL.iter().enumerate() {
+            let hotkey = self.get(first);
+            for &second in &HotkeyAction::ALL[i + 1..] {
+                if first.is_available() && second.is_available() && self.get(second).matches(hotkey.input, hotkey.modifiers) {
+                    pairs.push((first, second));
+                }
+            }
+        }
+        pairs
+    }
+
+    // Name of the combination bound to an action, for button labels and log lines
+    pub fn name(&self, action: HotkeyAction) -> String {
+        self.get(action).name()
+    }
+}
+
+// Keys use key_name. Mouse buttons use the usual Mouse1-5 numbering; side buttons arrive as
+// Unknown(1)/(2) on Windows and Unknown(8)/(9) on X11
+pub fn input_name(input: PhysicalInput) -> String {
+    match input {
+        PhysicalInput::Key(key) => key_name(key),
+        PhysicalInput::Button(button) => match button {
+            Button::Left => "Mouse1".to_string(),
+            Button::Right => "Mouse2".to_string(),
+            Button::Middle => "Mouse3".to_string(),
+            Button::Unknown(1 | 8) => "Mouse4".to_string(),
+            Button::Unknown(2 | 9) => "Mouse5".to_string(),
+            Button::Unknown(n) => format!("MouseButton{}", n),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL: Modifiers = Modifiers { ctrl: true, shift: false, alt: false };
+    const F6: PhysicalInput = PhysicalInput::Key(RdevKey::F6);
+    const F7: PhysicalInput = PhysicalInput::Key(RdevKey::F7);
+
+    #[test]
+    fn tracks_modifiers_from_single_events() {
+        let mut tracker = ModifierTracker::default();
+        assert_eq!(tracker.current(), Modifiers::NONE);
+
+        assert!(tracker.handle(RdevKey::ControlLeft, true));
+        assert!(tracker.handle(RdevKey::ShiftRight, true));
+        assert_eq!(tracker.current(), Modifiers { ctrl: true, shift: true, alt: false });
+
+        assert!(tracker.handle(RdevKey::ShiftRight, false));
+        assert_eq!(tracker.current(), CTRL);
+
+        assert!(!tracker.handle(RdevKey::F6, true));
+        assert_eq!(tracker.current(), CTRL);
+    }
+
+    #[test]
+    fn both_sides_of_a_modifier_count_until_both_are_released() {
+        let mut tracker = ModifierTracker::default();
+        tracker.handle(RdevKey::ControlLeft, true);
+        tracker.handle(RdevKey::ControlRight, true);
+        tracker.handle(RdevKey::ControlLeft, false);
+        assert!(tracker.current().ctrl);
+        tracker.handle(RdevKey::ControlRight, false);
+        assert!(!tracker.current().ctrl);
+    }
+
+    #[test]
+    fn a_modifier_can_be_left_out_of_its_own_chord() {
+        let mut tracker = ModifierTracker::default();
+        tracker.handle(RdevKey::ShiftLeft, true);
+        tracker.handle(RdevKey::ControlRight, true);
+        assert_eq!(tracker.current_without(RdevKey::ControlRight), Modifiers { ctrl: false, shift: true, alt: false });
+        assert_eq!(tracker.current_without(RdevKey::ShiftRight), Modifiers { ctrl: true, shift: true, alt: false });
+    }
+
+    #[test]
+    fn every_key_name_round_trips() {
+        for (key, name) in KEY_NAMES {
+            assert_eq!(key_name(key), name);
+            assert_eq!(key_from_name(name), Some(key), "{}", name);
+        }
+        assert_eq!(key_name(RdevKey::Unknown(0x47)), "Unknown(0x47)");
+        assert_eq!(key_from_name("Unknown(0x47)"), Some(RdevKey::Unknown(0x47)));
+        assert_eq!(key_from_name("numpad7"), Some(RdevKey::Kp7));
+        assert_eq!(key_from_name("Nonsense"), None);
+    }
+
+    #[test]
+    fn bindings_serialize_by_name() {
+        let mut bindings = HotkeyBindings {
+            start_clicking: Hotkey { input: PhysicalInput::Key(RdevKey::Kp5), modifiers: CTRL, activation: Activation::Toggle, double_tap: true },
+            ..HotkeyBindings::default()
+        };
+        bindings.stop.input = PhysicalInput::Key(RdevKey::Pause);
+        bindings.pause.input = PhysicalInput::Key(RdevKey::ControlRight);
+        bindings.one_shot.input = PhysicalInput::Key(RdevKey::Unknown(0x47));
+        bindings.next_profile.input = PhysicalInput::Button(Button::Unknown(SIDE_BUTTONS.1));
+
+        let json = serde_json::to_string(&bindings).unwrap();
+        assert!(json.contains(r#""input":"Numpad5""#), "{}", json);
+        assert!(json.contains(r#""input":"RightCtrl""#), "{}", json);
+        assert_eq!(serde_json::from_str::<HotkeyBindings>(&json).unwrap(), bindings);
+
+        for input in [PhysicalInput::Key(RdevKey::ScrollLock), PhysicalInput::Key(RdevKey::Insert), PhysicalInput::Button(Button::Middle), PhysicalInput::Button(Button::Unknown(12))] {
+            let json = serde_json::to_string(&input).unwrap();
+            assert_eq!(serde_json::from_str::<PhysicalInput>(&json).unwrap(), input);
+        }
+        assert!(serde_json::from_str::<PhysicalInput>(r#""Nonsense""#).is_err());
+    }
+
+    #[test]
+    fn missing_bindings_fall_back_to_defaults() {
+        let bindings: HotkeyBindings = serde_json::from_str(r#"{"stop":{"input":"Insert"}}"#).unwrap();
+        assert_eq!(bindings.stop, Hotkey::new(PhysicalInput::Key(RdevKey::Insert)));
+        assert_eq!(bindings.start_clicking, HotkeyBindings::default().start_clicking);
+    }
+
+    #[test]
+    fn repeated_presses_need_only_one_release() {
+        let mut tracker = ModifierTracker::default();
+        tracker.handle(RdevKey::Alt, true);
+        tracker.handle(RdevKey::Alt, true);
+        tracker.handle(RdevKey::Alt, false);
+        assert_eq!(tracker.current(), Modifiers::NONE);
+    }
+
+    #[test]
+    fn chords_match_only_with_exact_modifiers() {
+        let mut bindings = HotkeyBindings::default();
+        bindings.start_clicking.modifiers = CTRL;
+
+        assert_eq!(bindings.action_for(F6, CTRL), Some(HotkeyAction::StartClicking));
+        assert_eq!(bindings.action_for(F6, Modifiers::NONE), None);
+        assert_eq!(bindings.action_for(F6, Modifiers { ctrl: true, shift: true, alt: false }), None);
+        assert_eq!(bindings.action_for(F7, Modifiers::NONE), Some(HotkeyAction::Stop));
+        assert_eq!(bindings.action_for(F7, CTRL), None);
+        assert_eq!(bindings.action_using_input(F6), Some(HotkeyAction::StartClicking));
+    }
+
+    #[test]
+    fn finds_actions_sharing_a_combination() {
+        let mut bindings = HotkeyBindings::default();
+        assert!(bindings.duplicates().is_empty());
+
+        bindings.one_shot.input = F6;
+        assert_eq!(bindings.duplicates(), vec![(HotkeyAction::StartClicking, HotkeyAction::OneShot)]);
+
+        bindings.one_shot.modifiers = CTRL;
+        assert!(bindings.duplicates().is_empty());
+    }
+
+    #[test]
+    fn names_include_modifiers() {
+        let mut hotkey = Hotkey::new(PhysicalInput::Key(RdevKey::KeyA));
+        assert_eq!(hotkey.name(), "A");
+        hotkey.modifiers = Modifiers { ctrl: true, shift: true, alt: true };
+        assert_eq!(hotkey.name(), "Ctrl+Shift+Alt+A");
+        assert_eq!(Hotkey::new(F7).name(), "F7");
+        hotkey.double_tap = true;
+        assert_eq!(hotkey.name(), "Ctrl+Shift+Alt+A ×2");
+    }
+
+    #[test]
+    fn double_tap_needs_two_presses_inside_the_window() {
+        let window = Duration::from_millis(400);
+        let start = Instant::now();
+        let mut taps = DoubleTapTracker::default();
+
+        assert!(!taps.tap(HotkeyAction::StartClicking, start, window));
+        assert!(taps.tap(HotkeyAction::StartClicking, start + Duration::from_millis(200), window));
+        // The pair is used up, so a third press starts over
+        assert!(!taps.tap(HotkeyAction::StartClicking, start + Duration::from_millis(300), window));
+
+        // Too slow
+        assert!(!taps.tap(HotkeyAction::Stop, start, window));
+        assert!(!taps.tap(HotkeyAction::Stop, start + Duration::from_millis(500), window));
+
+        // A first tap of one action doesn't count for another
+        assert!(!taps.tap(HotkeyAction::StartKeys, start + Duration::from_millis(600), window));
+        assert!(!taps.tap(HotkeyAction::PauseResume, start + Duration::from_millis(650), window));
+    }
+
+    #[test]
+    fn mouse_buttons_bind_and_display_like_keys() {
+        let mut bindings = HotkeyBindings::default();
+        let side = PhysicalInput::Button(Button::Unknown(2));
+        bindings.start_clicking.input = side;
+
+        assert_eq!(bindings.action_for(side, Modifiers::NONE), Some(HotkeyAction::StartClicking));
+        assert_eq!(bindings.action_for(F6, Modifiers::NONE), None);
+        assert_eq!(bindings.name(HotkeyAction::StartClicking), "Mouse5");
+        assert_eq!(input_name(PhysicalInput::Button(Button::Unknown(8))), "Mouse4");
+        assert!(PhysicalInput::Button(Button::Left).is_primary_button());
+        assert!(!side.is_primary_button());
+    }
+}
@@ -0,0 +1,287 @@
+// Remote control over HTTP, for home automation.
+//
+// Off until enabled in the Hotkeys settings, and only ever bound to 127.0.0.1. Every request
+// needs the token shown next to the setting, as `Authorization: Bearer TOKEN` or `?token=TOKEN`
+// for clients that can't set headers:
+//
+//   POST /start?mode=click|keys   POST /stop   POST /interval?ms=100   GET /status
+//   GET /events                   a WebSocket pushing the status and new log lines
+//
+// Requests are turned into the same commands the local socket takes (see ipc). The server
+// thread runs for the whole session, idling while the API is off, so shutdown can join it.
+
+use crate::health;
+use crate::ipc::{self, IpcCommand};
+use crate::{log_info, log_warn};
+use crate::state::{lock_state, parse_interval_ms, ActiveMode, AppState};
+use rand::Rng;
+use serde_json::json;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tiny_http::{Header, Request, Response, Server};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+pub const DEFAULT_PORT: u16 = 47614;
+// How often the server checks whether it has been switched off, and the event stream pushes
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+static NEXT_CLIENT: AtomicU64 = AtomicU64::new(1);
+
+// 32 hex digits, made fresh each session and whenever the user asks
+pub fn random_token() -> String {
+    let mut rng = rand::thread_rng();
+    (0..16).map(|_| format!("{:02x}", rng.gen::<u8>())).collect()
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiRequest {
+    Command(IpcCommand),
+    Events,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ApiError {
+    Unauthorized,
+    NotFound,
+    MethodNotAllowed,
+    BadRequest(String),
+}
+
+impl ApiError {
+    pub fn status(&self) -> u16 {
+        match self {
+            ApiError::Unauthorized => 401,
+            ApiError::NotFound => 404,
+            ApiError::MethodNotAllowed => 405,
+            ApiError::BadRequest(_) => 400,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::Unauthorized => "missing or wrong token".to_string(),
+            ApiError::NotFound => "no such endpoint".to_string(),
+            ApiError::MethodNotAllowed => "wrong method for this endpoint".to_string(),
+            ApiError::BadRequest(message) => message.clone(),
+        }
+    }
+}
+
+// The value of `name` in a query string; no percent-decoding, since no value needs it
+fn query_value<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&').find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))
+}
+
+// What a request asks for. `authorization` is the Authorization header, if any
+pub fn parse_request(method: &str, url: &str, authorization: Option<&str>, token: &str) -> Result<ApiRequest, ApiError> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let given = authorization.and_then(|header| header.trim().strip_prefix("Bearer ")).or_else(|| query_value(query, "token"));
+    if token.is_empty() || given.map(str::trim) != Some(token) {
+        return Err(ApiError::Unauthorized);
+    }
+    let (expected_method, request) = match path {
+        "/start" => {
+            let mode = match query_value(query, "mode") {
+                Some("click") => ActiveMode::Clicking,
+                Some("keys") => ActiveMode::KeystrokeInjection,
+                _ => return Err(ApiError::BadRequest("mode must be click or keys".to_string())),
+            };
+            ("POST", ApiRequest::Command(IpcCommand::Start(mode)))
+        },
+        "/stop" => ("POST", ApiRequest::Command(IpcCommand::Stop)),
+        "/interval" => {
+            let ms = query_value(query, "ms").ok_or_else(|| ApiError::BadRequest("ms is required".to_string()))?;
+            let us = parse_interval_ms(ms).map_err(ApiError::BadRequest)?;
+            ("POST", ApiRequest::Command(IpcCommand::SetInterval(us)))
+        },
+        "/status" => ("GET", ApiRequest::Command(IpcCommand::Status)),
+        "/events" => ("GET", ApiRequest::Events),
+        _ => return Err(ApiError::NotFound),
+    };
+    if !method.eq_ignore_ascii_case(expected_method) {
+        return Err(ApiError::MethodNotAllowed);
+    }
+    Ok(request)
+}
+
+fn json_response(status: u16, body: serde_json::Value) -> Response<std::io::Cursor<Vec<u8>>> {
+    let header = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).expect("static header is valid");
+    Response::from_string(body.to_string()).with_status_code(status).with_header(header)
+}
+
+fn header<'a>(request: &'a Request, name: &'static str) -> Option<&'a str> {
+    request.headers().iter().find(|header| header.field.equiv(name)).map(|header| header.value.as_str())
+}
+
+fn handle(request: Request, state: &Arc<Mutex<AppState>>, is_running: &Arc<AtomicBool>) {
+    let source = format!("HTTP client {}", NEXT_CLIENT.fetch_add(1, Ordering::Relaxed));
+    let method = request.method().to_string();
+    let url = request.url().to_string();
+    let parsed = {
+        let mut state = lock_state(state);
+        // The token stays out of the log
+        let path = url.split('?').next().unwrap_or_default();
+        log_info!(state.log, "{}: {} {}", source, method, path);
+        parse_request(&method, &url, header(&request, "Authorization"), &state.http_token)
+    };
+    match parsed {
+        Ok(ApiRequest::Command(command)) => {
+            let reply = ipc::run_command(&mut lock_state(state), command, &source, Instant::now());
+            let response = match reply {
+                Ok(text) if text.is_empty() => json_response(200, json!({ "ok": true })),
+                Ok(text) => json_response(200, json!({ "ok": true, "status": text })),
+                Err(e) => json_response(409, json!({ "ok": false, "error": e })),
+            };
+            let _ = request.respond(response);
+        },
+        Ok(ApiRequest::Events) => start_event_stream(request, Arc::clone(state), Arc::clone(is_running)),
+        Err(e) => {
+            let _ = request.respond(json_response(e.status(), json!({ "ok": false, "error": e.message() })));
+        },
+    }
+}
+
+// Upgrade to a WebSocket and push the status and new log lines until the client goes away,
+// the API is switched off or the app exits
+fn start_event_stream(request: Request, state: Arc<Mutex<AppState>>, is_running: Arc<AtomicBool>) {
+    let Some(key) = header(&request, "Sec-WebSocket-Key").map(str::to_string) else {
+        let _ = request.respond(json_response(400, json!({ "ok": false, "error": "/events is a WebSocket" })));
+        return;
+    };
+    let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+    let response = Response::empty(101)
+        .with_header(Header::from_bytes(&b"Sec-WebSocket-Accept"[..], accept.as_bytes()).expect("accept key is ASCII"));
+    let stream = request.upgrade("websocket", response);
+    thread::spawn(move || {
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        // Only lines logged from now on are sent
+        let mut log_cursor = lock_state(&state).log.total();
+        let mut last_status = String::new();
+        while is_running.load(Ordering::SeqCst) {
+            let update = {
+                let state = lock_state(&state);
+                if !state.http_enabled {
+                    break;
+                }
+                // The log may have been cleared meanwhile
+                if log_cursor > state.log.total() {
+                    log_cursor = 0;
+                }
+                let lines: String = state.log
+                    .since(log_cursor)
+                    .filter(|entry| entry.level <= state.log_level)
+                    .map(|entry| entry.line() + "\n")
+                    .collect();
+                log_cursor = state.log.total();
+                let status = state.current_status(Instant::now());
+                (status != last_status || !lines.is_empty()).then(|| {
+                    last_status = status.clone();
+                    json!({ "status": status, "mode": state.active_mode.label(), "log": lines })
+                })
+            };
+            if let Some(update) = update {
+                if socket.send(Message::Text(update.to_string())).is_err() {
+                    return;
+                }
+            }
+            thread::sleep(EVENT_INTERVAL);
+        }
+        let _ = socket.close(None);
+    });
+}
+
+fn keep_serving(state: &Mutex<AppState>, port: u16) -> bool {
+    let state = lock_state(state);
+    state.http_enabled && state.http_port == port
+}
+
+// Serve the API whenever it's enabled, until `is_running` goes false
+pub fn start_http_server(state: Arc<Mutex<AppState>>, is_running: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while is_running.load(Ordering::SeqCst) {
+            health::HTTP.beat();
+            let port = {
+                let state = lock_state(&state);
+                state.http_enabled.then_some(state.http_port)
+            };
+            let Some(port) = port else {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            };
+            let server = match Server::http(("127.0.0.1", port)) {
+                Ok(server) => server,
+                Err(e) => {
+                    let mut state = lock_state(&state);
+                    log_warn!(state.log, "HTTP API unavailable: couldn't listen on port {}: {}", port, e);
+                    state.http_enabled = false;
+                    state.request_repaint();
+                    continue;
+                },
+            };
+            log_info!(lock_state(&state).log, "HTTP API listening on http://127.0.0.1:{}", port);
+            // A changed port is picked up by serving again on the new one
+            while is_running.load(Ordering::SeqCst) && keep_serving(&state, port) {
+                health::HTTP.beat();
+                match server.recv_timeout(POLL_INTERVAL) {
+                    Ok(Some(request)) => handle(request, &state, &is_running),
+                    Ok(None) => {},
+                    Err(_) => thread::sleep(POLL_INTERVAL),
+                }
+            }
+            drop(server);
+            log_info!(lock_state(&state).log, "HTTP API stopped");
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123abcd";
+
+    #[test]
+    fn parses_each_endpoint() {
+        let bearer = Some("Bearer 0123abcd");
+        assert_eq!(
+            parse_request("POST", "/start?mode=click", bearer, TOKEN),
+            Ok(ApiRequest::Command(IpcCommand::Start(ActiveMode::Clicking))),
+        );
+        assert_eq!(
+            parse_request("POST", "/start?mode=keys&token=0123abcd", None, TOKEN),
+            Ok(ApiRequest::Command(IpcCommand::Start(ActiveMode::KeystrokeInjection))),
+        );
+        assert_eq!(parse_request("POST", "/stop", bearer, TOKEN), Ok(ApiRequest::Command(IpcCommand::Stop)));
+        assert_eq!(
+            parse_request("POST", "/interval?ms=12.5", bearer, TOKEN),
+            Ok(ApiRequest::Command(IpcCommand::SetInterval(12_500))),
+        );
+        assert_eq!(parse_request("get", "/status", bearer, TOKEN), Ok(ApiRequest::Command(IpcCommand::Status)));
+        assert_eq!(parse_request("GET", "/events?token=0123abcd", None, TOKEN), Ok(ApiRequest::Events));
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let bearer = Some("Bearer 0123abcd");
+        assert_eq!(parse_request("POST", "/stop", None, TOKEN), Err(ApiError::Unauthorized));
+        assert_eq!(parse_request("POST", "/stop", Some("Bearer nope"), TOKEN), Err(ApiError::Unauthorized));
+        assert_eq!(parse_request("POST", "/stop?token=", None, ""), Err(ApiError::Unauthorized));
+        assert_eq!(parse_request("GET", "/stop", bearer, TOKEN), Err(ApiError::MethodNotAllowed));
+        assert_eq!(parse_request("POST", "/launch", bearer, TOKEN), Err(ApiError::NotFound));
+        assert!(matches!(parse_request("POST", "/start?mode=dance", bearer, TOKEN), Err(ApiError::BadRequest(_))));
+        assert!(matches!(parse_request("POST", "/interval", bearer, TOKEN), Err(ApiError::BadRequest(_))));
+        assert!(matches!(parse_request("POST", "/interval?ms=-3", bearer, TOKEN), Err(ApiError::BadRequest(_))));
+    }
+
+    #[test]
+    fn tokens_are_fresh_hex() {
+        let token = random_token();
+        assert_eq!(token.len(), 32);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(token, random_token());
+    }
+}
@@ -0,0 +1,118 @@
+// Keeping to one running copy of the app.
+//
+// Two copies would each run a hotkey listener and an action thread, so one hotkey press would
+// start two clickers. The first copy listens on a fixed localhost port; a later one finds the
+// port taken, asks whoever has it to bring its window to the front, and exits. The OS frees
+// the port however the first copy ends, so a crash never leaves a stale lock behind.
+
+use crate::log_info;
+use crate::state::{lock_state, AppState};
+use eframe::egui;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const INSTANCE_ADDR: &str = "127.0.0.1:47613";
+pub const ALLOW_MULTIPLE_FLAG: &str = "--allow-multiple";
+
+// What the running copy answers with, so a port taken by some other program isn't mistaken for it
+const GREETING: &str = "ez_autoclicker";
+const ANSWER_TIMEOUT: Duration = Duration::from_millis(500);
+const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+pub enum Instance {
+    // Nothing else is running; keep the listener for as long as the app runs
+    First(TcpListener),
+    // Another copy was told to show itself
+    AlreadyRunning,
+    // The port is taken by something that isn't this app; carry on without the check
+    Unknown,
+}
+
+// Claim the port, or find out what holds it
+pub fn claim(addr: &str) -> Instance {
+    match TcpListener::bind(addr) {
+        Ok(listener) => Instance::First(listener),
+        Err(_) if ask_to_show(addr) => Instance::AlreadyRunning,
+        Err(_) => Instance::Unknown,
+    }
+}
+
+// Claim the port once the copy that started us has let go of it, as after a restart
+pub fn claim_when_free(addr: &str, wait: Duration) -> Instance {
+    let deadline = Instant::now() + wait;
+    while Instant::now() < deadline {
+        if let Ok(listener) = TcpListener::bind(addr) {
+            return Instance::First(listener);
+        }
+        thread::sleep(RETRY_INTERVAL);
+    }
+    claim(addr)
+}
+
+// Knock on the running copy; true if it answered as this app
+pub fn ask_to_show(addr: &str) -> bool {
+    let Ok(addr) = addr.parse::<SocketAddr>() else {
+        return false;
+    };
+    let Ok(stream) = TcpStream::connect_timeout(&addr, ANSWER_TIMEOUT) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(ANSWER_TIMEOUT));
+    let mut answer = String::new();
+    BufReader::new(stream).read_line(&mut answer).is_ok() && answer.trim() == GREETING
+}
+
+// Answer every later copy and bring the window to the front for it
+pub fn start_instance_listener(listener: TcpListener, state: Arc<Mutex<AppState>>) {
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let _ = writeln!(stream, "{}", GREETING);
+            let mut state = lock_state(&state);
+            log_info!(state.log, "Another copy was started; showing this one instead");
+            if let Some(ctx) = &state.repaint {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Minimized(false));
+                ctx.send_viewport_cmd(egui::ViewportCommand::Focus);
+            }
+            state.request_repaint();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_second_copy_finds_the_first() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let state = Arc::new(Mutex::new(AppState::default()));
+        start_instance_listener(listener, Arc::clone(&state));
+
+        assert!(matches!(claim(&addr), Instance::AlreadyRunning));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !lock_state(&state).log.contains("Another copy") && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(lock_state(&state).log.contains("Another copy was started"));
+    }
+
+    #[test]
+    fn a_port_taken_by_something_else_is_not_an_instance() {
+        let stranger = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = stranger.local_addr().unwrap().to_string();
+        assert!(matches!(claim(&addr), Instance::Unknown));
+    }
+
+    #[test]
+    fn a_free_port_is_claimed() {
+        let addr = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        assert!(matches!(claim(&addr), Instance::First(_)));
+    }
+}
@@ -0,0 +1,355 @@
+// Remote control over a local socket, for scripts that shouldn't have to fake hotkeys.
+//
+// Off until enabled in the Hotkeys settings. Clients connect to a named pipe on Windows or a
+// Unix domain socket elsewhere, both only open to the current user, and send one command per
+// line; each gets one line back starting with "ok" or "error". Commands go through the same
+// AppState calls and send_update the UI uses, and every one is logged with the client it came
+// from.
+
+use crate::{log_info, log_warn};
+use crate::state::{lock_state, parse_interval_ms, ActiveMode, AppState};
+use crate::timing;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often the listener checks whether it has been switched off
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Numbers clients in the log
+static NEXT_CLIENT: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IpcCommand {
+    Start(ActiveMode),
+    Stop,
+    // Microseconds
+    SetInterval(u64),
+    Status,
+}
+
+pub fn parse_command(line: &str) -> Result<IpcCommand, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["start", "click"] => Ok(IpcCommand::Start(ActiveMode::Clicking)),
+        ["start", "keys"] => Ok(IpcCommand::Start(ActiveMode::KeystrokeInjection)),
+        ["stop"] => Ok(IpcCommand::Stop),
+        ["set", "interval", ms] => parse_interval_ms(ms).map(IpcCommand::SetInterval),
+        ["status"] => Ok(IpcCommand::Status),
+        _ => Err(format!("unknown command '{}'; use start click|keys, stop, set interval MS or status", line.trim())),
+    }
+}
+
+// Carry out `command` for `source`; the status text for Status, empty otherwise. Shared by
+// every remote control
+pub fn run_command(state: &mut AppState, command: IpcCommand, source: &str, now: Instant) -> Result<String, String> {
+    match command {
+        IpcCommand::Start(mode) => {
+            state.start_mode(&mode, now);
+            if state.active_mode != mode {
+                return Err("couldn't start; see the log".to_string());
+            }
+        },
+        IpcCommand::Stop => state.stop_all(&format!("Stopped by {}\n", source), now),
+        IpcCommand::SetInterval(us) => {
+            state.click_interval.us = us;
+            state.key_interval.us = us;
+        },
+        IpcCommand::Status => {
+            return Ok(format!(
+                "{} · click interval {} · key interval {}",
+                state.current_status(now),
+                timing::format_us(state.click_interval.us),
+                timing::format_us(state.key_interval.us),
+            ));
+        },
+    }
+    state.send_update();
+    state.request_repaint();
+    Ok(String::new())
+}
+
+// Run one line from `source` and return the reply
+pub fn execute(state: &mut AppState, line: &str, source: &str, now: Instant) -> String {
+    log_info!(state.log, "IPC ({}): {}", source, line.trim());
+    let reply = parse_command(line).and_then(|command| run_command(state, command, &format!("IPC ({})", source), now));
+    match reply {
+        Ok(text) if text.is_empty() => "ok".to_string(),
+        Ok(text) => format!("ok {}", text),
+        Err(e) => format!("error {}", e),
+    }
+}
+
+// Answer `source`'s commands until it hangs up
+fn serve(mut writer: impl Write, reader: impl Read, state: Arc<Mutex<AppState>>, source: String) {
+    for line in BufReader::new(reader).lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = execute(&mut lock_state(&state), &line, &source, Instant::now());
+        if writeln!(writer, "{}", reply).and_then(|()| writer.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+// Whether the listener should keep going; clears ipc_listening under the same lock when it
+// shouldn't, so the UI never sees it listening after it decided to stop
+fn keep_listening(state: &Mutex<AppState>, is_running: &AtomicBool) -> bool {
+    let mut state = lock_state(state);
+    if state.ipc_enabled && is_running.load(Ordering::SeqCst) {
+        return true;
+    }
+    state.ipc_listening = false;
+    false
+}
+
+fn give_up(state: &Mutex<AppState>, error: String) {
+    let mut state = lock_state(state);
+    log_warn!(state.log, "Remote control unavailable: {}", error);
+    state.ipc_enabled = false;
+    state.ipc_listening = false;
+    state.request_repaint();
+}
+
+#[cfg(unix)]
+pub fn endpoint() -> std::path::PathBuf {
+    // The runtime directory is the user's own; the temp directory is shared, so the socket goes
+    // in a directory of the user's own there
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => std::path::PathBuf::from(dir).join("ez_autoclicker.sock"),
+        None => {
+            let user = std::env::var("USER").unwrap_or_default();
+            std::env::temp_dir().join(format!("ez_autoclicker-{}", user)).join("ez_autoclicker.sock")
+        },
+    }
+}
+
+// Make sure only the user can reach into `dir`, creating it if need be. The socket only gets its
+// own 0600 after bind creates it, so until then the directory is what keeps others out
+#[cfg(unix)]
+fn ensure_private_dir(dir: &std::path::Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+    if let Err(e) = std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        if e.kind() != std::io::ErrorKind::AlreadyExists {
+            return Err(e);
+        }
+    }
+    // One someone else left open (or a symlink to one) won't do. Someone else's private one
+    // can't be bound in at all
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.permissions().mode() & 0o077 != 0 {
+        return Err(std::io::Error::new(std::io::ErrorKind::PermissionDenied, format!("{} is open to other users", dir.display())));
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn endpoint() -> std::path::PathBuf {
+    let user = std::env::var("USERNAME").unwrap_or_default();
+    std::path::PathBuf::from(format!(r"\\.\pipe\ez_autoclicker-{}", user))
+}
+
+// Listen until ipc_enabled goes off. The caller sets ipc_listening first
+#[cfg(unix)]
+pub fn start_ipc_listener(state: Arc<Mutex<AppState>>, is_running: Arc<AtomicBool>) {
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    thread::spawn(move || {
+        let path = endpoint();
+        if let Some(dir) = path.parent() {
+            if let Err(e) = ensure_private_dir(dir) {
+                return give_up(&state, format!("couldn't secure {}: {}", dir.display(), e));
+            }
+        }
+        // A socket file nobody answers on is left over from a crash
+        if path.exists() && UnixStream::connect(&path).is_err() {
+            let _ = std::fs::remove_file(&path);
+        }
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) => return give_up(&state, format!("couldn't listen on {}: {}", path.display(), e)),
+        };
+        if let Err(e) = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .and_then(|()| listener.set_nonblocking(true))
+        {
+            let _ = std::fs::remove_file(&path);
+            return give_up(&state, format!("couldn't secure {}: {}", path.display(), e));
+        }
+        log_info!(lock_state(&state).log, "Remote control listening on {}", path.display());
+
+        while keep_listening(&state, &is_running) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let source = format!("client {}", NEXT_CLIENT.fetch_add(1, Ordering::Relaxed));
+                    let _ = stream.set_nonblocking(false);
+                    let Ok(reader) = stream.try_clone() else {
+                        continue;
+                    };
+                    let state = Arc::clone(&state);
+                    thread::spawn(move || serve(stream, reader, state, source));
+                },
+                // Usually WouldBlock: nobody is waiting to connect
+                Err(_) => thread::sleep(POLL_INTERVAL),
+            }
+        }
+        let _ = std::fs::remove_file(&path);
+        log_info!(lock_state(&state).log, "Remote control stopped");
+    });
+}
+
+#[cfg(windows)]
+pub fn start_ipc_listener(state: Arc<Mutex<AppState>>, is_running: Arc<AtomicBool>) {
+    use std::fs::File;
+    use std::os::windows::ffi::OsStrExt;
+    use std::os::windows::io::FromRawHandle;
+    use std::ptr;
+    use winapi::shared::minwindef::{DWORD, FALSE};
+    use winapi::shared::sddl::{ConvertStringSecurityDescriptorToSecurityDescriptorW, SDDL_REVISION_1};
+    use winapi::shared::winerror::{ERROR_NO_DATA, ERROR_PIPE_CONNECTED};
+    use winapi::um::errhandlingapi::GetLastError;
+    use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    use winapi::um::minwinbase::SECURITY_ATTRIBUTES;
+    use winapi::um::namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, SetNamedPipeHandleState};
+    use winapi::um::winbase::{LocalFree, PIPE_ACCESS_DUPLEX, PIPE_NOWAIT, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT};
+    use winapi::um::winnt::HANDLE;
+
+    // Only the owner gets in; the default descriptor would let everyone read
+    const OWNER_ONLY: &str = "D:P(A;;GA;;;OW)";
+    const BUFFER_SIZE: DWORD = 4096;
+
+    thread::spawn(move || {
+        let name: Vec<u16> = endpoint().as_os_str().encode_wide().chain(Some(0)).collect();
+        let sddl: Vec<u16> = OWNER_ONLY.encode_utf16().chain(Some(0)).collect();
+        let mut descriptor = ptr::null_mut();
+        // SAFETY: the SDDL string is NUL-terminated; the descriptor is freed on the way out
+        if unsafe { ConvertStringSecurityDescriptorToSecurityDescriptorW(sddl.as_ptr(), SDDL_REVISION_1 as DWORD, &mut descriptor, ptr::null_mut()) } == 0 {
+            return give_up(&state, format!("couldn't restrict the pipe to this user (error {})", unsafe { GetLastError() }));
+        }
+        let mut attributes = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as DWORD,
+            lpSecurityDescriptor: descriptor,
+            bInheritHandle: FALSE,
+        };
+        // One pipe instance per client; PIPE_NOWAIT lets the wait for a client notice being
+        // switched off, and the connected instance is switched back to blocking
+        let create = |attributes: &mut SECURITY_ATTRIBUTES| -> HANDLE {
+            // SAFETY: the name is NUL-terminated and the attributes outlive the call
+            unsafe {
+                CreateNamedPipeW(
+                    name.as_ptr(),
+                    PIPE_ACCESS_DUPLEX,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_NOWAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    BUFFER_SIZE,
+                    BUFFER_SIZE,
+                    0,
+                    attributes,
+                )
+            }
+        };
+        log_info!(lock_state(&state).log, "Remote control listening on {}", endpoint().display());
+
+        let mut pipe = INVALID_HANDLE_VALUE;
+        while keep_listening(&state, &is_running) {
+            if pipe == INVALID_HANDLE_VALUE {
+                pipe = create(&mut attributes);
+                if pipe == INVALID_HANDLE_VALUE {
+                    give_up(&state, format!("couldn't create the pipe (error {})", unsafe { GetLastError() }));
+                    break;
+                }
+            }
+            // SAFETY: `pipe` is a valid pipe handle owned by this loop
+            let connected = unsafe { ConnectNamedPipe(pipe, ptr::null_mut()) != 0 || GetLastError() == ERROR_PIPE_CONNECTED };
+            if !connected {
+                // ERROR_NO_DATA: a client came and went before being served. Anything else,
+                // usually ERROR_PIPE_LISTENING, means nobody has connected yet
+                if unsafe { GetLastError() } == ERROR_NO_DATA {
+                    unsafe {
+                        DisconnectNamedPipe(pipe);
+                    }
+                } else {
+                    thread::sleep(POLL_INTERVAL);
+                }
+                continue;
+            }
+            let mut mode: DWORD = PIPE_READMODE_BYTE | PIPE_WAIT;
+            // SAFETY: as above; the File takes over the handle and closes it when dropped
+            let stream = unsafe {
+                SetNamedPipeHandleState(pipe, &mut mode, ptr::null_mut(), ptr::null_mut());
+                File::from_raw_handle(pipe as _)
+            };
+            pipe = INVALID_HANDLE_VALUE;
+            let source = format!("client {}", NEXT_CLIENT.fetch_add(1, Ordering::Relaxed));
+            let Ok(reader) = stream.try_clone() else {
+                continue;
+            };
+            let state = Arc::clone(&state);
+            thread::spawn(move || serve(stream, reader, state, source));
+        }
+        // SAFETY: both were created above and aren't used after this
+        unsafe {
+            if pipe != INVALID_HANDLE_VALUE {
+                CloseHandle(pipe);
+            }
+            LocalFree(descriptor);
+        }
+        log_info!(lock_state(&state).log, "Remote control stopped");
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse_command("start click"), Ok(IpcCommand::Start(ActiveMode::Clicking)));
+        assert_eq!(parse_command("  start   keys \r"), Ok(IpcCommand::Start(ActiveMode::KeystrokeInjection)));
+        assert_eq!(parse_command("stop"), Ok(IpcCommand::Stop));
+        assert_eq!(parse_command("set interval 100"), Ok(IpcCommand::SetInterval(100_000)));
+        assert_eq!(parse_command("status"), Ok(IpcCommand::Status));
+        assert!(parse_command("set interval 0").is_err());
+        assert!(parse_command("start dancing").is_err());
+        assert!(parse_command("").is_err());
+    }
+
+    #[test]
+    fn commands_act_on_the_state_and_are_logged() {
+        let now = Instant::now();
+        let mut state = AppState::default();
+        assert_eq!(execute(&mut state, "set interval 100", "client 1", now), "ok");
+        assert_eq!(state.click_interval.us, 100_000);
+        assert_eq!(execute(&mut state, "start click", "client 1", now), "ok");
+        assert_eq!(state.active_mode, ActiveMode::Clicking);
+        assert!(execute(&mut state, "status", "client 2", now).starts_with("ok Clicking"));
+        assert_eq!(execute(&mut state, "stop", "client 2", now), "ok");
+        assert_eq!(state.active_mode, ActiveMode::None);
+        assert!(execute(&mut state, "jump", "client 2", now).starts_with("error"));
+        assert!(state.log.contains("IPC (client 1): set interval 100"));
+        assert!(state.log.contains("IPC (client 2): jump"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_socket_directory_must_be_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("ez_autoclicker_ipc_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        ensure_private_dir(&dir).unwrap();
+        assert_eq!(std::fs::metadata(&dir).unwrap().permissions().mode() & 0o777, 0o700);
+        // Already there and still private is fine; opened up, it isn't
+        ensure_private_dir(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(ensure_private_dir(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
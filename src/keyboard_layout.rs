@@ -0,0 +1,236 @@
+// Typing characters the way the target keyboard layout produces them.
+//
+// enigo's Unicode(c) only presses the key that carries `c`, so characters behind AltGr
+// ('@' on a German keyboard) or a dead key ('ê' on a French one) come out wrong.
+// Each character is classified against a small per-layout table first:
+//   Direct   the key is typed as before
+//   AltGr    AltGr is held around the key that carries the character
+//   Dead     the dead key is typed, then the base letter it combines with
+//   Unicode  the character isn't on the layout at all and is entered as text
+
+use enigo::{Direction, Enigo, InputResult, Key, Keyboard};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardLayout {
+    #[default]
+    Us,
+    De,
+    Fr,
+    Es,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stroke {
+    Direct(char),
+    // The unshifted character on the key AltGr is held around
+    AltGr(char),
+    Dead { accent: char, base: char },
+    Unicode(char),
+}
+
+// Characters each dead key composes: (dead key, base letters, results)
+const COMPOSITIONS: [(char, &str, &str); 5] = [
+    ('^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    ('´', "aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+    ('`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ('¨', "aeiouyAEIOU", "äëïöüÿÄËÏÖÜ"),
+    ('~', "anoANO", "ãñõÃÑÕ"),
+];
+
+impl KeyboardLayout {
+    pub const ALL: [KeyboardLayout; 4] = [
+        KeyboardLayout::Us,
+        KeyboardLayout::De,
+        KeyboardLayout::Fr,
+        KeyboardLayout::Es,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            KeyboardLayout::Us => "English (US)",
+            KeyboardLayout::De => "German (DE)",
+            KeyboardLayout::Fr => "French (FR)",
+            KeyboardLayout::Es => "Spanish (ES)",
+        }
+    }
+
+    // (character, key it sits on) for everything typed with AltGr
+    fn altgr_table(self) -> &'static [(char, char)] {
+        match self {
+            KeyboardLayout::Us => &[],
+            KeyboardLayout::De => &[
+                ('@', 'q'), ('€', 'e'), ('µ', 'm'), ('²', '2'), ('³', '3'), ('{', '7'),
+                ('[', '8'), (']', '9'), ('}', '0'), ('\\', 'ß'), ('~', '+'), ('|', '<'),
+            ],
+            KeyboardLayout::Fr => &[
+                ('~', 'é'), ('#', '"'), ('{', '\''), ('[', '('), ('|', '-'), ('`', 'è'),
+                ('\\', '_'), ('^', 'ç'), ('@', 'à'), (']', ')'), ('}', '='), ('€', 'e'),
+            ],
+            KeyboardLayout::Es => &[
+                ('\\', 'º'), ('|', '1'), ('@', '2'), ('#', '3'), ('~', '4'), ('€', 'e'),
+                ('¬', '6'), ('[', '`'), (']', '+'), ('{', '´'), ('}', 'ç'),
+            ],
+        }
+    }
+
+    // Non-ASCII characters with a key of their own
+    fn extra_keys(self) -> &'static str {
+        match self {
+            KeyboardLayout::Us => "",
+            KeyboardLayout::De => "äöüÄÖÜß§°",
+            KeyboardLayout::Fr => "éèàçùµ£¤°§",
+            KeyboardLayout::Es => "ñÑçÇºª¡¿·",
+        }
+    }
+
+    fn dead_keys(self) -> &'static str {
+        match self {
+            KeyboardLayout::Us => "",
+            KeyboardLayout::De => "^´`",
+            KeyboardLayout::Fr => "^¨",
+            KeyboardLayout::Es => "´`^¨",
+        }
+    }
+
+    // How to produce `c` on this layout
+    pub fn classify(self, c: char) -> Stroke {
+        if let Some(&(_, base)) = self.altgr_table().iter().find(|(ch, _)| *ch == c) {
+            return Stroke::AltGr(base);
+        }
+        // A dead key on its own needs a space after it to come out as itself
+        if self.dead_keys().contains(c) {
+            return Stroke::Dead { accent: c, base: ' ' };
+        }
+        if c.is_ascii() || self.extra_keys().contains(c) {
+            return Stroke::Direct(c);
+        }
+        for (accent, bases, results) in COMPOSITIONS {
+            if !self.dead_keys().contains(accent) {
+                continue;
+            }
+            if let Some(base) = results.chars().position(|r| r == c).and_then(|i| bases.chars().nth(i)) {
+                return Stroke::Dead { accent, base };
+            }
+        }
+        Stroke::Unicode(c)
+    }
+}
+
+// Windows treats Ctrl+Alt as AltGr; elsewhere AltGr strokes are entered as text instead
+#[cfg(target_os = "windows")]
+fn altgr(enigo: &mut Enigo, down: bool) -> InputResult<bool> {
+    if down {
+        enigo.key(Key::Control, Direction::Press)?;
+        enigo.key(Key::Alt, Direction::Press)?;
+    } else {
+        enigo.key(Key::Alt, Direction::Release)?;
+        enigo.key(Key::Control, Direction::Release)?;
+    }
+    Ok(true)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn altgr(_enigo: &mut Enigo, _down: bool) -> InputResult<bool> {
+    Ok(false)
+}
+
+fn type_text(enigo: &mut Enigo, c: char) -> InputResult<()> {
+    enigo.text(&c.to_string())
+}
+
+pub fn tap_char(enigo: &mut Enigo, c: char, layout: KeyboardLayout) -> InputResult<()> {
+    match layout.classify(c) {
+        Stroke::Direct(c) => enigo.key(Key::Unicode(c), Direction::Click),
+        Stroke::AltGr(base) => {
+            if altgr(enigo, true)? {
+                // Let go of AltGr even when the key itself failed
+                let result = enigo.key(Key::Unicode(base), Direction::Click);
+                altgr(enigo, false)?;
+                result
+            } else {
+                type_text(enigo, c)
+            }
+        },
+        Stroke::Dead { accent, base } => {
+            enigo.key(Key::Unicode(accent), Direction::Click)?;
+            enigo.key(Key::Unicode(base), Direction::Click)
+        },
+        Stroke::Unicode(c) => type_text(enigo, c),
+    }
+}
+
+// Compositions and text entry can't be held, so those are typed on press and ignored on release
+pub fn press_char(enigo: &mut Enigo, c: char, layout: KeyboardLayout) -> InputResult<()> {
+    match layout.classify(c) {
+        Stroke::Direct(c) => enigo.key(Key::Unicode(c), Direction::Press),
+        Stroke::AltGr(base) => {
+            if altgr(enigo, true)? {
+                enigo.key(Key::Unicode(base), Direction::Press)
+            } else {
+                type_text(enigo, c)
+            }
+        },
+        Stroke::Dead { .. } | Stroke::Unicode(_) => tap_char(enigo, c, layout),
+    }
+}
+
+pub fn release_char(enigo: &mut Enigo, c: char, layout: KeyboardLayout) -> InputResult<()> {
+    match layout.classify(c) {
+        Stroke::Direct(c) => enigo.key(Key::Unicode(c), Direction::Release),
+        Stroke::AltGr(base) => {
+            if cfg!(target_os = "windows") {
+                let result = enigo.key(Key::Unicode(base), Direction::Release);
+                altgr(enigo, false)?;
+                result
+            } else {
+                Ok(())
+            }
+        },
+        Stroke::Dead { .. } | Stroke::Unicode(_) => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_characters_per_layout() {
+        let cases = [
+            (KeyboardLayout::Us, 'a', Stroke::Direct('a')),
+            (KeyboardLayout::Us, '@', Stroke::Direct('@')),
+            (KeyboardLayout::Us, 'ã', Stroke::Unicode('ã')),
+            (KeyboardLayout::Us, 'é', Stroke::Unicode('é')),
+            (KeyboardLayout::De, '@', Stroke::AltGr('q')),
+            (KeyboardLayout::De, '€', Stroke::AltGr('e')),
+            (KeyboardLayout::De, '{', Stroke::AltGr('7')),
+            (KeyboardLayout::De, 'ü', Stroke::Direct('ü')),
+            (KeyboardLayout::De, 'ê', Stroke::Dead { accent: '^', base: 'e' }),
+            (KeyboardLayout::De, 'á', Stroke::Dead { accent: '´', base: 'a' }),
+            (KeyboardLayout::De, 'ã', Stroke::Unicode('ã')),
+            (KeyboardLayout::De, '^', Stroke::Dead { accent: '^', base: ' ' }),
+            (KeyboardLayout::Fr, '@', Stroke::AltGr('à')),
+            (KeyboardLayout::Fr, 'é', Stroke::Direct('é')),
+            (KeyboardLayout::Fr, 'ê', Stroke::Dead { accent: '^', base: 'e' }),
+            (KeyboardLayout::Fr, 'ï', Stroke::Dead { accent: '¨', base: 'i' }),
+            (KeyboardLayout::Fr, 'á', Stroke::Unicode('á')),
+            (KeyboardLayout::Es, '@', Stroke::AltGr('2')),
+            (KeyboardLayout::Es, 'ñ', Stroke::Direct('ñ')),
+            (KeyboardLayout::Es, 'ó', Stroke::Dead { accent: '´', base: 'o' }),
+            (KeyboardLayout::Es, 'Ü', Stroke::Dead { accent: '¨', base: 'U' }),
+            (KeyboardLayout::Es, 'õ', Stroke::Unicode('õ')),
+        ];
+        for (layout, c, expected) in cases {
+            assert_eq!(layout.classify(c), expected, "{:?} {:?}", layout, c);
+        }
+    }
+
+    #[test]
+    fn composition_tables_line_up() {
+        for (accent, bases, results) in COMPOSITIONS {
+            assert_eq!(bases.chars().count(), results.chars().count(), "dead key {:?}", accent);
+        }
+    }
+}
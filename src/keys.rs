@@ -0,0 +1,534 @@
+// Key names and sequence steps: parsing the Keys field and mapping names to enigo and rdev keys.
+
+use enigo::{Button, Key as EnigoKey};
+use rdev::Key as RdevKey;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Mouse buttons that can appear in a sequence
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClickButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl ClickButton {
+    pub const ALL: [ClickButton; 3] = [ClickButton::Left, ClickButton::Right, ClickButton::Middle];
+    
+    pub fn to_enigo(self) -> Button {
+        match self {
+            ClickButton::Left => Button::Left,
+            ClickButton::Right => Button::Right,
+            ClickButton::Middle => Button::Middle,
+        }
+    }
+    
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token.to_lowercase().as_str() {
+            "click" | "lclick" => Some(ClickButton::Left),
+            "rclick" => Some(ClickButton::Right),
+            "mclick" => Some(ClickButton::Middle),
+            _ => None,
+        }
+    }
+    
+    pub fn token(self) -> &'static str {
+        match self {
+            ClickButton::Left => "click",
+            ClickButton::Right => "rclick",
+            ClickButton::Middle => "mclick",
+        }
+    }
+    
+    pub fn to_rdev(self) -> rdev::Button {
+        match self {
+            ClickButton::Left => rdev::Button::Left,
+            ClickButton::Right => rdev::Button::Right,
+            ClickButton::Middle => rdev::Button::Middle,
+        }
+    }
+    
+    pub fn label(self) -> &'static str {
+        match self {
+            ClickButton::Left => "Left",
+            ClickButton::Right => "Right",
+            ClickButton::Middle => "Middle",
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum ActionType {
+    Click(ClickButton),
+    KeyPress(String),
+}
+
+// What a single sequence step does
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepKind {
+    Key(String),
+    Click(ClickButton),
+    Wait(u64),
+}
+
+// One row of the injection sequence, edited either in the step editor or as a text token
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct SequenceStep {
+    pub kind: StepKind,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // Keep this step held until the next step is down ("w(hold)")
+    #[serde(default)]
+    pub handover: bool,
+}
+
+pub fn default_enabled() -> bool {
+    true
+}
+impl SequenceStep {
+    pub fn new(kind: StepKind) -> Self {
+        Self { kind, enabled: true, handover: false }
+    }
+    
+    // Parse one comma-separated token: "click"/"rclick"/"mclick", "wait(ms)" or a key name.
+    // A leading '#' marks the step as disabled ("#w"); a lone "#" is still the '#' key.
+    // A trailing "(hold)" keeps the step down until the next one is pressed ("w(hold)").
+    pub fn parse_token(token: &str) -> Result<Self, String> {
+        if let Some(rest) = token.strip_prefix('#').filter(|rest| !rest.is_empty()) {
+            let mut step = Self::parse_token(rest)?;
+            step.enabled = false;
+            return Ok(step);
+        }
+        let split = token.len().saturating_sub("(hold)".len());
+        if let (Some(rest), Some(suffix)) = (token.get(..split), token.get(split..)) {
+            if suffix.eq_ignore_ascii_case("(hold)") && !rest.trim().is_empty() {
+                let mut step = Self::parse_token(rest.trim())?;
+                if matches!(step.kind, StepKind::Wait(_)) {
+                    return Err("wait steps can't be held".to_string());
+                }
+                step.handover = true;
+                return Ok(step);
+            }
+        }
+        
+        let lower = token.to_lowercase();
+        let kind = if let Some(args) = lower.strip_prefix("wait(") {
+            let ms = args
+                .strip_suffix(')')
+                .and_then(|ms| ms.trim().parse::<u64>().ok())
+                .filter(|&ms| ms > 0)
+                .ok_or("expected wait(ms) with a whole number of milliseconds")?;
+            StepKind::Wait(ms)
+        } else if let Some(button) = ClickButton::from_token(token) {
+            StepKind::Click(button)
+        } else if token.chars().count() == 1 || is_named_key(token) {
+            StepKind::Key(token.to_string())
+        } else {
+            return Err("unknown key name".to_string());
+        };
+        Ok(Self::new(kind))
+    }
+    
+    pub fn to_token(&self) -> String {
+        let token = match &self.kind {
+            StepKind::Key(key) => key.clone(),
+            StepKind::Click(button) => button.token().to_string(),
+            StepKind::Wait(ms) => format!("wait({})", ms),
+        };
+        let token = if self.handover { format!("{}(hold)", token) } else { token };
+        if self.enabled { token } else { format!("#{}", token) }
+    }
+    
+    // The input this step drives, or None for a wait step
+    pub fn action(&self) -> Option<ActionType> {
+        match &self.kind {
+            StepKind::Key(key) => Some(ActionType::KeyPress(key.clone())),
+            StepKind::Click(button) => Some(ActionType::Click(*button)),
+            StepKind::Wait(_) => None,
+        }
+    }
+    
+    pub fn wait_duration(&self) -> Duration {
+        match self.kind {
+            StepKind::Wait(ms) => Duration::from_millis(ms),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+// A token in the Keys field that didn't parse
+#[derive(Clone, PartialEq, Debug)]
+pub struct TokenError {
+    pub index: usize,
+    pub token: String,
+    pub message: String,
+}
+
+// Whether a multi-character name refers to a special key rather than being a typo
+pub fn is_named_key(name: &str) -> bool {
+    matches!(map_key_str_to_enigo_key(name), Some(key) if !matches!(key, EnigoKey::Unicode(_)))
+}
+
+// The character a key name stands for, when it is one character rather than a named key
+pub fn single_char(key_str: &str) -> Option<char> {
+    let mut chars = key_str.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+// Add a helper function to map key strings to EnigoKey, needed for key_down/key_up
+pub fn map_key_str_to_enigo_key(key_str: &str) -> Option<EnigoKey> {
+    match key_str.to_lowercase().as_str() {
+        "space" => Some(EnigoKey::Space),
+        "enter" | "return" => Some(EnigoKey::Return),
+        "tab" => Some(EnigoKey::Tab),
+        "backspace" | "back" => Some(EnigoKey::Backspace),
+        "esc" | "escape" => Some(EnigoKey::Escape),
+        "up" => Some(EnigoKey::UpArrow),
+        "down" => Some(EnigoKey::DownArrow),
+        "left" => Some(EnigoKey::LeftArrow),
+        "right" => Some(EnigoKey::RightArrow),
+        "shift" => Some(EnigoKey::Shift),
+        "control" | "ctrl" => Some(EnigoKey::Control),
+        "alt" => Some(EnigoKey::Alt),
+        "win" | "windows" | "meta" => Some(EnigoKey::Meta),
+        "caps" | "capslock" => Some(EnigoKey::CapsLock),
+        "delete" | "del" => Some(EnigoKey::Delete),
+        "home" => Some(EnigoKey::Home),
+        "end" => Some(EnigoKey::End),
+        "pageup" | "pgup" => Some(EnigoKey::PageUp),
+        "pagedown" | "pgdn" => Some(EnigoKey::PageDown),
+        "f1" => Some(EnigoKey::F1),
+        "f2" => Some(EnigoKey::F2),
+        "f3" => Some(EnigoKey::F3),
+        "f4" => Some(EnigoKey::F4),
+        "f5" => Some(EnigoKey::F5),
+        "f6" => Some(EnigoKey::F6),
+        "f7" => Some(EnigoKey::F7),
+        "f8" => Some(EnigoKey::F8),
+        "f9" => Some(EnigoKey::F9),
+        "f10" => Some(EnigoKey::F10),
+        "f11" => Some(EnigoKey::F11),
+        "f12" => Some(EnigoKey::F12),
+        // This handles single character keys
+        _ => key_str.chars().next().map(EnigoKey::Unicode),
+    }
+}
+
+// The physical key rdev reports for a key name, used to recognise our own injected keys
+pub fn map_key_str_to_rdev_key(key_str: &str) -> Option<RdevKey> {
+    let lower = key_str.to_lowercase();
+    let key = match lower.as_str() {
+        "space" => RdevKey::Space,
+        "enter" | "return" => RdevKey::Return,
+        "tab" => RdevKey::Tab,
+        "backspace" | "back" => RdevKey::Backspace,
+        "esc" | "escape" => RdevKey::Escape,
+        "up" => RdevKey::UpArrow,
+        "down" => RdevKey::DownArrow,
+        "left" => RdevKey::LeftArrow,
+        "right" => RdevKey::RightArrow,
+        "shift" => RdevKey::ShiftLeft,
+        "control" | "ctrl" => RdevKey::ControlLeft,
+        "alt" => RdevKey::Alt,
+        "win" | "windows" | "meta" => RdevKey::MetaLeft,
+        "caps" | "capslock" => RdevKey::CapsLock,
+        "delete" | "del" => RdevKey::Delete,
+        "home" => RdevKey::Home,
+        "end" => RdevKey::End,
+        "pageup" | "pgup" => RdevKey::PageUp,
+        "pagedown" | "pgdn" => RdevKey::PageDown,
+        "f1" => RdevKey::F1,
+        "f2" => RdevKey::F2,
+        "f3" => RdevKey::F3,
+        "f4" => RdevKey::F4,
+        "f5" => RdevKey::F5,
+        "f6" => RdevKey::F6,
+        "f7" => RdevKey::F7,
+        "f8" => RdevKey::F8,
+        "f9" => RdevKey::F9,
+        "f10" => RdevKey::F10,
+        "f11" => RdevKey::F11,
+        "f12" => RdevKey::F12,
+        _ => {
+            let mut chars = lower.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            map_char_to_rdev_key(c)?
+        },
+    };
+    Some(key)
+}
+
+pub fn map_char_to_rdev_key(c: char) -> Option<RdevKey> {
+    let key = match c {
+        'a' => RdevKey::KeyA, 'b' => RdevKey::KeyB, 'c' => RdevKey::KeyC, 'd' => RdevKey::KeyD,
+        'e' => RdevKey::KeyE, 'f' => RdevKey::KeyF, 'g' => RdevKey::KeyG, 'h' => RdevKey::KeyH,
+        'i' => RdevKey::KeyI, 'j' => RdevKey::KeyJ, 'k' => RdevKey::KeyK, 'l' => RdevKey::KeyL,
+        'm' => RdevKey::KeyM, 'n' => RdevKey::KeyN, 'o' => RdevKey::KeyO, 'p' => RdevKey::KeyP,
+        'q' => RdevKey::KeyQ, 'r' => RdevKey::KeyR, 's' => RdevKey::KeyS, 't' => RdevKey::KeyT,
+        'u' => RdevKey::KeyU, 'v' => RdevKey::KeyV, 'w' => RdevKey::KeyW, 'x' => RdevKey::KeyX,
+        'y' => RdevKey::KeyY, 'z' => RdevKey::KeyZ,
+        '0' => RdevKey::Num0, '1' => RdevKey::Num1, '2' => RdevKey::Num2, '3' => RdevKey::Num3,
+        '4' => RdevKey::Num4, '5' => RdevKey::Num5, '6' => RdevKey::Num6, '7' => RdevKey::Num7,
+        '8' => RdevKey::Num8, '9' => RdevKey::Num9,
+        ' ' => RdevKey::Space,
+        _ => return None,
+    };
+    Some(key)
+}
+
+// The Windows virtual-key code for a key name, for the SendInput backend. Letters and digits
+// map to their own codes; other single characters have none and are typed as Unicode instead
+pub fn map_key_str_to_virtual_key(key_str: &str) -> Option<u16> {
+    let lower = key_str.to_lowercase();
+    let vk = match lower.as_str() {
+        "space" => 0x20,
+        "enter" | "return" => 0x0D,
+        "tab" => 0x09,
+        "backspace" | "back" => 0x08,
+        "esc" | "escape" => 0x1B,
+        "up" => 0x26,
+        "down" => 0x28,
+        "left" => 0x25,
+        "right" => 0x27,
+        "shift" => 0x10,
+        "control" | "ctrl" => 0x11,
+        "alt" => 0x12,
+        "win" | "windows" | "meta" => 0x5B,
+        "caps" | "capslock" => 0x14,
+        "delete" | "del" => 0x2E,
+        "home" => 0x24,
+        "end" => 0x23,
+        "pageup" | "pgup" => 0x21,
+        "pagedown" | "pgdn" => 0x22,
+        _ => {
+            if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()).filter(|n| (1..=12).contains(n)) {
+                return Some(0x70 + n - 1);
+            }
+            match single_char(&lower)? {
+                c @ ('a'..='z' | '0'..='9') => c.to_ascii_uppercase() as u16,
+                ' ' => 0x20,
+                _ => return None,
+            }
+        },
+    };
+    Some(vk)
+}
+
+// The Linux evdev key code for a key name, for the uinput backend. A virtual device has no
+// layout of its own, so single characters name the key they sit on in a US layout, unshifted
+pub fn map_key_str_to_evdev_key(key_str: &str) -> Option<u16> {
+    let lower = key_str.to_lowercase();
+    let code = match lower.as_str() {
+        "space" => 57,
+        "enter" | "return" => 28,
+        "tab" => 15,
+        "backspace" | "back" => 14,
+        "esc" | "escape" => 1,
+        "up" => 103,
+        "down" => 108,
+        "left" => 105,
+        "right" => 106,
+        "shift" => 42,
+        "control" | "ctrl" => 29,
+        "alt" => 56,
+        "win" | "windows" | "meta" => 125,
+        "caps" | "capslock" => 58,
+        "delete" | "del" => 111,
+        "home" => 102,
+        "end" => 107,
+        "pageup" | "pgup" => 104,
+        "pagedown" | "pgdn" => 109,
+        "f11" => 87,
+        "f12" => 88,
+        _ => {
+            if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u16>().ok()).filter(|n| (1..=10).contains(n)) {
+                return Some(58 + n);
+            }
+            map_char_to_evdev_key(single_char(&lower)?)?
+        },
+    };
+    Some(code)
+}
+
+pub fn map_char_to_evdev_key(c: char) -> Option<u16> {
+    let code = match c {
+        '1'..='9' => c as u16 - '1' as u16 + 2,
+        '0' => 11, '-' => 12, '=' => 13,
+        'q' => 16, 'w' => 17, 'e' => 18, 'r' => 19, 't' => 20, 'y' => 21, 'u' => 22, 'i' => 23,
+        'o' => 24, 'p' => 25, '[' => 26, ']' => 27,
+        'a' => 30, 's' => 31, 'd' => 32, 'f' => 33, 'g' => 34, 'h' => 35, 'j' => 36, 'k' => 37,
+        'l' => 38, ';' => 39, '\'' => 40, '`' => 41, '\\' => 43,
+        'z' => 44, 'x' => 45, 'c' => 46, 'v' => 47, 'b' => 48, 'n' => 49, 'm' => 50,
+        ',' => 51, '.' => 52, '/' => 53,
+        ' ' => 57,
+        _ => return None,
+    };
+    Some(code)
+}
+
+// The key names with an evdev code that aren't single characters
+const EVDEV_NAMED_KEYS: [&str; 31] = [
+    "space", "enter", "tab", "backspace", "esc", "up", "down", "left", "right", "shift", "ctrl", "alt",
+    "meta", "capslock", "delete", "home", "end", "pageup", "pagedown",
+    "f1", "f2", "f3", "f4", "f5", "f6", "f7", "f8", "f9", "f10", "f11", "f12",
+];
+
+// The rdev key for an evdev code, for hotkeys read straight from /dev/input. The inverse of
+// map_key_str_to_evdev_key, plus the right-hand modifiers, which have no name of their own
+pub fn map_evdev_key_to_rdev_key(code: u16) -> Option<RdevKey> {
+    match code {
+        54 => return Some(RdevKey::ShiftRight),
+        97 => return Some(RdevKey::ControlRight),
+        100 => return Some(RdevKey::AltGr),
+        126 => return Some(RdevKey::MetaRight),
+        _ => {},
+    }
+    if let Some(name) = EVDEV_NAMED_KEYS.iter().find(|name| map_key_str_to_evdev_key(name) == Some(code)) {
+        return map_key_str_to_rdev_key(name);
+    }
+    let c = ('a'..='z').chain('0'..='9').find(|&c| map_char_to_evdev_key(c) == Some(code))?;
+    map_char_to_rdev_key(c)
+}
+
+// Keys that sit in the extended block and need KEYEVENTF_EXTENDEDKEY, or they arrive as their
+// numpad twins
+pub fn is_extended_virtual_key(vk: u16) -> bool {
+    matches!(vk, 0x21..=0x28 | 0x2E | 0x5B)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(token: &str) -> Result<SequenceStep, String> {
+        SequenceStep::parse_token(token)
+    }
+
+    #[test]
+    fn parses_keys_clicks_and_waits() {
+        assert_eq!(parse("w").unwrap().kind, StepKind::Key("w".to_string()));
+        assert_eq!(parse("Enter").unwrap().kind, StepKind::Key("Enter".to_string()));
+        assert_eq!(parse("click").unwrap().kind, StepKind::Click(ClickButton::Left));
+        assert_eq!(parse("RClick").unwrap().kind, StepKind::Click(ClickButton::Right));
+        assert_eq!(parse("wait(250)").unwrap().kind, StepKind::Wait(250));
+        assert!(parse("wait(0)").is_err());
+        assert!(parse("wait(abc)").is_err());
+        assert!(parse("enterr").is_err());
+    }
+
+    #[test]
+    fn parses_disabled_and_held_steps() {
+        let disabled = parse("#w").unwrap();
+        assert_eq!(disabled.kind, StepKind::Key("w".to_string()));
+        assert!(!disabled.enabled);
+        // A lone '#' is the key itself
+        assert_eq!(parse("#").unwrap().kind, StepKind::Key("#".to_string()));
+
+        let held = parse("w(hold)").unwrap();
+        assert!(held.handover && held.enabled);
+        assert!(parse("#space(HOLD)").is_ok_and(|step| step.handover && !step.enabled));
+        assert!(parse("wait(100)(hold)").is_err());
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        for token in ["w", "click", "mclick", "wait(50)", "#s", "shift(hold)", "#click(hold)"] {
+            assert_eq!(parse(token).unwrap().to_token(), token);
+        }
+    }
+
+    #[test]
+    fn maps_key_names_to_enigo() {
+        assert_eq!(map_key_str_to_enigo_key("space"), Some(EnigoKey::Space));
+        assert_eq!(map_key_str_to_enigo_key("Return"), Some(EnigoKey::Return));
+        assert_eq!(map_key_str_to_enigo_key("ESC"), Some(EnigoKey::Escape));
+        assert_eq!(map_key_str_to_enigo_key("pgdn"), Some(EnigoKey::PageDown));
+        assert_eq!(map_key_str_to_enigo_key("win"), Some(EnigoKey::Meta));
+        assert_eq!(map_key_str_to_enigo_key("F12"), Some(EnigoKey::F12));
+        assert_eq!(map_key_str_to_enigo_key("a"), Some(EnigoKey::Unicode('a')));
+        assert_eq!(map_key_str_to_enigo_key(""), None);
+        assert_eq!(ClickButton::Right.to_enigo(), Button::Right);
+        assert!(is_named_key("ctrl"));
+        assert!(!is_named_key("ctrlx"));
+    }
+
+    #[test]
+    fn maps_key_names_to_rdev() {
+        assert_eq!(map_key_str_to_rdev_key("A"), Some(RdevKey::KeyA));
+        assert_eq!(map_key_str_to_rdev_key("7"), Some(RdevKey::Num7));
+        assert_eq!(map_key_str_to_rdev_key("Shift"), Some(RdevKey::ShiftLeft));
+        assert_eq!(map_key_str_to_rdev_key("ab"), None);
+        assert_eq!(map_key_str_to_rdev_key("é"), None);
+    }
+
+    #[test]
+    fn maps_key_names_to_virtual_keys() {
+        assert_eq!(map_key_str_to_virtual_key("w"), Some(0x57));
+        assert_eq!(map_key_str_to_virtual_key("W"), Some(0x57));
+        assert_eq!(map_key_str_to_virtual_key("7"), Some(0x37));
+        assert_eq!(map_key_str_to_virtual_key("Enter"), Some(0x0D));
+        assert_eq!(map_key_str_to_virtual_key("F1"), Some(0x70));
+        assert_eq!(map_key_str_to_virtual_key("f12"), Some(0x7B));
+        assert_eq!(map_key_str_to_virtual_key("f13"), None);
+        assert_eq!(map_key_str_to_virtual_key("é"), None);
+        assert!(is_extended_virtual_key(map_key_str_to_virtual_key("left").unwrap()));
+        assert!(!is_extended_virtual_key(map_key_str_to_virtual_key("a").unwrap()));
+    }
+
+    #[test]
+    fn maps_key_names_to_evdev_keys() {
+        assert_eq!(map_key_str_to_evdev_key("w"), Some(17));
+        assert_eq!(map_key_str_to_evdev_key("W"), Some(17));
+        assert_eq!(map_key_str_to_evdev_key("1"), Some(2));
+        assert_eq!(map_key_str_to_evdev_key("9"), Some(10));
+        assert_eq!(map_key_str_to_evdev_key("0"), Some(11));
+        assert_eq!(map_key_str_to_evdev_key("Enter"), Some(28));
+        assert_eq!(map_key_str_to_evdev_key("F1"), Some(59));
+        assert_eq!(map_key_str_to_evdev_key("F10"), Some(68));
+        assert_eq!(map_key_str_to_evdev_key("F11"), Some(87));
+        assert_eq!(map_key_str_to_evdev_key("ctrl"), Some(29));
+        assert_eq!(map_key_str_to_evdev_key("é"), None);
+        assert_eq!(map_key_str_to_evdev_key("f13"), None);
+    }
+
+    #[test]
+    fn maps_evdev_keys_back_to_rdev_keys() {
+        assert_eq!(map_evdev_key_to_rdev_key(17), Some(RdevKey::KeyW));
+        assert_eq!(map_evdev_key_to_rdev_key(11), Some(RdevKey::Num0));
+        assert_eq!(map_evdev_key_to_rdev_key(64), Some(RdevKey::F6));
+        assert_eq!(map_evdev_key_to_rdev_key(29), Some(RdevKey::ControlLeft));
+        assert_eq!(map_evdev_key_to_rdev_key(97), Some(RdevKey::ControlRight));
+        assert_eq!(map_evdev_key_to_rdev_key(0), None);
+        for name in EVDEV_NAMED_KEYS {
+            let code = map_key_str_to_evdev_key(name).unwrap();
+            assert_eq!(map_evdev_key_to_rdev_key(code), map_key_str_to_rdev_key(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn every_letter_has_an_evdev_key() {
+        let codes: Vec<u16> = ('a'..='z').filter_map(map_char_to_evdev_key).collect();
+        assert_eq!(codes.len(), 26);
+        let mut unique = codes.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(unique.len(), 26);
+    }
+
+    #[test]
+    fn single_char_only_matches_one_character() {
+        assert_eq!(single_char("w"), Some('w'));
+        assert_eq!(single_char("é"), Some('é'));
+        assert_eq!(single_char("space"), None);
+        assert_eq!(single_char(""), None);
+    }
+}
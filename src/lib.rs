@@ -0,0 +1,39 @@
+pub mod active_window;
+pub mod backend;
+pub mod backup;
+pub mod cli;
+pub mod clock;
+pub mod config;
+pub mod display_server;
+pub mod elevation;
+#[cfg(target_os = "linux")]
+pub mod evdev;
+pub mod health;
+pub mod hotkeys;
+pub mod http_api;
+pub mod instance;
+pub mod ipc;
+pub mod keyboard_layout;
+pub mod keys;
+pub mod listener;
+pub mod log_file;
+pub mod logging;
+pub mod macro_dsl;
+pub mod permissions;
+pub mod presets;
+pub mod profiles;
+pub mod safety;
+pub mod schedule;
+pub mod scheduler;
+pub mod scripting;
+#[cfg(windows)]
+pub mod sendinput;
+pub mod session;
+pub mod settings;
+pub mod state;
+#[cfg(windows)]
+pub mod timer_resolution;
+pub mod timing;
+pub mod ui;
+#[cfg(target_os = "linux")]
+pub mod uinput;
@@ -0,0 +1,409 @@
+// The rdev hotkey listener threads and the input probe.
+
+use crate::active_window;
+use crate::display_server::DisplayServer;
+use crate::health;
+use crate::hotkeys::{Activation, DoubleTapTracker, HotkeyAction, ModifierTracker, Modifiers, PhysicalInput};
+use crate::keys::{map_key_str_to_rdev_key, ActionType};
+use crate::logging::{self, Category};
+use crate::{log_error, log_info, log_warn};
+use crate::state::{lock_state, AppState};
+use rdev::{grab, listen, Event, EventType, Key as RdevKey};
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Auto-repeat sends presses every few tens of milliseconds; a held key that has been silent
+// for this long has really been released and pressed again
+pub const HOTKEY_REPEAT_TIMEOUT: Duration = Duration::from_secs(1);
+// The same hotkey action never fires twice within this window
+pub const HOTKEY_DEBOUNCE: Duration = Duration::from_millis(300);
+// Mouse movement counts as user input at most this often
+pub const MOTION_NOTE_INTERVAL: Duration = Duration::from_millis(100);
+
+// Input tracking for one rdev listener; both the plain and the grab listener use it
+pub struct HotkeyListener {
+    pub state: Arc<Mutex<AppState>>,
+    pub hold_released: Arc<AtomicBool>,
+    // Whether this is the grab listener, which takes over once it is running
+    pub grabbing: bool,
+    // Keys and buttons currently down with their last press event, so OS auto-repeat doesn't re-trigger hotkeys
+    pub pressed: Vec<(PhysicalInput, Instant)>,
+    // When each action last fired, for rate-limiting
+    pub last_triggered: Vec<(HotkeyAction, Instant)>,
+    pub double_taps: DoubleTapTracker,
+    pub modifiers: ModifierTracker,
+    // The hold-to-run hotkey currently keeping a mode running
+    pub holding: Option<(PhysicalInput, HotkeyAction)>,
+    // Hotkey presses that were swallowed, so their releases are swallowed too
+    pub swallowed: Vec<PhysicalInput>,
+    // Whether the input probe has been told events are arriving
+    pub seen_event: bool,
+    // A modifier pressed with nothing else since, which a rebind can capture on release
+    pub lone_modifier: Option<RdevKey>,
+    // When mouse movement last counted as user input; moves arrive far too often to lock for each
+    pub last_motion: Option<Instant>,
+}
+
+impl HotkeyListener {
+    pub fn new(state: Arc<Mutex<AppState>>, hold_released: Arc<AtomicBool>, grabbing: bool) -> Self {
+        Self {
+            state,
+            hold_released,
+            grabbing,
+            pressed: Vec::new(),
+            last_triggered: Vec::new(),
+            double_taps: DoubleTapTracker::default(),
+            modifiers: ModifierTracker::default(),
+            holding: None,
+            swallowed: Vec::new(),
+            seen_event: false,
+            lone_modifier: None,
+            last_motion: None,
+        }
+    }
+    
+    // Handle one event; returns true when it should be kept from the focused app
+    pub fn handle(&mut self, event: &Event) -> bool {
+        health::HOTKEY.beat();
+        if !self.seen_event {
+            self.seen_event = true;
+            let mut state = lock_state(&self.state);
+            if state.input_probe == InputProbe::Blocked {
+                log_info!(state.log, "Input events are arriving now; hotkeys should work");
+                state.request_repaint();
+            }
+            state.input_probe = InputProbe::Working;
+        }
+        let (input, is_press) = match event.event_type {
+            EventType::KeyPress(key) => {
+                // Modifiers make up chords, but one bound on its own (RightCtrl) works like any key
+                if self.modifiers.handle(key, true) {
+                    self.lone_modifier = Some(key);
+                    return self.press(PhysicalInput::Key(key), self.modifiers.current_without(key));
+                }
+                self.lone_modifier = None;
+                (PhysicalInput::Key(key), true)
+            },
+            EventType::KeyRelease(key) => {
+                self.modifiers.handle(key, false);
+                if self.lone_modifier == Some(key) {
+                    self.lone_modifier = None;
+                    self.capture_modifier(key);
+                }
+                (PhysicalInput::Key(key), false)
+            },
+            EventType::ButtonPress(button) => {
+                self.lone_modifier = None;
+                (PhysicalInput::Button(button), true)
+            },
+            EventType::ButtonRelease(button) => (PhysicalInput::Button(button), false),
+            EventType::MouseMove { .. } | EventType::Wheel { .. } => {
+                let now = Instant::now();
+                if self.last_motion.is_none_or(|last| now.saturating_duration_since(last) >= MOTION_NOTE_INTERVAL) {
+                    self.last_motion = Some(now);
+                    lock_state(&self.state).note_user_input(now);
+                }
+                return false;
+            },
+        };
+        
+        if is_press {
+            self.press(input, self.modifiers.current())
+        } else {
+            self.release(input)
+        }
+    }
+    
+    // While rebinding, a modifier only becomes the hotkey if it was released without being
+    // part of a chord
+    pub fn capture_modifier(&mut self, key: RdevKey) {
+        let mut state = lock_state(&self.state);
+        if let (Some(action), true) = (state.capturing_hotkey, state.hotkey_grab_active == self.grabbing) {
+            state.capture_hotkey(action, PhysicalInput::Key(key), self.modifiers.current());
+            state.request_repaint();
+        }
+    }
+    
+    pub fn press(&mut self, input: PhysicalInput, modifiers: Modifiers) -> bool {
+        let now = Instant::now();
+        // A press for something already down is auto-repeat, unless it has been quiet long
+        // enough that the release must have been missed
+        if let Some((_, last_seen)) = self.pressed.iter_mut().find(|(p, _)| *p == input) {
+            let repeat = now.saturating_duration_since(*last_seen) < HOTKEY_REPEAT_TIMEOUT;
+            *last_seen = now;
+            if repeat {
+                return self.swallowed.contains(&input);
+            }
+        } else {
+            self.pressed.push((input, now));
+        }
+        
+        let mut state = lock_state(&self.state);
+        // Events arriving again means a restarted listener is back up
+        if !self.grabbing && state.hotkey_listener != ListenerStatus::Running {
+            state.hotkey_listener = ListenerStatus::Running;
+            log_info!(state.log, "Hotkey listener recovered");
+            state.request_repaint();
+        }
+        // Only one listener acts on hotkeys at a time
+        if state.hotkey_grab_active != self.grabbing {
+            return false;
+        }
+        // Our own injected keys and clicks come back through the listener on some platforms
+        if state.injected.lock().is_ok_and(|injected| injected.is_echo(input, now)) {
+            return false;
+        }
+        state.note_user_input(now);
+        // While rebinding, the next press becomes the hotkey instead of triggering anything
+        if let Some(action) = state.capturing_hotkey {
+            // Modifiers wait for their release, in case they start a chord
+            if !matches!(input, PhysicalInput::Key(key) if Modifiers::is_modifier(key)) {
+                state.capture_hotkey(action, input, modifiers);
+                state.request_repaint();
+            }
+            return false;
+        }
+        let Some(action) = state.hotkeys.action_for(input, modifiers) else {
+            return false;
+        };
+        // Stop and panic are the safety valves and always work
+        if !state.hotkeys_enabled && !matches!(action, HotkeyAction::Stop | HotkeyAction::Panic) {
+            return false;
+        }
+        // Typing "f6" into the Keys field shouldn't start the clicker; stopping and pausing still work
+        if state.editing_text && !matches!(action, HotkeyAction::Stop | HotkeyAction::PauseResume | HotkeyAction::Panic) {
+            return false;
+        }
+        // The first press of a double-tap hotkey only arms it; toggles then take a double tap each way
+        let hotkey = *state.hotkeys.get(action);
+        let armed = !hotkey.double_tap
+            || self.double_taps.tap(action, now, Duration::from_millis(state.double_tap_window_ms));
+        // Outside the target application hotkeys belong to whatever is focused, except the safety valves
+        if !state.window_filter.trim().is_empty() && !matches!(action, HotkeyAction::Stop | HotkeyAction::Panic) {
+            let title = active_window::foreground_title();
+            if !active_window::title_matches(&state.window_filter, title.as_deref()) {
+                return false;
+            }
+        }
+        // Second line of defence for platforms that drop release events
+        self.last_triggered.retain(|(_, at)| now.saturating_duration_since(*at) < HOTKEY_DEBOUNCE);
+        if armed && !self.last_triggered.iter().any(|(a, _)| *a == action) {
+            self.last_triggered.push((action, now));
+            if state.in_mode_cooldown(action, now) {
+                log_info!(state.log, "Ignored {} (debounced)", hotkey.name());
+            } else {
+                state.trigger_hotkey(action, now);
+                state.send_update();
+                if hotkey.activation == Activation::Hold {
+                    self.holding = Some((input, action));
+                }
+            }
+            state.request_repaint();
+        }
+        if state.suppress_hotkeys {
+            self.swallowed.push(input);
+            return true;
+        }
+        false
+    }
+    
+    pub fn release(&mut self, input: PhysicalInput) -> bool {
+        self.pressed.retain(|(p, _)| *p != input);
+        let swallowed = self.swallowed.contains(&input);
+        self.swallowed.retain(|&p| p != input);
+        
+        if let Some((_, action)) = self.holding.filter(|(held, _)| *held == input) {
+            self.holding = None;
+            // Stop new presses right away, even if the state lock is busy for a while
+            self.hold_released.store(true, Ordering::SeqCst);
+            { // Scope for state lock
+                let mut state = lock_state(&self.state);
+                state.release_hotkey(action, Instant::now());
+                state.send_update();
+                state.request_repaint();
+            }
+            self.hold_released.store(false, Ordering::SeqCst);
+        }
+        swallowed
+    }
+}
+
+// How often a failed hotkey listener is restarted before giving up, and the first delay between tries
+pub const LISTENER_RETRIES: u32 = 5;
+pub const LISTENER_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+// Whether the plain hotkey listener is up
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ListenerStatus {
+    Running,
+    Retrying,
+    // Out of retries; only the Retry button brings it back
+    Failed,
+}
+
+// Whether the hotkey listener can see input at all
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InputProbe {
+    Pending,
+    Working,
+    // Nothing arrived, not even our own test event; almost always a missing permission
+    Blocked,
+}
+
+// How long the probe waits for the listener to start, then for any event to arrive
+pub const PROBE_SETTLE: Duration = Duration::from_millis(500);
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+// Send a harmless synthetic event once the listener has had time to start and see whether
+// anything arrives. Without the permission the listener fails silently, so this is the only
+// way to notice
+pub fn start_input_probe(state: Arc<Mutex<AppState>>) {
+    thread::spawn(move || {
+        logging::set_thread_category(Category::Hotkey);
+        thread::sleep(PROBE_SETTLE);
+        // A stray release of a modifier that isn't down changes nothing anywhere
+        let _ = rdev::simulate(&EventType::KeyRelease(RdevKey::ShiftRight));
+        let deadline = Instant::now() + PROBE_TIMEOUT;
+        while Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(100));
+            if lock_state(&state).input_probe != InputProbe::Pending {
+                return;
+            }
+        }
+        let mut state = lock_state(&state);
+        if state.input_probe == InputProbe::Pending {
+            state.input_probe = InputProbe::Blocked;
+            state.permission_dialog_pending = true;
+            log_warn!(state.log, "No input events received; hotkeys probably lack permission");
+            state.request_repaint();
+        }
+    });
+}
+
+// Start whichever hotkey listener works in this session: under Wayland rdev hears nothing
+// outside our own window, so the input devices are read directly instead
+pub fn start_hotkey_listener(
+    state: Arc<Mutex<AppState>>,
+    is_running: Arc<AtomicBool>,
+    hold_released: Arc<AtomicBool>,
+    display_server: DisplayServer,
+) -> thread::JoinHandle<()> {
+    #[cfg(target_os = "linux")]
+    if display_server == DisplayServer::Wayland {
+        return crate::evdev::start_evdev_hotkey_thread(state, is_running, hold_released);
+    }
+    let _ = display_server;
+    start_hotkey_thread(state, is_running, hold_released)
+}
+
+// Start the hotkey listener thread, restarting rdev's listener with backoff when it errors out
+pub fn start_hotkey_thread(
+    state: Arc<Mutex<AppState>>,
+    is_running: Arc<AtomicBool>,
+    hold_released: Arc<AtomicBool>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        logging::set_thread_category(Category::Hotkey);
+        let mut delay = LISTENER_RETRY_DELAY;
+        for attempt in 0..=LISTENER_RETRIES {
+            if attempt > 0 {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            if !is_running.load(Ordering::SeqCst) {
+                return;
+            }
+            
+            let mut listener = HotkeyListener::new(Arc::clone(&state), Arc::clone(&hold_released), false);
+            let listener_running = Arc::clone(&is_running);
+            let result = listen(move |event| {
+                if listener_running.load(Ordering::SeqCst) {
+                    listener.handle(&event);
+                }
+            });
+            
+            let mut state = lock_state(&state);
+            let reason = match result {
+                Err(e) => format!("{:?}", e),
+                Ok(()) => "stopped unexpectedly".to_string(),
+            };
+            if attempt < LISTENER_RETRIES {
+                state.hotkey_listener = ListenerStatus::Retrying;
+                log_error!(state.log, "Hotkey listener error: {}; retrying in {}s", reason, delay.as_secs());
+            } else {
+                state.hotkey_listener = ListenerStatus::Failed;
+                log_error!(state.log, "Hotkey listener error: {}; giving up", reason);
+                state.hotkeys_unavailable = Some(format!("the listener keeps failing ({})", reason));
+            }
+            state.request_repaint();
+        }
+    })
+}
+
+// Take over hotkey handling with rdev's grab API so bound hotkeys can be swallowed.
+// Grabbing needs Accessibility permission on macOS and read access to /dev/input on Linux,
+// and isn't available under Wayland; if it fails the plain listener keeps handling hotkeys.
+pub fn start_grab_thread(state: Arc<Mutex<AppState>>, is_running: Arc<AtomicBool>, hold_released: Arc<AtomicBool>) {
+    let state_err = Arc::clone(&state);
+    
+    thread::spawn(move || {
+        logging::set_thread_category(Category::Hotkey);
+        // grab wants an Fn callback, so the listener's tracking lives in a RefCell
+        let listener = RefCell::new(HotkeyListener::new(state, hold_released, true));
+        let result = grab(move |event| {
+            if is_running.load(Ordering::SeqCst) && listener.borrow_mut().handle(&event) {
+                None
+            } else {
+                Some(event)
+            }
+        });
+        
+        if let Err(e) = result {
+            let mut state = lock_state(&state_err);
+            state.hotkey_grab_active = false;
+            state.suppress_hotkeys = false;
+            log_warn!(
+                state.log,
+                "Can't suppress hotkeys here ({:?}); falling back to listening, so the focused app will see them too",
+                e,
+            );
+            state.request_repaint();
+        }
+    });
+}
+
+// How long after injecting an input its echo through the rdev listener is ignored
+pub const INJECTION_ECHO_WINDOW: Duration = Duration::from_millis(100);
+
+impl PhysicalInput {
+    pub fn from_action(action: &ActionType) -> Option<Self> {
+        match action {
+            ActionType::Click(button) => Some(PhysicalInput::Button(button.to_rdev())),
+            ActionType::KeyPress(key_str) => map_key_str_to_rdev_key(key_str).map(PhysicalInput::Key),
+        }
+    }
+}
+
+// Inputs we injected recently, so the hotkey listener can tell our own events from the user's
+#[derive(Clone, Default)]
+pub struct InjectionFilter {
+    pub recent: Vec<(PhysicalInput, Instant)>,
+}
+
+impl InjectionFilter {
+    pub fn record(&mut self, action: &ActionType, now: Instant) {
+        self.recent.retain(|(_, at)| now.saturating_duration_since(*at) < INJECTION_ECHO_WINDOW);
+        if let Some(input) = PhysicalInput::from_action(action) {
+            self.recent.push((input, now));
+        }
+    }
+    
+    pub fn is_echo(&self, input: PhysicalInput, now: Instant) -> bool {
+        self.recent
+            .iter()
+            .any(|(recent, at)| *recent == input && now.saturating_duration_since(*at) < INJECTION_ECHO_WINDOW)
+    }
+}
@@ -0,0 +1,169 @@
+// Mirroring the log to a file in the config directory, for long unattended runs.
+//
+// While the setting is on, every entry recorded anywhere is also sent, timestamped, to the
+// writer thread here (see logging::set_file_sink), so neither the UI nor the action thread
+// ever waits on the disk. The file is rotated once it reaches MAX_FILE_BYTES, keeping
+// KEPT_FILES older ones as autoclicker.log.1 (newest) onwards. If the file can't be written,
+// the log says so once and the setting switches itself off.
+
+use crate::config;
+use crate::{log_info, log_warn};
+use crate::logging;
+use crate::state::{lock_state, AppState};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+pub const MAX_FILE_BYTES: u64 = 5 * 1024 * 1024;
+pub const KEPT_FILES: u32 = 3;
+// How often the writer checks whether the setting changed
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+pub fn log_file_path() -> PathBuf {
+    config::config_dir().join("autoclicker.log")
+}
+
+// `path` with `.n` appended, the nth newest rotated file
+fn rotated_path(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+pub struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    kept_files: u32,
+}
+
+impl LogFile {
+    // Append to `path`, creating it and its directory if needed
+    pub fn open(path: &Path, max_bytes: u64, kept_files: u32) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(LogFile { path: path.to_path_buf(), file, size, max_bytes, kept_files })
+    }
+
+    pub fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    // Shift every kept file one place older, dropping the oldest, and start an empty one
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.kept_files == 0 {
+            self.file = File::create(&self.path)?;
+        } else {
+            let _ = fs::remove_file(rotated_path(&self.path, self.kept_files));
+            for n in (1..self.kept_files).rev() {
+                let from = rotated_path(&self.path, n);
+                if from.exists() {
+                    fs::rename(&from, rotated_path(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+            self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        }
+        self.size = 0;
+        Ok(())
+    }
+}
+
+// Stop writing after a failure, telling the user once
+fn give_up(state: &Mutex<AppState>, e: io::Error) {
+    logging::set_file_sink(None);
+    let mut state = lock_state(state);
+    state.log_to_file = false;
+    log_warn!(state.log, "Stopped saving the log to {}: {}", log_file_path().display(), e);
+    state.request_repaint();
+}
+
+// Write every line received until the sink is removed, the setting goes off or the app exits
+fn write_lines(state: &Mutex<AppState>, is_running: &AtomicBool, lines: Receiver<String>, mut file: LogFile) {
+    while is_running.load(Ordering::SeqCst) && lock_state(state).log_to_file {
+        match lines.recv_timeout(POLL_INTERVAL) {
+            Ok(line) => {
+                if let Err(e) = file.write_line(&line) {
+                    give_up(state, e);
+                    return;
+                }
+            },
+            Err(RecvTimeoutError::Timeout) => {},
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+    // Whatever was logged before the sink went away still belongs in the file
+    logging::set_file_sink(None);
+    for line in lines.try_iter() {
+        if file.write_line(&line).is_err() {
+            break;
+        }
+    }
+    let _ = file.file.flush();
+}
+
+// Mirror the log to log_file_path() whenever state.log_to_file is on, until `is_running` goes
+// false
+pub fn start_log_file_writer(state: Arc<Mutex<AppState>>, is_running: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while is_running.load(Ordering::SeqCst) {
+            if !lock_state(&state).log_to_file {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            let path = log_file_path();
+            let file = match LogFile::open(&path, MAX_FILE_BYTES, KEPT_FILES) {
+                Ok(file) => file,
+                Err(e) => {
+                    give_up(&state, e);
+                    continue;
+                },
+            };
+            let (sender, lines) = mpsc::channel();
+            logging::set_file_sink(Some(sender));
+            log_info!(lock_state(&state).log, "Saving the log to {}", path.display());
+            write_lines(&state, &is_running, lines, file);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_and_keeps_only_the_newest_files() {
+        let dir = std::env::temp_dir().join(format!("ez_autoclicker_log_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("autoclicker.log");
+        let mut file = LogFile::open(&path, 20, 2).unwrap();
+        for line in ["first line", "second line", "third line", "fourth line"] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\n");
+        assert_eq!(fs::read_to_string(rotated_path(&path, 1)).unwrap(), "third line\n");
+        assert_eq!(fs::read_to_string(rotated_path(&path, 2)).unwrap(), "second line\n");
+        assert!(!rotated_path(&path, 3).exists());
+
+        // Reopening carries on from the existing size
+        let mut file = LogFile::open(&path, 20, 2).unwrap();
+        file.write_line("fifth").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth line\nfifth\n");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
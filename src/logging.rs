@@ -0,0 +1,355 @@
+// The in-app log: entries with a time, a level, a category and the module that wrote them.
+//
+// Everything that has the state at hand logs into its AppState's LogBuffer with log_info! and
+// friends, so the action thread's copy can hand its lines over with the rest of its progress.
+// Lines logged through the `log` crate's macros, which is what the libraries we use do, are
+// caught by AppLogger and drained into the window's log by the UI. The level dropdown only
+// changes what is shown; debug entries are always kept.
+//
+// The category says where an entry came from. Each thread that acts on the user's behalf tags
+// itself with set_thread_category when it starts (the window's thread is Ui by default), and
+// errors are filed under Error wherever they happen, so the category checkboxes can pick out
+// e.g. only what hotkeys did.
+//
+// Only the newest entries are kept, DEFAULT_MAX_ENTRIES unless the user changes it, so an
+// overnight run doesn't fill memory; the log area says how many were dropped.
+//
+// Setting EZ_AUTOCLICKER_LOG (error, warn, info, debug) also echoes every entry to stderr, and
+// the log file setting hands every entry to log_file's writer thread.
+
+use chrono::{DateTime, Local};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::sync::mpsc::Sender;
+use std::sync::Mutex;
+
+pub const ENV_VAR: &str = "EZ_AUTOCLICKER_LOG";
+pub const DEFAULT_MAX_ENTRIES: usize = 5_000;
+pub const MAX_ENTRIES_RANGE: RangeInclusive<usize> = 100..=100_000;
+// Library records below this are dropped; winit and friends are chatty at info
+const LIBRARY_LEVEL: Level = Level::Warn;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    Hotkey,
+    Ui,
+    Action,
+    Error,
+}
+
+impl Category {
+    pub const ALL: [Category; 4] = [Category::Hotkey, Category::Ui, Category::Action, Category::Error];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Category::Hotkey => "hotkey",
+            Category::Ui => "ui",
+            Category::Action => "action",
+            Category::Error => "error",
+        }
+    }
+}
+
+thread_local! {
+    static THREAD_CATEGORY: Cell<Category> = const { Cell::new(Category::Ui) };
+}
+
+// File everything this thread logs from now on under `category`, errors aside
+pub fn set_thread_category(category: Category) {
+    THREAD_CATEGORY.with(|current| current.set(category));
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    pub time: DateTime<Local>,
+    pub level: Level,
+    pub category: Category,
+    pub target: String,
+    pub message: String,
+}
+
+impl LogEntry {
+    // As shown in the window, e.g. "[14:32:07.123] [hotkey] Started clicking! (F6)"; other
+    // levels than info also name the module
+    pub fn line(&self) -> String {
+        let target = self.target.strip_prefix("auto_clicker::").unwrap_or(&self.target);
+        let tag = match self.level {
+            Level::Info => String::new(),
+            Level::Error => format!("[{}] ", target),
+            level => format!("[{} {}] ", level.as_str().to_lowercase(), target),
+        };
+        format!("[{}] [{}] {}{}", self.time.format("%H:%M:%S%.3f"), self.category.label(), tag, self.message)
+    }
+}
+
+// Where stderr echoes go, if anywhere; read once from ENV_VAR
+static ECHO: Mutex<Option<LevelFilter>> = Mutex::new(None);
+// The log file writer's channel while the log is being saved
+static FILE_SINK: Mutex<Option<Sender<String>>> = Mutex::new(None);
+
+// Copy `entry` to stderr and the log file, as configured
+fn mirror(entry: &LogEntry) {
+    let echo_level = ECHO.lock().ok().and_then(|echo| *echo);
+    if echo_level.is_some_and(|filter| entry.level <= filter) {
+        eprintln!("{:<5} {}: {}", entry.level, entry.target, entry.message);
+    }
+    if let Ok(sink) = FILE_SINK.lock() {
+        if let Some(sender) = sink.as_ref() {
+            let time = entry.time.format("%Y-%m-%d %H:%M:%S%.3f");
+            let category = entry.category.label();
+            let _ = sender.send(format!("{} {:<5} [{}] {}: {}", time, entry.level, category, entry.target, entry.message));
+        }
+    }
+}
+
+// Send every entry from now on to `sink`, or stop with None
+pub fn set_file_sink(sink: Option<Sender<String>>) {
+    if let Ok(mut current) = FILE_SINK.lock() {
+        *current = sink;
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LogBuffer {
+    entries: VecDeque<LogEntry>,
+    max_entries: usize,
+    // Entries dropped from the front so far
+    trimmed: u64,
+}
+
+impl Default for LogBuffer {
+    fn default() -> Self {
+        LogBuffer::new()
+    }
+}
+
+impl LogBuffer {
+    pub const fn new() -> Self {
+        LogBuffer { entries: VecDeque::new(), max_entries: DEFAULT_MAX_ENTRIES, trimmed: 0 }
+    }
+
+    pub fn record(&mut self, level: Level, target: &str, message: String) {
+        let category = if level == Level::Error { Category::Error } else { THREAD_CATEGORY.with(Cell::get) };
+        let entry = LogEntry { time: Local::now(), level, category, target: target.to_string(), message };
+        mirror(&entry);
+        self.entries.push_back(entry);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+            self.trimmed += 1;
+        }
+    }
+
+    pub fn max_entries(&self) -> usize {
+        self.max_entries
+    }
+
+    pub fn set_max_entries(&mut self, max_entries: usize) {
+        self.max_entries = max_entries.max(1);
+        self.trim();
+    }
+
+    pub fn trimmed(&self) -> u64 {
+        self.trimmed
+    }
+
+    // Text made of whole lines, each logged at info; for messages built elsewhere
+    pub fn push_str(&mut self, text: &str) {
+        for line in text.lines() {
+            self.record(Level::Info, module_path!(), line.to_string());
+        }
+    }
+
+    pub fn append(&mut self, other: LogBuffer) {
+        self.trimmed += other.trimmed;
+        self.entries.extend(other.entries);
+        self.trim();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+
+    // How many entries were ever recorded here, kept or not; a position for since()
+    pub fn total(&self) -> u64 {
+        self.trimmed + self.entries.len() as u64
+    }
+
+    // The kept entries recorded after the first `total` ones
+    pub fn since(&self, total: u64) -> impl Iterator<Item = &LogEntry> {
+        self.entries.iter().skip(total.saturating_sub(self.trimmed) as usize)
+    }
+
+    // Whether any entry's message contains `text`
+    pub fn contains(&self, text: &str) -> bool {
+        self.entries.iter().any(|entry| entry.message.contains(text))
+    }
+
+    // How many entries' messages contain `text`
+    pub fn count(&self, text: &str) -> usize {
+        self.entries.iter().filter(|entry| entry.message.contains(text)).count()
+    }
+
+    // The entries at `filter` or above, one per line
+    pub fn render(&self, filter: LevelFilter) -> String {
+        self.entries
+            .iter()
+            .filter(|entry| entry.level <= filter)
+            .map(|entry| entry.line() + "\n")
+            .collect()
+    }
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($log:expr, $($arg:tt)*) => { $log.record(::log::Level::Error, module_path!(), format!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($log:expr, $($arg:tt)*) => { $log.record(::log::Level::Warn, module_path!(), format!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($log:expr, $($arg:tt)*) => { $log.record(::log::Level::Info, module_path!(), format!($($arg)*)) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($log:expr, $($arg:tt)*) => { $log.record(::log::Level::Debug, module_path!(), format!($($arg)*)) };
+}
+
+// Records from the `log` crate, waiting for the UI to move them into the state's log
+static PENDING: Mutex<LogBuffer> = Mutex::new(LogBuffer::new());
+
+// The `log` crate's sink
+pub struct AppLogger;
+
+impl Log for AppLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= LIBRARY_LEVEL
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if let Ok(mut pending) = PENDING.lock() {
+            pending.record(record.level(), record.target(), record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: AppLogger = AppLogger;
+
+// Install AppLogger and read ENV_VAR; once, at startup
+pub fn init() {
+    let echo_level = std::env::var(ENV_VAR).ok().and_then(|level| level.parse::<LevelFilter>().ok());
+    if let Ok(mut echo) = ECHO.lock() {
+        *echo = echo_level;
+    }
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LIBRARY_LEVEL.to_level_filter());
+    }
+}
+
+// Library records logged since the last call
+pub fn take_pending() -> LogBuffer {
+    PENDING.lock().map(|mut pending| std::mem::take(&mut *pending)).unwrap_or_default()
+}
+
+// The levels the log can be filtered to
+pub const FILTERS: [LevelFilter; 4] = [LevelFilter::Error, LevelFilter::Warn, LevelFilter::Info, LevelFilter::Debug];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rendered lines without their "[14:32:07.123] " timestamps
+    fn untimed(text: &str) -> String {
+        text.lines().map(|line| format!("{}\n", &line["[00:00:00.000] ".len()..])).collect()
+    }
+
+    #[test]
+    fn filters_by_level_and_tags_non_info_lines() {
+        let mut log = LogBuffer::default();
+        log_info!(log, "Started clicking! ({})", "F6");
+        log_debug!(log, "Next action in {} ms", 100);
+        log_warn!(log, "Careful");
+        log.push_str("Two\nlines\n");
+        assert_eq!(log.len(), 5);
+        assert_eq!(
+            untimed(&log.render(LevelFilter::Info)),
+            "[ui] Started clicking! (F6)\n[ui] [warn logging::tests] Careful\n[ui] Two\n[ui] lines\n",
+        );
+        assert_eq!(untimed(&log.render(LevelFilter::Warn)), "[ui] [warn logging::tests] Careful\n");
+        assert!(log.render(LevelFilter::Debug).contains("[ui] [debug logging::tests] Next action in 100 ms"));
+        assert!(log.contains("Next action"));
+        assert_eq!(log.count("lines"), 1);
+    }
+
+    #[test]
+    fn keeps_only_the_newest_entries() {
+        let mut log = LogBuffer::default();
+        log.set_max_entries(3);
+        for i in 0..5 {
+            log_info!(log, "line {}", i);
+        }
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.trimmed(), 2);
+        assert_eq!(untimed(&log.render(LevelFilter::Info)), "[ui] line 2\n[ui] line 3\n[ui] line 4\n");
+
+        let position = log.total();
+        let mut copy = LogBuffer::default();
+        log_info!(copy, "from the action thread");
+        log.append(copy);
+        assert_eq!(log.trimmed(), 3);
+        let new: Vec<_> = log.since(position).map(|entry| entry.message.as_str()).collect();
+        assert_eq!(new, ["from the action thread"]);
+        // Positions from before a trim only see what is left
+        assert_eq!(log.since(0).count(), 3);
+    }
+
+    #[test]
+    fn entries_are_filed_under_their_thread_and_errors_apart() {
+        let log = std::thread::spawn(|| {
+            set_thread_category(Category::Hotkey);
+            let mut log = LogBuffer::default();
+            log_info!(log, "Started clicking! (F6)");
+            log_error!(log, "Hotkey listener failed");
+            log
+        })
+        .join()
+        .unwrap();
+        let categories: Vec<_> = log.iter().map(|entry| entry.category).collect();
+        assert_eq!(categories, [Category::Hotkey, Category::Error]);
+        assert_eq!(untimed(&log.render(LevelFilter::Info)), "[hotkey] Started clicking! (F6)\n[error] [logging::tests] Hotkey listener failed\n");
+        let line = log.iter().next().unwrap().line();
+        assert!(line.starts_with('[') && line.as_bytes()[13] == b']', "{}", line);
+    }
+
+    #[test]
+    fn library_records_wait_for_the_ui() {
+        AppLogger.log(&Record::builder().level(Level::Warn).target("winit").args(format_args!("surface lost")).build());
+        AppLogger.log(&Record::builder().level(Level::Info).target("winit").args(format_args!("resized")).build());
+        let pending = take_pending();
+        assert!(pending.iter().any(|entry| entry.target == "winit" && entry.message == "surface lost"));
+        assert!(!pending.contains("resized"));
+    }
+}
@@ -0,0 +1,429 @@
+// Tiny macro language for the script box:
+//
+//   hold(w, 500); click; wait(200); repeat(3) { press(space); wait(50) }
+//
+// Statements are separated by ';' (optional after a closing '}') and run back-to-back;
+// only wait and hold take time. Scripts compile to a flat list of ops where repeat
+// blocks become jump markers, so large repeat counts cost nothing up front.
+
+use crate::keys::{is_named_key, single_char, ClickButton};
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction {
+    Press(String),
+    Hold(String, u64),
+    Click(ClickButton),
+    Wait(u64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Op {
+    Run(Instruction),
+    // Start of a repeat block running its body `count` times
+    RepeatStart { count: u32 },
+    // End of a repeat block; `start` is the index of the matching RepeatStart
+    RepeatEnd { start: usize },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Program {
+    ops: Vec<Op>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MacroError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for MacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TokenKind {
+    Word(String),
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    Comma,
+    Semicolon,
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    kind: TokenKind,
+    line: usize,
+    column: usize,
+}
+
+fn describe(kind: &TokenKind) -> String {
+    match kind {
+        TokenKind::Word(word) => format!("'{}'", word),
+        TokenKind::LParen => "'('".to_string(),
+        TokenKind::RParen => "')'".to_string(),
+        TokenKind::LBrace => "'{'".to_string(),
+        TokenKind::RBrace => "'}'".to_string(),
+        TokenKind::Comma => "','".to_string(),
+        TokenKind::Semicolon => "';'".to_string(),
+    }
+}
+
+// Split the script into punctuation and words; a word is any run of other non-space characters
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+    let mut word_start = (1, 1);
+    let (mut line, mut column) = (1, 1);
+
+    for c in source.chars() {
+        let punctuation = match c {
+            '(' => Some(TokenKind::LParen),
+            ')' => Some(TokenKind::RParen),
+            '{' => Some(TokenKind::LBrace),
+            '}' => Some(TokenKind::RBrace),
+            ',' => Some(TokenKind::Comma),
+            ';' => Some(TokenKind::Semicolon),
+            _ => None,
+        };
+
+        if punctuation.is_some() || c.is_whitespace() {
+            if !word.is_empty() {
+                tokens.push(Token {
+                    kind: TokenKind::Word(std::mem::take(&mut word)),
+                    line: word_start.0,
+                    column: word_start.1,
+                });
+            }
+            if let Some(kind) = punctuation {
+                tokens.push(Token { kind, line, column });
+            }
+        } else {
+            if word.is_empty() {
+                word_start = (line, column);
+            }
+            word.push(c);
+        }
+
+        if c == '\n' {
+            line += 1;
+            column = 1;
+        } else {
+            column += 1;
+        }
+    }
+
+    if !word.is_empty() {
+        tokens.push(Token {
+            kind: TokenKind::Word(word),
+            line: word_start.0,
+            column: word_start.1,
+        });
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // Position reported for errors at the end of the script
+    end: (usize, usize),
+    ops: Vec<Op>,
+}
+
+impl Parser {
+    fn error_at(&self, token: Option<&Token>, message: String) -> MacroError {
+        let (line, column) = token.map_or(self.end, |t| (t.line, t.column));
+        MacroError { line, column, message }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), MacroError> {
+        match self.next() {
+            Some(token) if token.kind == kind => Ok(()),
+            Some(token) => Err(self.error_at(
+                Some(&token),
+                format!("expected {}, found {}", describe(&kind), describe(&token.kind)),
+            )),
+            None => Err(self.error_at(None, format!("expected {}, found end of script", describe(&kind)))),
+        }
+    }
+
+    fn expect_word(&mut self, what: &str) -> Result<(String, Token), MacroError> {
+        match self.next() {
+            Some(token) => match &token.kind {
+                TokenKind::Word(word) => Ok((word.clone(), token)),
+                other => Err(self.error_at(Some(&token), format!("expected {}, found {}", what, describe(other)))),
+            },
+            None => Err(self.error_at(None, format!("expected {}, found end of script", what))),
+        }
+    }
+
+    fn expect_number(&mut self, what: &str) -> Result<u64, MacroError> {
+        let (word, token) = self.expect_word(what)?;
+        word.parse::<u64>()
+            .map_err(|_| self.error_at(Some(&token), format!("expected {}, found '{}'", what, word)))
+    }
+
+    // A key name the backends can type: one character or a named key like space or f5, so a
+    // typo is caught here rather than when the macro reaches it
+    fn expect_key(&mut self) -> Result<String, MacroError> {
+        let (key, token) = self.expect_word("a key name")?;
+        if single_char(&key).is_none() && !is_named_key(&key) {
+            return Err(self.error_at(Some(&token), format!("unknown key '{}'", key)));
+        }
+        Ok(key)
+    }
+
+    // Statements until the end of the script or a closing brace
+    fn parse_block(&mut self, nested: bool) -> Result<(), MacroError> {
+        let start_len = self.ops.len();
+        loop {
+            match self.peek().map(|t| t.kind.clone()) {
+                None => {
+                    if nested {
+                        return Err(self.error_at(None, "missing '}' to close repeat block".to_string()));
+                    }
+                    break;
+                },
+                Some(TokenKind::RBrace) if nested => break,
+                Some(TokenKind::Semicolon) => {
+                    self.pos += 1;
+                    continue;
+                },
+                _ => {},
+            }
+
+            let ended_with_block = self.parse_statement()?;
+
+            // Statements need a ';' between them, except straight after a block
+            match self.peek().map(|t| t.kind.clone()) {
+                Some(TokenKind::Semicolon) => self.pos += 1,
+                Some(TokenKind::RBrace) if nested => {},
+                None => {},
+                Some(_) if ended_with_block => {},
+                Some(other) => {
+                    let token = self.peek().cloned();
+                    return Err(self.error_at(token.as_ref(), format!("expected ';' before {}", describe(&other))));
+                },
+            }
+        }
+
+        // Every block must contain something that takes time or touches input, or a
+        // repeat of it would spin forever without yielding
+        if nested && self.ops.len() == start_len {
+            let token = self.peek().cloned();
+            return Err(self.error_at(token.as_ref(), "repeat block is empty".to_string()));
+        }
+        Ok(())
+    }
+
+    // Returns true when the statement ended with a '}' block
+    fn parse_statement(&mut self) -> Result<bool, MacroError> {
+        let (name, token) = self.expect_word("a statement")?;
+        match name.to_lowercase().as_str() {
+            "press" => {
+                self.expect(TokenKind::LParen)?;
+                let key = self.expect_key()?;
+                self.expect(TokenKind::RParen)?;
+                self.ops.push(Op::Run(Instruction::Press(key)));
+            },
+            "hold" => {
+                self.expect(TokenKind::LParen)?;
+                let key = self.expect_key()?;
+                self.expect(TokenKind::Comma)?;
+                let ms = self.expect_number("a duration in ms")?;
+                self.expect(TokenKind::RParen)?;
+                self.ops.push(Op::Run(Instruction::Hold(key, ms)));
+            },
+            "click" => {
+                // Bare "click" is a left click; "click(right)" picks the button
+                let mut button = ClickButton::Left;
+                if matches!(self.peek().map(|t| &t.kind), Some(TokenKind::LParen)) {
+                    self.pos += 1;
+                    let (name, button_token) = self.expect_word("a mouse button")?;
+                    button = match name.to_lowercase().as_str() {
+                        "left" | "l" => ClickButton::Left,
+                        "right" | "r" => ClickButton::Right,
+                        "middle" | "m" => ClickButton::Middle,
+                        _ => {
+                            return Err(self.error_at(
+                                Some(&button_token),
+                                format!("unknown mouse button '{}' (use left, right or middle)", name),
+                            ))
+                        },
+                    };
+                    self.expect(TokenKind::RParen)?;
+                }
+                self.ops.push(Op::Run(Instruction::Click(button)));
+            },
+            "wait" => {
+                self.expect(TokenKind::LParen)?;
+                let ms = self.expect_number("a duration in ms")?;
+                self.expect(TokenKind::RParen)?;
+                self.ops.push(Op::Run(Instruction::Wait(ms)));
+            },
+            "repeat" => {
+                self.expect(TokenKind::LParen)?;
+                let count_token = self.peek().cloned();
+                let count = self.expect_number("a repeat count")?;
+                let count = u32::try_from(count)
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| self.error_at(count_token.as_ref(), "repeat count must be between 1 and 4294967295".to_string()))?;
+                self.expect(TokenKind::RParen)?;
+                self.expect(TokenKind::LBrace)?;
+
+                let start = self.ops.len();
+                self.ops.push(Op::RepeatStart { count });
+                self.parse_block(true)?;
+                self.expect(TokenKind::RBrace)?;
+                self.ops.push(Op::RepeatEnd { start });
+                return Ok(true);
+            },
+            _ => {
+                return Err(self.error_at(
+                    Some(&token),
+                    format!("unknown statement '{}' (expected press, hold, click, wait or repeat)", name),
+                ))
+            },
+        }
+        Ok(false)
+    }
+}
+
+// Parse a script into a runnable program, reporting the first error with its position
+pub fn parse(source: &str) -> Result<Program, MacroError> {
+    let line_count = source.lines().count().max(1);
+    let last_line_len = source.lines().last().map_or(0, |l| l.chars().count());
+    let mut parser = Parser {
+        tokens: tokenize(source),
+        pos: 0,
+        end: (line_count, last_line_len + 1),
+        ops: Vec::new(),
+    };
+    parser.parse_block(false)?;
+    if let Some(token) = parser.peek().cloned() {
+        return Err(parser.error_at(Some(&token), format!("unexpected {}", describe(&token.kind))));
+    }
+    if parser.ops.is_empty() {
+        return Err(parser.error_at(None, "script is empty".to_string()));
+    }
+    Ok(Program { ops: parser.ops })
+}
+
+// Walks a program one instruction at a time, keeping loop counters between calls
+#[derive(Clone, Debug)]
+pub struct MacroRunner {
+    program: Program,
+    pc: usize,
+    // Remaining iterations for each open repeat block, innermost last
+    counters: Vec<u32>,
+}
+
+impl MacroRunner {
+    pub fn new(program: Program) -> Self {
+        Self { program, pc: 0, counters: Vec::new() }
+    }
+
+    // The next instruction to execute, or None once the program has finished
+    pub fn next_instruction(&mut self) -> Option<Instruction> {
+        while let Some(op) = self.program.ops.get(self.pc) {
+            match op {
+                Op::Run(instruction) => {
+                    let instruction = instruction.clone();
+                    self.pc += 1;
+                    return Some(instruction);
+                },
+                Op::RepeatStart { count } => {
+                    self.counters.push(*count);
+                    self.pc += 1;
+                },
+                Op::RepeatEnd { start } => {
+                    let remaining = self.counters.last_mut().map(|n| {
+                        *n -= 1;
+                        *n
+                    });
+                    if remaining.unwrap_or(0) > 0 {
+                        self.pc = start + 1;
+                    } else {
+                        self.counters.pop();
+                        self.pc += 1;
+                    }
+                },
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every instruction the program runs, repeats unrolled
+    fn run(source: &str) -> Vec<Instruction> {
+        let mut runner = MacroRunner::new(parse(source).unwrap());
+        std::iter::from_fn(|| runner.next_instruction()).collect()
+    }
+
+    fn error(source: &str) -> (usize, usize, String) {
+        let error = parse(source).unwrap_err();
+        (error.line, error.column, error.message)
+    }
+
+    #[test]
+    fn runs_statements_in_order() {
+        assert_eq!(
+            run("hold(w, 500); click; click(right); wait(200); press(Space)"),
+            vec![
+                Instruction::Hold("w".to_string(), 500),
+                Instruction::Click(ClickButton::Left),
+                Instruction::Click(ClickButton::Right),
+                Instruction::Wait(200),
+                Instruction::Press("Space".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn repeats_nested_blocks() {
+        let ops = run("repeat(2) { press(a); repeat(3) { click } wait(10) } press(b)");
+        let a = Instruction::Press("a".to_string());
+        let click = Instruction::Click(ClickButton::Left);
+        let wait = Instruction::Wait(10);
+        let mut expected = Vec::new();
+        for _ in 0..2 {
+            expected.extend([a.clone(), click.clone(), click.clone(), click.clone(), wait.clone()]);
+        }
+        expected.push(Instruction::Press("b".to_string()));
+        assert_eq!(ops, expected);
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        assert_eq!(error("click;\n  jump(3)"), (2, 3, "unknown statement 'jump' (expected press, hold, click, wait or repeat)".to_string()));
+        assert_eq!(error("press(spcae)"), (1, 7, "unknown key 'spcae'".to_string()));
+        assert_eq!(error("hold(ctrl, 5s)"), (1, 12, "expected a duration in ms, found '5s'".to_string()));
+        assert_eq!(error("click wait(5)"), (1, 7, "expected ';' before 'wait'".to_string()));
+        assert_eq!(error("repeat(0) { click }"), (1, 8, "repeat count must be between 1 and 4294967295".to_string()));
+        assert_eq!(error("repeat(2) { }"), (1, 13, "repeat block is empty".to_string()));
+        assert_eq!(error("repeat(2) { click"), (1, 18, "missing '}' to close repeat block".to_string()));
+        assert_eq!(error("click; }"), (1, 8, "expected a statement, found '}'".to_string()));
+        assert_eq!(error("  "), (1, 3, "script is empty".to_string()));
+    }
+}
@@ -0,0 +1,153 @@
+// The macOS privacy permissions hotkeys and injection need.
+//
+// macOS starts the app without complaint but hands it no input events (Input Monitoring) and
+// drops everything it injects (Accessibility) until the user allows it in System Settings.
+// Neither is reported as an error anywhere, so the permissions are checked directly, at startup
+// and then every few seconds until both are granted. Elsewhere nothing is ever missing.
+
+use crate::log_info;
+use crate::state::{lock_state, AppState};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+// How often missing permissions are checked again
+pub const RECHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Permission {
+    // Needed to inject input
+    Accessibility,
+    // Needed to see the user's keys and buttons, i.e. for hotkeys
+    InputMonitoring,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 2] = [Permission::Accessibility, Permission::InputMonitoring];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Permission::Accessibility => "Accessibility",
+            Permission::InputMonitoring => "Input Monitoring",
+        }
+    }
+
+    pub fn needed_for(self) -> &'static str {
+        match self {
+            Permission::Accessibility => "clicking and typing",
+            Permission::InputMonitoring => "hotkeys",
+        }
+    }
+
+    // The System Settings pane to grant it in
+    pub fn settings_url(self) -> &'static str {
+        match self {
+            Permission::Accessibility => "x-apple.systempreferences:com.apple.preference.security?Privacy_Accessibility",
+            Permission::InputMonitoring => "x-apple.systempreferences:com.apple.preference.security?Privacy_ListenEvent",
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "ApplicationServices", kind = "framework")]
+extern "C" {
+    fn AXIsProcessTrusted() -> u8;
+}
+
+#[cfg(target_os = "macos")]
+#[link(name = "IOKit", kind = "framework")]
+extern "C" {
+    fn IOHIDCheckAccess(request_type: u32) -> u32;
+}
+
+#[cfg(target_os = "macos")]
+fn is_granted(permission: Permission) -> bool {
+    const LISTEN_EVENT: u32 = 1;
+    const ACCESS_GRANTED: u32 = 0;
+    // SAFETY: both are plain queries without arguments to get wrong; neither prompts the user
+    unsafe {
+        match permission {
+            Permission::Accessibility => AXIsProcessTrusted() != 0,
+            Permission::InputMonitoring => IOHIDCheckAccess(LISTEN_EVENT) == ACCESS_GRANTED,
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+fn is_granted(_permission: Permission) -> bool {
+    true
+}
+
+pub fn missing() -> Vec<Permission> {
+    Permission::ALL.into_iter().filter(|&permission| !is_granted(permission)).collect()
+}
+
+// Open the pane `permission` is granted in
+pub fn open_settings(permission: Permission) -> Result<(), String> {
+    std::process::Command::new("open")
+        .arg(permission.settings_url())
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("couldn't open System Settings: {}", e))
+}
+
+// An injection error as the log should show it: a generic failure while Accessibility is
+// missing is really the missing permission
+pub fn explain_injection_error(error: String, missing: &[Permission]) -> String {
+    if missing.contains(&Permission::Accessibility) {
+        format!("missing Accessibility permission (System Settings → Privacy & Security → Accessibility): {}", error)
+    } else {
+        error
+    }
+}
+
+// Keep state.missing_permissions up to date until nothing is missing, asking the UI to explain
+// on the first check if something is
+pub fn start_permission_watch(state: Arc<Mutex<AppState>>, is_running: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let mut first = true;
+        while is_running.load(Ordering::SeqCst) {
+            let now_missing = missing();
+            {
+                let mut state = lock_state(&state);
+                if first && !now_missing.is_empty() {
+                    state.permission_dialog_pending = true;
+                }
+                let granted: Vec<Permission> = state.missing_permissions.iter().copied().filter(|p| !now_missing.contains(p)).collect();
+                for permission in granted {
+                    log_info!(state.log, "{} permission granted", permission.label());
+                }
+                if state.missing_permissions != now_missing {
+                    state.missing_permissions = now_missing.clone();
+                    state.send_update();
+                    state.request_repaint();
+                }
+            }
+            if now_missing.is_empty() {
+                return;
+            }
+            first = false;
+            thread::sleep(RECHECK_INTERVAL);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blames_injection_failures_on_a_missing_permission() {
+        let explained = explain_injection_error("event not posted".to_string(), &[Permission::Accessibility]);
+        assert!(explained.starts_with("missing Accessibility permission"));
+        assert!(explained.ends_with("event not posted"));
+        assert_eq!(explain_injection_error("event not posted".to_string(), &[Permission::InputMonitoring]), "event not posted");
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn nothing_is_missing_elsewhere() {
+        assert!(missing().is_empty());
+    }
+}
@@ -0,0 +1,48 @@
+// Built-in sequence templates offered in the presets dropdown.
+// Adding a preset is just another entry in PRESETS.
+
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub keys: &'static str,
+    pub interval_ms: u64,
+    pub hold_mode: bool,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "WASD strafe",
+        description: "Holds A then D in turn to strafe left and right.",
+        keys: "a, d",
+        interval_ms: 400,
+        hold_mode: true,
+    },
+    Preset {
+        name: "AFK fishing",
+        description: "Right-click to cast, wait for a bite, right-click to reel in.",
+        keys: "rclick, wait(15000), rclick",
+        interval_ms: 1000,
+        hold_mode: false,
+    },
+    Preset {
+        name: "Form tab-fill",
+        description: "Tabs to the next field and ticks it with space, then submits with enter.",
+        keys: "tab, space, tab, space, enter",
+        interval_ms: 200,
+        hold_mode: false,
+    },
+    Preset {
+        name: "Anti-AFK jump",
+        description: "Taps space every 30 seconds to avoid idle kicks.",
+        keys: "space",
+        interval_ms: 30_000,
+        hold_mode: false,
+    },
+    Preset {
+        name: "Auto-walk forward",
+        description: "Keeps W held down, re-pressing it every 5 seconds.",
+        keys: "w",
+        interval_ms: 5000,
+        hold_mode: true,
+    },
+];
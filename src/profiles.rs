@@ -0,0 +1,174 @@
+// User-saved settings snapshots, cycled with the profile hotkey.
+//
+// Each profile is its own TOML file in the profiles folder of the config directory, so one can
+// be copied to another machine or fixed by hand without touching the rest. The file name only
+// has to be unique; the profile's name is the one inside it.
+
+use crate::config;
+use crate::hotkeys::HotkeyBindings;
+use crate::state::AppState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub click_interval_us: u64,
+    pub key_interval_us: u64,
+    pub keys: String,
+    pub hold_mode: bool,
+    pub hold_range: bool,
+    pub hold_min_ms: u64,
+    pub hold_max_ms: u64,
+    // Bindings that replace the current ones when this profile is applied; None keeps them
+    pub hotkeys: Option<HotkeyBindings>,
+}
+
+// Fields missing from a profile saved by an older version keep the app's defaults
+impl Default for Profile {
+    fn default() -> Self {
+        AppState::default().current_profile("", false)
+    }
+}
+
+pub fn profiles_dir() -> PathBuf {
+    config::config_dir().join("profiles")
+}
+
+// The file name a profile gets in `dir`: its name with anything a file system might object to
+// replaced, e.g. "CS: aim" → "CS_ aim.toml", and a number added when that's taken by another
+// profile, so "CS? aim" next to it becomes "CS_ aim (2).toml"
+pub fn profile_path(dir: &Path, name: &str) -> PathBuf {
+    let stem: String = name
+        .trim()
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, ' ' | '-' | '_') { c } else { '_' })
+        .collect();
+    let mut path = dir.join(format!("{}.toml", stem));
+    let mut n = 1;
+    while path.exists() {
+        n += 1;
+        path = dir.join(format!("{} ({}).toml", stem, n));
+    }
+    path
+}
+
+fn read_profile_file(path: &Path) -> Result<Profile, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    toml::from_str(&text).map_err(|e| e.message().to_string())
+}
+
+// The file in `dir` holding the profile called `name`, whatever the file is called
+fn find_profile_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().and_then(|ext| ext.to_str()) == Some("toml"))
+        .find(|path| read_profile_file(path).is_ok_and(|profile| profile.name == name))
+}
+
+// Save over the profile's own file, or into a new one
+pub fn save_profile_file(dir: &Path, profile: &Profile) -> Result<PathBuf, String> {
+    let path = find_profile_file(dir, &profile.name).unwrap_or_else(|| profile_path(dir, &profile.name));
+    let text = toml::to_string(profile).map_err(|e| e.to_string())?;
+    fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?;
+    fs::write(&path, text).map_err(|e| format!("couldn't write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+// Remove the profile's file; one that's already gone is fine
+pub fn delete_profile_file(dir: &Path, name: &str) -> Result<(), String> {
+    let Some(path) = find_profile_file(dir, name) else {
+        return Ok(());
+    };
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("couldn't delete {}: {}", path.display(), e)),
+        _ => Ok(()),
+    }
+}
+
+// Every profile in `dir` sorted by name, and why each file that couldn't be used was skipped.
+// No folder yet means no profiles
+pub fn load_profile_files(dir: &Path) -> (Vec<Profile>, Vec<String>) {
+    let mut profiles: Vec<Profile> = Vec::new();
+    let mut errors = Vec::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return (profiles, errors);
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().and_then(|ext| ext.to_str()) != Some("toml") {
+            continue;
+        }
+        match read_profile_file(&path) {
+            Ok(profile) if profile.name.trim().is_empty() => errors.push(format!("{}: the profile has no name", path.display())),
+            Ok(profile) if profiles.iter().any(|p| p.name == profile.name) => {
+                errors.push(format!("{}: another file already has a profile called '{}'", path.display(), profile.name));
+            },
+            Ok(profile) => profiles.push(profile),
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    errors.sort();
+    (profiles, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_round_trip_through_their_files() {
+        let dir = std::env::temp_dir().join(format!("ez_autoclicker_profiles_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(load_profile_files(&dir), (vec![], vec![]));
+
+        let mut state = AppState { hold_mode: true, ..AppState::default() };
+        state.key_to_inject = "a, b".to_string();
+        let game = state.current_profile("CS: aim", true);
+        let farm = AppState::default().current_profile("Farm", false);
+        assert_eq!(save_profile_file(&dir, &game).unwrap(), dir.join("CS_ aim.toml"));
+        save_profile_file(&dir, &farm).unwrap();
+        fs::write(dir.join("broken.toml"), "name = ").unwrap();
+        fs::write(dir.join("notes.txt"), "not a profile").unwrap();
+
+        let (profiles, errors) = load_profile_files(&dir);
+        assert_eq!(profiles, vec![game.clone(), farm.clone()]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken.toml"));
+
+        delete_profile_file(&dir, "CS: aim").unwrap();
+        delete_profile_file(&dir, "CS: aim").unwrap();
+        assert_eq!(load_profile_files(&dir).0, vec![farm]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn names_that_make_the_same_file_name_get_their_own_files() {
+        let dir = std::env::temp_dir().join(format!("ez_autoclicker_profiles_clash_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let colon = AppState::default().current_profile("CS: aim", false);
+        let question = AppState { hold_mode: true, ..AppState::default() }.current_profile("CS? aim", false);
+        assert_eq!(save_profile_file(&dir, &colon).unwrap(), dir.join("CS_ aim.toml"));
+        assert_eq!(save_profile_file(&dir, &question).unwrap(), dir.join("CS_ aim (2).toml"));
+        // Saving again goes back into the same file
+        assert_eq!(save_profile_file(&dir, &colon).unwrap(), dir.join("CS_ aim.toml"));
+        assert_eq!(load_profile_files(&dir), (vec![colon.clone(), question], vec![]));
+
+        delete_profile_file(&dir, "CS? aim").unwrap();
+        assert_eq!(load_profile_files(&dir).0, vec![colon]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn older_profiles_get_defaults_for_new_fields() {
+        let profile: Profile = toml::from_str("name = \"Old\"\nkeys = \"q\"\nhold_mode = true\n").unwrap();
+        assert_eq!(profile.name, "Old");
+        assert_eq!(profile.click_interval_us, AppState::default().click_interval.us);
+        assert_eq!(profile.hold_min_ms, AppState::default().hold_min_ms);
+        assert!(profile.hotkeys.is_none());
+    }
+}
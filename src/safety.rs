@@ -0,0 +1,181 @@
+// Letting go of held keys and buttons when something panics or the app closes.
+//
+// The action thread notes what it holds down here, outside its own stack, so the panic hook
+// can release it through a fresh backend before the panic unwinds or aborts anything, and
+// shutdown can release whatever the thread didn't get to before the process exits. Shutdown, with
+// or without the window, waits for the threads here too.
+
+use crate::backend::{BackendKind, InputBackend, SelectedBackend};
+use crate::keys::{ActionType, ClickButton};
+use crate::state::{lock_state, AppState};
+use crate::{log_info, log_warn};
+use std::panic;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+// What is currently held down. There is one for the whole process; tests make their own
+pub struct HeldInputs(Mutex<Vec<ActionType>>);
+
+impl Default for HeldInputs {
+    fn default() -> Self {
+        HeldInputs::new()
+    }
+}
+
+impl HeldInputs {
+    pub const fn new() -> Self {
+        HeldInputs(Mutex::new(Vec::new()))
+    }
+
+    pub fn note(&self, down: &[ActionType]) {
+        let mut held = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        held.clear();
+        held.extend_from_slice(down);
+    }
+
+    pub fn take(&self) -> Vec<ActionType> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    // Release what is still noted as held, through a backend made only if there is any; returns
+    // how many inputs were let go
+    pub fn release_leftovers<B, F>(&self, make_backend: F) -> usize
+    where
+        B: InputBackend,
+        F: FnOnce() -> Result<B, String>,
+    {
+        let held = self.take();
+        if held.is_empty() {
+            return 0;
+        }
+        if let Ok(mut backend) = make_backend() {
+            release_all(&mut backend, &held);
+        }
+        held.len()
+    }
+}
+
+static HELD: HeldInputs = HeldInputs::new();
+// The backend the action thread injects through, so the panic hook releases through the same one
+static BACKEND: Mutex<Option<BackendKind>> = Mutex::new(None);
+
+// Called by the action thread whenever what it holds down changes
+pub fn note_held(down: &[ActionType]) {
+    HELD.note(down);
+}
+
+// Called by the action thread whenever the backend it injects through changes
+pub fn note_backend(kind: Option<BackendKind>) {
+    *BACKEND.lock().unwrap_or_else(|e| e.into_inner()) = kind;
+}
+
+// The last step of shutdown: the action thread normally releases everything itself on its way
+// out, but if it couldn't be joined in time this makes sure nothing stays down after exit
+pub fn release_leftovers<B, F>(make_backend: F) -> usize
+where
+    B: InputBackend,
+    F: FnOnce() -> Result<B, String>,
+{
+    HELD.release_leftovers(make_backend)
+}
+
+// Release `held` plus every mouse button. Failures are ignored; something already went wrong
+pub fn release_all(backend: &mut dyn InputBackend, held: &[ActionType]) {
+    for action in held {
+        let _ = match action {
+            ActionType::Click(button) => backend.mouse_up(*button),
+            ActionType::KeyPress(key) => backend.key_up(key),
+        };
+    }
+    for button in ClickButton::ALL {
+        if !held.contains(&ActionType::Click(button)) {
+            let _ = backend.mouse_up(button);
+        }
+    }
+}
+
+// Release whatever the action thread holds, then report the panic as usual. Installed once at
+// startup; covers panics on every thread. Before the action thread has a backend it hasn't
+// pressed anything either
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let held = HELD.take();
+        let kind = *BACKEND.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(Ok(mut backend)) = kind.map(SelectedBackend::new) {
+            release_all(&mut backend, &held);
+        }
+        default_hook(info);
+    }));
+}
+
+// Wait for a background thread to finish, giving up after `timeout`. rdev's listen has no way to
+// be interrupted, so the hotkey thread usually outlives this and ends with the process instead.
+pub fn join_with_timeout(state: &Mutex<AppState>, name: &str, handle: thread::JoinHandle<()>, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    while !handle.is_finished() && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    if !handle.is_finished() {
+        log_warn!(lock_state(state).log, "Shutdown: {} thread still running after {:?}, leaving it to the process exit", name, timeout);
+        return;
+    }
+    match handle.join() {
+        Ok(()) => log_info!(lock_state(state).log, "Shutdown: {} thread stopped", name),
+        Err(_) => log_warn!(lock_state(state).log, "Shutdown: {} thread panicked", name),
+    }
+}
+
+// The message a panic was raised with, for the log
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, MockCall};
+
+    #[test]
+    fn releases_held_inputs_and_every_button() {
+        let mut backend = MockBackend::default();
+        release_all(&mut backend, &[ActionType::KeyPress("w".to_string()), ActionType::Click(ClickButton::Right)]);
+        assert_eq!(
+            backend.calls(),
+            [
+                MockCall::KeyUp("w".to_string()),
+                MockCall::MouseUp(ClickButton::Right),
+                MockCall::MouseUp(ClickButton::Left),
+                MockCall::MouseUp(ClickButton::Middle),
+            ],
+        );
+    }
+
+    #[test]
+    fn releases_leftovers_once() {
+        let held = HeldInputs::new();
+        let backend = MockBackend::default();
+        assert_eq!(held.release_leftovers(|| Ok(backend.clone())), 0);
+        assert!(backend.calls().is_empty());
+
+        held.note(&[ActionType::KeyPress("w".to_string())]);
+        assert_eq!(held.release_leftovers(|| Ok(backend.clone())), 1);
+        assert_eq!(backend.calls()[0], MockCall::KeyUp("w".to_string()));
+        let calls = backend.calls().len();
+        assert_eq!(held.release_leftovers(|| Ok(backend.clone())), 0);
+        assert_eq!(backend.calls().len(), calls);
+    }
+
+    #[test]
+    fn reads_panic_messages() {
+        let payload = panic::catch_unwind(|| panic!("index {} out of bounds", 3)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "index 3 out of bounds");
+        let payload = panic::catch_unwind(|| panic!("plain")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "plain");
+    }
+}
@@ -0,0 +1,178 @@
+// Starting at a wall-clock time, once or on chosen days of the week.
+//
+// Clock times are compared against the local wall clock rather than converted to an Instant
+// up front, so a schedule armed before the machine sleeps (or before a DST change) still fires
+// at the time the user typed.
+
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime, TimeZone};
+use serde::{Deserialize, Serialize};
+
+pub const WEEKDAY_NAMES: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+// Run for `run_minutes` from `start` on every checked day
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Recurrence {
+    pub enabled: bool,
+    // Monday first
+    pub days: [bool; 7],
+    pub start: NaiveTime,
+    pub run_minutes: u64,
+}
+
+impl Default for Recurrence {
+    fn default() -> Self {
+        Recurrence {
+            enabled: false,
+            days: [true, true, true, true, true, false, false],
+            start: NaiveTime::from_hms_opt(9, 0, 0).unwrap_or_default(),
+            run_minutes: 5,
+        }
+    }
+}
+
+impl Recurrence {
+    fn run_length(&self) -> chrono::Duration {
+        chrono::Duration::minutes(self.run_minutes as i64)
+    }
+
+    // The occurrence on `date`'s day, if that day is checked
+    fn occurrence_on(&self, date: chrono::NaiveDate) -> Option<NaiveDateTime> {
+        let weekday = date.weekday().num_days_from_monday() as usize;
+        self.days[weekday].then(|| date.and_time(self.start))
+    }
+
+    // Start of the occurrence running at `now`, if any. Yesterday's is checked too, since a
+    // run can go past midnight
+    pub fn current(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        let today = now.date();
+        [today.pred_opt(), Some(today)]
+            .into_iter()
+            .flatten()
+            .filter_map(|date| self.occurrence_on(date))
+            .find(|&start| start <= now && now < start + self.run_length())
+    }
+
+    // Start of the first occurrence after `now`; None when no day is checked
+    pub fn next(&self, now: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=7)
+            .filter_map(|offset| now.date().checked_add_days(chrono::Days::new(offset)))
+            .filter_map(|date| self.occurrence_on(date))
+            .find(|&start| start > now)
+    }
+}
+
+// "06:00" or "6:00"; seconds aren't offered since the UI only shows minutes
+pub fn parse_clock_time(text: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(text.trim(), "%H:%M")
+        .map_err(|_| format!("'{}' isn't a time like 06:00 or 18:30", text.trim()))
+}
+
+// The next time the clock shows `time`: later today, or tomorrow if that has passed
+pub fn next_occurrence(now: NaiveDateTime, time: NaiveTime) -> NaiveDateTime {
+    let today = now.date().and_time(time);
+    if today > now {
+        today
+    } else {
+        today + chrono::Duration::days(1)
+    }
+}
+
+// next_occurrence() in local time. A time skipped by a DST change runs at the first moment
+// after the gap instead
+pub fn next_local(now: DateTime<Local>, time: NaiveTime) -> DateTime<Local> {
+    let mut naive = next_occurrence(now.naive_local(), time);
+    loop {
+        if let Some(at) = Local.from_local_datetime(&naive).earliest() {
+            return at;
+        }
+        naive += chrono::Duration::minutes(1);
+    }
+}
+
+// How far off a scheduled start is, to the minute: "7h 12m", "12m", or "45s" when close
+pub fn format_until(now: DateTime<Local>, at: DateTime<Local>) -> String {
+    let seconds = (at - now).num_seconds().max(0);
+    if seconds < 60 {
+        return format!("{}s", seconds);
+    }
+    let minutes = (seconds + 59) / 60;
+    match (minutes / 60, minutes % 60) {
+        (0, m) => format!("{}m", m),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, 15).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn parses_clock_times() {
+        assert_eq!(parse_clock_time("06:00"), Ok(NaiveTime::from_hms_opt(6, 0, 0).unwrap()));
+        assert_eq!(parse_clock_time(" 18:30 "), Ok(NaiveTime::from_hms_opt(18, 30, 0).unwrap()));
+        assert_eq!(parse_clock_time("6:05"), Ok(NaiveTime::from_hms_opt(6, 5, 0).unwrap()));
+        for text in ["", "6", "24:00", "12:60", "noon"] {
+            assert!(parse_clock_time(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn next_occurrence_is_today_or_tomorrow() {
+        let six = NaiveTime::from_hms_opt(6, 0, 0).unwrap();
+        assert_eq!(next_occurrence(at(5, 30), six), at(6, 0));
+        assert_eq!(next_occurrence(at(22, 48), six), at(6, 0) + chrono::Duration::days(1));
+        // The current minute has already started, so it's tomorrow's
+        assert_eq!(next_occurrence(at(6, 0), six), at(6, 0) + chrono::Duration::days(1));
+    }
+
+    #[test]
+    fn recurrence_runs_on_checked_days_only() {
+        // 2024-03-15 is a Friday
+        let recurrence = Recurrence { enabled: true, ..Recurrence::default() };
+        assert_eq!(recurrence.current(at(9, 0)), Some(at(9, 0)));
+        assert_eq!(recurrence.current(at(9, 4)), Some(at(9, 0)));
+        assert_eq!(recurrence.current(at(9, 5)), None);
+        assert_eq!(recurrence.current(at(8, 59)), None);
+        // Saturday and Sunday are off, so Monday's is next
+        assert_eq!(recurrence.next(at(9, 30)), Some(at(9, 0) + chrono::Duration::days(3)));
+        assert_eq!(recurrence.next(at(8, 0)), Some(at(9, 0)));
+    }
+
+    #[test]
+    fn recurrence_runs_past_midnight() {
+        let recurrence = Recurrence {
+            enabled: true,
+            days: [false, false, false, false, true, false, false],
+            start: NaiveTime::from_hms_opt(23, 50, 0).unwrap(),
+            run_minutes: 30,
+        };
+        let friday_night = at(23, 50);
+        assert_eq!(recurrence.current(at(23, 55)), Some(friday_night));
+        assert_eq!(recurrence.current(friday_night + chrono::Duration::minutes(15)), Some(friday_night));
+        assert_eq!(recurrence.current(friday_night + chrono::Duration::minutes(30)), None);
+        assert_eq!(recurrence.next(at(23, 55)), Some(friday_night + chrono::Duration::days(7)));
+    }
+
+    #[test]
+    fn recurrence_without_days_never_runs() {
+        let recurrence = Recurrence { enabled: true, days: [false; 7], ..Recurrence::default() };
+        assert_eq!(recurrence.current(at(9, 0)), None);
+        assert_eq!(recurrence.next(at(9, 0)), None);
+    }
+
+    #[test]
+    fn formats_time_until_start() {
+        let now = Local.from_local_datetime(&at(12, 0)).earliest().unwrap();
+        let later = |seconds| now + chrono::Duration::seconds(seconds);
+        assert_eq!(format_until(now, later(45)), "45s");
+        assert_eq!(format_until(now, later(12 * 60)), "12m");
+        assert_eq!(format_until(now, later(7 * 3600 + 11 * 60 + 30)), "7h 12m");
+        assert_eq!(format_until(now, later(-5)), "0s");
+    }
+}
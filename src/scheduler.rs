@@ -0,0 +1,1745 @@
+// The action thread and the press/release decisions it makes on each pass.
+
+use crate::active_window;
+use crate::backend::{self, InjectionErrors, InputBackend, BACKEND_REBUILD_ATTEMPTS, BACKEND_REBUILD_DELAY};
+use crate::clock::{Clock, SystemClock};
+use crate::health;
+use crate::keys::{ActionType, ClickButton};
+use crate::{log_debug, log_error, log_info, log_warn};
+use crate::logging::{self, Category, LogBuffer};
+use crate::macro_dsl::{Instruction, MacroRunner};
+use crate::permissions;
+use crate::safety;
+use crate::scripting;
+use crate::session::group_thousands;
+use crate::state::{lock_state, ActiveMode, AppState, Command, ScheduleInputs};
+#[cfg(windows)]
+use crate::timer_resolution;
+use crate::timing::{self, Lateness};
+use chrono::Local;
+use std::any::Any;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// The action thread never sleeps for less (shorter waits are yielded through) or for longer
+// (so long intervals don't delay noticing a shutdown)
+pub const MIN_PARK: Duration = Duration::from_millis(1);
+pub const MAX_PARK: Duration = Duration::from_millis(50);
+// How often a failed backend checks for the Retry button
+const BACKEND_RETRY_POLL: Duration = Duration::from_millis(100);
+// How far ahead of a deadline precision mode stops sleeping and starts spinning
+pub const SPIN_WINDOW: Duration = Duration::from_millis(2);
+// How long shutdown waits for a stopped script to return; sleep() and the op counter notice
+// the stop within milliseconds
+const SCRIPT_JOIN_TIMEOUT: Duration = Duration::from_millis(500);
+
+// How often the action thread checks whether the target window still has focus
+pub const FOCUS_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+// How often the action thread compares the wall clock with a scheduled start
+pub const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+// Input operations the action thread performs, in the order they are returned
+#[derive(PartialEq, Clone, Debug)]
+pub enum InputOp {
+    Down(ActionType),
+    Up(ActionType),
+    Tap(ActionType),
+}
+
+
+impl InputOp {
+    pub fn action(&self) -> &ActionType {
+        match self {
+            InputOp::Down(action) | InputOp::Up(action) | InputOp::Tap(action) => action,
+        }
+    }
+}
+
+// The action thread's timing and press/release decisions, kept free of any input backend
+// so each pass can be reasoned about (and checked) as "state + time in, input ops out"
+pub struct ActionScheduler {
+    pub next_action_time: Instant,
+    // State to track currently held action and when it should be released
+    pub held: Option<ActionType>,
+    pub release_time: Option<Instant>,
+    // A handover hold stays down until the next step has been pressed
+    pub held_handover: bool,
+    // End of a wait step while in hold mode
+    pub wait_until: Option<Instant>,
+    // Only warn once per stretch of an all-disabled sequence
+    pub warned_all_disabled: bool,
+    // Macro being executed, tagged with the run it was started for
+    pub macro_runner: Option<(u64, MacroRunner)>,
+    // Every input pressed and not yet released, so a panic or shutdown can let go of all of them
+    pub down: Vec<ActionType>,
+    // Kept while precision mode is on and reported when the run ends
+    pub lateness: Lateness,
+    // When the last scheduled action ran, and the settings its successor was scheduled with
+    pub last_fired: Option<Instant>,
+    pub schedule_inputs: Option<ScheduleInputs>,
+    // The mode change the schedule was last restarted for
+    pub activated_at: Option<Instant>,
+}
+
+impl ActionScheduler {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            next_action_time: now,
+            held: None,
+            release_time: None,
+            held_handover: false,
+            wait_until: None,
+            warned_all_disabled: false,
+            macro_runner: None,
+            down: Vec::new(),
+            lateness: Lateness::default(),
+            last_fired: None,
+            schedule_inputs: None,
+            activated_at: None,
+        }
+    }
+    
+    // A run starts or resumes: forget the old schedule, which may be long past and would
+    // otherwise fire a burst of actions, and time the first action by the setting. Macros keep
+    // their own timing
+    pub fn restart_schedule(&mut self, state: &mut AppState, now: Instant) {
+        if !matches!(state.active_mode, ActiveMode::Clicking | ActiveMode::KeystrokeInjection) {
+            return;
+        }
+        let interval = state.draw_interval(&state.active_mode.clone(), now);
+        self.next_action_time = state.first_action.deadline(now, interval);
+        self.last_fired = None;
+        self.schedule_inputs = Some(state.schedule_inputs());
+        log_debug!(
+            state.log,
+            "Schedule restarted; first action due in {}",
+            timing::format_us(self.next_action_time.saturating_duration_since(now).as_micros() as u64),
+        );
+    }
+    
+    // An action due at next_action_time was just performed; schedule the one after it
+    pub fn schedule_next(&mut self, state: &mut AppState, now: Instant, interval: Duration) {
+        let late = now.saturating_duration_since(self.next_action_time);
+        if state.precision_timing {
+            self.lateness.record(late);
+        }
+        let tolerance = Duration::from_millis(state.late_tolerance_ms);
+        state.missed_deadlines.record(late, tolerance, now);
+        if late > tolerance {
+            log_debug!(state.log, "Deadline missed by {}", timing::format_us(late.as_micros() as u64));
+        }
+        if let Some(report) = state.missed_deadlines.report(tolerance, now, false) {
+            log_warn!(state.log, "{}", report);
+        }
+        self.next_action_time = timing::next_deadline(self.next_action_time, now, interval, state.late_policy);
+        self.last_fired = Some(now);
+    }
+    
+    // Release every tracked input plus all mouse buttons, and forget any hold in progress
+    pub fn release_everything(&mut self, ops: &mut Vec<InputOp>) {
+        self.held = None;
+        self.release_time = None;
+        self.held_handover = false;
+        self.wait_until = None;
+        for action in self.down.drain(..) {
+            ops.push(InputOp::Up(action));
+        }
+        for button in ClickButton::ALL {
+            let action = ActionType::Click(button);
+            if !ops.iter().any(|op| *op == InputOp::Up(action.clone())) {
+                ops.push(InputOp::Up(action));
+            }
+        }
+    }
+    
+    // Keep `down` in step with the ops about to be performed
+    pub fn track_down(&mut self, ops: &[InputOp]) {
+        for op in ops {
+            match op {
+                InputOp::Down(action) if !self.down.contains(action) => self.down.push(action.clone()),
+                InputOp::Up(action) => self.down.retain(|down| down != action),
+                _ => {},
+            }
+        }
+    }
+    
+    // When the next pass is due: the release of a hold, the end of a wait step, or the next
+    // action. Only needs the scheduler, so the thread can work out its sleep without the state
+    pub fn deadline(&self) -> Instant {
+        match (&self.held, self.release_time, self.wait_until) {
+            (Some(_), Some(release), _) => release,
+            (_, _, Some(until)) => until,
+            _ => self.next_action_time,
+        }
+    }
+    
+    // Let go of whatever is held, if anything
+    pub fn release_held(&mut self, ops: &mut Vec<InputOp>) {
+        if let Some(held) = self.held.take() {
+            ops.push(InputOp::Up(held));
+        }
+        self.release_time = None;
+        self.held_handover = false;
+    }
+    
+    // Decide what to press and release at `now`
+    pub fn tick(&mut self, state: &mut AppState, now: Instant) -> Vec<InputOp> {
+        let mut ops = Vec::new();
+        
+        if state.panic_requested {
+            state.panic_requested = false;
+            self.release_everything(&mut ops);
+            return ops;
+        }
+        
+        if let Some(action) = state.pending_one_shot.take() {
+            ops.push(InputOp::Tap(action));
+        }
+        
+        // Every start and resume goes through set_mode or toggle_pause, which stamp mode_changed_at;
+        // checking the stamp rather than for a pass in None also catches a stop and start
+        // between two passes
+        if self.activated_at != state.mode_changed_at {
+            self.activated_at = state.mode_changed_at;
+            // Whatever is held or waited on belongs to the run that just ended, even when the
+            // new one starts without a stop in between
+            self.release_held(&mut ops);
+            self.wait_until = None;
+            self.restart_schedule(state, now);
+        }
+        
+        // Counting down to the start: hold nothing and sleep until the countdown ends
+        if let Some(until) = state.start_pending_until {
+            if now < until {
+                self.release_held(&mut ops);
+                self.wait_until = None;
+                self.next_action_time = until;
+                self.track_down(&ops);
+                return ops;
+            }
+            state.start_pending_until = None;
+            state.session.start(now);
+            log_info!(state.log, "Actions started");
+            self.restart_schedule(state, now);
+        }
+        state.check_duration_limit(now);
+        // Resting in the duty cycle: let go of everything and pick up where the schedule left off
+        if let Some(rest) = state.duty_rest_remaining(now) {
+            self.release_held(&mut ops);
+            self.wait_until = None;
+            self.next_action_time = now + rest;
+            self.track_down(&ops);
+            return ops;
+        }
+        // Idle activation: the same, until the user has kept their hands off long enough
+        match state.idle_wait_remaining(now) {
+            Some(wait) => {
+                if state.idle_active {
+                    state.idle_active = false;
+                    log_info!(state.log, "User input; waiting for idle");
+                }
+                self.release_held(&mut ops);
+                self.wait_until = None;
+                self.next_action_time = now + wait;
+                self.track_down(&ops);
+                return ops;
+            },
+            None if state.idle_activation && state.active_mode != ActiveMode::None && !state.idle_active => {
+                state.idle_active = true;
+                log_info!(state.log, "Idle for {}s; actions running", state.idle_threshold_s);
+            },
+            None => {},
+        }
+        // Everything pushed from here on is the mode's own input, counted against the limit
+        let first_mode_op = ops.len();
+        
+        // Settings changed while waiting: count the new interval from the last action. Whatever
+        // is held was pressed for the old settings (and may not even be in the sequence anymore),
+        // so it's let go now instead of at a release time worked out from them
+        let inputs = state.schedule_inputs();
+        if self.schedule_inputs.as_ref() != Some(&inputs) {
+            if self.schedule_inputs.is_some() {
+                self.release_held(&mut ops);
+                self.next_action_time = timing::rescheduled_deadline(self.last_fired, now, state.draw_interval(&inputs.0, now));
+            }
+            self.schedule_inputs = Some(inputs);
+        }
+        
+        // Handover holds only make sense inside a running sequence
+        if self.held_handover && state.active_mode != ActiveMode::KeystrokeInjection {
+            self.release_held(&mut ops);
+        }
+        
+        // Check if a held action should be released based on time; handover holds
+        // are instead released by the sequence once the next step is down
+        if let Some(r_time) = self.release_time {
+            if now >= r_time && !self.held_handover {
+                self.release_held(&mut ops);
+            }
+        }
+        
+        match state.active_mode.clone() {
+            ActiveMode::None => {
+                // If stopped, release anything being held
+                self.release_held(&mut ops);
+                self.wait_until = None;
+                // The first action of the next run is due straight away
+                self.next_action_time = now;
+                self.last_fired = None;
+                if let Some(summary) = self.lateness.take_summary() {
+                    log_info!(state.log, "Precision timing: {}", summary);
+                }
+                let tolerance = Duration::from_millis(state.late_tolerance_ms);
+                if let Some(report) = state.missed_deadlines.report(tolerance, now, true) {
+                    log_warn!(state.log, "{}", report);
+                }
+            },
+            ActiveMode::Clicking => {
+                // Drawn every pass, so each action (or hold) gets a fresh value when randomized
+                let interval = state.draw_interval(&ActiveMode::Clicking, now);
+                if state.hold_mode {
+                    // Start hold if not currently holding; if already holding, do nothing until release_time.
+                    // With a hold range the presses keep to the interval, otherwise the next starts right away
+                    if self.held.is_none() && (!state.hold_range || now >= self.next_action_time) {
+                        self.held = Some(ActionType::Click(ClickButton::Left));
+                        self.release_time = Some(now + state.hold_length(interval));
+                        ops.push(InputOp::Down(ActionType::Click(ClickButton::Left)));
+                        if state.hold_range {
+                            self.schedule_next(state, now, interval);
+                        }
+                    }
+                } else {
+                    // Release if hold was previously active
+                    self.release_held(&mut ops);
+                    if now >= self.next_action_time {
+                        ops.push(InputOp::Tap(ActionType::Click(ClickButton::Left)));
+                        self.schedule_next(state, now, interval);
+                    }
+                }
+            },
+            ActiveMode::KeystrokeInjection => {
+                let interval = state.draw_interval(&ActiveMode::KeystrokeInjection, now);
+                self.tick_sequence(state, now, interval, &mut ops);
+            },
+            ActiveMode::Macro => self.tick_macro(state, now, &mut ops),
+            ActiveMode::Script => {
+                // Scripts drive input from their own thread; nothing stays held here
+                self.release_held(&mut ops);
+            },
+        }
+        let performed = ops[first_mode_op..].iter().filter(|op| !matches!(op, InputOp::Up(_))).count();
+        state.record_actions(performed as u64, now);
+        self.track_down(&ops);
+        ops
+    }
+    
+    pub fn tick_sequence(&mut self, state: &mut AppState, now: Instant, interval: Duration, ops: &mut Vec<InputOp>) {
+        let Some(idx) = state.key_index().and_then(|index| state.next_enabled_index_from(index)) else {
+            // Nothing enabled to run behaves like an empty sequence
+            self.release_held(ops);
+            if !state.steps.is_empty() && !self.warned_all_disabled {
+                log_warn!(state.log, "every step is disabled, nothing to inject");
+                self.warned_all_disabled = true;
+            }
+            return;
+        };
+        self.warned_all_disabled = false;
+        
+        let due = if state.hold_mode {
+            if self.wait_until.is_some_and(|until| now < until) {
+                false // Still pausing for a wait step
+            } else if self.held.is_none() {
+                !state.hold_range || now >= self.next_action_time
+            } else {
+                // A handover hold hands over once its time is up; plain holds were released above
+                self.held_handover && self.release_time.is_some_and(|r_time| now >= r_time)
+            }
+        } else {
+            // Release if hold was previously active, but keep a handover step down until the next one
+            if !self.held_handover {
+                self.release_held(ops);
+            }
+            self.wait_until = None;
+            now >= self.next_action_time
+        };
+        if !due {
+            return;
+        }
+        self.wait_until = None;
+        
+        let step = state.steps[idx].clone();
+        state.advance_key_index(idx);
+        let previous = self.held.take();
+        self.release_time = None;
+        self.held_handover = false;
+        
+        match step.action() {
+            Some(action) => {
+                if state.hold_mode || step.handover {
+                    // Press the new step before letting go of the previous one so there is no gap;
+                    // the same input simply stays down
+                    if previous.as_ref() != Some(&action) {
+                        ops.push(InputOp::Down(action.clone()));
+                        if let Some(previous) = previous {
+                            ops.push(InputOp::Up(previous));
+                        }
+                    }
+                    self.held = Some(action);
+                    self.held_handover = step.handover;
+                    if state.hold_mode {
+                        self.release_time = Some(now + state.hold_length(interval));
+                    }
+                } else {
+                    match previous {
+                        Some(previous) if previous == action => {
+                            ops.push(InputOp::Up(previous));
+                            ops.push(InputOp::Tap(action));
+                        },
+                        Some(previous) => {
+                            ops.push(InputOp::Tap(action));
+                            ops.push(InputOp::Up(previous));
+                        },
+                        None => ops.push(InputOp::Tap(action)),
+                    }
+                }
+                if !state.hold_mode || state.hold_range {
+                    self.schedule_next(state, now, interval);
+                }
+            },
+            None => {
+                // Wait step: let go of whatever is held and pause before the next step
+                if let Some(previous) = previous {
+                    ops.push(InputOp::Up(previous));
+                }
+                if state.hold_mode {
+                    self.wait_until = Some(now + step.wait_duration());
+                } else {
+                    // Waits keep to the schedule too but aren't actions, so they aren't measured
+                    self.next_action_time = timing::next_deadline(self.next_action_time, now, step.wait_duration(), state.late_policy);
+                }
+            },
+        }
+    }
+    
+    pub fn tick_macro(&mut self, state: &mut AppState, now: Instant, ops: &mut Vec<InputOp>) {
+        // A fresh start replaces whatever run was in progress
+        if self.macro_runner.as_ref().map(|(id, _)| *id) != Some(state.macro_run_id) {
+            self.macro_runner = state.macro_program
+                .clone()
+                .map(|program| (state.macro_run_id, MacroRunner::new(program)));
+            self.next_action_time = now;
+        }
+        
+        // One statement per pass so Stop All is honored in between; holds block until released
+        if self.held.is_some() || now < self.next_action_time {
+            return;
+        }
+        match self.macro_runner.as_mut().and_then(|(_, runner)| runner.next_instruction()) {
+            Some(Instruction::Press(key)) => ops.push(InputOp::Tap(ActionType::KeyPress(key))),
+            Some(Instruction::Click(button)) => ops.push(InputOp::Tap(ActionType::Click(button))),
+            Some(Instruction::Hold(key, ms)) => {
+                self.held = Some(ActionType::KeyPress(key.clone()));
+                self.release_time = Some(now + Duration::from_millis(ms));
+                ops.push(InputOp::Down(ActionType::KeyPress(key)));
+            },
+            Some(Instruction::Wait(ms)) => {
+                self.next_action_time = now + Duration::from_millis(ms);
+            },
+            None => {
+                self.macro_runner = None;
+                state.set_mode(ActiveMode::None, "Stopped", "Macro finished\n", now);
+            },
+        }
+    }
+}
+
+// The action thread's own copy of the state. Updates from the UI side bring new settings; the
+// run's progress is the thread's to keep unless the UI side reset it (started, stopped, edited
+// the sequence...) since the two copies last agreed
+pub struct ActionCopy {
+    pub state: AppState,
+    // run_epoch of the last progress both copies had
+    agreed_epoch: u64,
+}
+
+impl ActionCopy {
+    pub fn new(shared: &AppState) -> Self {
+        let mut state = shared.clone();
+        state.commands = None;
+        state.log = LogBuffer::default();
+        Self { agreed_epoch: state.run_epoch, state }
+    }
+    
+    pub fn apply(&mut self, command: Command) {
+        match command {
+            Command::Update(mut update) => {
+                update.commands = None;
+                update.log = std::mem::take(&mut self.state.log);
+                // Requests not yet acted on aren't dropped by a later update
+                update.panic_requested |= self.state.panic_requested;
+                if update.pending_one_shot.is_none() {
+                    update.pending_one_shot = self.state.pending_one_shot.take();
+                }
+                if update.run_epoch > self.agreed_epoch {
+                    self.agreed_epoch = update.run_epoch;
+                } else {
+                    update.take_progress_from(&self.state);
+                }
+                self.state = *update;
+            },
+            Command::UserInput(at) => self.state.last_user_input = at,
+        }
+    }
+    
+    // Hand the log and progress over to the shared state. Progress is left out when the UI
+    // side reset it meanwhile; the update carrying that reset is already on its way
+    pub fn publish(&mut self, shared: &mut AppState) {
+        let mut changed = !self.state.log.is_empty();
+        shared.log.append(std::mem::take(&mut self.state.log));
+        changed |= shared.backend_in_use != self.state.backend_in_use;
+        shared.backend_in_use = self.state.backend_in_use;
+        if shared.run_epoch == self.agreed_epoch {
+            changed |= shared.active_mode != self.state.active_mode || shared.status != self.state.status;
+            shared.take_progress_from(&self.state);
+            self.agreed_epoch = self.state.run_epoch;
+        }
+        // While a mode runs the UI keeps repainting by itself
+        if changed {
+            shared.request_repaint();
+        }
+    }
+}
+
+// Sleep for up to `timeout`, waking early for a command from the UI side
+pub fn wait_for_command(commands: &Receiver<Command>, copy: &mut ActionCopy, timeout: Duration) {
+    match commands.recv_timeout(timeout) {
+        Ok(command) => copy.apply(command),
+        Err(RecvTimeoutError::Timeout) => {},
+        // Nothing can send anymore, so there's nothing to wake early for
+        Err(RecvTimeoutError::Disconnected) => thread::sleep(timeout),
+    }
+}
+
+// Send one input operation through the backend
+pub fn perform_input(backend: &mut dyn InputBackend, op: InputOp) -> Result<(), String> {
+    match op {
+        InputOp::Down(ActionType::Click(button)) => backend.mouse_down(button),
+        InputOp::Down(ActionType::KeyPress(key_str)) => backend.key_down(&key_str),
+        InputOp::Up(ActionType::Click(button)) => backend.mouse_up(button),
+        InputOp::Up(ActionType::KeyPress(key_str)) => backend.key_up(&key_str),
+        InputOp::Tap(ActionType::Click(button)) => backend.mouse_click(button),
+        InputOp::Tap(ActionType::KeyPress(key_str)) => backend.key_click(&key_str),
+    }
+}
+
+// Let go of everything the scheduler may have left down; the last thing the action thread does
+pub fn release_on_shutdown(scheduler: &mut ActionScheduler, backend: &mut dyn InputBackend) {
+    let mut ops = Vec::new();
+    scheduler.release_everything(&mut ops);
+    for op in ops {
+        // Nothing left to report a failure to; try every release regardless
+        let _ = perform_input(backend, op);
+    }
+}
+
+// What the action loop works with that outlives a panic in it
+struct ActionThread<B> {
+    copy: ActionCopy,
+    backend: B,
+    commands: Receiver<Command>,
+    shared: Arc<Mutex<AppState>>,
+    is_running: Arc<AtomicBool>,
+    hold_released: Arc<AtomicBool>,
+    // Last script run handed off to its own thread, and that thread
+    spawned_script_run: u64,
+    script_thread: Option<thread::JoinHandle<()>>,
+    clock: Arc<dyn Clock>,
+    // Builds the backend again when injection keeps failing mid-run
+    make_backend: Box<dyn FnMut() -> Result<B, String> + Send>,
+}
+
+impl<B: InputBackend> ActionThread<B> {
+    // Make passes until shutdown, then let go of everything
+    fn run(&mut self) {
+        let ActionThread {
+            copy,
+            backend,
+            commands,
+            shared,
+            is_running,
+            hold_released,
+            spawned_script_run,
+            script_thread,
+            clock,
+            make_backend,
+        } = self;
+        let mut injection_errors = InjectionErrors::default();
+        let mut scheduler = ActionScheduler::new(clock.now());
+        let mut next_focus_check = clock.now();
+        let mut next_schedule_check = clock.now();
+        // Whether auto-pause is on, as of the last pass
+        let mut watch_focus = false;
+        let mut high_priority = false;
+        let mut was_running = false;
+        // The input_backend setting as of the last switch
+        let mut requested_backend = None;
+        let rate_meter = Arc::clone(&copy.state.rate_meter);
+        let injected = Arc::clone(&copy.state.injected);
+        let hot = Arc::clone(&copy.state.hot);
+        // The 1 ms system timer, held while a mode runs; dropping it gives it back, also on exit
+        // Only tried once per run, so a failure is logged once
+        #[cfg(windows)]
+        let mut high_res_timer: Option<Result<timer_resolution::HighResTimer, String>> = None;
+        
+        while is_running.load(Ordering::SeqCst) {
+            health::ACTION.beat();
+            while let Ok(command) = commands.try_recv() {
+                copy.apply(command);
+            }
+            // The UI side's latest mode and timing, even before its update arrives; a mode it
+            // has already left gets nothing but releases until then
+            let mode_current = hot.load_into(&mut copy.state);
+            let now = clock.now();
+            // Asking for the focused window can be slow, so do it rarely
+            let focus_title = if watch_focus && now >= next_focus_check {
+                next_focus_check = now + FOCUS_CHECK_INTERVAL;
+                Some(active_window::foreground_title())
+            } else {
+                None
+            };
+            let wall_clock = (now >= next_schedule_check).then(|| {
+                next_schedule_check = now + SCHEDULE_CHECK_INTERVAL;
+                Local::now()
+            });
+            
+            let state = &mut copy.state;
+            if let Some(title) = &focus_title {
+                state.check_window_focus(title.as_deref(), now);
+            }
+            if let Some(wall_clock) = wall_clock {
+                state.check_scheduled_start(wall_clock, now);
+                state.check_recurrence(wall_clock, now);
+            }
+            watch_focus = state.pause_outside_window && !state.window_filter.trim().is_empty();
+            let running = state.active_mode != ActiveMode::None;
+            // A new run is measured from its first action, not from the idle time before it
+            if running && !was_running {
+                if let Ok(mut meter) = rate_meter.lock() {
+                    meter.clear();
+                }
+            }
+            was_running = running;
+            // Precision mode is on and something is running
+            let precise = state.precision_timing && running;
+            let want_priority = precise && state.precision_priority;
+            if want_priority != high_priority {
+                high_priority = want_priority;
+                if !timing::set_high_priority(want_priority) && want_priority {
+                    log_warn!(state.log, "Raising the thread priority isn't supported on this system");
+                }
+            }
+            #[cfg(windows)]
+            {
+                if running && high_res_timer.is_none() {
+                    let timer = timer_resolution::HighResTimer::acquire();
+                    match &timer {
+                        Ok(_) => log_info!(state.log, "High-resolution timer engaged (1 ms)"),
+                        Err(e) => log_warn!(state.log, "Couldn't raise the timer resolution: {}", e),
+                    }
+                    high_res_timer = Some(timer);
+                } else if !running {
+                    if let Some(Ok(_)) = high_res_timer.take() {
+                        log_info!(state.log, "High-resolution timer released");
+                    }
+                }
+            }
+            let ops = scheduler.tick(state, now);
+            for op in &ops {
+                if let InputOp::Up(action) = op {
+                    log_debug!(state.log, "Releasing {:?}", action);
+                }
+            }
+            if ops.iter().any(|op| !matches!(op, InputOp::Tap(_))) {
+                safety::note_held(&scheduler.down);
+            }
+            // Switched when the setting changes, so one that can't be built is logged once
+            if requested_backend != Some(state.input_backend) {
+                requested_backend = Some(state.input_backend);
+                if let Err(e) = backend.select(state.input_backend) {
+                    log_warn!(state.log, "Couldn't switch to the {} backend: {}", state.input_backend.label(), e);
+                }
+            }
+            let in_use = backend.kind();
+            if in_use != state.backend_in_use {
+                safety::note_backend(in_use);
+                state.backend_in_use = in_use;
+            }
+            backend.set_layout(state.keyboard_layout);
+            // Note what is about to be injected before it can echo back through the listener
+            if !ops.is_empty() {
+                if let Ok(mut injected) = injected.lock() {
+                    for op in &ops {
+                        injected.record(op.action(), now);
+                    }
+                }
+            }
+            
+            if state.active_mode == ActiveMode::Script
+                && *spawned_script_run != state.script_run_id
+                && state.start_pending_until.is_none()
+            {
+                *spawned_script_run = state.script_run_id;
+                if let Some(path) = &state.script_path {
+                    // The previous script has already been told to stop by the new run
+                    *script_thread = Some(scripting::spawn_script(path, Arc::clone(shared), *spawned_script_run));
+                }
+            }
+            
+            let mut performed = false;
+            let mut error_line = None;
+            for op in ops {
+                // A hold-to-run key was just let go, or the mode was left; only let releases
+                // through until the stop lands
+                if (hold_released.load(Ordering::SeqCst) || !mode_current) && !matches!(op, InputOp::Up(_)) {
+                    continue;
+                }
+                let is_action = !matches!(op, InputOp::Up(_));
+                match perform_input(backend, op) {
+                    Ok(()) => {
+                        performed |= is_action;
+                        injection_errors.succeeded();
+                    },
+                    Err(e) => {
+                        let e = permissions::explain_injection_error(e, &state.missing_permissions);
+                        error_line = injection_errors.failed(&e, clock.now()).or(error_line);
+                    },
+                }
+            }
+            if performed {
+                if let Ok(mut meter) = rate_meter.lock() {
+                    meter.record(Instant::now());
+                }
+            }
+            if let Some(line) = error_line {
+                log_error!(copy.state.log, "{}", line);
+            }
+            copy.state.injection_failing = injection_errors.is_failing();
+            if injection_errors.needs_rebuild() {
+                recover_injection(&mut **make_backend, backend, &mut injection_errors, copy, shared, is_running, &**clock);
+                // The rebuilt backend starts on whatever make_backend picks, so the setting is applied again
+                requested_backend = None;
+            }
+            // Never waits for the UI; whatever isn't published now goes out after the next pass
+            if let Ok(mut state) = shared.try_lock() {
+                copy.publish(&mut state);
+            }
+            
+            // Add a small sleep to prevent busy-waiting and excessive CPU usage
+            let deadline = scheduler.deadline();
+            let after = clock.now();
+            let sleep_duration = deadline.saturating_duration_since(after);
+            let overdue = after.saturating_duration_since(deadline);
+            if sleep_duration.is_zero() && overdue >= MIN_PARK {
+                // Long past: nothing is scheduled (idle, or every step disabled)
+                wait_for_command(commands, copy, MIN_PARK);
+            } else if precise && sleep_duration <= SPIN_WINDOW {
+                // Precision mode: the OS could overshoot a sleep this short, so spin up to the
+                // deadline. Spun in real time, which only differs from the clock's in tests
+                let deadline = Instant::now() + sleep_duration;
+                while Instant::now() < deadline {
+                    std::hint::spin_loop();
+                }
+            } else if sleep_duration < MIN_PARK {
+                // Shorter than the OS timer can sleep; sub-millisecond intervals need the CPU anyway
+                thread::yield_now();
+            } else {
+                // Waiting on the command channel wakes the thread as soon as the UI side changes
+                // something, and the cap notices a shutdown even in the middle of a multi-minute
+                // interval. Precision mode wakes early and spins the rest of the way
+                let park = if precise { sleep_duration - SPIN_WINDOW } else { sleep_duration };
+                wait_for_command(commands, copy, park.min(MAX_PARK));
+            }
+        }
+        
+        // Shutdown stopped the script along with everything else; let it finish injecting first
+        if let Some(handle) = script_thread.take() {
+            safety::join_with_timeout(shared, "script", handle, SCRIPT_JOIN_TIMEOUT);
+        }
+        // Ensure anything still held is released on shutdown
+        release_on_shutdown(&mut scheduler, backend);
+        safety::note_held(&[]);
+    }
+    
+    // A pass panicked: the panic hook has let go of what was held, so stop the run and say so
+    fn recover(&mut self, payload: &(dyn Any + Send)) {
+        let message = format!(
+            "Internal error on the action thread ({}); stopped everything and restarted it\n",
+            safety::panic_message(payload),
+        );
+        self.copy.state.stop_all(&message, self.clock.now());
+        let mut state = lock_state(&self.shared);
+        self.copy.publish(&mut state);
+    }
+}
+
+// Build the backend once, treating a panic like any other failure
+fn try_build_backend<B>(make_backend: &mut dyn FnMut() -> Result<B, String>) -> Result<B, String> {
+    panic::catch_unwind(AssertUnwindSafe(make_backend))
+        .unwrap_or_else(|payload| Err(format!("the input library crashed ({})", safety::panic_message(&*payload))))
+}
+
+// Injection kept failing mid-run, as it does when the display session goes away (a remote
+// desktop reconnecting, switching users): pause the mode, build the backend again and resume on
+// the new one. Stops the run once BACKEND_REBUILD_ATTEMPTS rebuilds in a row have failed or
+// haven't made injection work again
+fn recover_injection<B>(
+    make_backend: &mut dyn FnMut() -> Result<B, String>,
+    backend: &mut B,
+    errors: &mut InjectionErrors,
+    copy: &mut ActionCopy,
+    shared: &Mutex<AppState>,
+    is_running: &AtomicBool,
+    clock: &dyn Clock,
+) {
+    let pause = matches!(copy.state.active_mode, ActiveMode::Clicking | ActiveMode::KeystrokeInjection | ActiveMode::Macro);
+    log_warn!(copy.state.log, "Input injection keeps failing; restarting the input backend");
+    if pause {
+        copy.state.toggle_pause("restarting the input backend", clock.now());
+    }
+    loop {
+        if !errors.start_rebuild() {
+            *errors = InjectionErrors::default();
+            let state = &mut copy.state;
+            log_error!(
+                state.log,
+                "Input injection still fails after restarting the input backend {} times; stopped",
+                BACKEND_REBUILD_ATTEMPTS,
+            );
+            state.paused_mode = None;
+            state.set_mode(ActiveMode::None, "Stopped (injection failed)", "", clock.now());
+            state.injection_failing = false;
+            break;
+        }
+        if let Ok(mut state) = shared.try_lock() {
+            copy.publish(&mut state);
+        }
+        let resume_at = Instant::now() + BACKEND_REBUILD_DELAY;
+        while Instant::now() < resume_at {
+            health::ACTION.beat();
+            if !is_running.load(Ordering::SeqCst) {
+                return;
+            }
+            thread::sleep(BACKEND_RETRY_POLL.min(resume_at.saturating_duration_since(Instant::now())));
+        }
+        let state = &mut copy.state;
+        match try_build_backend(make_backend) {
+            Ok(rebuilt) => {
+                *backend = rebuilt;
+                log_info!(state.log, "Restarted the input backend (attempt {} of {})", errors.rebuilds(), BACKEND_REBUILD_ATTEMPTS);
+                // Unless the run was stopped or restarted meanwhile
+                if pause && state.active_mode == ActiveMode::None && state.paused_mode.is_some() {
+                    state.toggle_pause("input backend restarted", clock.now());
+                }
+                break;
+            },
+            Err(e) => log_warn!(
+                state.log,
+                "Couldn't restart the input backend (attempt {} of {}): {}",
+                errors.rebuilds(),
+                BACKEND_REBUILD_ATTEMPTS,
+                e,
+            ),
+        }
+    }
+    if let Ok(mut state) = shared.try_lock() {
+        copy.publish(&mut state);
+    }
+}
+
+// Build the backend, treating a panic like any other failure. While it fails the UI shows why
+// and offers a Retry button, so this waits for that (or shutdown) and tries again
+fn build_backend<B, F>(make_backend: &mut F, shared: &Mutex<AppState>, is_running: &AtomicBool) -> Option<B>
+where
+    F: FnMut() -> Result<B, String>,
+{
+    let mut failed = false;
+    loop {
+        let result = try_build_backend(make_backend);
+        let mut state = lock_state(shared);
+        match result {
+            Ok(backend) => {
+                if failed {
+                    state.injection_unavailable = None;
+                    state.injection_retry_requested = false;
+                    log_info!(state.log, "Input injection is available now");
+                    state.request_repaint();
+                }
+                return Some(backend);
+            },
+            Err(e) => {
+                log_error!(state.log, "Couldn't start input injection: {}", e);
+                state.injection_unavailable = Some(e);
+                state.injection_dialog_pending = !failed;
+                state.injection_retry_requested = false;
+                state.request_repaint();
+            },
+        }
+        drop(state);
+        failed = true;
+        while !std::mem::take(&mut lock_state(shared).injection_retry_requested) {
+            health::ACTION.beat();
+            if !is_running.load(Ordering::SeqCst) {
+                return None;
+            }
+            thread::sleep(BACKEND_RETRY_POLL);
+        }
+    }
+}
+
+// Start the action thread that performs clicks and key presses. Enigo can't be sent between
+// threads on every platform, so the backend is built on the action thread by `make_backend`.
+// The thread works on its own copy of the state: nothing between waking up for a deadline and
+// performing its inputs waits for the shared lock, and results are published with try_lock
+pub fn start_action_thread<B, F>(
+    state: Arc<Mutex<AppState>>,
+    is_running: Arc<AtomicBool>,
+    hold_released: Arc<AtomicBool>,
+    make_backend: F,
+) -> thread::JoinHandle<()>
+where
+    B: InputBackend,
+    F: FnMut() -> Result<B, String> + Send + 'static,
+{
+    start_action_thread_with_clock(state, is_running, hold_released, make_backend, Arc::new(SystemClock))
+}
+
+// The same, scheduling by `clock` instead of the system's
+pub fn start_action_thread_with_clock<B, F>(
+    state: Arc<Mutex<AppState>>,
+    is_running: Arc<AtomicBool>,
+    hold_released: Arc<AtomicBool>,
+    mut make_backend: F,
+    clock: Arc<dyn Clock>,
+) -> thread::JoinHandle<()>
+where
+    B: InputBackend,
+    F: FnMut() -> Result<B, String> + Send + 'static,
+{
+    let (sender, commands) = mpsc::channel();
+    let copy = {
+        let mut state = lock_state(&state);
+        state.commands = Some(sender);
+        state.mirror_hot_settings();
+        state.hot.active_mode.store(state.active_mode.to_u8(), Ordering::SeqCst);
+        ActionCopy::new(&state)
+    };
+    // `state` is the thread's copy from here on
+    let shared = state;
+    thread::spawn(move || {
+        logging::set_thread_category(Category::Action);
+        let Some(backend) = build_backend(&mut make_backend, &shared, &is_running) else {
+            return;
+        };
+        let mut action_thread = ActionThread {
+            copy,
+            backend,
+            commands,
+            shared,
+            is_running,
+            hold_released,
+            spawned_script_run: 0,
+            script_thread: None,
+            clock,
+            make_backend: Box::new(make_backend),
+        };
+        // A panicking pass doesn't take the thread down with it; the loop starts over
+        while let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| action_thread.run())) {
+            action_thread.recover(&*payload);
+        }
+    })
+}
+
+// Measure the sleep granularity off the UI thread; it takes a few timer ticks
+pub fn start_granularity_probe(state: Arc<Mutex<AppState>>) {
+    thread::spawn(move || {
+        logging::set_thread_category(Category::Action);
+        let granularity = timing::measure_sleep_granularity();
+        let mut state = lock_state(&state);
+        state.sleep_granularity = Some(granularity);
+        log_info!(
+            state.log,
+            "Sleep granularity: ~{}",
+            timing::format_us(granularity.as_micros() as u64),
+        );
+        state.request_repaint();
+    });
+}
+
+// How long the benchmark runs each backend for
+pub const BENCHMARK_DURATION: Duration = Duration::from_secs(1);
+
+// Measure how fast each available backend can inject, one after the other, and log the results.
+// The caller sets benchmark_running; it's cleared once every backend is done
+pub fn start_backend_benchmark(state: Arc<Mutex<AppState>>) {
+    thread::spawn(move || {
+        logging::set_thread_category(Category::Action);
+        for &kind in backend::BackendKind::AVAILABLE {
+            let result = backend::build_backend(kind).and_then(|mut backend| backend::benchmark(&mut *backend, BENCHMARK_DURATION));
+            let mut state = lock_state(&state);
+            match result {
+                Ok(rate) => log_info!(
+                    state.log,
+                    "Benchmark: {} manages ~{} CPS ({} inputs/s)",
+                    kind.label(),
+                    group_thousands((rate / 2.0) as u64),
+                    group_thousands(rate as u64),
+                ),
+                Err(e) => log_error!(state.log, "Benchmark: {} failed: {}", kind.label(), e),
+            }
+        }
+        let mut state = lock_state(&state);
+        state.benchmark_running = false;
+        state.request_repaint();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, MockCall};
+    use crate::clock::MockClock;
+    use crate::timing::{FirstAction, Interval, LatePolicy};
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    fn key(name: &str) -> ActionType {
+        ActionType::KeyPress(name.to_string())
+    }
+
+    fn left() -> ActionType {
+        ActionType::Click(ClickButton::Left)
+    }
+
+    // One pass the way the action thread makes it, with the ops sent to `backend`
+    fn pass(scheduler: &mut ActionScheduler, state: &mut AppState, now: Instant, backend: &mut MockBackend) {
+        for op in scheduler.tick(state, now) {
+            perform_input(backend, op).unwrap();
+        }
+    }
+
+    // A state running `keys` at the default 1s key interval
+    fn injecting(keys: &str, hold_mode: bool, now: Instant) -> AppState {
+        let mut state = AppState { key_to_inject: keys.to_string(), hold_mode, ..AppState::default() };
+        state.parse_key_sequence();
+        state.start_keystroke_injection(now);
+        state
+    }
+
+    #[test]
+    fn clicks_on_schedule() {
+        let start = Instant::now();
+        let mut state = AppState::default();
+        state.start_clicking(start);
+        let mut scheduler = ActionScheduler::new(start);
+
+        assert_eq!(scheduler.tick(&mut state, start), vec![InputOp::Tap(left())]);
+        assert_eq!(scheduler.tick(&mut state, start + INTERVAL / 2), vec![]);
+        assert_eq!(scheduler.tick(&mut state, start + INTERVAL), vec![InputOp::Tap(left())]);
+    }
+
+    #[test]
+    fn starting_again_after_a_stop_does_not_burst() {
+        let start = Instant::now();
+        let mut state = AppState { late_policy: LatePolicy::CatchUp, ..AppState::default() };
+        state.start_clicking(start);
+        let mut scheduler = ActionScheduler::new(start);
+        assert_eq!(scheduler.tick(&mut state, start), vec![InputOp::Tap(left())]);
+
+        // Stopped and started again between two passes, well after the next click was due
+        let restart = start + INTERVAL * 5;
+        state.stop_all("", restart);
+        state.start_clicking(restart);
+        assert_eq!(scheduler.tick(&mut state, restart), vec![InputOp::Tap(left())]);
+        assert_eq!(scheduler.tick(&mut state, restart), vec![]);
+        assert_eq!(scheduler.tick(&mut state, restart + INTERVAL / 2), vec![]);
+        assert_eq!(scheduler.tick(&mut state, restart + INTERVAL), vec![InputOp::Tap(left())]);
+    }
+
+    #[test]
+    fn first_action_can_wait_an_interval() {
+        let start = Instant::now();
+        let mut state = AppState { first_action: FirstAction::AfterInterval, ..AppState::default() };
+        state.start_clicking(start);
+        let mut scheduler = ActionScheduler::new(start);
+        assert_eq!(scheduler.tick(&mut state, start), vec![]);
+        assert_eq!(scheduler.tick(&mut state, start + INTERVAL), vec![InputOp::Tap(left())]);
+
+        // Same after stopping, with a pass in between, and switching to a sequence
+        let stop = start + INTERVAL * 2;
+        state.stop_all("", stop);
+        assert_eq!(scheduler.tick(&mut state, stop), vec![]);
+        let restart = stop + INTERVAL * 3;
+        state.key_to_inject = "w".to_string();
+        state.parse_key_sequence();
+        state.start_keystroke_injection(restart);
+        assert_eq!(scheduler.tick(&mut state, restart), vec![]);
+        assert_eq!(scheduler.tick(&mut state, restart + INTERVAL), vec![InputOp::Tap(key("w"))]);
+
+        // Resuming a paused run waits too
+        let pause = restart + INTERVAL * 2;
+        state.toggle_pause("F8", pause);
+        assert_eq!(scheduler.tick(&mut state, pause), vec![]);
+        let resume = pause + INTERVAL * 4;
+        state.toggle_pause("F8", resume);
+        assert_eq!(scheduler.tick(&mut state, resume), vec![]);
+        assert_eq!(scheduler.tick(&mut state, resume + INTERVAL), vec![InputOp::Tap(key("w"))]);
+    }
+
+    #[test]
+    fn hold_mode_releases_before_pressing_again() {
+        let start = Instant::now();
+        let mut state = AppState { hold_mode: true, ..AppState::default() };
+        state.start_clicking(start);
+        let mut scheduler = ActionScheduler::new(start);
+
+        assert_eq!(scheduler.tick(&mut state, start), vec![InputOp::Down(left())]);
+        assert_eq!(scheduler.tick(&mut state, start + INTERVAL / 2), vec![]);
+        assert_eq!(
+            scheduler.tick(&mut state, start + INTERVAL),
+            vec![InputOp::Up(left()), InputOp::Down(left())],
+        );
+    }
+
+    #[test]
+    fn stopping_releases_the_held_input() {
+        let start = Instant::now();
+        let mut state = AppState { hold_mode: true, ..AppState::default() };
+        state.start_clicking(start);
+        let mut scheduler = ActionScheduler::new(start);
+        scheduler.tick(&mut state, start);
+
+        let later = start + INTERVAL / 4;
+        state.set_mode(ActiveMode::None, "Stopped", "", later);
+        assert_eq!(scheduler.tick(&mut state, later), vec![InputOp::Up(left())]);
+        assert_eq!(scheduler.tick(&mut state, later + INTERVAL), vec![]);
+    }
+
+    #[test]
+    fn editing_the_sequence_releases_the_held_key() {
+        let start = Instant::now();
+        let mut state = injecting("w, s", true, start);
+        let mut scheduler = ActionScheduler::new(start);
+        assert_eq!(scheduler.tick(&mut state, start), vec![InputOp::Down(key("w"))]);
+
+        let later = start + INTERVAL / 4;
+        state.key_to_inject = "a".to_string();
+        state.parse_key_sequence();
+        assert_eq!(scheduler.tick(&mut state, later), vec![InputOp::Up(key("w")), InputOp::Down(key("a"))]);
+        assert_eq!(scheduler.down, [key("a")]);
+    }
+
+    #[test]
+    fn turning_hold_mode_off_releases_the_held_input() {
+        let start = Instant::now();
+        let mut state = AppState { hold_mode: true, ..AppState::default() };
+        state.start_clicking(start);
+        let mut scheduler = ActionScheduler::new(start);
+        assert_eq!(scheduler.tick(&mut state, start), vec![InputOp::Down(left())]);
+
+        let later = start + INTERVAL / 4;
+        state.hold_mode = false;
+        assert_eq!(scheduler.tick(&mut state, later), vec![InputOp::Up(left()), InputOp::Tap(left())]);
+        assert!(scheduler.down.is_empty());
+    }
+
+    #[test]
+    fn changing_the_interval_releases_the_held_key() {
+        let start = Instant::now();
+        let mut state = injecting("w, s", true, start);
+        let mut scheduler = ActionScheduler::new(start);
+        assert_eq!(scheduler.tick(&mut state, start), vec![InputOp::Down(key("w"))]);
+
+        // The next hold is as long as the new interval
+        let later = start + INTERVAL / 4;
+        state.key_interval.us = 2_000_000;
+        assert_eq!(scheduler.tick(&mut state, later), vec![InputOp::Up(key("w")), InputOp::Down(key("s"))]);
+        assert_eq!(scheduler.tick(&mut state, later + INTERVAL), vec![]);
+        assert_eq!(
+            scheduler.tick(&mut state, later + INTERVAL * 2),
+            vec![InputOp::Up(key("s")), InputOp::Down(key("w"))],
+        );
+    }
+
+    #[test]
+    fn hold_mode_sequence_hands_over_between_steps() {
+        let start = Instant::now();
+        let mut state = injecting("w, s", true, start);
+        let mut scheduler = ActionScheduler::new(start);
+
+        assert_eq!(scheduler.tick(&mut state, start), vec![InputOp::Down(key("w"))]);
+        assert_eq!(
+            scheduler.tick(&mut state, start + INTERVAL),
+            vec![InputOp::Up(key("w")), InputOp::Down(key("s"))],
+        );
+    }
+
+    #[test]
+    fn held_step_stays_down_until_the_next_is_pressed() {
+        let start = Instant::now();
+        let mut state = injecting("w(hold), s", false, start);
+        let mut scheduler = ActionScheduler::new(start);
+
+        assert_eq!(scheduler.tick(&mut state, start), vec![InputOp::Down(key("w"))]);
+        assert_eq!(scheduler.tick(&mut state, start + INTERVAL / 2), vec![]);
+        assert_eq!(
+            scheduler.tick(&mut state, start + INTERVAL),
+            vec![InputOp::Tap(key("s")), InputOp::Up(key("w"))],
+        );
+    }
+
+    #[test]
+    fn panic_releases_everything() {
+        let start = Instant::now();
+        let mut state = injecting("shift", true, start);
+        let mut scheduler = ActionScheduler::new(start);
+        scheduler.tick(&mut state, start);
+
+        state.panic_requested = true;
+        assert_eq!(
+            scheduler.tick(&mut state, start + INTERVAL / 2),
+            vec![
+                InputOp::Up(key("shift")),
+                InputOp::Up(left()),
+                InputOp::Up(ActionType::Click(ClickButton::Right)),
+                InputOp::Up(ActionType::Click(ClickButton::Middle)),
+            ],
+        );
+    }
+
+    #[test]
+    fn deadline_follows_holds_waits_and_the_schedule() {
+        let start = Instant::now();
+        let mut state = injecting("w, wait(300), s", true, start);
+        let mut scheduler = ActionScheduler::new(start);
+
+        scheduler.tick(&mut state, start);
+        assert_eq!(scheduler.deadline(), start + INTERVAL);
+        // The wait step lets go of w and holds nothing until it ends
+        scheduler.tick(&mut state, start + INTERVAL);
+        assert_eq!(scheduler.deadline(), start + INTERVAL + Duration::from_millis(300));
+
+        state.stop_all("", start + INTERVAL * 2);
+        scheduler.tick(&mut state, start + INTERVAL * 2);
+        assert_eq!(scheduler.deadline(), start + INTERVAL * 2);
+    }
+
+    #[test]
+    fn backend_sees_hold_mode_presses_and_releases() {
+        let start = Instant::now();
+        let mut state = AppState { hold_mode: true, ..AppState::default() };
+        state.start_clicking(start);
+        let mut scheduler = ActionScheduler::new(start);
+        let mut backend = MockBackend::default();
+        for second in 0..3 {
+            pass(&mut scheduler, &mut state, start + INTERVAL * second, &mut backend);
+        }
+
+        let left = ClickButton::Left;
+        assert_eq!(
+            backend.calls(),
+            [
+                MockCall::MouseDown(left),
+                MockCall::MouseUp(left),
+                MockCall::MouseDown(left),
+                MockCall::MouseUp(left),
+                MockCall::MouseDown(left),
+            ],
+        );
+    }
+
+    #[test]
+    fn backend_sees_sequence_steps_in_order() {
+        let start = Instant::now();
+        let mut state = injecting("a, click, wait(500), b", false, start);
+        let mut scheduler = ActionScheduler::new(start);
+        let mut backend = MockBackend::default();
+        for ms in [0, 500, 1_000, 2_000, 2_250, 2_500] {
+            pass(&mut scheduler, &mut state, start + Duration::from_millis(ms), &mut backend);
+        }
+
+        assert_eq!(
+            backend.calls(),
+            [
+                MockCall::KeyClick("a".to_string()),
+                MockCall::MouseClick(ClickButton::Left),
+                MockCall::KeyClick("b".to_string()),
+            ],
+        );
+    }
+
+    #[test]
+    fn copy_keeps_its_progress_and_takes_new_settings() {
+        let start = Instant::now();
+        let mut shared = injecting("a, b, c", false, start);
+        let mut copy = ActionCopy::new(&shared);
+        let mut scheduler = ActionScheduler::new(start);
+        scheduler.tick(&mut copy.state, start);
+        assert_eq!(copy.state.current_key_index, 1);
+
+        shared.hold_mode = true;
+        copy.apply(Command::Update(Box::new(shared.clone())));
+        assert!(copy.state.hold_mode);
+        assert_eq!(copy.state.current_key_index, 1);
+
+        copy.publish(&mut shared);
+        assert_eq!(shared.current_key_index, 1);
+        assert_eq!(shared.session.actions(), 1);
+    }
+
+    #[test]
+    fn a_reset_on_the_ui_side_wins() {
+        let start = Instant::now();
+        let mut shared = injecting("a, b, c", false, start);
+        let mut copy = ActionCopy::new(&shared);
+        let mut scheduler = ActionScheduler::new(start);
+        scheduler.tick(&mut copy.state, start);
+
+        shared.stop_all("Stopped\n", start);
+        // The reset isn't overwritten by progress made before the thread heard of it
+        copy.publish(&mut shared);
+        assert_eq!(shared.active_mode, ActiveMode::None);
+        assert_eq!(shared.session.actions(), 0);
+
+        copy.apply(Command::Update(Box::new(shared.clone())));
+        assert_eq!(copy.state.active_mode, ActiveMode::None);
+        assert_eq!(copy.state.current_key_index, 0);
+    }
+
+    #[test]
+    fn later_updates_keep_an_unhandled_panic() {
+        let start = Instant::now();
+        let mut shared = AppState::default();
+        let mut copy = ActionCopy::new(&shared);
+        shared.panic(start);
+        copy.apply(Command::Update(Box::new(shared.clone())));
+        shared.panic_requested = false;
+        copy.apply(Command::Update(Box::new(shared.clone())));
+        assert!(copy.state.panic_requested);
+    }
+
+    #[test]
+    fn action_thread_starts_when_told_to() {
+        let state = Arc::new(Mutex::new(AppState { click_interval: Interval::fixed(60_000_000), ..AppState::default() }));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let backend = MockBackend::default();
+        let thread_backend = backend.clone();
+        let handle = start_action_thread(
+            Arc::clone(&state),
+            Arc::clone(&is_running),
+            Arc::new(AtomicBool::new(false)),
+            move || Ok(thread_backend.clone()),
+        );
+
+        {
+            let mut state = state.lock().unwrap();
+            state.start_clicking(Instant::now());
+            state.send_update();
+        }
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while state.lock().unwrap().session.actions() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        is_running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        assert_eq!(backend.calls()[0], MockCall::MouseClick(ClickButton::Left));
+        assert_eq!(state.lock().unwrap().session.actions(), 1);
+    }
+
+    #[test]
+    fn action_thread_retries_a_failed_backend_when_asked() {
+        let state = Arc::new(Mutex::new(AppState { click_interval: Interval::fixed(60_000_000), ..AppState::default() }));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let backend = MockBackend::default();
+        let thread_backend = backend.clone();
+        let mut attempts = 0;
+        let handle = start_action_thread(
+            Arc::clone(&state),
+            Arc::clone(&is_running),
+            Arc::new(AtomicBool::new(false)),
+            move || {
+                attempts += 1;
+                match attempts {
+                    1 => Err("no display".to_string()),
+                    2 => panic!("XOpenDisplay failed"),
+                    _ => Ok(thread_backend.clone()),
+                }
+            },
+        );
+        let wait_for = |done: &dyn Fn(&AppState) -> bool| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !done(&state.lock().unwrap()) && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(5));
+            }
+        };
+
+        wait_for(&|state| state.injection_unavailable.is_some());
+        {
+            let mut state = state.lock().unwrap();
+            assert_eq!(state.injection_unavailable.as_deref(), Some("no display"));
+            assert!(state.injection_dialog_pending);
+            state.injection_dialog_pending = false;
+            // Nothing starts while there's no backend
+            state.start_clicking(Instant::now());
+            assert_eq!(state.active_mode, ActiveMode::None);
+            state.injection_retry_requested = true;
+        }
+        // The panic counts as a failure too, without another dialog
+        wait_for(&|state| state.injection_unavailable.as_deref().is_some_and(|e| e.contains("XOpenDisplay")));
+        assert!(!state.lock().unwrap().injection_dialog_pending);
+        state.lock().unwrap().injection_retry_requested = true;
+        wait_for(&|state| state.injection_unavailable.is_none());
+        {
+            let mut state = state.lock().unwrap();
+            state.start_clicking(Instant::now());
+            state.send_update();
+        }
+        wait_for(&|state| state.session.actions() > 0);
+        is_running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        assert_eq!(backend.calls()[0], MockCall::MouseClick(ClickButton::Left));
+        assert!(state.lock().unwrap().log.contains("Input injection is available now"));
+    }
+
+    #[test]
+    fn action_thread_releases_held_input_on_shutdown() {
+        let mut state = AppState { hold_mode: true, click_interval: Interval::fixed(60_000_000), ..AppState::default() };
+        state.start_clicking(Instant::now());
+        let state = Arc::new(Mutex::new(state));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let backend = MockBackend::default();
+        let thread_backend = backend.clone();
+        let handle = start_action_thread(
+            Arc::clone(&state),
+            Arc::clone(&is_running),
+            Arc::new(AtomicBool::new(false)),
+            move || Ok(thread_backend.clone()),
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend.calls().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        is_running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        assert_eq!(
+            backend.calls(),
+            [
+                MockCall::MouseDown(ClickButton::Left),
+                MockCall::MouseUp(ClickButton::Left),
+                MockCall::MouseUp(ClickButton::Right),
+                MockCall::MouseUp(ClickButton::Middle),
+            ],
+        );
+    }
+
+    #[test]
+    fn action_thread_survives_a_panic() {
+        let mut state = AppState { click_interval: Interval::fixed(10_000), ..AppState::default() };
+        state.start_clicking(Instant::now());
+        let state = Arc::new(Mutex::new(state));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let backend = MockBackend::default();
+        backend.panic_next();
+        let thread_backend = backend.clone();
+        let handle = start_action_thread(
+            Arc::clone(&state),
+            Arc::clone(&is_running),
+            Arc::new(AtomicBool::new(false)),
+            move || Ok(thread_backend.clone()),
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !state.lock().unwrap().log.contains("Internal error") && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(state.lock().unwrap().active_mode, ActiveMode::None);
+        {
+            let mut state = state.lock().unwrap();
+            state.start_clicking(Instant::now());
+            state.send_update();
+        }
+        while backend.calls().len() < 3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        is_running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        assert!(state.lock().unwrap().log.contains("mock backend told to panic"));
+        assert!(backend.calls().len() >= 3);
+    }
+
+    #[test]
+    fn action_thread_reports_injection_failures_once() {
+        let mut state = AppState { click_interval: Interval::fixed(10_000), ..AppState::default() };
+        state.start_clicking(Instant::now());
+        let state = Arc::new(Mutex::new(state));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let backend = MockBackend::default();
+        backend.fail_with(Some("no display"));
+        let thread_backend = backend.clone();
+        let handle = start_action_thread(
+            Arc::clone(&state),
+            Arc::clone(&is_running),
+            Arc::new(AtomicBool::new(false)),
+            move || Ok(thread_backend.clone()),
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !state.lock().unwrap().injection_failing && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        is_running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.current_status(Instant::now()), "Clicking (errors!)");
+        assert_eq!(state.log.count("Input injection failed: no display"), 1);
+    }
+
+    // Clicks every 5ms on a backend failing with "display gone", which `rebuild` is asked for
+    // again once that has gone on long enough; returns once `done` or after 5s
+    fn run_failing_backend<R>(rebuild: R, done: impl Fn(&AppState) -> bool) -> (Arc<Mutex<AppState>>, MockBackend)
+    where
+        R: FnMut(&MockBackend) -> Result<MockBackend, String> + Send + 'static,
+    {
+        let mut state = AppState { click_interval: Interval::fixed(5_000), ..AppState::default() };
+        state.start_clicking(Instant::now());
+        let state = Arc::new(Mutex::new(state));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let backend = MockBackend::default();
+        backend.fail_with(Some("display gone"));
+        let thread_backend = backend.clone();
+        let mut rebuild = rebuild;
+        let mut built = false;
+        let handle = start_action_thread(
+            Arc::clone(&state),
+            Arc::clone(&is_running),
+            Arc::new(AtomicBool::new(false)),
+            move || {
+                if !std::mem::replace(&mut built, true) {
+                    return Ok(thread_backend.clone());
+                }
+                rebuild(&thread_backend)
+            },
+        );
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(&state.lock().unwrap()) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        is_running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+        (state, backend)
+    }
+
+    #[test]
+    fn action_thread_restarts_a_failing_backend_and_resumes() {
+        // The first rebuild finds the session back
+        let (state, backend) = run_failing_backend(
+            |backend| {
+                backend.fail_with(None);
+                Ok(backend.clone())
+            },
+            |state| state.log.contains("Resumed clicking"),
+        );
+
+        let state = state.lock().unwrap();
+        assert!(state.log.contains("Input injection keeps failing; restarting the input backend"));
+        assert!(state.log.contains("Paused clicking (restarting the input backend)"));
+        assert!(state.log.contains("Restarted the input backend (attempt 1 of 3)"));
+        assert!(state.log.contains("Resumed clicking (input backend restarted)"));
+        assert_eq!(state.active_mode, ActiveMode::Clicking);
+        assert!(backend.calls().len() > backend::INJECTION_REBUILD_AFTER as usize);
+    }
+
+    #[test]
+    fn action_thread_stops_when_rebuilding_the_backend_keeps_failing() {
+        let (state, _) = run_failing_backend(
+            |_| Err("no display".to_string()),
+            // Pausing for the rebuild leaves no mode running either
+            |state| state.status == "Stopped (injection failed)",
+        );
+
+        let state = state.lock().unwrap();
+        assert_eq!(state.active_mode, ActiveMode::None);
+        assert_eq!(state.status, "Stopped (injection failed)");
+        assert_eq!(state.log.count("Couldn't restart the input backend"), BACKEND_REBUILD_ATTEMPTS as usize);
+        assert!(state.log.contains("Input injection still fails after restarting the input backend 3 times; stopped"));
+        assert!(!state.log.contains("Resumed clicking"));
+    }
+
+    // Drives a scheduler the way the action thread does, with the time coming from a MockClock:
+    // every pass ticks at the clock's time, and between passes the clock jumps straight to the
+    // scheduler's next deadline
+    struct Simulation {
+        clock: MockClock,
+        start: Instant,
+        scheduler: ActionScheduler,
+        state: AppState,
+        // Everything performed, by time since the start
+        ops: Vec<(Duration, InputOp)>,
+    }
+
+    impl Simulation {
+        // `state` is started by `start` at the clock's first instant
+        fn new(mut state: AppState, start: impl FnOnce(&mut AppState, Instant)) -> Self {
+            let clock = MockClock::new();
+            let now = clock.now();
+            start(&mut state, now);
+            Simulation { scheduler: ActionScheduler::new(now), start: now, clock, state, ops: Vec::new() }
+        }
+
+        fn elapsed(&self) -> Duration {
+            self.clock.now() - self.start
+        }
+
+        fn pass(&mut self) {
+            let now = self.clock.now();
+            for op in self.scheduler.tick(&mut self.state, now) {
+                self.ops.push((now - self.start, op));
+            }
+        }
+
+        // Pass at every deadline up to `end` after the start, and at `end` itself
+        fn run_until(&mut self, end: Duration) {
+            let end = self.start + end;
+            loop {
+                self.pass();
+                let next = self.scheduler.deadline();
+                // Nothing scheduled, or not before the end
+                if next <= self.clock.now() || next > end {
+                    break;
+                }
+                self.clock.set(next);
+            }
+            if self.clock.now() < end {
+                self.clock.set(end);
+                self.pass();
+            }
+        }
+
+        // Hand the recorded ops over, starting afresh
+        fn take_ops(&mut self) -> Vec<(Duration, InputOp)> {
+            std::mem::take(&mut self.ops)
+        }
+    }
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn simulated_clicking_keeps_to_a_100ms_interval() {
+        let state = AppState { click_interval: Interval::fixed(100_000), ..AppState::default() };
+        let mut sim = Simulation::new(state, AppState::start_clicking);
+        sim.run_until(ms(1_000));
+
+        let expected: Vec<_> = (0..=10).map(|i| (ms(i * 100), InputOp::Tap(left()))).collect();
+        assert_eq!(sim.take_ops(), expected);
+        assert_eq!(sim.state.session.actions(), 11);
+        assert_eq!(sim.scheduler.deadline(), sim.start + ms(1_100));
+    }
+
+    #[test]
+    fn simulated_hold_mode_presses_and_releases_in_cycles() {
+        let state = AppState {
+            click_interval: Interval::fixed(100_000),
+            hold_mode: true,
+            hold_range: true,
+            hold_min_ms: 30,
+            hold_max_ms: 30,
+            ..AppState::default()
+        };
+        let mut sim = Simulation::new(state, AppState::start_clicking);
+        sim.run_until(ms(250));
+
+        assert_eq!(
+            sim.take_ops(),
+            vec![
+                (ms(0), InputOp::Down(left())),
+                (ms(30), InputOp::Up(left())),
+                (ms(100), InputOp::Down(left())),
+                (ms(130), InputOp::Up(left())),
+                (ms(200), InputOp::Down(left())),
+                (ms(230), InputOp::Up(left())),
+            ],
+        );
+        assert!(sim.scheduler.down.is_empty());
+    }
+
+    #[test]
+    fn simulated_mode_switch_mid_hold_lets_go_straight_away() {
+        let mut sim = Simulation::new(AppState::default(), |state, now| {
+            *state = injecting("w", true, now);
+        });
+        sim.run_until(ms(400));
+        assert_eq!(sim.take_ops(), vec![(ms(0), InputOp::Down(key("w")))]);
+
+        // Switched to clicking with w still down for another 600ms: w goes up before the
+        // click's own hold starts, not when its old release time comes around
+        let now = sim.clock.now();
+        sim.state.start_clicking(now);
+        sim.run_until(ms(1_400));
+        assert_eq!(
+            sim.take_ops(),
+            vec![
+                (ms(400), InputOp::Up(key("w"))),
+                (ms(400), InputOp::Down(left())),
+                (ms(1_400), InputOp::Up(left())),
+                (ms(1_400), InputOp::Down(left())),
+            ],
+        );
+        assert_eq!(sim.scheduler.down, vec![left()]);
+    }
+
+    #[test]
+    fn simulated_sequence_wraps_around() {
+        let mut sim = Simulation::new(AppState::default(), |state, now| {
+            *state = injecting("a, b, c", false, now);
+        });
+        sim.run_until(INTERVAL * 4);
+
+        let taps: Vec<_> = ["a", "b", "c", "a", "b"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (INTERVAL * i as u32, InputOp::Tap(key(name))))
+            .collect();
+        assert_eq!(sim.take_ops(), taps);
+        assert_eq!(sim.state.loop_count, 1);
+        assert_eq!(sim.state.current_key_display, "b");
+    }
+
+    #[test]
+    fn simulated_stop_during_the_countdown_never_starts() {
+        let state = AppState { start_delay_s: 3, ..AppState::default() };
+        let mut sim = Simulation::new(state, AppState::start_clicking);
+        sim.run_until(ms(1_500));
+        assert_eq!(sim.scheduler.deadline(), sim.start + Duration::from_secs(3));
+
+        let now = sim.clock.now();
+        sim.state.stop_all("", now);
+        sim.run_until(Duration::from_secs(5));
+        assert_eq!(sim.elapsed(), Duration::from_secs(5));
+        assert_eq!(sim.take_ops(), vec![]);
+        assert_eq!(sim.state.session.actions(), 0);
+        assert!(sim.state.log.contains("Start countdown cancelled"));
+        assert!(!sim.state.log.contains("Actions started"));
+    }
+}
@@ -0,0 +1,240 @@
+// Rhai scripting for automation the UI can't express.
+//
+// Scripts get a deliberately small API:
+//   click()            left click
+//   key(name)          tap a key, using the same names as the Keys field
+//   move_to(x, y)      move the mouse to absolute screen coordinates
+//   sleep(ms)          pause, returning early if the script is stopped
+//   stopped()          true once Stop All was pressed or another mode started
+// print() output goes to the app log.
+
+use crate::backend::{self, InputBackend};
+use crate::keys::{is_named_key, single_char, ActionType, ClickButton};
+use crate::log_info;
+use crate::logging::{self, Category};
+use crate::state::{lock_state, ActiveMode, AppState};
+use rhai::{Dynamic, Engine, EvalAltResult};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How often a sleeping script re-checks the stop flag
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+// Whether the script started as `run_id` should stop
+fn is_stopped(state: &Arc<Mutex<AppState>>, run_id: u64) -> bool {
+    let state = lock_state(state);
+    state.active_mode != ActiveMode::Script || state.script_run_id != run_id
+}
+
+fn log(state: &Arc<Mutex<AppState>>, message: &str) {
+    let mut state = lock_state(state);
+    log_info!(state.log, "{}", message);
+    state.request_repaint();
+}
+
+// Let the hotkey listener know this input is ours, not the user's
+fn record_injection(state: &Arc<Mutex<AppState>>, action: ActionType) {
+    if let Ok(mut injected) = lock_state(state).injected.lock() {
+        injected.record(&action, Instant::now());
+    }
+}
+
+// Injection failures end the script with the error in the log
+fn injection_error(what: &str, error: String) -> Box<EvalAltResult> {
+    format!("{} failed: {}", what, error).into()
+}
+
+fn build_engine(state: &Arc<Mutex<AppState>>, run_id: u64, backend: Box<dyn InputBackend>) -> Engine {
+    let backend = Rc::new(RefCell::new(backend));
+    let mut engine = Engine::new();
+
+    // Keep scripts from exhausting the machine or reaching outside the API
+    engine.set_max_call_levels(64);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(100_000);
+    engine.set_max_map_size(10_000);
+    engine.disable_symbol("eval");
+
+    let click_backend = Rc::clone(&backend);
+    let click_state = Arc::clone(state);
+    engine.register_fn("click", move || {
+        record_injection(&click_state, ActionType::Click(ClickButton::Left));
+        click_backend
+            .borrow_mut()
+            .mouse_click(ClickButton::Left)
+            .map_err(|e| injection_error("click()", e))
+    });
+
+    let key_backend = Rc::clone(&backend);
+    let key_state = Arc::clone(state);
+    engine.register_fn("key", move |name: &str| {
+        if single_char(name).is_none() && !is_named_key(name) {
+            log(&key_state, &format!("Script: unknown key '{}'", name));
+            return Ok(());
+        }
+        record_injection(&key_state, ActionType::KeyPress(name.to_string()));
+        let layout = lock_state(&key_state).keyboard_layout;
+        let mut backend = key_backend.borrow_mut();
+        backend.set_layout(layout);
+        backend.key_click(name).map_err(|e| injection_error("key()", e))
+    });
+
+    let move_backend = Rc::clone(&backend);
+    engine.register_fn("move_to", move |x: i64, y: i64| {
+        let x = x.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        let y = y.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        move_backend.borrow_mut().move_to(x, y).map_err(|e| injection_error("move_to()", e))
+    });
+
+    let sleep_state = Arc::clone(state);
+    engine.register_fn("sleep", move |ms: i64| {
+        let deadline = Instant::now() + Duration::from_millis(ms.max(0) as u64);
+        while !is_stopped(&sleep_state, run_id) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            thread::sleep(remaining.min(SLEEP_SLICE));
+        }
+    });
+
+    let stopped_state = Arc::clone(state);
+    engine.register_fn("stopped", move || is_stopped(&stopped_state, run_id));
+
+    // Also stop scripts that loop without ever calling sleep() or stopped()
+    let progress_state = Arc::clone(state);
+    engine.on_progress(move |ops| {
+        if ops % 1000 == 0 && is_stopped(&progress_state, run_id) {
+            Some(Dynamic::UNIT)
+        } else {
+            None
+        }
+    });
+
+    let print_state = Arc::clone(state);
+    engine.on_print(move |text| log(&print_state, &format!("Script: {}", text)));
+
+    engine
+}
+
+// Run the script file on its own thread; errors end up in the log instead of killing anything
+pub fn spawn_script(path: &Path, state: Arc<Mutex<AppState>>, run_id: u64) -> thread::JoinHandle<()> {
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        logging::set_thread_category(Category::Action);
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(e) => {
+                log(&state, &format!("Failed to read script {}: {}", path.display(), e));
+                stop_if_current(&state, run_id, "Script failed");
+                return;
+            },
+        };
+
+        // The same backend the settings pick for everything else
+        let kind = lock_state(&state).input_backend;
+        match backend::build_backend(kind) {
+            Ok(backend) => run_script(&source, &state, run_id, backend),
+            Err(e) => {
+                log(&state, &format!("Script can't inject input: {}", e));
+                stop_if_current(&state, run_id, "Script failed");
+            },
+        }
+    })
+}
+
+// Run `source` to the end on this thread, injecting through `backend`
+pub fn run_script(source: &str, state: &Arc<Mutex<AppState>>, run_id: u64, backend: Box<dyn InputBackend>) {
+    let engine = build_engine(state, run_id, backend);
+    match engine.run(source) {
+        Ok(()) => stop_if_current(state, run_id, "Script finished"),
+        Err(e) => match *e {
+            EvalAltResult::ErrorTerminated(..) => log(state, "Script stopped"),
+            _ => {
+                log(state, &format!("Script error: {}", e));
+                stop_if_current(state, run_id, "Script failed");
+            },
+        },
+    }
+}
+
+// Return to idle unless the user already moved on to something else
+fn stop_if_current(state: &Arc<Mutex<AppState>>, run_id: u64, message: &str) {
+    let mut state = lock_state(state);
+    if state.active_mode == ActiveMode::Script && state.script_run_id == run_id {
+        state.set_mode(ActiveMode::None, "Stopped", &format!("{}\n", message), Instant::now());
+        state.send_update();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, MockCall};
+
+    // State running the script started as run 1
+    fn script_state() -> Arc<Mutex<AppState>> {
+        let state = AppState { active_mode: ActiveMode::Script, script_run_id: 1, ..AppState::default() };
+        Arc::new(Mutex::new(state))
+    }
+
+    #[test]
+    fn api_calls_reach_the_backend() {
+        let state = script_state();
+        let mock = MockBackend::default();
+        run_script(r#"click(); key("a"); key("space"); move_to(10, 20); print("done")"#, &state, 1, Box::new(mock.clone()));
+        assert_eq!(
+            mock.calls(),
+            vec![
+                MockCall::MouseClick(ClickButton::Left),
+                MockCall::KeyClick("a".to_string()),
+                MockCall::KeyClick("space".to_string()),
+                MockCall::MoveTo(10, 20),
+            ],
+        );
+        let state = lock_state(&state);
+        assert!(state.log.contains("Script: done"));
+        assert!(state.log.contains("Script finished"));
+        assert_eq!(state.active_mode, ActiveMode::None);
+    }
+
+    #[test]
+    fn unknown_keys_are_logged_and_skipped() {
+        let state = script_state();
+        let mock = MockBackend::default();
+        run_script(r#"key("spcae"); key("b")"#, &state, 1, Box::new(mock.clone()));
+        assert_eq!(mock.calls(), vec![MockCall::KeyClick("b".to_string())]);
+        assert!(lock_state(&state).log.contains("Script: unknown key 'spcae'"));
+    }
+
+    #[test]
+    fn injection_failure_ends_the_script() {
+        let state = script_state();
+        let mock = MockBackend::default();
+        mock.fail_with(Some("blocked"));
+        run_script(r#"click(); key("a")"#, &state, 1, Box::new(mock.clone()));
+        assert_eq!(mock.calls(), vec![MockCall::MouseClick(ClickButton::Left)]);
+        let state = lock_state(&state);
+        assert!(state.log.contains("click() failed: blocked"));
+        assert!(state.log.contains("Script failed"));
+        assert_eq!(state.active_mode, ActiveMode::None);
+    }
+
+    #[test]
+    fn a_replaced_run_stops_without_touching_the_mode() {
+        // Run 1 was replaced by run 2, so run 1 sees stopped() and sleep() returns at once
+        let state = script_state();
+        lock_state(&state).script_run_id = 2;
+        let mock = MockBackend::default();
+        let started = Instant::now();
+        run_script("sleep(10000); while !stopped() { click(); }", &state, 1, Box::new(mock.clone()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(mock.calls().is_empty());
+        assert_eq!(lock_state(&state).active_mode, ActiveMode::Script);
+    }
+}
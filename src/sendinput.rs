@@ -0,0 +1,134 @@
+// Injects straight through Win32 SendInput, skipping enigo's per-call overhead.
+//
+// A click goes out as one SendInput call carrying both the press and the release, so it costs
+// a single trip into the kernel. Keys are sent by virtual-key code where they have one and as
+// Unicode characters otherwise, which doesn't depend on the keyboard layout.
+
+use crate::backend::{keyboard_allowed, mouse_allowed, InputBackend};
+use crate::keys::{is_extended_virtual_key, map_key_str_to_virtual_key, ClickButton};
+use std::mem;
+use winapi::ctypes::c_int;
+use winapi::shared::minwindef::DWORD;
+use winapi::um::winuser::{
+    GetSystemMetrics, SendInput, INPUT, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT, KEYEVENTF_EXTENDEDKEY,
+    KEYEVENTF_KEYUP, KEYEVENTF_UNICODE, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP,
+    MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP,
+    MOUSEEVENTF_VIRTUALDESK, MOUSEINPUT, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+    SM_YVIRTUALSCREEN,
+};
+
+pub struct SendInputBackend(());
+
+impl SendInputBackend {
+    pub fn new() -> Result<Self, String> {
+        Ok(SendInputBackend(()))
+    }
+}
+
+fn mouse_input(flags: DWORD, dx: i32, dy: i32) -> INPUT {
+    // SAFETY: INPUT is plain data for which all zeroes is valid; the union is then set to the
+    // mouse variant that type_ says it holds
+    unsafe {
+        let mut input: INPUT = mem::zeroed();
+        input.type_ = INPUT_MOUSE;
+        *input.u.mi_mut() = MOUSEINPUT { dx, dy, mouseData: 0, dwFlags: flags, time: 0, dwExtraInfo: 0 };
+        input
+    }
+}
+
+fn key_input(vk: u16, scan: u16, flags: DWORD) -> INPUT {
+    // SAFETY: as in mouse_input, with the keyboard variant
+    unsafe {
+        let mut input: INPUT = mem::zeroed();
+        input.type_ = INPUT_KEYBOARD;
+        *input.u.ki_mut() = KEYBDINPUT { wVk: vk, wScan: scan, dwFlags: flags, time: 0, dwExtraInfo: 0 };
+        input
+    }
+}
+
+fn button_flags(button: ClickButton) -> (DWORD, DWORD) {
+    match button {
+        ClickButton::Left => (MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP),
+        ClickButton::Right => (MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP),
+        ClickButton::Middle => (MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP),
+    }
+}
+
+// The inputs for pressing (or releasing) `key`
+fn key_inputs(key: &str, up: bool) -> Result<Vec<INPUT>, String> {
+    keyboard_allowed()?;
+    let up_flag = if up { KEYEVENTF_KEYUP } else { 0 };
+    if let Some(vk) = map_key_str_to_virtual_key(key) {
+        let extended = if is_extended_virtual_key(vk) { KEYEVENTF_EXTENDEDKEY } else { 0 };
+        return Ok(vec![key_input(vk, 0, up_flag | extended)]);
+    }
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => {
+            let mut units = [0; 2];
+            Ok(c.encode_utf16(&mut units).iter().map(|&unit| key_input(0, unit, up_flag | KEYEVENTF_UNICODE)).collect())
+        },
+        _ => Err(format!("unknown key '{}'", key)),
+    }
+}
+
+// Send `inputs` in one call. SendInput reports nothing but how many went through; none at all
+// usually means the target runs with higher privileges
+fn send(mut inputs: Vec<INPUT>) -> Result<(), String> {
+    // SAFETY: the pointer and count describe `inputs`, which outlives the call
+    let sent = unsafe { SendInput(inputs.len() as u32, inputs.as_mut_ptr(), mem::size_of::<INPUT>() as c_int) };
+    if sent as usize == inputs.len() {
+        Ok(())
+    } else {
+        Err(format!("SendInput sent {} of {} inputs (blocked, e.g. by a window running as administrator?)", sent, inputs.len()))
+    }
+}
+
+impl InputBackend for SendInputBackend {
+    fn mouse_down(&mut self, button: ClickButton) -> Result<(), String> {
+        mouse_allowed()?;
+        send(vec![mouse_input(button_flags(button).0, 0, 0)])
+    }
+
+    fn mouse_up(&mut self, button: ClickButton) -> Result<(), String> {
+        mouse_allowed()?;
+        send(vec![mouse_input(button_flags(button).1, 0, 0)])
+    }
+
+    fn mouse_click(&mut self, button: ClickButton) -> Result<(), String> {
+        mouse_allowed()?;
+        let (down, up) = button_flags(button);
+        send(vec![mouse_input(down, 0, 0), mouse_input(up, 0, 0)])
+    }
+
+    fn key_down(&mut self, key: &str) -> Result<(), String> {
+        send(key_inputs(key, false)?)
+    }
+
+    fn key_up(&mut self, key: &str) -> Result<(), String> {
+        send(key_inputs(key, true)?)
+    }
+
+    fn key_click(&mut self, key: &str) -> Result<(), String> {
+        let mut inputs = key_inputs(key, false)?;
+        inputs.extend(key_inputs(key, true)?);
+        send(inputs)
+    }
+
+    fn move_to(&mut self, x: i32, y: i32) -> Result<(), String> {
+        mouse_allowed()?;
+        // Absolute moves are in 0..=65535 across the whole virtual desktop
+        // SAFETY: plain Win32 calls without pointers
+        let (left, top, width, height) = unsafe {
+            (
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+                GetSystemMetrics(SM_CXVIRTUALSCREEN),
+                GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            )
+        };
+        let scale = |position: i32, origin: i32, size: i32| ((position - origin) as i64 * 65_535 / (size - 1).max(1) as i64) as i32;
+        let flags = MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK;
+        send(vec![mouse_input(flags, scale(x, left, width), scale(y, top, height))])
+    }
+}
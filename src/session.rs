@@ -0,0 +1,147 @@
+// How long the current run has been going and how many actions it performed.
+//
+// Started whenever a mode starts, frozen when it stops so the last run's numbers stay on screen,
+// and continued (not restarted) when a paused mode is resumed.
+
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Default)]
+pub struct SessionStats {
+    started: Option<Instant>,
+    stopped: Option<Instant>,
+    actions: u64,
+}
+
+impl SessionStats {
+    // A fresh run starting at `now`
+    pub fn start(&mut self, now: Instant) {
+        *self = SessionStats { started: Some(now), stopped: None, actions: 0 };
+    }
+
+    // Freeze the numbers; returns them as a log line if a run was going
+    pub fn stop(&mut self, now: Instant) -> Option<String> {
+        if !self.is_running() {
+            return None;
+        }
+        self.stopped = Some(now);
+        Some(format!("Ran {} — {} actions", format_clock(self.elapsed(now)), group_thousands(self.actions)))
+    }
+
+    // Pick a stopped run back up, leaving the time it was stopped out of the elapsed time
+    pub fn resume(&mut self, now: Instant) {
+        if let (Some(started), Some(stopped)) = (self.started, self.stopped.take()) {
+            self.started = Some(started + now.saturating_duration_since(stopped));
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.started.is_some() && self.stopped.is_none()
+    }
+
+    pub fn record(&mut self, count: u64) {
+        if self.is_running() {
+            self.actions += count;
+        }
+    }
+
+    pub fn actions(&self) -> u64 {
+        self.actions
+    }
+
+    // Time since the start, up to the stop once stopped
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        match self.started {
+            Some(started) => self.stopped.unwrap_or(now).saturating_duration_since(started),
+            None => Duration::ZERO,
+        }
+    }
+
+    // "Running 04:12 — 2,531 actions", or the frozen numbers of the last run; None before any run
+    pub fn readout(&self, now: Instant) -> Option<String> {
+        self.started?;
+        let verb = if self.is_running() { "Running" } else { "Last run" };
+        Some(format!("{} {} — {} actions", verb, format_clock(self.elapsed(now)), group_thousands(self.actions)))
+    }
+}
+
+// "04:12", or "1:04:12" past an hour
+pub fn format_clock(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
+// 2531 as "2,531"
+pub fn group_thousands(n: u64) -> String {
+    let digits = n.to_string();
+    let mut grouped = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_and_freezes_a_run() {
+        let start = Instant::now();
+        let mut session = SessionStats::default();
+        assert_eq!(session.readout(start), None);
+        session.record(5);
+        assert_eq!(session.actions(), 0);
+
+        session.start(start);
+        session.record(2_530);
+        session.record(1);
+        let running = start + Duration::from_secs(252);
+        assert_eq!(session.readout(running).as_deref(), Some("Running 04:12 — 2,531 actions"));
+
+        assert_eq!(session.stop(running).as_deref(), Some("Ran 04:12 — 2,531 actions"));
+        assert_eq!(session.stop(running), None);
+        session.record(10);
+        let later = running + Duration::from_secs(60);
+        assert_eq!(session.readout(later).as_deref(), Some("Last run 04:12 — 2,531 actions"));
+    }
+
+    #[test]
+    fn resuming_leaves_out_the_paused_time() {
+        let start = Instant::now();
+        let mut session = SessionStats::default();
+        session.start(start);
+        session.stop(start + Duration::from_secs(10));
+        session.resume(start + Duration::from_secs(70));
+        assert!(session.is_running());
+        assert_eq!(session.elapsed(start + Duration::from_secs(75)), Duration::from_secs(15));
+    }
+
+    #[test]
+    fn starting_again_resets() {
+        let start = Instant::now();
+        let mut session = SessionStats::default();
+        session.start(start);
+        session.record(3);
+        session.start(start + Duration::from_secs(5));
+        assert_eq!(session.actions(), 0);
+        assert_eq!(session.elapsed(start + Duration::from_secs(6)), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn formats_clock_and_counts() {
+        assert_eq!(format_clock(Duration::from_secs(0)), "00:00");
+        assert_eq!(format_clock(Duration::from_secs(3_725)), "1:02:05");
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1_000), "1,000");
+        assert_eq!(group_thousands(1_234_567), "1,234,567");
+    }
+}
@@ -0,0 +1,174 @@
+// The settings kept between runs in eframe's storage (see AutoClickerApp::save).
+//
+// Only what the user chose is stored: no run progress, and nothing that belongs to this session
+// such as the window filter or the detected display server. Restoring goes through the same
+// checks as editing, so an outdated or damaged store can't bring back an interval or sequence
+// the UI would have refused.
+
+use crate::hotkeys::HotkeyBindings;
+use crate::keyboard_layout::KeyboardLayout;
+use crate::state::{AppState, INTERVAL_RANGE, MAX_START_DELAY_S};
+use crate::timing::{self, FirstAction, Interval, LatePolicy};
+use serde::{Deserialize, Serialize};
+
+// Where the settings go in eframe's key-value storage
+pub const STORAGE_KEY: &str = "settings";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedSettings {
+    pub click_interval: Interval,
+    pub key_interval: Interval,
+    pub interval_as_cps: bool,
+    pub late_policy: LatePolicy,
+    pub first_action: FirstAction,
+    pub keys: String,
+    pub keyboard_layout: KeyboardLayout,
+    pub hold_mode: bool,
+    pub hold_range: bool,
+    pub hold_min_ms: u64,
+    pub hold_max_ms: u64,
+    pub hotkeys: HotkeyBindings,
+    pub suppress_hotkeys: bool,
+    pub interval_step: u64,
+    pub interval_step_percent: bool,
+    pub interval_presets: Vec<u64>,
+    pub start_delay_s: u64,
+    pub action_limit: u64,
+    pub duration_limit_us: u64,
+    pub log_to_file: bool,
+    // The profiles themselves have files of their own (see profiles)
+    pub active_profile: Option<String>,
+}
+
+// Anything missing from an older store keeps its default
+impl Default for SavedSettings {
+    fn default() -> Self {
+        SavedSettings::of(&AppState::default())
+    }
+}
+
+// Keep every value the interval controls allow in range
+fn checked_interval(mut interval: Interval) -> Interval {
+    let (low, high) = (*INTERVAL_RANGE.start(), *INTERVAL_RANGE.end());
+    interval.us = interval.us.clamp(low, high);
+    interval.min_us = interval.min_us.clamp(low, high);
+    interval.max_us = interval.max_us.clamp(low, high);
+    interval.std_dev_us = interval.std_dev_us.min(high);
+    interval.fix_range();
+    interval
+}
+
+impl SavedSettings {
+    pub fn of(state: &AppState) -> Self {
+        SavedSettings {
+            click_interval: state.click_interval,
+            key_interval: state.key_interval,
+            interval_as_cps: state.interval_as_cps,
+            late_policy: state.late_policy,
+            first_action: state.first_action,
+            keys: state.key_to_inject.clone(),
+            keyboard_layout: state.keyboard_layout,
+            hold_mode: state.hold_mode,
+            hold_range: state.hold_range,
+            hold_min_ms: state.hold_min_ms,
+            hold_max_ms: state.hold_max_ms,
+            hotkeys: state.hotkeys,
+            suppress_hotkeys: state.suppress_hotkeys,
+            interval_step: state.interval_step,
+            interval_step_percent: state.interval_step_percent,
+            interval_presets: state.interval_presets.clone(),
+            start_delay_s: state.start_delay_s,
+            action_limit: state.action_limit,
+            duration_limit_us: state.duration_limit_us,
+            log_to_file: state.log_to_file,
+            active_profile: state.active_profile.clone(),
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        serde_json::from_str(json).map_err(|e| e.to_string())
+    }
+
+    pub fn apply_to(self, state: &mut AppState) {
+        state.click_interval = checked_interval(self.click_interval);
+        state.key_interval = checked_interval(self.key_interval);
+        state.interval_as_cps = self.interval_as_cps;
+        state.late_policy = self.late_policy;
+        state.first_action = self.first_action;
+        state.key_to_inject = self.keys;
+        state.keyboard_layout = self.keyboard_layout;
+        state.hold_mode = self.hold_mode;
+        state.hold_range = self.hold_range;
+        state.hold_min_ms = self.hold_min_ms.clamp(1, 600_000);
+        state.hold_max_ms = self.hold_max_ms.clamp(1, 600_000).max(state.hold_min_ms);
+        state.hotkeys = self.hotkeys;
+        state.suppress_hotkeys = self.suppress_hotkeys;
+        state.interval_step = self.interval_step;
+        state.interval_step_percent = self.interval_step_percent;
+        state.interval_presets = self.interval_presets.into_iter().filter(|us| INTERVAL_RANGE.contains(us)).collect();
+        state.interval_presets.sort_unstable();
+        state.interval_presets.dedup();
+        state.start_delay_s = self.start_delay_s.min(MAX_START_DELAY_S);
+        state.action_limit = self.action_limit;
+        state.duration_limit_us = self.duration_limit_us;
+        state.duration_limit_entry = match self.duration_limit_us {
+            0 => String::new(),
+            us => timing::format_hms(us),
+        };
+        state.log_to_file = self.log_to_file;
+        // Only a profile that was loaded can be active
+        state.active_profile = self.active_profile.filter(|name| state.profiles.iter().any(|p| &p.name == name));
+        // Re-validate the sequence and hotkeys just like an edit in the Keys field
+        state.parse_key_sequence();
+        state.mirror_hot_settings();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_and_validates_like_an_edit() {
+        let mut state = AppState { hold_mode: true, ..AppState::default() };
+        state.click_interval.us = 250_000;
+        state.key_to_inject = "a, bogus, wait(50)".to_string();
+        state.start_delay_s = 5;
+        let json = SavedSettings::of(&state).to_json();
+
+        let mut restored = AppState::default();
+        restored.parse_key_sequence();
+        SavedSettings::from_json(&json).unwrap().apply_to(&mut restored);
+        assert_eq!(restored.click_interval.us, 250_000);
+        assert!(restored.hold_mode);
+        assert_eq!(restored.start_delay_s, 5);
+        assert_eq!(restored.key_to_inject, "a, bogus, wait(50)");
+        assert_eq!(restored.steps.len(), 2);
+        assert_eq!(restored.sequence_errors[0].token, "bogus");
+    }
+
+    #[test]
+    fn out_of_range_values_are_pulled_back() {
+        let json = r#"{"click_interval": {"us": 0, "min_us": 900, "max_us": 5}, "start_delay_s": 999, "interval_presets": [0, 10000]}"#;
+        let mut state = AppState::default();
+        SavedSettings::from_json(json).unwrap().apply_to(&mut state);
+        assert_eq!(state.click_interval.us, 1);
+        assert_eq!((state.click_interval.min_us, state.click_interval.max_us), (900, 900));
+        assert_eq!(state.start_delay_s, MAX_START_DELAY_S);
+        assert_eq!(state.interval_presets, vec![10_000]);
+        // Missing fields keep their defaults
+        assert_eq!(state.key_interval, Interval::default());
+        assert_eq!(state.key_to_inject, AppState::default().key_to_inject);
+    }
+
+    #[test]
+    fn corrupt_store_is_an_error() {
+        assert!(SavedSettings::from_json("{\"click_interval\": ").is_err());
+        assert!(SavedSettings::from_json("[1, 2]").is_err());
+    }
+}
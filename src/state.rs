@@ -0,0 +1,1696 @@
+// The state shared between the UI, the hotkey listener and the action thread.
+
+use crate::active_window;
+use crate::backend::{self, BackendKind};
+use crate::display_server::DisplayServer;
+use crate::http_api;
+use crate::hotkeys::{Activation, Hotkey, HotkeyAction, HotkeyBindings, Modifiers, PhysicalInput};
+use crate::keyboard_layout::KeyboardLayout;
+use crate::keys::{map_key_str_to_rdev_key, ActionType, SequenceStep, StepKind, TokenError};
+use crate::listener::{InjectionFilter, InputProbe, ListenerStatus};
+use crate::{log_debug, log_error, log_info, log_warn};
+use crate::logging::{Category, LogBuffer};
+use crate::macro_dsl::{self, Program};
+use crate::permissions::Permission;
+use crate::presets::Preset;
+use crate::profiles::Profile;
+use crate::schedule::{self, Recurrence};
+use crate::session::SessionStats;
+use crate::timing::{self, FirstAction, Interval, IntervalField, LatePolicy, MissedDeadlines, RateMeter};
+use chrono::{DateTime, Local, NaiveDateTime};
+use eframe::egui;
+use log::LevelFilter;
+use rdev::Key as RdevKey;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// Define activation modes
+#[derive(Clone, PartialEq, Debug)]
+pub enum ActiveMode {
+    None,
+    Clicking,
+    KeystrokeInjection,
+    Macro,
+    Script,
+}
+
+impl ActiveMode {
+    // The modes a scheduled start can run
+    pub const STARTABLE: [ActiveMode; 4] = [
+        ActiveMode::Clicking,
+        ActiveMode::KeystrokeInjection,
+        ActiveMode::Macro,
+        ActiveMode::Script,
+    ];
+    
+    pub fn label(&self) -> &'static str {
+        match self {
+            ActiveMode::None => "Idle",
+            ActiveMode::Clicking => "Clicking",
+            ActiveMode::KeystrokeInjection => "Keystroke injection",
+            ActiveMode::Macro => "Macro",
+            ActiveMode::Script => "Script",
+        }
+    }
+    
+    // Whether this build can run the mode; see backend::MOUSE_ENABLED. Macros and scripts
+    // always can, and find out per step when they use what's missing
+    pub fn is_available(&self) -> bool {
+        match self {
+            ActiveMode::Clicking => backend::MOUSE_ENABLED,
+            ActiveMode::KeystrokeInjection => backend::KEYBOARD_ENABLED,
+            _ => true,
+        }
+    }
+    
+    // Which interval the mode runs at, for log lines
+    pub fn interval_label(&self) -> &'static str {
+        match self {
+            ActiveMode::KeystrokeInjection => "Key interval",
+            _ => "Click interval",
+        }
+    }
+    
+    // As stored in HotSettings
+    pub fn to_u8(&self) -> u8 {
+        self.clone() as u8
+    }
+    
+    pub fn from_u8(value: u8) -> ActiveMode {
+        match value {
+            1 => ActiveMode::Clicking,
+            2 => ActiveMode::KeystrokeInjection,
+            3 => ActiveMode::Macro,
+            4 => ActiveMode::Script,
+            _ => ActiveMode::None,
+        }
+    }
+    
+    // Status line shown while the mode runs
+    pub fn running_status(&self) -> &'static str {
+        match self {
+            ActiveMode::None => "Stopped",
+            ActiveMode::Clicking => "Clicking...",
+            ActiveMode::KeystrokeInjection => "Injecting keystrokes...",
+            ActiveMode::Macro => "Running macro...",
+            ActiveMode::Script => "Running script...",
+        }
+    }
+}
+
+// What the UI side sends the action thread, which runs on a copy of the state of its own so
+// performing inputs never waits for the UI's lock
+pub enum Command {
+    // The state as the UI side left it; see ActionCopy::apply for what the thread takes from it
+    Update(Box<AppState>),
+    // The user touched the mouse or keyboard, for idle activation
+    UserInput(Instant),
+}
+
+// Lock-free mirrors of what the action thread checks every pass, written the moment they change
+// so the thread doesn't have to wait for the next update. The AppState fields stay the source
+// of truth; these are only ever copied from them
+#[derive(Debug, Default)]
+pub struct HotSettings {
+    pub active_mode: AtomicU8,
+    pub hold_mode: AtomicBool,
+    pub click_interval_us: AtomicU64,
+    pub key_interval_us: AtomicU64,
+}
+
+impl HotSettings {
+    pub fn active_mode(&self) -> ActiveMode {
+        ActiveMode::from_u8(self.active_mode.load(Ordering::SeqCst))
+    }
+    
+    // Bring `state`'s mirrored settings up to date; returns whether its mode is still the
+    // current one
+    pub fn load_into(&self, state: &mut AppState) -> bool {
+        state.hold_mode = self.hold_mode.load(Ordering::SeqCst);
+        state.click_interval.us = self.click_interval_us.load(Ordering::SeqCst);
+        state.key_interval.us = self.key_interval_us.load(Ordering::SeqCst);
+        self.active_mode() == state.active_mode
+    }
+}
+
+// Lock the shared state. If a thread panicked while holding the lock, its changes may be half
+// made: the state is taken over anyway, the run is stopped and the log says so, rather than
+// every later lock failing and the UI going quietly dead
+pub fn lock_state(state: &Mutex<AppState>) -> MutexGuard<'_, AppState> {
+    state.lock().unwrap_or_else(|poisoned| {
+        state.clear_poison();
+        let mut state = poisoned.into_inner();
+        log_error!(state.log, "INTERNAL ERROR: a thread crashed while updating the state; recovered and stopped everything");
+        state.stop_all("", Instant::now());
+        state.send_update();
+        state.request_repaint();
+        state
+    })
+}
+
+// Source of run_epoch values; shared by every copy so the UI side and the action thread never
+// hand out the same one
+static NEXT_RUN_EPOCH: AtomicU64 = AtomicU64::new(1);
+
+// A mode stopped by the pause hotkey, with enough of its progress to pick up where it left off
+#[derive(Clone, Debug)]
+pub struct PausedMode {
+    pub mode: ActiveMode,
+    pub key_index: usize,
+    pub step: Option<usize>,
+    pub loop_count: u64,
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    // Time between clicks and between sequence steps (and the length of each hold), kept apart
+    // so switching modes doesn't mean retyping the interval
+    pub click_interval: Interval,
+    pub key_interval: Interval,
+    // Show intervals as clicks per second; only the UI converts, everything else stays in ms
+    pub interval_as_cps: bool,
+    // What the scheduler does after falling more than an interval behind
+    pub late_policy: LatePolicy,
+    // Whether a run starts with an action or with a wait of one interval
+    pub first_action: FirstAction,
+    // Where updates for the action thread go; None in the thread's own copy
+    pub commands: Option<Sender<Command>>,
+    // Renewed whenever the mode or the run's progress is reset, so the action thread can tell
+    // whether an update or its own copy has the newer progress
+    pub run_epoch: u64,
+    // Shared by every copy of the state
+    pub hot: Arc<HotSettings>,
+    // The UI's context, for other threads to ask for a repaint after changing what it shows
+    pub repaint: Option<egui::Context>,
+    // When the action thread last performed inputs, for the achieved rate; separately locked
+    // so recording never waits for the state
+    pub rate_meter: Arc<Mutex<RateMeter>>,
+    // Spin through the last moments before each deadline instead of trusting the OS sleep
+    pub precision_timing: bool,
+    pub precision_priority: bool,
+    // Actions that ran more than late_tolerance_ms after their deadline during this run
+    pub missed_deadlines: MissedDeadlines,
+    pub late_tolerance_ms: u64,
+    // What a 1 ms sleep actually takes here, measured at startup; shorter intervals need
+    // precision timing to be kept
+    pub sleep_granularity: Option<Duration>,
+    // How inputs are injected, and whether the backends are being measured against each other
+    pub input_backend: BackendKind,
+    // The backend the action thread actually injects through, as of its last pass
+    pub backend_in_use: Option<BackendKind>,
+    pub benchmark_running: bool,
+    // Guardrail against typos like 1 ms for 1 s: drawn intervals never go below 1 / max_cps.
+    // rate_capped says whether the last draw was clamped, for the UI
+    pub rate_cap: bool,
+    pub max_cps: f64,
+    pub rate_capped: bool,
+    pub rate_cap_logged: bool,
+    // For warm_up_s after each start the rate eases up from warm_up_start_cps; 0 turns it off
+    pub warm_up_s: u64,
+    pub warm_up_start_cps: f64,
+    // An interval outside SAFE_INTERVAL_RANGE waiting for the user to confirm it
+    pub extreme_interval: Option<(ActiveMode, IntervalField, u64)>,
+    pub active_mode: ActiveMode,
+    pub last_action: Instant,
+    // Only run while the user has been idle for idle_threshold_s. last_user_input excludes
+    // our own injected input; idle_active is whether actions were running as of the last pass
+    pub idle_activation: bool,
+    pub idle_threshold_s: u64,
+    pub last_user_input: Instant,
+    pub idle_active: bool,
+    // Set by the action thread after several injection calls in a row failed
+    pub injection_failing: bool,
+    pub status: String,
+    pub log: LogBuffer,
+    // The least important entries the log area shows
+    pub log_level: LevelFilter,
+    // The categories the log area shows
+    pub log_categories: Vec<Category>,
+    // Mirror the log to a rotated file in the config directory (see log_file)
+    pub log_to_file: bool,
+    pub key_to_inject: String,
+    pub keyboard_layout: KeyboardLayout,
+    pub current_key_index: usize,
+    pub current_step: Option<usize>,
+    // The sequence_revision the position above was recorded against; see key_index
+    pub position_revision: Option<u64>,
+    pub loop_count: u64,
+    pub current_key_display: String,
+    pub upcoming_keys: Vec<String>,
+    pub steps: Vec<SequenceStep>,
+    // Counts edits to the steps, so the action thread can tell a key it holds belongs to an old sequence
+    pub sequence_revision: u64,
+    pub hold_mode: bool,
+    // In hold mode, hold each press for a random length in this range (ms) and space the
+    // presses by the interval, instead of holding for the interval itself
+    pub hold_range: bool,
+    pub hold_min_ms: u64,
+    pub hold_max_ms: u64,
+    pub settings_dirty: bool,
+    // Separately locked so the action thread can record what it injects without the state
+    pub injected: Arc<Mutex<InjectionFilter>>,
+    pub hotkey_conflicts: Vec<String>,
+    pub hotkeys: HotkeyBindings,
+    // Action waiting for the next key press to become its hotkey
+    pub capturing_hotkey: Option<HotkeyAction>,
+    // A left/right click binding waiting for the user to confirm it
+    pub pending_hotkey: Option<(HotkeyAction, PhysicalInput, Modifiers)>,
+    // Swallow bound hotkeys so the focused app doesn't see them
+    pub suppress_hotkeys: bool,
+    // Whether the grab listener has taken over from the plain one
+    pub hotkey_grab_active: bool,
+    pub hotkey_listener: ListenerStatus,
+    // The session we run in, detected at startup; decides which listener and backend are used
+    pub display_server: DisplayServer,
+    // Why global hotkeys or injection can't work at all, kept on screen until they can
+    pub hotkeys_unavailable: Option<String>,
+    pub injection_unavailable: Option<String>,
+    // The UI thread should explain injection_unavailable in a dialog; set on the first failure
+    pub injection_dialog_pending: bool,
+    // The Retry button was pressed; the action thread builds the backend again and clears it
+    pub injection_retry_requested: bool,
+    // macOS privacy permissions not granted yet, rechecked until they are
+    pub missing_permissions: Vec<Permission>,
+    // Accept commands over the local socket (see ipc); ipc_listening while its thread runs
+    pub ipc_enabled: bool,
+    pub ipc_listening: bool,
+    // Accept requests on 127.0.0.1:http_port carrying http_token (see http_api)
+    pub http_enabled: bool,
+    pub http_port: u16,
+    pub http_token: String,
+    // Title of the focused window while it runs as administrator and so can't receive our input
+    pub elevated_target: Option<String>,
+    // Whether the listener has been seen receiving anything since startup
+    pub input_probe: InputProbe,
+    // The probe failed and the UI thread should explain which permission is missing
+    pub permission_dialog_pending: bool,
+    // Off turns every hotkey except stop and panic into a normal key
+    pub hotkeys_enabled: bool,
+    // One of our own text fields has keyboard focus, so typed keys aren't hotkeys
+    pub editing_text: bool,
+    pub paused_mode: Option<PausedMode>,
+    // How far the interval hotkeys move the interval, in ms or percent
+    pub interval_step: u64,
+    pub interval_step_percent: bool,
+    // One-click intervals under each interval control, in microseconds
+    pub interval_presets: Vec<u64>,
+    // The interval typed as a duration ("15m", "1h30m") for each mode, and why the last one didn't parse
+    pub click_interval_entry: String,
+    pub key_interval_entry: String,
+    pub interval_entry_error: Option<(ActiveMode, String)>,
+    // Longest gap between the two presses of a double-tap hotkey
+    pub double_tap_window_ms: u64,
+    // Activation hotkeys are ignored for this long after the mode changes
+    pub mode_cooldown_ms: u64,
+    pub mode_changed_at: Option<Instant>,
+    // Seconds between starting a mode and its first action, to switch to the target window
+    pub start_delay_s: u64,
+    // When the current start's countdown ends; nothing is performed until then
+    pub start_pending_until: Option<Instant>,
+    // Start `scheduled_mode` when the wall clock reaches `scheduled_start`
+    pub start_at_entry: String,
+    pub start_at_error: Option<String>,
+    pub scheduled_mode: ActiveMode,
+    pub scheduled_start: Option<DateTime<Local>>,
+    // Weekly runs of `scheduled_mode`, with the occurrence currently running and the one the
+    // user stopped by hand, which isn't restarted
+    pub recurrence: Recurrence,
+    pub recurrence_time_entry: String,
+    pub recurrence_running: Option<NaiveDateTime>,
+    pub recurrence_skipped: Option<NaiveDateTime>,
+    // Alternate between running for duty_active_s and resting for duty_rest_s, counted from the
+    // start of the run
+    pub duty_cycle: bool,
+    pub duty_active_s: u64,
+    pub duty_rest_s: u64,
+    // Stop after this many actions; 0 runs until stopped
+    pub action_limit: u64,
+    // Stop this long after the first action was due; 0 runs until stopped. Typed as a duration
+    pub duration_limit_us: u64,
+    pub duration_limit_entry: String,
+    pub duration_limit_error: Option<String>,
+    // Time and actions since the mode started (or since its start countdown ended)
+    pub session: SessionStats,
+    pub profiles: Vec<Profile>,
+    pub active_profile: Option<String>,
+    // Inputs for saving the current settings as a profile
+    pub profile_name: String,
+    pub profile_with_hotkeys: bool,
+    // What the one-shot hotkey presses, written like a Keys field token
+    pub one_shot: String,
+    // A one-shot action waiting for the action thread's next pass
+    pub pending_one_shot: Option<ActionType>,
+    // Set by the panic hotkey; the action thread releases everything it has down
+    pub panic_requested: bool,
+    // Hotkeys only work while the focused window's title contains this; empty means anywhere
+    pub window_filter: String,
+    // Pause the running mode while that window isn't focused and resume when it is again
+    pub pause_outside_window: bool,
+    // The current pause came from losing focus, so regaining it may resume
+    pub paused_for_focus: bool,
+    // When "Use current window" reads the title, leaving time to switch to the target
+    pub window_capture_at: Option<Instant>,
+    pub sequence_errors: Vec<TokenError>,
+    pub macro_script: String,
+    pub macro_error: Option<String>,
+    pub macro_program: Option<Program>,
+    pub macro_run_id: u64,
+    pub script_path: Option<PathBuf>,
+    pub script_run_id: u64,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            click_interval: Interval::default(),
+            key_interval: Interval::default(),
+            interval_as_cps: false,
+            late_policy: LatePolicy::default(),
+            first_action: FirstAction::default(),
+            commands: None,
+            run_epoch: 0,
+            hot: Arc::new(HotSettings::default()),
+            repaint: None,
+            rate_meter: Arc::new(Mutex::new(RateMeter::default())),
+            precision_timing: false,
+            precision_priority: false,
+            missed_deadlines: MissedDeadlines::default(),
+            late_tolerance_ms: 10,
+            sleep_granularity: None,
+            input_backend: BackendKind::default(),
+            backend_in_use: None,
+            benchmark_running: false,
+            rate_cap: true,
+            max_cps: 100.0,
+            rate_capped: false,
+            rate_cap_logged: false,
+            warm_up_s: 0,
+            warm_up_start_cps: 2.0,
+            extreme_interval: None,
+            active_mode: ActiveMode::None,
+            last_action: Instant::now(),
+            idle_activation: false,
+            idle_threshold_s: 60,
+            last_user_input: Instant::now(),
+            idle_active: false,
+            injection_failing: false,
+            status: "Stopped".to_string(),
+            log: LogBuffer::default(),
+            log_level: LevelFilter::Info,
+            log_categories: Category::ALL.to_vec(),
+            log_to_file: false,
+            key_to_inject: "w, s".to_string(), 
+            keyboard_layout: KeyboardLayout::default(),
+            current_key_index: 0,
+            current_step: None,
+            position_revision: None,
+            loop_count: 0,
+            current_key_display: String::new(),
+            upcoming_keys: Vec::new(),
+            steps: vec![
+                SequenceStep::new(StepKind::Key("w".to_string())),
+                SequenceStep::new(StepKind::Key("s".to_string())),
+            ],
+            sequence_revision: 0,
+            hold_mode: false,
+            hold_range: false,
+            hold_min_ms: 50,
+            hold_max_ms: 150,
+            settings_dirty: false,
+            injected: Arc::new(Mutex::new(InjectionFilter::default())),
+            hotkey_conflicts: Vec::new(),
+            hotkeys: HotkeyBindings::default(),
+            capturing_hotkey: None,
+            pending_hotkey: None,
+            suppress_hotkeys: false,
+            hotkey_grab_active: false,
+            hotkey_listener: ListenerStatus::Running,
+            display_server: DisplayServer::Other,
+            hotkeys_unavailable: None,
+            injection_unavailable: None,
+            injection_dialog_pending: false,
+            injection_retry_requested: false,
+            missing_permissions: Vec::new(),
+            ipc_enabled: false,
+            ipc_listening: false,
+            http_enabled: false,
+            http_port: http_api::DEFAULT_PORT,
+            http_token: http_api::random_token(),
+            elevated_target: None,
+            input_probe: InputProbe::Pending,
+            permission_dialog_pending: false,
+            hotkeys_enabled: true,
+            editing_text: false,
+            paused_mode: None,
+            interval_step: 10,
+            interval_step_percent: false,
+            interval_presets: DEFAULT_INTERVAL_PRESETS.to_vec(),
+            click_interval_entry: String::new(),
+            key_interval_entry: String::new(),
+            interval_entry_error: None,
+            double_tap_window_ms: 400,
+            mode_cooldown_ms: 250,
+            mode_changed_at: None,
+            start_delay_s: 0,
+            start_pending_until: None,
+            start_at_entry: String::new(),
+            start_at_error: None,
+            scheduled_mode: if backend::MOUSE_ENABLED { ActiveMode::Clicking } else { ActiveMode::KeystrokeInjection },
+            scheduled_start: None,
+            recurrence: Recurrence::default(),
+            recurrence_time_entry: "09:00".to_string(),
+            recurrence_running: None,
+            recurrence_skipped: None,
+            duty_cycle: false,
+            duty_active_s: 30,
+            duty_rest_s: 90,
+            action_limit: 0,
+            duration_limit_us: 0,
+            duration_limit_entry: String::new(),
+            duration_limit_error: None,
+            session: SessionStats::default(),
+            profiles: Vec::new(),
+            active_profile: None,
+            profile_name: String::new(),
+            profile_with_hotkeys: false,
+            one_shot: "click".to_string(),
+            pending_one_shot: None,
+            panic_requested: false,
+            window_filter: String::new(),
+            pause_outside_window: false,
+            paused_for_focus: false,
+            window_capture_at: None,
+            sequence_errors: Vec::new(),
+            macro_script: "hold(w, 500); click; wait(200); repeat(3) { press(space); wait(100) }".to_string(),
+            macro_error: None,
+            macro_program: None,
+            macro_run_id: 0,
+            script_path: None,
+            script_run_id: 0,
+        }
+    }
+}
+
+// Intervals the UI and the interval hotkeys allow
+pub const INTERVAL_RANGE: RangeInclusive<u64> = 1..=86_400_000_000;
+
+// An interval given as milliseconds, fractions allowed, as microseconds. What the command line
+// and the remote controls accept
+pub fn parse_interval_ms(text: &str) -> Result<u64, String> {
+    let ms: f64 = text.parse().map_err(|_| format!("'{}' isn't a number of milliseconds", text))?;
+    let us = (ms * 1000.0).round() as u64;
+    if !ms.is_finite() || !INTERVAL_RANGE.contains(&us) {
+        return Err(format!("{} ms is out of range", text));
+    }
+    Ok(us)
+}
+// Intervals outside 1 ms to 10 minutes have to be confirmed, since they're usually a typo
+pub const SAFE_INTERVAL_RANGE: RangeInclusive<u64> = 1_000..=600_000_000;
+
+// Longest start delay the UI offers
+pub const MAX_START_DELAY_S: u64 = 60;
+
+// The quick interval presets offered until the user edits them
+pub const DEFAULT_INTERVAL_PRESETS: [u64; 6] = [10_000, 50_000, 100_000, 500_000, 1_000_000, 5_000_000];
+
+// Mode, hold mode, the interval and the sequence revision the schedule was computed from
+pub type ScheduleInputs = (ActiveMode, bool, Interval, u64);
+
+// How many keys after the current one are previewed in the UI
+pub const UPCOMING_KEY_PREVIEW: usize = 4;
+
+impl AppState {
+    // Whether `mode` can start or resume right now; logs why not
+    pub fn can_start(&mut self, mode: &ActiveMode) -> bool {
+        if !mode.is_available() {
+            log_warn!(self.log, "Can't start: {} isn't part of this build", mode.label().to_lowercase());
+            return false;
+        }
+        if *mode == ActiveMode::None {
+            return true;
+        }
+        if let Some(reason) = &self.injection_unavailable {
+            log_warn!(self.log, "Can't start: input injection is unavailable ({})", reason);
+            return false;
+        }
+        if *mode == ActiveMode::KeystrokeInjection {
+            if let Some(error) = self.sequence_errors.first() {
+                log_warn!(
+                    self.log,
+                    "Cannot inject sequence: key {} '{}' is invalid ({})",
+                    error.index + 1,
+                    error.token,
+                    error.message,
+                );
+                return false;
+            }
+            if !self.has_enabled_steps() {
+                log_warn!(self.log, "Cannot inject empty key sequence!");
+                return false;
+            }
+        }
+        true
+    }
+    
+    // Helper to update state for a given mode
+    pub fn set_mode(&mut self, mode: ActiveMode, status: &str, log_message: &str, now: Instant) {
+        if !self.can_start(&mode) {
+            return;
+        }
+        // Clone the mode for later comparison
+        let mode_clone = mode.clone();
+        self.active_mode = mode;
+        self.status = status.to_string();
+        self.log.push_str(log_message);
+        self.last_action = now;
+        self.mode_changed_at = Some(now);
+        self.renew_run_epoch();
+        self.hot.active_mode.store(self.active_mode.to_u8(), Ordering::SeqCst);
+        if mode_clone == ActiveMode::None && self.start_pending_until.take().is_some() {
+            log_info!(self.log, "Start countdown cancelled");
+        }
+        if let Some(summary) = self.session.stop(now) {
+            log_info!(self.log, "{}", summary);
+        }
+        if mode_clone != ActiveMode::None {
+            self.start_pending_until = (self.start_delay_s > 0).then(|| now + Duration::from_secs(self.start_delay_s));
+            self.session.start(now);
+            self.missed_deadlines.clear();
+            self.rate_cap_logged = false;
+            self.idle_active = false;
+            self.settings_dirty = false;
+            // Starting something new abandons whatever was paused
+            self.paused_mode = None;
+        }
+        
+        // Clear key display if stopping
+        if mode_clone == ActiveMode::None {
+            self.current_key_display = String::new();
+            self.upcoming_keys.clear();
+        }
+        // Set initial key display if starting keystroke injection
+        else if mode_clone == ActiveMode::KeystrokeInjection {
+            if let Some(first) = self.next_enabled_index_from(0) {
+                self.current_key_display = self.steps[first].to_token();
+                self.upcoming_keys = self.preview_keys_from(first + 1);
+            }
+            self.current_step = None;
+            self.loop_count = 0;
+        }
+    }
+    
+    // Load a built-in template; nothing starts until the user presses Start
+    pub fn apply_preset(&mut self, preset: &Preset) {
+        self.key_to_inject = preset.keys.to_string();
+        self.key_interval = Interval::fixed(preset.interval_ms * 1_000);
+        self.hold_mode = preset.hold_mode;
+        self.parse_key_sequence();
+        self.settings_dirty = true;
+        log_info!(
+            self.log,
+            "Applied preset '{}': keys '{}', interval {}ms, hold mode {}",
+            preset.name,
+            preset.keys,
+            preset.interval_ms,
+            if preset.hold_mode { "on" } else { "off" },
+        );
+    }
+    
+    // Re-check the macro script after an edit so errors show while typing
+    pub fn validate_macro(&mut self) {
+        self.macro_error = macro_dsl::parse(&self.macro_script).err().map(|e| e.to_string());
+    }
+    
+    // Compile the macro script and start running it, refusing scripts with errors
+    pub fn start_macro(&mut self, now: Instant) {
+        match macro_dsl::parse(&self.macro_script) {
+            Ok(program) => {
+                self.macro_program = Some(program);
+                self.macro_error = None;
+                self.macro_run_id += 1;
+                self.set_mode(ActiveMode::Macro, "Running macro...", "Started macro\n", now);
+            },
+            Err(e) => {
+                log_error!(self.log, "Macro error at {}", e);
+                self.macro_error = Some(e.to_string());
+            },
+        }
+    }
+    
+    // Shared start path for the button and its hotkey
+    pub fn start_keystroke_injection(&mut self, now: Instant) {
+        if !self.can_start(&ActiveMode::KeystrokeInjection) {
+            return;
+        }
+        // Store key_to_inject in a temporary variable before calling set_mode
+        let log_message = format!(
+            "Started injecting keys '{}' ({})\n",
+            self.key_to_inject, self.hotkeys.name(HotkeyAction::StartKeys)
+        );
+        self.set_mode(
+            ActiveMode::KeystrokeInjection,
+            "Injecting keystrokes...",
+            &log_message,
+            now
+        );
+        self.current_key_index = 0;
+        self.flag_hotkey_conflicts();
+    }
+    
+    pub fn start_clicking(&mut self, now: Instant) {
+        let log_message = format!("Started clicking! ({})\n", self.hotkeys.name(HotkeyAction::StartClicking));
+        self.set_mode(ActiveMode::Clicking, "Clicking...", &log_message, now);
+        self.flag_hotkey_conflicts();
+    }
+    
+    // Conflicts don't block starting; the echo filter keeps injected input from triggering
+    // hotkeys, but the user should know the setup is ambiguous
+    pub fn flag_hotkey_conflicts(&mut self) {
+        if self.hotkey_conflicts.is_empty() {
+            return;
+        }
+        for conflict in &self.hotkey_conflicts {
+            log_warn!(self.log, "running with unresolved hotkey conflict: {}", conflict);
+        }
+        self.status.push_str(" ⚠ hotkey conflict");
+    }
+    
+    // Run the action bound to a hotkey, honouring its toggle setting
+    pub fn trigger_hotkey(&mut self, action: HotkeyAction, now: Instant) {
+        let name = self.hotkeys.name(action);
+        let toggle = self.hotkeys.get(action).activation == Activation::Toggle;
+        match action {
+            HotkeyAction::StartKeys if toggle && self.active_mode == ActiveMode::KeystrokeInjection => {
+                let log_message = format!("Toggled keystroke injection off ({})\n", name);
+                self.set_mode(ActiveMode::None, "Stopped", &log_message, now);
+            },
+            HotkeyAction::StartKeys => self.start_keystroke_injection(now),
+            HotkeyAction::StartClicking if toggle && self.active_mode == ActiveMode::Clicking => {
+                let log_message = format!("Toggled clicking off ({})\n", name);
+                self.set_mode(ActiveMode::None, "Stopped", &log_message, now);
+            },
+            HotkeyAction::StartClicking => self.start_clicking(now),
+            HotkeyAction::Stop => self.stop_all(&format!("Stopped all actions! ({})\n", name), now),
+            HotkeyAction::PauseResume => self.toggle_pause(&name, now),
+            HotkeyAction::ShorterInterval => self.adjust_interval(false, &name),
+            HotkeyAction::LongerInterval => self.adjust_interval(true, &name),
+            HotkeyAction::NextProfile => self.cycle_profile(&name, now),
+            HotkeyAction::OneShot => self.queue_one_shot(&name),
+            HotkeyAction::Panic => self.panic(now),
+        }
+    }
+    
+    // Drop everything, whatever state we're in, and have the action thread release all inputs
+    pub fn panic(&mut self, now: Instant) {
+        self.paused_mode = None;
+        self.pending_one_shot = None;
+        self.capturing_hotkey = None;
+        self.panic_requested = true;
+        self.set_mode(ActiveMode::None, "Stopped", "PANIC: released all inputs\n", now);
+    }
+    
+    // Hand one click or key press to the action thread without touching the running mode
+    pub fn queue_one_shot(&mut self, hotkey: &str) {
+        let action = SequenceStep::parse_token(self.one_shot.trim())
+            .and_then(|step| step.action().ok_or_else(|| "waits can't be one-shots".to_string()));
+        match action {
+            Ok(action) => {
+                log_info!(self.log, "One-shot: {} ({})", self.one_shot.trim(), hotkey);
+                self.pending_one_shot = Some(action);
+            },
+            Err(message) => {
+                log_warn!(self.log, "Invalid one-shot action '{}': {}", self.one_shot.trim(), message);
+            },
+        }
+    }
+    
+    // The current settings as a profile called `name`
+    pub fn current_profile(&self, name: &str, with_hotkeys: bool) -> Profile {
+        Profile {
+            name: name.to_string(),
+            click_interval_us: self.click_interval.us,
+            key_interval_us: self.key_interval.us,
+            keys: self.key_to_inject.clone(),
+            hold_mode: self.hold_mode,
+            hold_range: self.hold_range,
+            hold_min_ms: self.hold_min_ms,
+            hold_max_ms: self.hold_max_ms,
+            hotkeys: with_hotkeys.then_some(self.hotkeys),
+        }
+    }
+    
+    // Take over `profile`'s settings, leaving the active profile alone
+    pub fn load_settings(&mut self, profile: &Profile) {
+        self.click_interval.us = profile.click_interval_us;
+        self.key_interval.us = profile.key_interval_us;
+        self.key_to_inject = profile.keys.clone();
+        self.hold_mode = profile.hold_mode;
+        self.hold_range = profile.hold_range;
+        self.hold_min_ms = profile.hold_min_ms.max(1);
+        self.hold_max_ms = profile.hold_max_ms.max(self.hold_min_ms);
+        if let Some(hotkeys) = profile.hotkeys {
+            self.hotkeys = hotkeys;
+        }
+        // Re-validate the sequence just like an edit in the Keys field
+        self.parse_key_sequence();
+    }
+    
+    // The active profile's name for the title and status line, with an asterisk once the
+    // settings no longer match what it holds
+    pub fn active_profile_label(&self) -> Option<String> {
+        let name = self.active_profile.as_ref()?;
+        let profile = self.profiles.iter().find(|p| &p.name == name)?;
+        if self.current_profile(name, profile.hotkeys.is_some()) == *profile {
+            Some(name.clone())
+        } else {
+            Some(format!("{}*", name))
+        }
+    }
+    
+    pub fn profile_index(&self, name: &str) -> Option<usize> {
+        self.profiles.iter().position(|p| p.name == name.trim())
+    }
+    
+    // Save the current settings under the typed name, replacing a profile of the same name.
+    // Returns the profile for the caller to write to its file
+    pub fn save_profile(&mut self) -> Option<Profile> {
+        let name = self.profile_name.trim().to_string();
+        if name.is_empty() {
+            log_warn!(self.log, "Enter a profile name first!");
+            return None;
+        }
+        let profile = self.current_profile(&name, self.profile_with_hotkeys);
+        match self.profiles.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = profile.clone(),
+            None => {
+                self.profiles.push(profile.clone());
+                self.profiles.sort_by(|a, b| a.name.cmp(&b.name));
+            },
+        }
+        self.active_profile = Some(name.clone());
+        log_info!(self.log, "Saved profile '{}'", name);
+        Some(profile)
+    }
+    
+    // Forget a saved profile. Returns it for the caller to remove its file
+    pub fn delete_profile(&mut self, index: usize) -> Option<Profile> {
+        if index >= self.profiles.len() {
+            return None;
+        }
+        let removed = self.profiles.remove(index);
+        if self.active_profile.as_deref() == Some(removed.name.as_str()) {
+            self.active_profile = None;
+        }
+        log_info!(self.log, "Deleted profile '{}'", removed.name);
+        Some(removed)
+    }
+    
+    // Switch to a saved profile, stopping whatever is running first
+    pub fn apply_profile(&mut self, index: usize, now: Instant) {
+        let Some(profile) = self.profiles.get(index).cloned() else {
+            return;
+        };
+        if self.active_mode != ActiveMode::None || self.paused_mode.is_some() {
+            self.stop_all("Stopped to switch profiles\n", now);
+        }
+        self.load_settings(&profile);
+        self.active_profile = Some(profile.name.clone());
+        self.profile_name = profile.name.clone();
+        log_info!(self.log, "Switched to profile '{}'", profile.name);
+        if !self.sequence_errors.is_empty() {
+            log_warn!(self.log, "The profile's key sequence has errors; fix them before starting");
+        }
+    }
+    
+    pub fn cycle_profile(&mut self, hotkey: &str, now: Instant) {
+        if self.profiles.is_empty() {
+            log_warn!(self.log, "No profiles to cycle through ({})", hotkey);
+            return;
+        }
+        let current = self.active_profile
+            .as_ref()
+            .and_then(|name| self.profiles.iter().position(|p| &p.name == name));
+        let next = current.map_or(0, |i| (i + 1) % self.profiles.len());
+        self.apply_profile(next, now);
+    }
+    
+    // Nudge the interval of the running (or paused) mode by the configured step; the scheduler
+    // picks it up for the next action
+    pub fn adjust_interval(&mut self, longer: bool, hotkey: &str) {
+        let mode = self.paused_mode.as_ref().map(|p| p.mode.clone()).unwrap_or_else(|| self.active_mode.clone());
+        let (step, percent) = (self.interval_step, self.interval_step_percent);
+        let interval = self.interval_mut(&mode);
+        let step = if percent {
+            (interval.us / 100 * step).max(1)
+        } else {
+            step * 1_000
+        };
+        let adjusted = if longer {
+            interval.us.saturating_add(step)
+        } else {
+            interval.us.saturating_sub(step)
+        };
+        // Hotkeys can't reach the extremes that need confirming in the UI
+        let (low, high) = (*SAFE_INTERVAL_RANGE.start(), *SAFE_INTERVAL_RANGE.end());
+        interval.us = adjusted.clamp(low.min(interval.us), high.max(interval.us));
+        let us = interval.us;
+        self.mirror_hot_settings();
+        log_info!(self.log, "{} set to {} ({})", mode.interval_label(), timing::format_us(us), hotkey);
+    }
+    
+    // How long the run has been performing actions; a start countdown doesn't count
+    pub fn run_elapsed(&self, now: Instant) -> Duration {
+        if self.start_pending_until.is_some() {
+            return Duration::ZERO;
+        }
+        self.session.elapsed(now)
+    }
+    
+    // Count actions the scheduler just performed and stop once the limit is reached
+    pub fn record_actions(&mut self, count: u64, now: Instant) {
+        if count == 0 || self.active_mode == ActiveMode::None {
+            return;
+        }
+        self.session.record(count);
+        if self.action_limit > 0 && self.session.actions() >= self.action_limit {
+            let message = format!("Completed {} actions in {:.1}s\n", self.session.actions(), self.run_elapsed(now).as_secs_f64());
+            self.stop_all(&message, now);
+        }
+    }
+    
+    // Stop once the run has lasted as long as the duration limit; whichever limit hits first wins
+    pub fn check_duration_limit(&mut self, now: Instant) {
+        if self.duration_limit_us == 0 || self.active_mode == ActiveMode::None {
+            return;
+        }
+        let elapsed = self.run_elapsed(now);
+        if elapsed >= Duration::from_micros(self.duration_limit_us) {
+            let message = format!(
+                "Time limit reached after {} ({} actions)\n",
+                timing::format_hms(elapsed.as_micros() as u64),
+                self.session.actions(),
+            );
+            self.stop_all(&message, now);
+        }
+    }
+    
+    // The delay before `mode`'s next action, or the length of its next hold, never shorter
+    // than the rate cap allows
+    pub fn draw_interval(&mut self, mode: &ActiveMode, now: Instant) -> Duration {
+        let mut interval = self.interval_for(mode).draw();
+        if let Some(progress) = self.warm_up_progress(now) {
+            interval = timing::warm_up_interval(interval, self.warm_up_start_cps, progress);
+        }
+        let floor = Duration::from_micros(timing::cps_to_us(self.max_cps));
+        self.rate_capped = self.rate_cap && interval < floor;
+        if !self.rate_capped {
+            return interval;
+        }
+        if !self.rate_cap_logged {
+            self.rate_cap_logged = true;
+            log_info!(
+                self.log,
+                "Interval {} is faster than the {} CPS cap; running at {} instead",
+                timing::format_us(interval.as_micros() as u64),
+                self.max_cps,
+                timing::format_us(floor.as_micros() as u64),
+            );
+        }
+        floor
+    }
+    
+    // How far through the warm-up the run is, from 0 to 1; None once it's over or without one
+    pub fn warm_up_progress(&self, now: Instant) -> Option<f64> {
+        if self.warm_up_s == 0 || self.active_mode == ActiveMode::None {
+            return None;
+        }
+        let progress = self.run_elapsed(now).as_secs_f64() / self.warm_up_s as f64;
+        (progress < 1.0).then_some(progress)
+    }
+    
+    // How long the next hold lasts: a fresh draw from the hold range, or the interval without one
+    pub fn hold_length(&mut self, interval: Duration) -> Duration {
+        if !self.hold_range {
+            return interval;
+        }
+        let range = timing::Delay::Uniform { min: self.hold_min_ms * 1_000, max: self.hold_max_ms * 1_000 };
+        let hold = Duration::from_micros(range.sample(&mut rand::thread_rng()));
+        log_debug!(self.log, "Holding for {}", timing::format_us(hold.as_micros() as u64));
+        hold
+    }
+    
+    // The status as of `now`: countdowns and waits take over from the mode's own status
+    pub fn current_status(&self, now: Instant) -> String {
+        let seconds = |left: Duration| left.as_secs_f64().ceil() as u64;
+        if let Some(until) = self.start_pending_until {
+            return format!("Starting in {}…", seconds(until.saturating_duration_since(now)).max(1));
+        }
+        if let Some(rest) = self.duty_rest_remaining(now) {
+            return format!("Resting ({}s left)", seconds(rest));
+        }
+        if let Some(wait) = self.idle_wait_remaining(now) {
+            return format!("Waiting for idle ({}s)", seconds(wait));
+        }
+        if self.injection_failing && self.active_mode != ActiveMode::None {
+            return format!("{} (errors!)", self.active_mode.label());
+        }
+        if self.idle_activation && self.active_mode != ActiveMode::None {
+            return format!("Active (idle) · {}", self.status);
+        }
+        match self.scheduled_start {
+            Some(at) if self.active_mode == ActiveMode::None => format!(
+                "Scheduled for {} (in {})",
+                at.format("%H:%M"),
+                schedule::format_until(Local::now(), at),
+            ),
+            _ => self.status.clone(),
+        }
+    }
+    
+    // How long until the user has been idle for long enough; None when actions may run
+    pub fn idle_wait_remaining(&self, now: Instant) -> Option<Duration> {
+        if !self.idle_activation || self.active_mode == ActiveMode::None {
+            return None;
+        }
+        let idle = now.saturating_duration_since(self.last_user_input);
+        Duration::from_secs(self.idle_threshold_s).checked_sub(idle).filter(|wait| !wait.is_zero())
+    }
+    
+    // The listener saw the user's own input; a run waiting on idle has to stop right away
+    pub fn note_user_input(&mut self, now: Instant) {
+        self.last_user_input = now;
+        if let (true, Some(commands)) = (self.idle_activation, &self.commands) {
+            let _ = commands.send(Command::UserInput(now));
+        }
+    }
+    
+    // How much of the current duty-cycle rest is left; None while active or without a cycle
+    pub fn duty_rest_remaining(&self, now: Instant) -> Option<Duration> {
+        if !self.duty_cycle || self.duty_active_s == 0 || self.duty_rest_s == 0 || self.active_mode == ActiveMode::None {
+            return None;
+        }
+        let period = self.duty_active_s + self.duty_rest_s;
+        let phase = Duration::from_micros((self.run_elapsed(now).as_micros() % (period as u128 * 1_000_000)) as u64);
+        let active = Duration::from_secs(self.duty_active_s);
+        (phase >= active).then(|| Duration::from_secs(period) - phase)
+    }
+    
+    // What's left before a limit stops the run, for the UI
+    pub fn limits_remaining(&self, now: Instant) -> Vec<String> {
+        let mut remaining = Vec::new();
+        if self.action_limit > 0 {
+            remaining.push(format!("{} actions", self.action_limit.saturating_sub(self.session.actions())));
+        }
+        if self.duration_limit_us > 0 {
+            let left = Duration::from_micros(self.duration_limit_us).saturating_sub(self.run_elapsed(now));
+            remaining.push(timing::format_hms(left.as_micros() as u64));
+        }
+        remaining
+    }
+    
+    // Stop everything, including a paused mode waiting to be resumed
+    pub fn stop_all(&mut self, log_message: &str, now: Instant) {
+        self.paused_mode = None;
+        self.set_mode(ActiveMode::None, "Stopped", log_message, now);
+    }
+    
+    // Pause the running mode, or resume the paused one exactly where it stopped
+    pub fn toggle_pause(&mut self, hotkey: &str, now: Instant) {
+        if self.active_mode == ActiveMode::None {
+            let Some(paused) = self.paused_mode.take() else {
+                log_info!(self.log, "Nothing to resume ({})", hotkey);
+                return;
+            };
+            // Checked like any other start; it stays paused until whatever is wrong is fixed
+            if !self.can_start(&paused.mode) {
+                self.paused_mode = Some(paused);
+                return;
+            }
+            self.active_mode = paused.mode.clone();
+            self.status = paused.mode.running_status().to_string();
+            self.last_action = now;
+            self.mode_changed_at = Some(now);
+            self.renew_run_epoch();
+            self.hot.active_mode.store(self.active_mode.to_u8(), Ordering::SeqCst);
+            self.session.resume(now);
+            self.current_key_index = paused.key_index;
+            self.current_step = paused.step;
+            self.loop_count = paused.loop_count;
+            if let Some(step) = paused.step.filter(|&step| step < self.steps.len()) {
+                self.current_key_display = self.steps[step].to_token();
+                self.upcoming_keys = self.preview_keys_from(self.current_key_index);
+            }
+            self.start_pending_until = (self.start_delay_s > 0).then(|| now + Duration::from_secs(self.start_delay_s));
+            log_info!(self.log, "Resumed {} ({})", paused.mode.label().to_lowercase(), hotkey);
+            self.flag_hotkey_conflicts();
+            return;
+        }
+        // A script only runs while its mode is active, so it can't be continued later
+        if self.active_mode == ActiveMode::Script {
+            log_warn!(self.log, "Scripts can't be paused; use Stop instead");
+            return;
+        }
+        let paused = PausedMode {
+            mode: self.active_mode.clone(),
+            key_index: self.current_key_index,
+            step: self.current_step,
+            loop_count: self.loop_count,
+        };
+        let status = format!("Paused (was {})", paused.mode.label());
+        let log_message = format!("Paused {} ({})\n", paused.mode.label().to_lowercase(), hotkey);
+        self.set_mode(ActiveMode::None, &status, &log_message, now);
+        self.paused_mode = Some(paused);
+    }
+    
+    // Auto-pause when the target window loses focus and resume when it comes back. Pausing or
+    // stopping by hand takes precedence, so only pauses made here are resumed
+    pub fn check_window_focus(&mut self, title: Option<&str>, now: Instant) {
+        if self.paused_mode.is_none() {
+            self.paused_for_focus = false;
+        }
+        if !self.pause_outside_window || self.window_filter.trim().is_empty() {
+            return;
+        }
+        let focused = active_window::title_matches(&self.window_filter, title);
+        let filter = self.window_filter.trim().to_string();
+        if !focused && matches!(self.active_mode, ActiveMode::Clicking | ActiveMode::KeystrokeInjection | ActiveMode::Macro) {
+            self.toggle_pause(&format!("'{}' lost focus", filter), now);
+            self.paused_for_focus = true;
+        } else if focused && self.paused_for_focus && self.active_mode == ActiveMode::None {
+            self.toggle_pause(&format!("'{}' focused", filter), now);
+            self.paused_for_focus = false;
+        }
+    }
+    
+    // Read the focused window's title once the "Use current window" countdown runs out
+    pub fn poll_window_capture(&mut self, now: Instant) {
+        if self.window_capture_at.is_none_or(|at| now < at) {
+            return;
+        }
+        self.window_capture_at = None;
+        match active_window::foreground_title().filter(|title| !title.trim().is_empty()) {
+            Some(title) => {
+                log_info!(self.log, "Window filter set to '{}'", title);
+                self.window_filter = title;
+            },
+            None => log_warn!(self.log, "Couldn't read the focused window's title on this system"),
+        }
+    }
+    
+    // The interval `mode` runs at; modes without one of their own share the click interval
+    pub fn interval_for(&self, mode: &ActiveMode) -> &Interval {
+        match mode {
+            ActiveMode::KeystrokeInjection => &self.key_interval,
+            _ => &self.click_interval,
+        }
+    }
+    
+    pub fn interval_mut(&mut self, mode: &ActiveMode) -> &mut Interval {
+        match mode {
+            ActiveMode::KeystrokeInjection => &mut self.key_interval,
+            _ => &mut self.click_interval,
+        }
+    }
+    
+    // Everything the action thread's timing and holds depend on; a change means rescheduling
+    pub fn schedule_inputs(&self) -> ScheduleInputs {
+        (self.active_mode.clone(), self.hold_mode, *self.interval_for(&self.active_mode), self.sequence_revision)
+    }
+    
+    // Hand the action thread a copy of the state once a change is complete, waking it if it
+    // sleeps. The log stays here, and a requested panic or one-shot goes with the copy
+    pub fn send_update(&mut self) {
+        if self.commands.is_none() {
+            return;
+        }
+        self.mirror_hot_settings();
+        let log = std::mem::take(&mut self.log);
+        let update = self.clone();
+        self.log = log;
+        self.panic_requested = false;
+        self.pending_one_shot = None;
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(Command::Update(Box::new(update)));
+        }
+    }
+    
+    // Wake the UI for a change made outside it; it only repaints by itself while something runs
+    pub fn request_repaint(&self) {
+        if let Some(ctx) = &self.repaint {
+            ctx.request_repaint();
+        }
+    }
+    
+    // Copy the settings HotSettings mirrors. The mode is left out: it's stored on every mode
+    // change, while this copy's may be behind the action thread's
+    pub fn mirror_hot_settings(&self) {
+        self.hot.hold_mode.store(self.hold_mode, Ordering::SeqCst);
+        self.hot.click_interval_us.store(self.click_interval.us, Ordering::SeqCst);
+        self.hot.key_interval_us.store(self.key_interval.us, Ordering::SeqCst);
+    }
+    
+    // Mark the run's progress as reset here, so it wins over the other side's copy
+    pub fn renew_run_epoch(&mut self) {
+        self.run_epoch = NEXT_RUN_EPOCH.fetch_add(1, Ordering::Relaxed);
+    }
+    
+    // Copy the mode and how far the run has got; the rest of the state is settings, which only
+    // the UI side changes
+    pub fn take_progress_from(&mut self, other: &AppState) {
+        self.run_epoch = other.run_epoch;
+        self.active_mode = other.active_mode.clone();
+        self.status = other.status.clone();
+        self.last_action = other.last_action;
+        self.mode_changed_at = other.mode_changed_at;
+        self.paused_mode = other.paused_mode.clone();
+        self.paused_for_focus = other.paused_for_focus;
+        self.current_key_index = other.current_key_index;
+        self.current_step = other.current_step;
+        self.position_revision = other.position_revision;
+        self.loop_count = other.loop_count;
+        self.current_key_display = other.current_key_display.clone();
+        self.upcoming_keys = other.upcoming_keys.clone();
+        self.session = other.session.clone();
+        self.missed_deadlines = other.missed_deadlines.clone();
+        self.rate_capped = other.rate_capped;
+        self.rate_cap_logged = other.rate_cap_logged;
+        self.idle_active = other.idle_active;
+        self.injection_failing = other.injection_failing;
+        self.start_pending_until = other.start_pending_until;
+        self.scheduled_start = other.scheduled_start;
+        self.recurrence_running = other.recurrence_running;
+        self.recurrence_skipped = other.recurrence_skipped;
+        self.macro_program = other.macro_program.clone();
+        self.macro_run_id = other.macro_run_id;
+        self.script_run_id = other.script_run_id;
+    }
+    
+    // Whether a hotkey press comes too soon after the last mode change. Flapping start/stop
+    // leaves hold mode half-released, so starting and pausing wait; stop and panic never do
+    pub fn in_mode_cooldown(&self, action: HotkeyAction, now: Instant) -> bool {
+        let activates = action.mode().is_some() || action == HotkeyAction::PauseResume;
+        activates && self.mode_changed_at.is_some_and(|at| {
+            now.saturating_duration_since(at) < Duration::from_millis(self.mode_cooldown_ms)
+        })
+    }
+    
+    // A hold-to-run hotkey was let go; stop its mode unless something else took over meanwhile
+    pub fn release_hotkey(&mut self, action: HotkeyAction, now: Instant) {
+        if self.hotkeys.get(action).activation != Activation::Hold || action.mode().as_ref() != Some(&self.active_mode) {
+            return;
+        }
+        let log_message = format!("Released {}, stopped {}\n", self.hotkeys.name(action), action.label().trim_start_matches("start "));
+        self.set_mode(ActiveMode::None, "Stopped", &log_message, now);
+    }
+    
+    // Handle the input captured while rebinding; left and right clicks wait for confirmation
+    pub fn capture_hotkey(&mut self, action: HotkeyAction, input: PhysicalInput, modifiers: Modifiers) {
+        self.capturing_hotkey = None;
+        if input == PhysicalInput::Key(RdevKey::Escape) && modifiers == Modifiers::NONE {
+            log_info!(self.log, "Hotkey rebinding cancelled");
+            return;
+        }
+        if input.is_primary_button() {
+            self.pending_hotkey = Some((action, input, modifiers));
+            return;
+        }
+        self.bind_hotkey(action, input, modifiers);
+    }
+    
+    // Bind a combination to an action, unless another action already uses it
+    pub fn bind_hotkey(&mut self, action: HotkeyAction, input: PhysicalInput, modifiers: Modifiers) {
+        let name = Hotkey { input, modifiers, ..*self.hotkeys.get(action) }.name();
+        match self.hotkeys.action_for(input, modifiers) {
+            Some(other) if other != action => {
+                log_info!(self.log, "{} is already the {} hotkey; pick another key", name, other.label());
+            },
+            _ => {
+                let hotkey = self.hotkeys.get_mut(action);
+                hotkey.input = input;
+                hotkey.modifiers = modifiers;
+                log_info!(self.log, "Bound {} to {}", action.label(), name);
+                self.validate_hotkeys();
+            },
+        }
+    }
+    
+    // Recheck bindings and steps after either changed, logging conflicts that weren't there before
+    pub fn validate_hotkeys(&mut self) {
+        let conflicts = self.find_hotkey_conflicts();
+        for conflict in conflicts.iter().filter(|c| !self.hotkey_conflicts.contains(c)) {
+            log_info!(self.log, "Hotkey conflict: {}", conflict);
+        }
+        self.hotkey_conflicts = conflicts;
+    }
+    
+    // Actions sharing a combination, then enabled steps that would press one of our own hotkeys
+    pub fn find_hotkey_conflicts(&self) -> Vec<String> {
+        let duplicates = self.hotkeys.duplicates().into_iter().map(|(first, second)| {
+            format!("{} and {} are both bound to {}", first.label(), second.label(), self.hotkeys.name(first))
+        });
+        let steps = self.steps
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| step.enabled)
+            .filter_map(|(i, step)| {
+                let (input, token) = match &step.kind {
+                    StepKind::Key(key) => (PhysicalInput::Key(map_key_str_to_rdev_key(key)?), key.as_str()),
+                    StepKind::Click(button) => (PhysicalInput::Button(button.to_rdev()), button.token()),
+                    StepKind::Wait(_) => return None,
+                };
+                self.hotkeys.action_using_input(input).map(|action| {
+                    format!("step {} '{}' is also the {} hotkey ({})", i + 1, token, action.label(), self.hotkeys.name(action))
+                })
+            });
+        duplicates.chain(steps).collect()
+    }
+    
+    // Start the loaded Rhai script; the action thread spawns it once it sees the new run id
+    pub fn start_script(&mut self, now: Instant) {
+        match &self.script_path {
+            Some(path) => {
+                let message = format!("Started script {}\n", path.display());
+                self.script_run_id += 1;
+                self.set_mode(ActiveMode::Script, "Running script...", &message, now);
+            },
+            None => log_warn!(self.log, "Load a script before starting it!"),
+        }
+    }
+    
+    // The start path for `mode`, the same one its button and hotkey take
+    pub fn start_mode(&mut self, mode: &ActiveMode, now: Instant) {
+        match mode {
+            ActiveMode::None => {},
+            ActiveMode::Clicking => self.start_clicking(now),
+            ActiveMode::KeystrokeInjection => self.start_keystroke_injection(now),
+            ActiveMode::Macro => self.start_macro(now),
+            ActiveMode::Script => self.start_script(now),
+        }
+    }
+    
+    // Arm a start at the typed clock time, today or tomorrow, whichever comes first
+    pub fn arm_scheduled_start(&mut self, clock: DateTime<Local>) {
+        match schedule::parse_clock_time(&self.start_at_entry) {
+            Ok(time) => {
+                let at = schedule::next_local(clock, time);
+                self.start_at_error = None;
+                self.scheduled_start = Some(at);
+                self.renew_run_epoch();
+                log_info!(
+                    self.log,
+                    "Scheduled {} for {} (in {})",
+                    self.scheduled_mode.label().to_lowercase(),
+                    at.format("%H:%M"),
+                    schedule::format_until(clock, at),
+                );
+            },
+            Err(message) => self.start_at_error = Some(message),
+        }
+    }
+    
+    pub fn cancel_scheduled_start(&mut self) {
+        if self.scheduled_start.take().is_some() {
+            self.renew_run_epoch();
+            log_info!(self.log, "Scheduled start cancelled");
+        }
+    }
+    
+    // Called by the action thread every so often; starts the scheduled mode once its time comes
+    pub fn check_scheduled_start(&mut self, clock: DateTime<Local>, now: Instant) {
+        let Some(at) = self.scheduled_start.filter(|&at| clock >= at) else {
+            return;
+        };
+        self.scheduled_start = None;
+        log_info!(self.log, "Scheduled start at {}", at.format("%H:%M"));
+        let mode = self.scheduled_mode.clone();
+        self.start_mode(&mode, now);
+    }
+    
+    // Start and stop the recurring runs. Stopping one by hand only skips that occurrence;
+    // the next one starts as usual
+    pub fn check_recurrence(&mut self, clock: DateTime<Local>, now: Instant) {
+        let current = if self.recurrence.enabled { self.recurrence.current(clock.naive_local()) } else { None };
+        if let Some(running) = self.recurrence_running {
+            if current != Some(running) {
+                self.recurrence_running = None;
+                if self.active_mode != ActiveMode::None || self.paused_mode.is_some() {
+                    self.stop_all("Recurring run finished\n", now);
+                }
+            } else if self.active_mode == ActiveMode::None && self.paused_mode.is_none() {
+                self.recurrence_running = None;
+                self.recurrence_skipped = Some(running);
+                log_info!(self.log, "Skipping the rest of this recurring run");
+            }
+            return;
+        }
+        let Some(start) = current.filter(|&start| self.recurrence_skipped != Some(start)) else {
+            return;
+        };
+        // Either way this occurrence is dealt with; it isn't retried every check
+        self.recurrence_skipped = Some(start);
+        if self.active_mode != ActiveMode::None || self.paused_mode.is_some() {
+            log_info!(self.log, "Skipped the recurring run at {}: something else is running", start.format("%H:%M"));
+            return;
+        }
+        log_info!(
+            self.log,
+            "Recurring run at {} for {} min",
+            start.format("%a %H:%M"),
+            self.recurrence.run_minutes,
+        );
+        let mode = self.scheduled_mode.clone();
+        self.start_mode(&mode, now);
+        if self.active_mode != ActiveMode::None {
+            self.recurrence_skipped = None;
+            self.recurrence_running = Some(start);
+        }
+    }
+    
+    // Whether the sequence has anything to run once disabled steps are skipped
+    pub fn has_enabled_steps(&self) -> bool {
+        self.steps.iter().any(|step| step.enabled)
+    }
+    
+    // First enabled step at or after `start`, wrapping around; None if every step is disabled
+    pub fn next_enabled_index_from(&self, start: usize) -> Option<usize> {
+        let len = self.steps.len();
+        (0..len)
+            .map(|i| (start + i) % len)
+            .find(|&i| self.steps[i].enabled)
+    }
+    
+    // Where the sequence continues from, checked against the current step list: a position
+    // recorded for another revision of the steps belongs to a different sequence and starts
+    // over, and anything past the end wraps. None when there are no steps at all
+    pub fn key_index(&mut self) -> Option<usize> {
+        let len = self.steps.len();
+        if self.position_revision.is_some_and(|recorded| recorded != self.sequence_revision) {
+            self.reset_sequence_position();
+        }
+        if len == 0 {
+            self.current_key_index = 0;
+            self.current_step = None;
+            return None;
+        }
+        self.current_key_index %= len;
+        self.current_step = self.current_step.filter(|&step| step < len);
+        Some(self.current_key_index)
+    }
+    
+    // Move past the step at `idx`, counting a new loop whenever the sequence starts over
+    pub fn advance_key_index(&mut self, idx: usize) {
+        let len = self.steps.len();
+        if self.current_step.is_some_and(|previous| idx <= previous) {
+            self.loop_count += 1;
+        }
+        self.current_key_display = self.steps[idx].to_token();
+        self.current_step = Some(idx);
+        self.current_key_index = (idx + 1) % len;
+        self.position_revision = Some(self.sequence_revision);
+        self.upcoming_keys = self.preview_keys_from(self.current_key_index);
+    }
+    
+    // Collect the keys that follow `start` in the sequence, wrapping around the end.
+    // Never shows more than one full loop so short sequences don't repeat themselves.
+    pub fn preview_keys_from(&self, start: usize) -> Vec<String> {
+        let enabled: Vec<usize> = (0..self.steps.len())
+            .map(|i| (start + i) % self.steps.len())
+            .filter(|&i| self.steps[i].enabled)
+            .collect();
+        let count = UPCOMING_KEY_PREVIEW.min(enabled.len().saturating_sub(1));
+        enabled
+            .iter()
+            .take(count)
+            .map(|&i| self.steps[i].to_token())
+            .collect()
+    }
+    
+    // Parse key sequence from input; invalid tokens are left out of the steps and reported instead
+    pub fn parse_key_sequence(&mut self) {
+        let mut steps = Vec::new();
+        let mut errors = Vec::new();
+        let tokens = self.key_to_inject
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty());
+        for (index, token) in tokens.enumerate() {
+            match SequenceStep::parse_token(token) {
+                Ok(step) => steps.push(step),
+                Err(message) => errors.push(TokenError { index, token: token.to_string(), message }),
+            }
+        }
+        self.steps = steps;
+        self.sequence_revision += 1;
+        self.sequence_errors = errors;
+        self.reset_sequence_position();
+        self.validate_hotkeys();
+    }
+    
+    // Rebuild the text field from the step list after the step editor changed it
+    pub fn sync_text_from_steps(&mut self) {
+        self.key_to_inject = self.steps
+            .iter()
+            .map(|step| step.to_token())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.sequence_revision += 1;
+        // The editor has already moved or reset the position to suit the new steps
+        self.position_revision = Some(self.sequence_revision);
+        // Editor rows can hold anything the text field could, so check them the same way
+        self.sequence_errors = self.steps
+            .iter()
+            .enumerate()
+            .filter_map(|(index, step)| {
+                let token = step.to_token();
+                SequenceStep::parse_token(&token)
+                    .err()
+                    .map(|message| TokenError { index, token, message })
+            })
+            .collect();
+        self.validate_hotkeys();
+    }
+    
+    // Move a step to a new position, keeping the running position on the same step
+    pub fn move_step(&mut self, from: usize, to: usize) {
+        let len = self.steps.len();
+        if from == to || from >= len || to >= len {
+            return;
+        }
+        let index = self.key_index().unwrap_or(0);
+        let step = self.steps.remove(from);
+        self.steps.insert(to, step);
+        
+        self.current_key_index = remap_index_after_move(index, from, to);
+        self.current_step = self.current_step.map(|i| remap_index_after_move(i, from, to));
+        self.upcoming_keys = self.preview_keys_from(self.current_key_index);
+        self.renew_run_epoch();
+    }
+    
+    // The old position means nothing for a different sequence, so start over
+    pub fn reset_sequence_position(&mut self) {
+        self.current_key_index = 0;
+        self.current_step = None;
+        self.position_revision = Some(self.sequence_revision);
+        self.loop_count = 0;
+        self.renew_run_epoch();
+    }
+}
+
+// Where an index ends up after the element at `from` is moved to `to`
+pub fn remap_index_after_move(index: usize, from: usize, to: usize) -> usize {
+    if index == from {
+        to
+    } else if from < index && index <= to {
+        index - 1
+    } else if to <= index && index < from {
+        index + 1
+    } else {
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading_a_profile_stops_the_mode_and_revalidates() {
+        let now = Instant::now();
+        let mut state = AppState { hold_mode: true, hold_range: true, ..AppState::default() };
+        state.key_to_inject = "a, bogus".to_string();
+        state.profile_name = "Broken".to_string();
+        let saved = state.save_profile().unwrap();
+        assert!(saved.hold_range);
+        state.profile_name = "Plain".to_string();
+        state.hold_mode = false;
+        state.key_to_inject = "w".to_string();
+        state.save_profile();
+        assert_eq!(state.profiles.iter().map(|p| p.name.as_str()).collect::<Vec<_>>(), ["Broken", "Plain"]);
+
+        state.parse_key_sequence();
+        state.start_keystroke_injection(now);
+        state.apply_profile(state.profile_index("Broken").unwrap(), now);
+        assert_eq!(state.active_mode, ActiveMode::None);
+        assert!(state.hold_mode && state.hold_range);
+        assert_eq!(state.sequence_errors[0].token, "bogus");
+        assert_eq!(state.active_profile.as_deref(), Some("Broken"));
+        assert!(state.log.contains("Switched to profile 'Broken'"));
+
+        assert_eq!(state.active_profile_label().as_deref(), Some("Broken"));
+        state.click_interval.us += 1_000;
+        assert_eq!(state.active_profile_label().as_deref(), Some("Broken*"));
+        state.click_interval.us -= 1_000;
+        assert_eq!(state.active_profile_label().as_deref(), Some("Broken"));
+
+        let deleted = state.delete_profile(0).unwrap();
+        assert_eq!(deleted.name, "Broken");
+        assert_eq!(state.active_profile, None);
+        assert_eq!(state.profile_index("Plain"), Some(0));
+    }
+
+    #[test]
+    fn parse_key_sequence_reports_bad_tokens() {
+        let mut state = AppState { key_to_inject: "w, click, , bogus, wait(50)".to_string(), ..AppState::default() };
+        state.parse_key_sequence();
+        let tokens: Vec<String> = state.steps.iter().map(|step| step.to_token()).collect();
+        assert_eq!(tokens, ["w", "click", "wait(50)"]);
+        assert_eq!(state.sequence_errors.len(), 1);
+        assert_eq!(state.sequence_errors[0].index, 2);
+        assert_eq!(state.sequence_errors[0].token, "bogus");
+    }
+
+    #[test]
+    fn hot_settings_mirror_the_state() {
+        let now = Instant::now();
+        let mut ui = AppState::default();
+        let mut thread_copy = ui.clone();
+        ui.start_clicking(now);
+        assert!(!ui.hot.load_into(&mut thread_copy));
+
+        ui.hold_mode = true;
+        ui.click_interval.us = 250_000;
+        ui.mirror_hot_settings();
+        thread_copy.start_clicking(now);
+        assert!(ui.hot.load_into(&mut thread_copy));
+        assert!(thread_copy.hold_mode);
+        assert_eq!(thread_copy.click_interval.us, 250_000);
+        for mode in ActiveMode::STARTABLE {
+            assert_eq!(ActiveMode::from_u8(mode.to_u8()), mode);
+        }
+    }
+
+    #[cfg(not(feature = "keyboard"))]
+    #[test]
+    fn keyboard_less_builds_refuse_keystroke_injection() {
+        let mut state = AppState::default();
+        state.trigger_hotkey(HotkeyAction::StartKeys, Instant::now());
+        assert_eq!(state.active_mode, ActiveMode::None);
+        assert!(state.log.contains("isn't part of this build"));
+        let f5 = state.hotkeys.get(HotkeyAction::StartKeys);
+        assert_eq!(state.hotkeys.action_for(f5.input, f5.modifiers), None);
+    }
+
+    #[cfg(not(feature = "mouse"))]
+    #[test]
+    fn mouse_less_builds_refuse_clicking() {
+        let mut state = AppState::default();
+        state.start_clicking(Instant::now());
+        assert_eq!(state.active_mode, ActiveMode::None);
+        assert!(state.log.contains("isn't part of this build"));
+    }
+
+    #[test]
+    fn recovers_a_poisoned_lock() {
+        let state = Arc::new(Mutex::new(AppState::default()));
+        lock_state(&state).start_clicking(Instant::now());
+        let poisoner = Arc::clone(&state);
+        let _ = std::thread::spawn(move || {
+            let _state = poisoner.lock().unwrap();
+            panic!("crashed while holding the lock");
+        })
+        .join();
+        assert!(state.is_poisoned());
+
+        let recovered = lock_state(&state);
+        assert_eq!(recovered.active_mode, ActiveMode::None);
+        assert!(recovered.log.contains("INTERNAL ERROR"));
+        drop(recovered);
+        assert!(!state.is_poisoned());
+    }
+
+    #[test]
+    fn key_index_wraps_and_starts_over_for_a_different_list() {
+        let mut state = AppState { key_to_inject: "a, b, c, d, e".to_string(), ..AppState::default() };
+        state.parse_key_sequence();
+        for idx in 0..4 {
+            state.advance_key_index(idx);
+        }
+        assert_eq!(state.key_index(), Some(4));
+
+        state.current_key_index = 7;
+        assert_eq!(state.key_index(), Some(2));
+        assert_eq!(state.current_step, Some(3));
+
+        // Edited to another list of the same length while running
+        state.sequence_revision += 1;
+        assert_eq!(state.key_index(), Some(0));
+        assert_eq!(state.current_step, None);
+        assert_eq!(state.loop_count, 0);
+
+        // Edited down to two steps while running
+        state.advance_key_index(1);
+        state.steps.truncate(2);
+        state.sequence_revision += 1;
+        assert_eq!(state.key_index(), Some(0));
+        assert_eq!(state.current_step, None);
+    }
+
+    #[test]
+    fn resuming_is_checked_like_starting() {
+        let now = Instant::now();
+        let mut state = AppState { key_to_inject: "a, b".to_string(), start_delay_s: 3, ..AppState::default() };
+        state.parse_key_sequence();
+        state.start_keystroke_injection(now);
+        state.toggle_pause("F8", now);
+        assert_eq!(state.active_mode, ActiveMode::None);
+        // A sequence broken while paused keeps it paused
+        state.key_to_inject = "a, bogus".to_string();
+        state.parse_key_sequence();
+        state.toggle_pause("F8", now);
+        assert_eq!(state.active_mode, ActiveMode::None);
+        assert!(state.paused_mode.is_some());
+        // Fixed, it resumes after the start delay like a fresh start
+        state.key_to_inject = "a, b".to_string();
+        state.parse_key_sequence();
+        state.toggle_pause("F8", now);
+        assert_eq!(state.active_mode, ActiveMode::KeystrokeInjection);
+        assert_eq!(state.start_pending_until, Some(now + Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn key_index_of_an_empty_list() {
+        let mut state = AppState::default();
+        state.advance_key_index(1);
+        state.steps.clear();
+        assert_eq!(state.key_index(), None);
+        assert_eq!(state.current_key_index, 0);
+        assert_eq!(state.current_step, None);
+        assert!(state.preview_keys_from(state.current_key_index).is_empty());
+    }
+
+    #[test]
+    fn remaps_indices_after_a_move() {
+        // Moving 0 to 2 in [a, b, c] gives [b, c, a]
+        assert_eq!(remap_index_after_move(0, 0, 2), 2);
+        assert_eq!(remap_index_after_move(1, 0, 2), 0);
+        assert_eq!(remap_index_after_move(2, 0, 2), 1);
+        // And back
+        assert_eq!(remap_index_after_move(2, 2, 0), 0);
+        assert_eq!(remap_index_after_move(0, 2, 0), 1);
+        assert_eq!(remap_index_after_move(1, 2, 0), 2);
+    }
+}
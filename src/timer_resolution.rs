@@ -0,0 +1,32 @@
+// Windows sleeps in steps of the system timer, 15.6 ms by default, so a 5 ms interval comes
+// out anywhere up to 16 ms. Requesting a 1 ms timer period while a mode runs makes ordinary
+// sleeps accurate to about a millisecond without spinning. The period is system-wide and
+// costs power, so it's only held while something is running.
+
+use winapi::um::mmsystem::TIMERR_NOERROR;
+use winapi::um::timeapi::{timeBeginPeriod, timeEndPeriod};
+
+const PERIOD_MS: u32 = 1;
+
+// Holds the 1 ms timer period until dropped
+pub struct HighResTimer(());
+
+impl HighResTimer {
+    pub fn acquire() -> Result<Self, String> {
+        // SAFETY: plain Win32 call without pointers; every successful call is matched by the
+        // timeEndPeriod in Drop
+        match unsafe { timeBeginPeriod(PERIOD_MS) } {
+            TIMERR_NOERROR => Ok(HighResTimer(())),
+            code => Err(format!("timeBeginPeriod failed with code {}", code)),
+        }
+    }
+}
+
+impl Drop for HighResTimer {
+    fn drop(&mut self) {
+        // SAFETY: matches the timeBeginPeriod that created this guard
+        unsafe {
+            timeEndPeriod(PERIOD_MS);
+        }
+    }
+}
@@ -0,0 +1,758 @@
+// How long to wait between actions.
+//
+// A fixed delay is trivially recognisable, so the interval can also be drawn per action,
+// either uniformly from a range or from a normal distribution that clusters around a mean
+// the way a person's timing does. Everything here is in microseconds, which covers both
+// sub-millisecond benchmarking and waits of an hour.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Normal samples further than this many standard deviations from the mean are pulled back in
+const NORMAL_TRUNCATION: f64 = 3.0;
+// No drawn delay is ever shorter than this
+pub const MIN_DELAY_US: u64 = 1;
+
+// The choice offered in the UI; the numbers live in Interval
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Distribution {
+    #[default]
+    Fixed,
+    Uniform,
+    Normal,
+}
+
+impl Distribution {
+    pub const ALL: [Distribution; 3] = [Distribution::Fixed, Distribution::Uniform, Distribution::Normal];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Distribution::Fixed => "Fixed",
+            Distribution::Uniform => "Random range",
+            Distribution::Normal => "Human-like",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Delay {
+    Fixed(u64),
+    Uniform { min: u64, max: u64 },
+    Normal { mean: u64, std_dev: u64 },
+}
+
+impl Delay {
+    // One delay in microseconds
+    pub fn sample<R: Rng + ?Sized>(self, rng: &mut R) -> u64 {
+        match self {
+            Delay::Fixed(us) => us.max(MIN_DELAY_US),
+            Delay::Uniform { min, max } => rng.gen_range(min..=max.max(min)).max(MIN_DELAY_US),
+            Delay::Normal { mean, std_dev } => {
+                let (low, high) = self.bounds();
+                let sample = mean as f64 + std_dev as f64 * standard_normal(rng);
+                (sample.round() as u64).clamp(low, high)
+            },
+        }
+    }
+
+    // What sample() averages out to, ignoring the floor
+    pub fn mean(self) -> u64 {
+        match self {
+            Delay::Fixed(us) => us,
+            Delay::Uniform { min, max } => (min + max.max(min)) / 2,
+            Delay::Normal { mean, .. } => mean,
+        }
+    }
+
+    // Shortest and longest delay sample() can return
+    pub fn bounds(self) -> (u64, u64) {
+        match self {
+            Delay::Fixed(us) => (us.max(MIN_DELAY_US), us.max(MIN_DELAY_US)),
+            Delay::Uniform { min, max } => (min.max(MIN_DELAY_US), max.max(min).max(MIN_DELAY_US)),
+            Delay::Normal { mean, std_dev } => {
+                let spread = (std_dev as f64 * NORMAL_TRUNCATION) as u64;
+                (mean.saturating_sub(spread).max(MIN_DELAY_US), mean.saturating_add(spread).max(MIN_DELAY_US))
+            },
+        }
+    }
+}
+
+// The values behind one interval control; clicking and key injection each have their own
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Interval {
+    pub distribution: Distribution,
+    // The fixed interval, and the mean of the human-like one
+    pub us: u64,
+    pub min_us: u64,
+    pub max_us: u64,
+    pub std_dev_us: u64,
+}
+
+impl Default for Interval {
+    fn default() -> Self {
+        Interval {
+            distribution: Distribution::Fixed,
+            us: 1_000_000,
+            min_us: 800_000,
+            max_us: 1_200_000,
+            std_dev_us: 150_000,
+        }
+    }
+}
+
+// The fields that take an interval, so one can be named while its new value waits for confirmation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntervalField {
+    Fixed,
+    Min,
+    Max,
+}
+
+impl Interval {
+    // A fixed interval with the randomized settings left at their defaults
+    pub fn fixed(us: u64) -> Self {
+        Interval { us, ..Interval::default() }
+    }
+
+    pub fn delay(&self) -> Delay {
+        match self.distribution {
+            Distribution::Fixed => Delay::Fixed(self.us),
+            Distribution::Uniform => Delay::Uniform { min: self.min_us, max: self.max_us },
+            Distribution::Normal => Delay::Normal { mean: self.us, std_dev: self.std_dev_us },
+        }
+    }
+
+    // The delay before the next action, or the length of the next hold
+    pub fn draw(&self) -> Duration {
+        Duration::from_micros(self.delay().sample(&mut rand::thread_rng()))
+    }
+
+    pub fn field_mut(&mut self, field: IntervalField) -> &mut u64 {
+        match field {
+            IntervalField::Fixed => &mut self.us,
+            IntervalField::Min => &mut self.min_us,
+            IntervalField::Max => &mut self.max_us,
+        }
+    }
+
+    // The minimum wins so the range is never empty
+    pub fn fix_range(&mut self) {
+        self.max_us = self.max_us.max(self.min_us);
+    }
+
+    // How the randomization reads in the status line; empty for a fixed interval
+    pub fn spread_label(&self) -> String {
+        match self.distribution {
+            Distribution::Fixed => String::new(),
+            Distribution::Uniform => format!("{}–{}", format_us(self.min_us), format_us(self.max_us)),
+            Distribution::Normal => format!("{}±{}", format_us(self.us), format_us(self.std_dev_us)),
+        }
+    }
+}
+
+// What to do when the action thread falls more than a whole interval behind schedule
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LatePolicy {
+    // Fire the missed actions back to back until the schedule is met again
+    CatchUp,
+    // Drop the missed actions and carry on one interval from now
+    #[default]
+    Skip,
+}
+
+impl LatePolicy {
+    pub const ALL: [LatePolicy; 2] = [LatePolicy::CatchUp, LatePolicy::Skip];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LatePolicy::CatchUp => "Catch up",
+            LatePolicy::Skip => "Skip missed",
+        }
+    }
+}
+
+// When the first action of a run is due, counted from starting or resuming the mode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FirstAction {
+    #[default]
+    Immediately,
+    AfterInterval,
+}
+
+impl FirstAction {
+    pub const ALL: [FirstAction; 2] = [FirstAction::Immediately, FirstAction::AfterInterval];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FirstAction::Immediately => "Immediately",
+            FirstAction::AfterInterval => "After one interval",
+        }
+    }
+
+    // The deadline of the first action for a run starting at `now`
+    pub fn deadline(self, now: Instant, interval: Duration) -> Instant {
+        match self {
+            FirstAction::Immediately => now,
+            FirstAction::AfterInterval => now + interval,
+        }
+    }
+}
+
+// Catching up never fires more than this many actions back to back; after a system sleep the
+// schedule is simply restarted
+const MAX_CATCH_UP: u32 = 10;
+
+// When the action after one due at `previous` is due. Counting from the previous deadline
+// rather than from `now` keeps processing time and sleep overshoot from adding up
+pub fn next_deadline(previous: Instant, now: Instant, interval: Duration, policy: LatePolicy) -> Instant {
+    let next = previous + interval;
+    let behind = now.saturating_duration_since(next);
+    if behind <= interval {
+        return next;
+    }
+    match policy {
+        LatePolicy::CatchUp if behind <= interval * MAX_CATCH_UP => next,
+        _ => now + interval,
+    }
+}
+
+// The rate actually achieved over the last few actions
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateStats {
+    pub cps: f64,
+    pub mean_period: Duration,
+    // Largest distance of any one period from the mean
+    pub jitter: Duration,
+}
+
+// When the last few actions were performed. The action thread writes to it after every input,
+// so it lives behind its own lock rather than the app state's
+#[derive(Clone, Debug, Default)]
+pub struct RateMeter {
+    times: VecDeque<Instant>,
+}
+
+impl RateMeter {
+    const CAPACITY: usize = 64;
+
+    pub fn record(&mut self, at: Instant) {
+        if self.times.len() == Self::CAPACITY {
+            self.times.pop_front();
+        }
+        self.times.push_back(at);
+    }
+
+    // A new run shouldn't count the pause before it
+    pub fn clear(&mut self) {
+        self.times.clear();
+    }
+
+    pub fn stats(&self) -> Option<RateStats> {
+        let (first, last) = (*self.times.front()?, *self.times.back()?);
+        let periods = self.times.len().checked_sub(1).filter(|&n| n > 0)?;
+        let mean_period = last.saturating_duration_since(first) / periods as u32;
+        let jitter = self.times
+            .iter()
+            .zip(self.times.iter().skip(1))
+            .map(|(a, b)| b.saturating_duration_since(*a).abs_diff(mean_period))
+            .max()
+            .unwrap_or_default();
+        let cps = if mean_period.is_zero() { f64::INFINITY } else { 1.0 / mean_period.as_secs_f64() };
+        Some(RateStats { cps, mean_period, jitter })
+    }
+}
+
+// Where the next deadline belongs after the interval changed mid-wait: one new interval after
+// the last action, or right away if that has already passed. Without this a 10 s wait would
+// run out before a new 200 ms interval took effect
+pub fn rescheduled_deadline(last_action: Option<Instant>, now: Instant, interval: Duration) -> Instant {
+    match last_action {
+        Some(last) => (last + interval).max(now),
+        None => now,
+    }
+}
+
+// How late actions ran compared to their deadlines, for reporting after a precise run
+#[derive(Clone, Debug, Default)]
+pub struct Lateness {
+    count: u32,
+    total: Duration,
+    max: Duration,
+}
+
+impl Lateness {
+    pub fn record(&mut self, late: Duration) {
+        self.count += 1;
+        self.total += late;
+        self.max = self.max.max(late);
+    }
+
+    // A one-line summary of everything recorded so far, starting over afterwards
+    pub fn take_summary(&mut self) -> Option<String> {
+        if self.count == 0 {
+            return None;
+        }
+        let summary = format!(
+            "{} actions, mean lateness {}, max {}",
+            self.count,
+            format_us((self.total / self.count).as_micros() as u64),
+            format_us(self.max.as_micros() as u64),
+        );
+        *self = Self::default();
+        Some(summary)
+    }
+}
+
+// How often missed deadlines are reported at most
+const MISSED_REPORT_WINDOW: Duration = Duration::from_secs(60);
+
+// Actions that ran more than a tolerance after their deadline: a running total for the UI,
+// and the ones since the last report so the log gets one line a minute instead of one per action
+#[derive(Clone, Debug, Default)]
+pub struct MissedDeadlines {
+    pub total: u64,
+    recent: u32,
+    window_start: Option<Instant>,
+}
+
+impl MissedDeadlines {
+    pub fn record(&mut self, late: Duration, tolerance: Duration, now: Instant) {
+        if late <= tolerance {
+            return;
+        }
+        self.total += 1;
+        self.recent += 1;
+        self.window_start.get_or_insert(now);
+    }
+
+    // A log line once a minute has passed since the first unreported miss; `flush` reports
+    // whatever is left, e.g. when the run stops
+    pub fn report(&mut self, tolerance: Duration, now: Instant, flush: bool) -> Option<String> {
+        let start = self.window_start?;
+        let full = now.saturating_duration_since(start) >= MISSED_REPORT_WINDOW;
+        if !full && !flush {
+            return None;
+        }
+        let period = if full { "in the last minute" } else { "before stopping" };
+        let line = format!("{} actions >{} late {}", self.recent, format_us(tolerance.as_micros() as u64), period);
+        self.recent = 0;
+        self.window_start = None;
+        Some(line)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+// Run the calling thread above normal priority so it's scheduled promptly after spinning;
+// returns false where that isn't supported
+#[cfg(target_os = "windows")]
+pub fn set_high_priority(high: bool) -> bool {
+    use winapi::um::processthreadsapi::{GetCurrentThread, SetThreadPriority};
+    use winapi::um::winbase::{THREAD_PRIORITY_HIGHEST, THREAD_PRIORITY_NORMAL};
+
+    let priority = if high { THREAD_PRIORITY_HIGHEST } else { THREAD_PRIORITY_NORMAL };
+    // SAFETY: GetCurrentThread returns a pseudo-handle that is always valid for the caller
+    unsafe { SetThreadPriority(GetCurrentThread(), priority as i32) != 0 }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn set_high_priority(_high: bool) -> bool {
+    false
+}
+
+// How long a 1 ms sleep really takes on this system, as the median of a few tries. Windows
+// rounds sleeps up to its ~15.6 ms timer tick unless something raised the timer resolution
+pub fn measure_sleep_granularity() -> Duration {
+    let mut samples: Vec<Duration> = (0..GRANULARITY_SAMPLES)
+        .map(|_| {
+            let start = Instant::now();
+            std::thread::sleep(Duration::from_millis(1));
+            start.elapsed()
+        })
+        .collect();
+    samples.sort_unstable();
+    samples[samples.len() / 2]
+}
+
+const GRANULARITY_SAMPLES: usize = 9;
+
+// Clicks per second for an interval; the UI can show either, but intervals are what's stored
+pub fn us_to_cps(us: u64) -> f64 {
+    1_000_000.0 / us.max(MIN_DELAY_US) as f64
+}
+
+pub fn cps_to_us(cps: f64) -> u64 {
+    if cps <= 0.0 {
+        return u64::MAX;
+    }
+    ((1_000_000.0 / cps).round() as u64).max(MIN_DELAY_US)
+}
+
+// The interval `progress` (0 to 1) of the way through a warm-up that eases the rate from
+// `start_cps` up to the rate of `target`. A target already slower than the start isn't touched
+pub fn warm_up_interval(target: Duration, start_cps: f64, progress: f64) -> Duration {
+    let target_cps = us_to_cps(target.as_micros() as u64);
+    if progress >= 1.0 || target_cps <= start_cps {
+        return target;
+    }
+    let cps = start_cps + (target_cps - start_cps) * progress.max(0.0);
+    Duration::from_micros(cps_to_us(cps))
+}
+
+// Short human-readable form of an interval for labels and log lines
+pub fn format_us(us: u64) -> String {
+    if us < 1_000 {
+        format!("{} µs", us)
+    } else if us < 60_000_000 {
+        // Drop trailing zeros so 1000 ms doesn't read as 1000.000 ms
+        let ms = format!("{:.3}", us as f64 / 1_000.0);
+        format!("{} ms", ms.trim_end_matches('0').trim_end_matches('.'))
+    } else if us.is_multiple_of(60_000_000) {
+        format!("{} min", us / 60_000_000)
+    } else {
+        format!("{:.1} min", us as f64 / 60_000_000.0)
+    }
+}
+
+// Long form for the duration entry's echo, e.g. "15m 0s" or "1h 30m 0s", so a typo in the
+// units stands out. Anything under a second keeps the short form
+pub fn format_hms(us: u64) -> String {
+    if us < 1_000_000 {
+        return format_us(us);
+    }
+    let ms = us / 1_000;
+    let (hours, minutes, millis) = (ms / 3_600_000, ms / 60_000 % 60, ms % 60_000);
+    let seconds = if millis % 1_000 == 0 {
+        format!("{}s", millis / 1_000)
+    } else {
+        let seconds = format!("{}.{:03}", millis / 1_000, millis % 1_000);
+        format!("{}s", seconds.trim_end_matches('0'))
+    };
+    match (hours, minutes) {
+        (0, 0) => seconds,
+        (0, _) => format!("{}m {}", minutes, seconds),
+        _ => format!("{}h {}m {}", hours, minutes, seconds),
+    }
+}
+
+// Parse a duration like "15m", "1h30m", "90s", "250ms" or a bare number of milliseconds
+// into microseconds. Parts can be fractional ("1.5h") and separated by spaces
+pub fn parse_duration(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Enter a duration like 15m, 1h30m, 90s or 500 (ms)".to_string());
+    }
+    if let Ok(ms) = text.parse::<f64>() {
+        return to_us(ms, 1_000.0);
+    }
+
+    let mut total = 0.0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(rest.len());
+        if number_len == 0 {
+            return Err(format!("Expected a number at '{}'", rest));
+        }
+        let (number, after) = rest.split_at(number_len);
+        let value: f64 = number.parse().map_err(|_| format!("'{}' isn't a number", number))?;
+        let unit_len = after.find(|c: char| !c.is_alphabetic() && c != 'µ').unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_len);
+        let scale = match unit.to_lowercase().as_str() {
+            "h" => 3_600_000_000.0,
+            "m" | "min" => 60_000_000.0,
+            "s" => 1_000_000.0,
+            "ms" => 1_000.0,
+            "us" | "µs" => 1.0,
+            "" => return Err(format!("'{}' needs a unit (h, m, s or ms)", number)),
+            _ => return Err(format!("Unknown unit '{}' (use h, m, s or ms)", unit)),
+        };
+        total += value * scale;
+        rest = after.trim_start();
+    }
+    to_us(total, 1.0)
+}
+
+fn to_us(value: f64, scale: f64) -> Result<u64, String> {
+    let us = (value * scale).round();
+    if us < 0.0 {
+        return Err("Durations can't be negative".to_string());
+    }
+    if !us.is_finite() || us > u64::MAX as f64 {
+        return Err("That duration is too long".to_string());
+    }
+    Ok(us as u64)
+}
+
+// Box-Muller, so this doesn't need a distributions crate for one function
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    // 1 - [0, 1) keeps ln() away from zero
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const SAMPLES: usize = 20_000;
+
+    fn mean_of(delay: Delay, rng: &mut StdRng) -> f64 {
+        (0..SAMPLES).map(|_| delay.sample(rng) as f64).sum::<f64>() / SAMPLES as f64
+    }
+
+    #[test]
+    fn warm_up_eases_the_rate_towards_the_target() {
+        let target = Duration::from_millis(50); // 20 CPS
+        assert_eq!(warm_up_interval(target, 2.0, 0.0), Duration::from_millis(500));
+        assert_eq!(warm_up_interval(target, 2.0, 0.5), Duration::from_micros(cps_to_us(11.0)));
+        assert_eq!(warm_up_interval(target, 2.0, 1.0), target);
+        // Already slower than the warm-up would start
+        let slow = Duration::from_secs(2);
+        assert_eq!(warm_up_interval(slow, 2.0, 0.0), slow);
+    }
+
+    #[test]
+    fn converts_between_cps_and_us() {
+        assert_eq!(cps_to_us(0.5), 2_000_000);
+        assert_eq!(cps_to_us(10.0), 100_000);
+        assert_eq!(cps_to_us(3.0), 333_333);
+        assert_eq!(cps_to_us(5_000_000.0), MIN_DELAY_US);
+        assert_eq!(cps_to_us(0.0), u64::MAX);
+        assert_eq!(us_to_cps(2_000_000), 0.5);
+        assert_eq!(us_to_cps(0), 1_000_000.0);
+        for us in [1, 7, 500, 333_333, 1_000_000, 2_000_000, 3_600_000_000] {
+            assert_eq!(cps_to_us(us_to_cps(us)), us);
+        }
+    }
+
+    #[test]
+    fn formats_intervals_in_a_fitting_unit() {
+        assert_eq!(format_us(250), "250 µs");
+        assert_eq!(format_us(1_000), "1 ms");
+        assert_eq!(format_us(2_500), "2.5 ms");
+        assert_eq!(format_us(1_000_000), "1000 ms");
+        assert_eq!(format_us(300_000_000), "5 min");
+        assert_eq!(format_us(90_000_000), "1.5 min");
+    }
+
+    #[test]
+    fn parses_durations_with_units() {
+        let cases = [
+            ("15m", 900_000_000),
+            ("1h30m", 5_400_000_000),
+            ("1h 30m 15s", 5_415_000_000),
+            ("90s", 90_000_000),
+            ("250ms", 250_000),
+            ("1.5h", 5_400_000_000),
+            ("2M", 120_000_000),
+            ("500us", 500),
+            ("900000", 900_000_000),
+            ("0.5", 500),
+            ("  45s ", 45_000_000),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_duration(text), Ok(expected), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_durations() {
+        for text in ["", "abc", "15x", "1h30", "m5", "1..5s", "-5s", "-5", "inf"] {
+            assert!(parse_duration(text).is_err(), "{:?}", text);
+        }
+        assert_eq!(parse_duration("15x"), Err("Unknown unit 'x' (use h, m, s or ms)".to_string()));
+        assert_eq!(parse_duration("1h30"), Err("'30' needs a unit (h, m, s or ms)".to_string()));
+    }
+
+    #[test]
+    fn formats_durations_as_hours_minutes_seconds() {
+        assert_eq!(format_hms(900_000_000), "15m 0s");
+        assert_eq!(format_hms(5_400_000_000), "1h 30m 0s");
+        assert_eq!(format_hms(90_000_000), "1m 30s");
+        assert_eq!(format_hms(1_250_000), "1.25s");
+        assert_eq!(format_hms(250_000), "250 ms");
+    }
+
+    #[test]
+    fn deadlines_follow_the_previous_deadline() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(100);
+        // Woke 8 ms late: the next deadline doesn't move
+        let next = next_deadline(start, start + Duration::from_millis(8), interval, LatePolicy::Skip);
+        assert_eq!(next, start + interval);
+        // Less than an interval behind still keeps the schedule
+        let next = next_deadline(start, start + Duration::from_millis(180), interval, LatePolicy::Skip);
+        assert_eq!(next, start + interval);
+    }
+
+    #[test]
+    fn falling_far_behind_follows_the_policy() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(100);
+        let now = start + Duration::from_millis(450);
+        assert_eq!(next_deadline(start, now, interval, LatePolicy::CatchUp), start + interval);
+        assert_eq!(next_deadline(start, now, interval, LatePolicy::Skip), now + interval);
+        // Too far behind to catch up sensibly
+        let asleep = start + Duration::from_secs(60);
+        assert_eq!(next_deadline(start, asleep, interval, LatePolicy::CatchUp), asleep + interval);
+    }
+
+    #[test]
+    fn interval_changes_reschedule_from_the_last_action() {
+        let last = Instant::now();
+        let now = last + Duration::from_millis(500);
+        // 10 s shortened to 2 s: due 2 s after the last action, not 10
+        assert_eq!(rescheduled_deadline(Some(last), now, Duration::from_secs(2)), last + Duration::from_secs(2));
+        // Shortened below the time already waited: due right away
+        assert_eq!(rescheduled_deadline(Some(last), now, Duration::from_millis(200)), now);
+        // Lengthened: waits the longer interval
+        assert_eq!(rescheduled_deadline(Some(last), now, Duration::from_secs(30)), last + Duration::from_secs(30));
+        assert_eq!(rescheduled_deadline(None, now, Duration::from_secs(2)), now);
+    }
+
+    #[test]
+    fn meter_reports_rate_and_jitter() {
+        let start = Instant::now();
+        let mut meter = RateMeter::default();
+        assert_eq!(meter.stats(), None);
+        meter.record(start);
+        assert_eq!(meter.stats(), None);
+        // Periods of 100, 110 and 90 ms
+        for ms in [100, 210, 300] {
+            meter.record(start + Duration::from_millis(ms));
+        }
+        let stats = meter.stats().unwrap();
+        assert_eq!(stats.mean_period, Duration::from_millis(100));
+        assert_eq!(stats.jitter, Duration::from_millis(10));
+        assert!((stats.cps - 10.0).abs() < 1e-9);
+
+        meter.clear();
+        assert_eq!(meter.stats(), None);
+    }
+
+    #[test]
+    fn meter_only_keeps_recent_actions() {
+        let start = Instant::now();
+        let mut meter = RateMeter::default();
+        // A slow start that has scrolled out of the window no longer counts
+        meter.record(start);
+        for i in 1..=RateMeter::CAPACITY as u64 {
+            meter.record(start + Duration::from_secs(10) + Duration::from_millis(i * 20));
+        }
+        let stats = meter.stats().unwrap();
+        assert_eq!(stats.mean_period, Duration::from_millis(20));
+        assert_eq!(stats.jitter, Duration::ZERO);
+    }
+
+    #[test]
+    fn missed_deadlines_are_reported_once_a_minute() {
+        let start = Instant::now();
+        let tolerance = Duration::from_millis(10);
+        let mut missed = MissedDeadlines::default();
+        missed.record(Duration::from_millis(5), tolerance, start);
+        assert_eq!(missed.total, 0);
+        assert_eq!(missed.report(tolerance, start, true), None);
+
+        for i in 0..12 {
+            missed.record(Duration::from_millis(25), tolerance, start + Duration::from_secs(i));
+        }
+        assert_eq!(missed.total, 12);
+        assert_eq!(missed.report(tolerance, start + Duration::from_secs(30), false), None);
+        assert_eq!(
+            missed.report(tolerance, start + Duration::from_secs(60), false).as_deref(),
+            Some("12 actions >10 ms late in the last minute"),
+        );
+        assert_eq!(missed.report(tolerance, start + Duration::from_secs(120), false), None);
+
+        missed.record(Duration::from_millis(25), tolerance, start + Duration::from_secs(121));
+        assert_eq!(
+            missed.report(tolerance, start + Duration::from_secs(122), true).as_deref(),
+            Some("1 actions >10 ms late before stopping"),
+        );
+        assert_eq!(missed.total, 13);
+    }
+
+    #[test]
+    fn lateness_summarises_and_resets() {
+        let mut lateness = Lateness::default();
+        assert_eq!(lateness.take_summary(), None);
+        for us in [100, 300, 2_000] {
+            lateness.record(Duration::from_micros(us));
+        }
+        assert_eq!(lateness.take_summary().as_deref(), Some("3 actions, mean lateness 800 µs, max 2 ms"));
+        assert_eq!(lateness.take_summary(), None);
+    }
+
+    #[test]
+    fn interval_delay_follows_the_distribution() {
+        let mut interval = Interval::fixed(50_000);
+        assert_eq!(interval.delay(), Delay::Fixed(50_000));
+        interval.distribution = Distribution::Uniform;
+        *interval.field_mut(IntervalField::Min) = 2_000_000;
+        interval.fix_range();
+        assert_eq!(interval.delay(), Delay::Uniform { min: 2_000_000, max: 2_000_000 });
+        interval.distribution = Distribution::Normal;
+        assert_eq!(interval.delay(), Delay::Normal { mean: 50_000, std_dev: 150_000 });
+    }
+
+    #[test]
+    fn fixed_is_fixed() {
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(Delay::Fixed(250).sample(&mut rng), 250);
+        assert_eq!(Delay::Fixed(0).sample(&mut rng), MIN_DELAY_US);
+    }
+
+    #[test]
+    fn uniform_stays_in_range() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let delay = Delay::Uniform { min: 800, max: 1200 };
+        for _ in 0..SAMPLES {
+            assert!((800..=1200).contains(&delay.sample(&mut rng)));
+        }
+        assert!((mean_of(delay, &mut rng) - delay.mean() as f64).abs() < 10.0);
+        // A reversed range collapses onto the minimum instead of panicking
+        assert_eq!(Delay::Uniform { min: 500, max: 100 }.sample(&mut rng), 500);
+    }
+
+    #[test]
+    fn normal_is_truncated() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let delay = Delay::Normal { mean: 1000, std_dev: 100 };
+        assert_eq!(delay.bounds(), (700, 1300));
+        for _ in 0..SAMPLES {
+            assert!((700..=1300).contains(&delay.sample(&mut rng)));
+        }
+
+        // A spread wider than the mean never goes below the floor
+        let wide = Delay::Normal { mean: 50, std_dev: 100 };
+        assert_eq!(wide.bounds(), (MIN_DELAY_US, 350));
+        for _ in 0..SAMPLES {
+            assert!(wide.sample(&mut rng) >= MIN_DELAY_US);
+        }
+    }
+
+    #[test]
+    fn normal_clusters_around_the_mean() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let delay = Delay::Normal { mean: 1000, std_dev: 150 };
+        assert!((mean_of(delay, &mut rng) - 1000.0).abs() < 5.0);
+
+        // About 68% of samples fall within one standard deviation
+        let within = (0..SAMPLES).filter(|_| delay.sample(&mut rng).abs_diff(1000) <= 150).count();
+        let share = within as f64 / SAMPLES as f64;
+        assert!((0.64..0.72).contains(&share), "{}", share);
+
+        assert_eq!(Delay::Normal { mean: 400, std_dev: 0 }.sample(&mut rng), 400);
+    }
+}
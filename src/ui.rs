@@ -0,0 +1,1877 @@
+// The egui front end.
+
+use crate::backend::{self, BackendKind, InputBackend, SelectedBackend};
+use crate::backup;
+use crate::config::{self, ConfigWriter};
+use crate::elevation::{self, RestartProfile};
+use crate::health::{self, Health};
+use crate::hotkeys::{Activation, Hotkey, HotkeyAction};
+use crate::http_api;
+use crate::ipc;
+use crate::keyboard_layout::KeyboardLayout;
+use crate::keys::{ClickButton, SequenceStep, StepKind};
+use crate::listener::{start_grab_thread, start_hotkey_listener, InputProbe, ListenerStatus};
+use crate::log_file;
+use crate::logging::{self, Category};
+use crate::{log_error, log_info, log_warn};
+use crate::permissions::{self, Permission};
+use crate::presets::PRESETS;
+use crate::profiles;
+use crate::safety;
+use crate::schedule;
+use crate::scheduler::start_backend_benchmark;
+use crate::settings::{self, SavedSettings};
+use crate::state::{lock_state, ActiveMode, AppState, INTERVAL_RANGE, MAX_START_DELAY_S, SAFE_INTERVAL_RANGE};
+use crate::timing::{self, Distribution, FirstAction, IntervalField, LatePolicy};
+use chrono::Local;
+use eframe::{egui, App};
+use rfd::{FileDialog, MessageButtons, MessageDialog, MessageDialogResult, MessageLevel};
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long "Use current window" waits before reading the title
+pub const WINDOW_CAPTURE_DELAY: Duration = Duration::from_secs(3);
+// The window title and heading; the active profile is added to the title
+pub const WINDOW_TITLE: &str = "Rust Auto Clicker";
+
+// How often the UI repaints by itself while something runs, and while a start is scheduled
+pub const ACTIVE_REPAINT_INTERVAL: Duration = Duration::from_millis(16);
+pub const SCHEDULED_REPAINT_INTERVAL: Duration = Duration::from_secs(1);
+// Even when idle, so a thread that stops responding turns its footer dot red
+pub const HEALTH_REPAINT_INTERVAL: Duration = Duration::from_secs(2);
+
+// How long shutdown waits for the action thread to release what it holds, and for the hotkey
+// thread, which can only stop between listener retries (see safety::join_with_timeout)
+pub const SHUTDOWN_JOIN_TIMEOUT: Duration = Duration::from_secs(2);
+pub const LISTENER_JOIN_TIMEOUT: Duration = Duration::from_millis(100);
+
+// How soon the UI repaints by itself: at 60fps while something runs so the status and counters
+// move, now and then while a start is scheduled, and not at all when idle
+pub fn repaint_interval(state: &AppState) -> Option<Duration> {
+    if state.active_mode != ActiveMode::None || state.start_pending_until.is_some() {
+        Some(ACTIVE_REPAINT_INTERVAL)
+    } else if state.scheduled_start.is_some() || state.recurrence.enabled {
+        Some(SCHEDULED_REPAINT_INTERVAL)
+    } else {
+        None
+    }
+}
+
+// Explain which macOS permissions are missing, offering to open the settings for the first one
+pub fn show_missing_permissions(missing: &[Permission]) {
+    let list = missing
+        .iter()
+        .map(|permission| format!("• {} (for {})", permission.label(), permission.needed_for()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let description = format!(
+        "macOS needs these permissions before the app can work:\n\n{}\n\n\
+Enable the app under each in System Settings → Privacy & Security. The warnings in the window \
+clear by themselves once they're granted; Input Monitoring may need a restart of the app.",
+        list,
+    );
+    let open = "Open Settings".to_string();
+    let result = MessageDialog::new()
+        .set_level(MessageLevel::Warning)
+        .set_title("Permissions needed")
+        .set_description(&description)
+        .set_buttons(MessageButtons::OkCancelCustom(open.clone(), "Later".to_string()))
+        .show();
+    if result == MessageDialogResult::Custom(open) {
+        let _ = permissions::open_settings(missing[0]);
+    }
+}
+
+#[cfg(target_os = "macos")]
+pub const PERMISSION_HELP: &str = "macOS only delivers keyboard and mouse events to apps that are allowed to see them.\n\n\
+Open System Settings → Privacy & Security and enable this app under both Accessibility and Input Monitoring, then restart it.\n\n\
+The buttons keep working in the meantime.";
+#[cfg(target_os = "linux")]
+pub const PERMISSION_HELP: &str = "The hotkey listener isn't receiving any input.\n\n\
+Under X11, make sure the app runs inside your desktop session (DISPLAY is set). \
+For suppressing hotkeys it also needs read access to /dev/input: add yourself to the input group \
+(sudo usermod -aG input $USER) and log in again.\n\n\
+Under Wayland, hotkeys are read from /dev/input and input is injected through /dev/uinput, \
+which both need the input group as above (uinput also a udev rule giving the group access to it).\n\n\
+The buttons keep working in the meantime.";
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub const PERMISSION_HELP: &str = "The hotkey listener isn't receiving any input.\n\n\
+Security software can block global input hooks, and windows running as administrator don't send input \
+to normal apps. Try running this app as administrator too.\n\n\
+The buttons keep working in the meantime.";
+
+// Shown once when the input backend can't be built at startup
+#[cfg(target_os = "linux")]
+pub const INJECTION_HELP: &str = "This usually means there's no desktop session to send input to: the app was started \
+over SSH or X forwarding, from a headless session, or without DISPLAY set. Under Wayland, input goes through \
+/dev/uinput, which needs the input group and a udev rule giving it access.";
+#[cfg(target_os = "macos")]
+pub const INJECTION_HELP: &str = "This usually means the app isn't allowed to control the computer. Enable it under \
+System Settings → Privacy & Security → Accessibility.";
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub const INJECTION_HELP: &str = "This usually means the app runs outside the interactive desktop, e.g. as a service \
+or in a disconnected remote session.";
+
+pub fn show_injection_unavailable(reason: &str) {
+    let description = format!(
+        "Input injection couldn't be started:\n\n{}\n\n{}\n\n\
+The window stays open, but nothing can be clicked or typed until injection works; fix the cause and press Retry.",
+        reason,
+        INJECTION_HELP,
+    );
+    MessageDialog::new()
+        .set_level(MessageLevel::Error)
+        .set_title("Input injection unavailable")
+        .set_description(&description)
+        .show();
+}
+
+// A helper struct to create UI buttons consistently
+pub struct ButtonConfig<'a> {
+    pub text: &'a str,
+    pub color: egui::Color32,
+    pub action: fn(&mut AppState, now: Instant),
+    // Start buttons are greyed out while injection is unavailable or the action thread is
+    // unresponsive, so clicks don't pile up behind it; Stop All never is
+    pub starts: bool,
+}
+
+// File dialogs requested from the UI
+pub enum FileAction {
+    Load,
+    Save,
+    LoadScript,
+    ExportAll,
+    ImportAll,
+}
+
+pub struct AutoClickerApp {
+    pub state: Arc<Mutex<AppState>>,
+    pub is_running: Arc<AtomicBool>,
+    pub hold_released: Arc<AtomicBool>,
+    // Background threads joined on exit, with how long to wait for each
+    pub threads: Vec<(&'static str, thread::JoinHandle<()>, Duration)>,
+    pub config_writer: ConfigWriter,
+    // Last title given to the window, which shows the active profile
+    pub window_title: String,
+}
+
+impl App for AutoClickerApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        let now = Instant::now();
+        let mut file_action = None;
+        // A text field keeps focus while the window is in the background, so check both
+        let window_focused = ctx.input(|i| i.viewport().focused.unwrap_or(false));
+        let editing_text = window_focused && ctx.wants_keyboard_input();
+        let mut show_permission_help;
+        let mut injection_failure = None;
+        let missing_permissions;
+        let mut restart_elevated = None;
+        let repaint_after;
+        let settings;
+        
+        { // Scope for state lock
+            let mut state = lock_state(&self.state);
+            state.editing_text = editing_text;
+            state.log.append(logging::take_pending());
+            show_permission_help = std::mem::take(&mut state.permission_dialog_pending);
+            if std::mem::take(&mut state.injection_dialog_pending) {
+                injection_failure = state.injection_unavailable.clone();
+            }
+            missing_permissions = state.missing_permissions.clone();
+            state.poll_window_capture(now);
+            let profile_label = state.active_profile_label();
+            let title = match &profile_label {
+                Some(profile) => format!("{} — {}", WINDOW_TITLE, profile),
+                None => WINDOW_TITLE.to_string(),
+            };
+            if title != self.window_title {
+                ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+                self.window_title = title;
+            }
+            egui::CentralPanel::default().show(ctx, |ui| {
+                // Everything scrolls so new sections never get cut off by the window size
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    // Header section
+                    ui.horizontal(|ui| {
+                        ui.heading(WINDOW_TITLE);
+                        self.show_profile_switcher(ui, &mut state, profile_label.as_deref(), now);
+                    });
+                    ui.horizontal(|ui| {
+                        ui.toggle_value(&mut state.interval_as_cps, "CPS")
+                            .on_hover_text("Enter intervals as actions per second instead of milliseconds between actions");
+                        ui.checkbox(&mut state.hold_mode, "Hold Mode").on_hover_text("When enabled, the action key/button will be held down continuously instead of once per interval.");
+                        ui.label("Start delay:");
+                        ui.add(egui::DragValue::new(&mut state.start_delay_s).clamp_range(0..=MAX_START_DELAY_S).suffix(" s"))
+                            .on_hover_text("Wait this long after starting before the first action, to switch to the target window");
+                        ui.label("Stop after:");
+                        ui.add(egui::DragValue::new(&mut state.action_limit).suffix(" actions"))
+                            .on_hover_text("Stop automatically after this many clicks or key presses; 0 runs until stopped");
+                        ui.label("or");
+                        let response = ui.add(
+                            egui::TextEdit::singleline(&mut state.duration_limit_entry)
+                                .hint_text("e.g. 10m")
+                                .desired_width(60.0)
+                        ).on_hover_text("Stop automatically after running this long, e.g. 10m or 1h30m; empty runs until stopped");
+                        if response.lost_focus() {
+                            let entry = state.duration_limit_entry.trim().to_string();
+                            let parsed = if entry.is_empty() { Ok(0) } else { timing::parse_duration(&entry) };
+                            match parsed {
+                                Ok(us) => {
+                                    state.duration_limit_us = us;
+                                    state.duration_limit_error = None;
+                                },
+                                Err(message) => state.duration_limit_error = Some(message),
+                            }
+                        }
+                        if let Some(message) = &state.duration_limit_error {
+                            ui.colored_label(egui::Color32::from_rgb(255, 100, 100), "⚠").on_hover_text(message);
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            let mut status = format!("Status: {}", state.current_status(now));
+                            let mode = state.paused_mode.as_ref().map(|p| p.mode.clone()).unwrap_or_else(|| state.active_mode.clone());
+                            if mode != ActiveMode::None {
+                                let interval = state.interval_for(&mode);
+                                let spread = interval.spread_label();
+                                if spread.is_empty() {
+                                    status.push_str(&format!(" · every {}", timing::format_hms(interval.us)));
+                                } else {
+                                    status.push_str(&format!(" · {}", spread));
+                                }
+                            }
+                            if state.start_pending_until.is_none() && state.warm_up_progress(now).is_some() {
+                                status.push_str(" · warming up");
+                            }
+                            if let Some(profile) = &profile_label {
+                                status.push_str(&format!(" · {}", profile));
+                            }
+                            ui.label(status);
+                            if state.rate_capped && state.active_mode != ActiveMode::None {
+                                ui.label("🛡").on_hover_text(format!(
+                                    "The interval is faster than the {} CPS safety cap, so actions run at the cap",
+                                    state.max_cps,
+                                ));
+                            }
+                        });
+                    });
+                
+                    if state.hold_mode {
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut state.hold_range, "Random hold length:")
+                                .on_hover_text("Hold each press for a random time in this range; presses are still spaced by the interval");
+                            ui.add_enabled(state.hold_range, egui::DragValue::new(&mut state.hold_min_ms).clamp_range(1..=600_000).suffix(" ms"));
+                            ui.label("to");
+                            ui.add_enabled(state.hold_range, egui::DragValue::new(&mut state.hold_max_ms).clamp_range(1..=600_000).suffix(" ms"));
+                            // The minimum wins so the range is never empty
+                            state.hold_max_ms = state.hold_max_ms.max(state.hold_min_ms);
+                        });
+                    }
+                    
+                    if let Some(readout) = state.session.readout(now).filter(|_| state.start_pending_until.is_none()) {
+                        ui.label(readout);
+                    }
+                    
+                    // What the action thread really achieves, to compare with the setting
+                    if state.active_mode != ActiveMode::None {
+                        let remaining = state.limits_remaining(now);
+                        if !remaining.is_empty() {
+                            ui.label(format!("Remaining: {}", remaining.join(" · ")));
+                        }
+                        if let Some(stats) = state.rate_meter.lock().ok().and_then(|meter| meter.stats()) {
+                            let mut actual = format!(
+                                "Actual: {:.1} CPS, jitter ±{:.1}ms",
+                                stats.cps,
+                                stats.jitter.as_secs_f64() * 1_000.0,
+                            );
+                            if state.missed_deadlines.total > 0 {
+                                actual.push_str(&format!(", {} missed deadlines", state.missed_deadlines.total));
+                            }
+                            ui.weak(actual);
+                        }
+                    }
+                    if let Some((mode, field, requested)) = state.extreme_interval.clone() {
+                        ui.horizontal(|ui| {
+                            let warning = if requested < *SAFE_INTERVAL_RANGE.start() {
+                                "floods the target with input and can make the system unresponsive"
+                            } else {
+                                "means actions are minutes apart"
+                            };
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 200, 0),
+                                format!("⚠ {} {}. Use it?", timing::format_us(requested), warning),
+                            );
+                            if ui.small_button("Use it").clicked() {
+                                let interval = state.interval_mut(&mode);
+                                *interval.field_mut(field) = requested;
+                                interval.fix_range();
+                                state.extreme_interval = None;
+                            }
+                            if ui.small_button("Cancel").clicked() {
+                                state.extreme_interval = None;
+                            }
+                        });
+                    }
+                    
+                    // Hotkeys being down is easy to miss, so keep it in view until they're back
+                    if state.hotkey_listener != ListenerStatus::Running {
+                        ui.horizontal_wrapped(|ui| {
+                            let warning = match &state.hotkeys_unavailable {
+                                Some(reason) => format!("⚠ Global hotkeys unavailable: {} — click buttons instead", reason),
+                                None => "⚠ Hotkeys unavailable — click buttons instead".to_string(),
+                            };
+                            ui.colored_label(egui::Color32::from_rgb(255, 100, 100), warning);
+                            if state.hotkey_listener == ListenerStatus::Failed && ui.button("Retry hotkeys").clicked() {
+                                state.hotkey_listener = ListenerStatus::Retrying;
+                                state.hotkeys_unavailable = None;
+                                log_info!(state.log, "Restarting hotkey listener");
+                                let _ = start_hotkey_listener(
+                                    Arc::clone(&self.state),
+                                    Arc::clone(&self.is_running),
+                                    Arc::clone(&self.hold_released),
+                                    state.display_server,
+                                );
+                            }
+                        });
+                    }
+                    for permission in state.missing_permissions.clone() {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 100, 100),
+                                format!("⚠ {} permission missing — {} won't work", permission.label(), permission.needed_for()),
+                            );
+                            if ui.button("Open Settings").clicked() {
+                                if let Err(e) = permissions::open_settings(permission) {
+                                    log_error!(state.log, "{}", e);
+                                }
+                            }
+                        });
+                    }
+                    if let Some(title) = state.elevated_target.clone() {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 100, 100),
+                                format!("⚠ '{}' runs as administrator — Windows blocks our input to it", title),
+                            );
+                            if ui.button("Restart as administrator").clicked() {
+                                restart_elevated = Some(RestartProfile::of(&state));
+                            }
+                        });
+                    }
+                    if let Some(reason) = state.injection_unavailable.clone() {
+                        ui.horizontal_wrapped(|ui| {
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 100, 100),
+                                format!("⚠ Input injection unavailable: {} — nothing can be clicked or typed", reason),
+                            );
+                            let retrying = state.injection_retry_requested;
+                            if ui.add_enabled(!retrying, egui::Button::new("Retry")).clicked() {
+                                state.injection_retry_requested = true;
+                                log_info!(state.log, "Retrying input injection...");
+                            }
+                        });
+                    }
+                    if state.input_probe == InputProbe::Blocked {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(egui::Color32::from_rgb(255, 100, 100), "⚠ No input events reach the app — hotkeys need a permission");
+                            if ui.button("How to fix").clicked() {
+                                show_permission_help = true;
+                            }
+                        });
+                    }
+                
+                    // Log area
+                    ui.add_space(5.0);
+                    self.show_log_area(ui, &mut state);
+                
+                    // Current key display
+                    if !state.current_key_display.is_empty() && state.active_mode == ActiveMode::KeystrokeInjection {
+                        ui.horizontal(|ui| {
+                            ui.label("Current key:");
+                            ui.strong(&state.current_key_display);
+                            if !state.upcoming_keys.is_empty() {
+                                let preview: String = state.upcoming_keys
+                                    .iter()
+                                    .map(|k| format!("→ {} ", k))
+                                    .collect();
+                                ui.label(egui::RichText::new(preview.trim_end()).monospace().weak());
+                            }
+                        });
+                    
+                        // Sequence progress and loop counter
+                        let total = state.steps.len().max(1);
+                        let position = state.current_step.unwrap_or(0).min(total - 1) + 1;
+                        ui.add(
+                            egui::ProgressBar::new(position as f32 / total as f32)
+                                .text(format!("Step {}/{} · loop #{}", position, total, state.loop_count + 1))
+                        );
+                    } else {
+                        ui.add_space(5.0);
+                    }
+                
+                    // Control buttons section
+                    ui.group(|ui| {
+                        ui.vertical(|ui| {
+                            if backend::MOUSE_ENABLED {
+                                ui.heading("Mouse Clicking");
+                                self.show_interval_row(ui, &mut state, ActiveMode::Clicking);
+                                let text = format!("Start Clicking ({})", state.hotkeys.name(HotkeyAction::StartClicking));
+                                self.create_action_button(ui, &mut state, now, ButtonConfig {
+                                    text: &text,
+                                    color: egui::Color32::from_rgb(0, 180, 255),
+                                    action: |state, now| state.start_clicking(now),
+                                    starts: true,
+                                });
+                            }
+                        
+                            if backend::KEYBOARD_ENABLED {
+                                ui.add_space(5.0);
+                                ui.heading("Keystroke Injection");
+                        
+                                // Built-in templates
+                                ui.horizontal(|ui| {
+                                    ui.label("Preset:");
+                                    egui::ComboBox::from_id_source("sequence_preset")
+                                        .selected_text("Choose a preset…")
+                                        .show_ui(ui, |ui| {
+                                            for preset in PRESETS {
+                                                if ui.selectable_label(false, preset.name)
+                                                    .on_hover_text(preset.description)
+                                                    .clicked()
+                                                {
+                                                    state.apply_preset(preset);
+                                                }
+                                            }
+                                        });
+                                    if state.settings_dirty {
+                                        ui.small("Review the settings, then press Start");
+                                    }
+                                });
+                            
+                                ui.horizontal(|ui| {
+                                    ui.label("Layout:");
+                                    egui::ComboBox::from_id_source("keyboard_layout")
+                                        .selected_text(state.keyboard_layout.label())
+                                        .show_ui(ui, |ui| {
+                                            for layout in KeyboardLayout::ALL {
+                                                ui.selectable_value(&mut state.keyboard_layout, layout, layout.label());
+                                            }
+                                        })
+                                        .response
+                                        .on_hover_text("The keyboard layout of the target machine, used to type AltGr and accented characters");
+                                });
+                            
+                                self.show_interval_row(ui, &mut state, ActiveMode::KeystrokeInjection);
+                            
+                                // Key input field
+                                ui.horizontal(|ui| {
+                                    ui.label("Keys:");
+                                    let sequence_valid = state.sequence_errors.is_empty();
+                                    let mut key_field = egui::TextEdit::singleline(&mut state.key_to_inject);
+                                    if !sequence_valid {
+                                        key_field = key_field.text_color(egui::Color32::from_rgb(255, 100, 100));
+                                    }
+                                    if ui.add(key_field)
+                                        .on_hover_text("Enter keys separated by commas (e.g., 'w, s, d' or 'space, enter'). Use 'click', 'rclick' or 'mclick' to mix in mouse clicks and 'wait(ms)' to pause. Append '(hold)' to keep a step held until the next one is pressed.")
+                                        .changed() 
+                                    {
+                                        state.parse_key_sequence();
+                                    }
+                                });
+                                if state.editing_text {
+                                    ui.weak("Hotkeys paused while editing");
+                                }
+                                for error in &state.sequence_errors {
+                                    ui.colored_label(
+                                        egui::Color32::from_rgb(255, 100, 100),
+                                        format!("⚠ Key {} '{}': {}", error.index + 1, error.token, error.message),
+                                    );
+                                }
+                                for conflict in &state.hotkey_conflicts {
+                                    ui.colored_label(egui::Color32::from_rgb(255, 100, 100), format!("⚠ {}", conflict));
+                                }
+                                self.show_step_editor(ui, &mut state);
+                            
+                                ui.horizontal(|ui| {
+                                    if ui.button("Load sequence…").on_hover_text("Load a .txt key list or a .json step list").clicked() {
+                                        file_action = Some(FileAction::Load);
+                                    }
+                                    if ui.button("Save sequence…").on_hover_text("Save as a .txt key list or a .json step list").clicked() {
+                                        file_action = Some(FileAction::Save);
+                                    }
+                                });
+                        
+                                let sequence_valid = state.sequence_errors.is_empty();
+                                let text = format!("Start Keystroke Injection ({})", state.hotkeys.name(HotkeyAction::StartKeys));
+                                ui.add_enabled_ui(sequence_valid, |ui| {
+                                    self.create_action_button(ui, &mut state, now, ButtonConfig {
+                                        text: &text,
+                                        color: egui::Color32::from_rgb(0, 180, 255),
+                                        action: |state, now| state.start_keystroke_injection(now),
+                                        starts: true,
+                                    });
+                                });
+                            }
+                            
+                            ui.add_space(5.0);
+                            ui.heading("Macro");
+                            if ui.add(
+                                egui::TextEdit::multiline(&mut state.macro_script)
+                                    .code_editor()
+                                    .desired_rows(3)
+                                    .desired_width(f32::INFINITY)
+                            )
+                                .on_hover_text("Statements: press(key); hold(key, ms); click or click(right); wait(ms); repeat(n) { ... }")
+                                .changed()
+                            {
+                                state.validate_macro();
+                            }
+                            if let Some(error) = &state.macro_error {
+                                ui.colored_label(egui::Color32::from_rgb(255, 100, 100), error);
+                            }
+                            self.create_action_button(ui, &mut state, now, ButtonConfig {
+                                text: "Start Macro",
+                                color: egui::Color32::from_rgb(0, 180, 255),
+                                action: |state, now| state.start_macro(now),
+                                starts: true,
+                            });
+                            
+                            ui.add_space(5.0);
+                            ui.heading("Script");
+                            ui.horizontal(|ui| {
+                                if ui.button("Load script…")
+                                    .on_hover_text("Rhai script using click(), key(name), move_to(x, y), sleep(ms) and stopped()")
+                                    .clicked()
+                                {
+                                    file_action = Some(FileAction::LoadScript);
+                                }
+                                match &state.script_path {
+                                    Some(path) => ui.monospace(path.file_name().unwrap_or_default().to_string_lossy().to_string()),
+                                    None => ui.weak("No script loaded"),
+                                };
+                            });
+                            self.create_action_button(ui, &mut state, now, ButtonConfig {
+                                text: "Start Script",
+                                color: egui::Color32::from_rgb(0, 180, 255),
+                                action: |state, now| state.start_script(now),
+                                starts: true,
+                            });
+                        
+                            ui.add_space(5.0);
+                            let text = format!("Stop All ({})", state.hotkeys.name(HotkeyAction::Stop));
+                            self.create_action_button(ui, &mut state, now, ButtonConfig {
+                                text: &text,
+                                color: egui::Color32::from_rgb(255, 100, 100),
+                                action: |state, now| state.stop_all("Stopped all actions\n", now),
+                                starts: false,
+                            });
+                        });
+                    });
+                
+                    if let Some(action) = self.show_profiles(ui, &mut state, now) {
+                        file_action = Some(action);
+                    }
+                    self.show_hotkey_editor(ui, &mut state, now);
+                    self.show_timing_settings(ui, &mut state);
+                    self.show_schedule_settings(ui, &mut state);
+                
+                    // Footer
+                    ui.add_space(5.0);
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut state.hotkeys_enabled, "")
+                            .on_hover_text("Enable hotkeys. Stop and panic keep working either way.");
+                        let hint = egui::RichText::new(format!(
+                            "Note: Auto clicker works in background. Hotkeys: {}=Keys, {}=Click, {}=Stop, {}=Pause",
+                            state.hotkeys.name(HotkeyAction::StartKeys),
+                            state.hotkeys.name(HotkeyAction::StartClicking),
+                            state.hotkeys.name(HotkeyAction::Stop),
+                            state.hotkeys.name(HotkeyAction::PauseResume)
+                        )).small();
+                        if state.hotkeys_enabled {
+                            ui.label(hint);
+                        } else {
+                            ui.label(hint.strikethrough().weak());
+                        }
+                        ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                            let now_ms = health::now_ms();
+                            for heartbeat in health::THREADS.iter().rev() {
+                                let health = heartbeat.health(now_ms);
+                                let color = match health {
+                                    Health::Alive => egui::Color32::from_rgb(0, 200, 0),
+                                    Health::Unresponsive(_) => egui::Color32::from_rgb(255, 60, 60),
+                                    Health::NotStarted => egui::Color32::GRAY,
+                                };
+                                ui.label(egui::RichText::new("●").color(color).small()).on_hover_text(heartbeat.describe(health));
+                            }
+                        });
+                    });
+                });
+            });
+            
+            // Changes reach the action thread once the frame is done with them
+            state.send_update();
+            repaint_after = repaint_interval(&state);
+            settings = SavedSettings::of(&state);
+        }
+        
+        if self.config_writer.due(&settings, now) {
+            self.write_config(&settings);
+        }
+        
+        // File dialogs block, so they run only after the state lock has been released
+        if let Some(action) = file_action {
+            match action {
+                FileAction::Load => self.load_sequence(),
+                FileAction::Save => self.save_sequence(),
+                FileAction::LoadScript => self.pick_script(),
+                FileAction::ExportAll => self.export_all(),
+                FileAction::ImportAll => self.import_all(),
+            }
+            // Show (and hand the action thread) whatever the file changed
+            ctx.request_repaint();
+        }
+        // The UAC prompt blocks too
+        if let Some(profile) = restart_elevated {
+            let path = elevation::restart_profile_path();
+            let result = elevation::write_restart_profile(&profile, &path).and_then(|()| elevation::relaunch_as_admin(&path));
+            match result {
+                Ok(()) => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+                Err(e) => {
+                    let _ = std::fs::remove_file(&path);
+                    log_error!(lock_state(&self.state).log, "{}", e);
+                },
+            }
+        }
+        if let Some(reason) = injection_failure {
+            show_injection_unavailable(&reason);
+        }
+        if show_permission_help && !missing_permissions.is_empty() {
+            show_missing_permissions(&missing_permissions);
+        } else if show_permission_help {
+            MessageDialog::new()
+                .set_level(MessageLevel::Warning)
+                .set_title("Hotkeys aren't receiving input")
+                .set_description(PERMISSION_HELP)
+                .show();
+        }
+        
+        // Otherwise egui sleeps until input, or until another thread asks for a repaint
+        ctx.request_repaint_after(repaint_after.map_or(HEALTH_REPAINT_INTERVAL, |interval| interval.min(HEALTH_REPAINT_INTERVAL)));
+    }
+    
+    // eframe calls this every so often and on exit
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        let settings = SavedSettings::of(&lock_state(&self.state));
+        storage.set_string(settings::STORAGE_KEY, settings.to_json());
+    }
+    
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        let settings = SavedSettings::of(&lock_state(&self.state));
+        if self.config_writer.flush(&settings) {
+            self.write_config(&settings);
+        }
+        self.shutdown();
+    }
+}
+
+impl AutoClickerApp {
+    // Read the saved profiles from their files; one that can't be read is skipped and logged
+    pub fn load_profiles(&self) {
+        let dir = profiles::profiles_dir();
+        let (loaded, errors) = profiles::load_profile_files(&dir);
+        let mut state = lock_state(&self.state);
+        for error in errors {
+            log_warn!(state.log, "Skipped a profile file: {}", error);
+        }
+        if !loaded.is_empty() {
+            log_info!(state.log, "Loaded {} profiles from {}", loaded.len(), dir.display());
+        }
+        state.profiles = loaded;
+    }
+    
+    // Take over the settings saved by the last run, if there are any and they can be read
+    pub fn restore_settings(&self, storage: Option<&dyn eframe::Storage>) {
+        let Some(json) = storage.and_then(|storage| storage.get_string(settings::STORAGE_KEY)) else {
+            return;
+        };
+        let mut state = lock_state(&self.state);
+        match SavedSettings::from_json(&json) {
+            Ok(saved) => saved.apply_to(&mut state),
+            Err(e) => log_warn!(state.log, "Couldn't restore the saved settings, starting with the defaults: {}", e),
+        }
+    }
+    
+    // Take over the settings in the config file, which win over the saved ones. A file that
+    // doesn't parse is explained in a dialog and left alone until it's fixed
+    pub fn load_config(&mut self) {
+        let path = config::config_path();
+        let result = config::read_config(&path);
+        let mut state = lock_state(&self.state);
+        let error = match result {
+            Ok(Some(settings)) => {
+                settings.apply_to(&mut state);
+                log_info!(state.log, "Loaded settings from {}", path.display());
+                None
+            },
+            Ok(None) => None,
+            Err(e) => {
+                log_error!(state.log, "Couldn't load the config file: {}", e);
+                Some(e)
+            },
+        };
+        self.config_writer = ConfigWriter::new(SavedSettings::of(&state));
+        drop(state);
+        if let Some(e) = error {
+            MessageDialog::new()
+                .set_level(MessageLevel::Error)
+                .set_title("Config file error")
+                .set_description(format!(
+                    "{}\n\nThe file was left as it is; the settings in it aren't used until it's fixed.",
+                    e,
+                ))
+                .show();
+        }
+    }
+    
+    pub fn write_config(&self, settings: &SavedSettings) {
+        if let Err(e) = config::write_config(&config::config_path(), settings) {
+            log_warn!(lock_state(&self.state).log, "Couldn't save the config file: {}", e);
+        }
+    }
+    
+    // Stop everything, let the action thread release what it holds, then wait for the threads.
+    // Leftovers go through the backend the action thread was injecting through
+    pub fn shutdown(&mut self) {
+        let kind = {
+            let state = lock_state(&self.state);
+            state.backend_in_use.unwrap_or(state.input_backend)
+        };
+        self.shutdown_with(move || SelectedBackend::new(kind));
+    }
+    
+    // Shutdown, releasing anything the action thread left held through a backend from `make_backend`
+    pub fn shutdown_with<B, F>(&mut self, make_backend: F)
+    where
+        B: InputBackend,
+        F: FnOnce() -> Result<B, String>,
+    {
+        log_info!(lock_state(&self.state).log, "Shutdown: signalling background threads");
+        self.is_running.store(false, Ordering::SeqCst);
+        { // Scope for state lock
+            let mut state = lock_state(&self.state);
+            log_info!(state.log, "Shutdown: stopping active mode");
+            state.stop_all("Shutting down\n", Instant::now());
+            // Also wakes the action thread, which then sees the flag and releases what it holds
+            state.send_update();
+        }
+        for (name, handle, timeout) in self.threads.drain(..) {
+            safety::join_with_timeout(&self.state, name, handle, timeout);
+        }
+        // Whatever the action thread didn't get to release is let go here, before the process exits
+        let released = safety::release_leftovers(make_backend);
+        let mut state = lock_state(&self.state);
+        if released > 0 {
+            log_warn!(state.log, "Shutdown: released {} held input(s) the action thread left behind", released);
+        }
+        log_info!(state.log, "Shutdown: done");
+    }
+    
+    // Load a sequence file chosen by the user; JSON holds the step list, anything else the raw key text
+    pub fn load_sequence(&self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("Sequence", &["txt", "json"])
+            .pick_file()
+        else {
+            return;
+        };
+        
+        let result = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| {
+                if is_json_path(&path) {
+                    serde_json::from_str::<Vec<SequenceStep>>(&contents)
+                        .map(|steps| steps.iter().map(|step| step.to_token()).collect::<Vec<_>>().join(", "))
+                        .map_err(|e| e.to_string())
+                } else {
+                    // One token per line works as well as a single comma-separated line
+                    Ok(contents.lines().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join(", "))
+                }
+            });
+        
+        let mut state = lock_state(&self.state);
+        match result {
+            Ok(text) => {
+                // Go through the same parsing as typing into the Keys field
+                state.key_to_inject = text;
+                state.parse_key_sequence();
+                let count = state.steps.len();
+                log_info!(state.log, "Loaded {} steps from {}", count, path.display());
+                if !state.has_enabled_steps() {
+                    log_warn!(state.log, "loaded sequence has no enabled steps");
+                }
+            },
+            Err(e) => {
+                log_error!(state.log, "Failed to load sequence from {}: {}", path.display(), e);
+            },
+        }
+    }
+    
+    // Choose the Rhai script to run; it is re-read from disk on every start
+    pub fn pick_script(&self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("Rhai script", &["rhai"])
+            .pick_file()
+        else {
+            return;
+        };
+        let mut state = lock_state(&self.state);
+        log_info!(state.log, "Loaded script {}", path.display());
+        state.script_path = Some(path);
+    }
+    
+    // Save the current sequence, as JSON steps or plain key text depending on the chosen extension
+    pub fn save_sequence(&self) {
+        let (text, steps) = {
+            let state = lock_state(&self.state);
+            (state.key_to_inject.clone(), state.steps.clone())
+        };
+        
+        let Some(path) = FileDialog::new()
+            .add_filter("Text", &["txt"])
+            .add_filter("JSON", &["json"])
+            .set_file_name("sequence.txt")
+            .save_file()
+        else {
+            return;
+        };
+        
+        let result = if is_json_path(&path) {
+            serde_json::to_string_pretty(&steps)
+                .map_err(|e| e.to_string())
+                .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()))
+        } else {
+            std::fs::write(&path, text).map_err(|e| e.to_string())
+        };
+        
+        let mut state = lock_state(&self.state);
+        match result {
+            Ok(()) => log_info!(state.log, "Saved sequence to {}", path.display()),
+            Err(e) => log_error!(state.log, "Failed to save sequence to {}: {}", path.display(), e),
+        }
+    }
+    
+    // Write the settings and every profile to one file for another machine
+    pub fn export_all(&self) {
+        let json = {
+            let state = lock_state(&self.state);
+            backup::export(&SavedSettings::of(&state), &state.profiles)
+        };
+        
+        let Some(path) = FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .set_file_name("ez_autoclicker_export.json")
+            .save_file()
+        else {
+            return;
+        };
+        
+        let result = std::fs::write(&path, json);
+        let mut state = lock_state(&self.state);
+        let count = state.profiles.len();
+        match result {
+            Ok(()) => log_info!(state.log, "Exported the settings and {} profiles to {}", count, path.display()),
+            Err(e) => log_error!(state.log, "Failed to export to {}: {}", path.display(), e),
+        }
+    }
+    
+    // Take over an exported file: everything rejected is listed before anything changes, new
+    // profiles are added, and each one whose name is taken here is only replaced if confirmed
+    pub fn import_all(&self) {
+        let Some(path) = FileDialog::new()
+            .add_filter("JSON", &["json"])
+            .pick_file()
+        else {
+            return;
+        };
+        
+        let current = SavedSettings::of(&lock_state(&self.state));
+        let result = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|json| backup::parse_import(&json, &current));
+        let import = match result {
+            Ok(import) => import,
+            Err(e) => {
+                log_error!(lock_state(&self.state).log, "Failed to import {}: {}", path.display(), e);
+                MessageDialog::new()
+                    .set_level(MessageLevel::Error)
+                    .set_title("Import failed")
+                    .set_description(format!("{} can't be imported: {}", path.display(), e))
+                    .show();
+                return;
+            },
+        };
+        
+        let existing = lock_state(&self.state).profiles.clone();
+        let (new, conflicting) = backup::split_conflicts(&existing, import.profiles);
+        let mut description = format!(
+            "{} settings and {} profiles can be imported from {}",
+            import.settings_accepted,
+            new.len() + conflicting.len(),
+            path.display(),
+        );
+        if !conflicting.is_empty() {
+            description += &format!(", {} of them with the name of a profile here (you'll be asked about each)", conflicting.len());
+        }
+        description += ".";
+        if !import.rejected.is_empty() {
+            description += &format!("\n\nThese entries were rejected and will be left out:\n\n• {}", import.rejected.join("\n• "));
+        }
+        let go_ahead = MessageDialog::new()
+            .set_level(if import.rejected.is_empty() { MessageLevel::Info } else { MessageLevel::Warning })
+            .set_title("Import settings and profiles")
+            .set_description(&description)
+            .set_buttons(MessageButtons::OkCancelCustom("Import".to_string(), "Cancel".to_string()))
+            .show();
+        if go_ahead != MessageDialogResult::Custom("Import".to_string()) {
+            return;
+        }
+        
+        let mut added = new;
+        let mut kept = 0;
+        for profile in conflicting {
+            let replace = MessageDialog::new()
+                .set_level(MessageLevel::Warning)
+                .set_title("Profile already exists")
+                .set_description(format!("There's already a profile called '{}'. Replace it with the imported one?", profile.name))
+                .set_buttons(MessageButtons::OkCancelCustom("Replace".to_string(), "Keep mine".to_string()))
+                .show();
+            if replace == MessageDialogResult::Custom("Replace".to_string()) {
+                added.push(profile);
+            } else {
+                kept += 1;
+            }
+        }
+        
+        let dir = profiles::profiles_dir();
+        let mut state = lock_state(&self.state);
+        for error in &import.rejected {
+            log_warn!(state.log, "Import skipped {}", error);
+        }
+        for profile in &added {
+            if let Err(e) = profiles::save_profile_file(&dir, profile) {
+                log_error!(state.log, "Couldn't save profile '{}': {}", profile.name, e);
+            }
+        }
+        let count = added.len();
+        for profile in added {
+            match state.profile_index(&profile.name) {
+                Some(i) => state.profiles[i] = profile,
+                None => state.profiles.push(profile),
+            }
+        }
+        state.profiles.sort_by(|a, b| a.name.cmp(&b.name));
+        // After the profiles, so an imported active profile can be found
+        import.settings.apply_to(&mut state);
+        state.send_update();
+        log_info!(
+            state.log,
+            "Imported {} settings and {} profiles from {} ({} kept as they were, {} rejected)",
+            import.settings_accepted,
+            count,
+            path.display(),
+            kept,
+            import.rejected.len(),
+        );
+    }
+    
+    // Helper to create consistent action buttons
+    pub fn create_action_button(&self, ui: &mut egui::Ui, state: &mut AppState, now: Instant, config: ButtonConfig) {
+        egui::Frame::none()
+            .stroke(egui::Stroke::new(2.0, config.color))
+            .show(ui, |ui| {
+                let enabled = !config.starts || (state.injection_unavailable.is_none() && !health::ACTION.is_unresponsive());
+                let response = ui.add_enabled_ui(enabled, |ui| {
+                    ui.add_sized([ui.available_width(), 40.0], egui::Button::new(config.text))
+                });
+                if response.inner.clicked() {
+                    (config.action)(state, now);
+                }
+            });
+    }
+    
+    // Dropdown in the header that switches profiles as soon as one is picked
+    pub fn show_profile_switcher(&self, ui: &mut egui::Ui, state: &mut AppState, label: Option<&str>, now: Instant) {
+        let mut picked = None;
+        let response = egui::ComboBox::from_id_source("profile_switcher")
+            .selected_text(label.unwrap_or("No profile"))
+            .show_ui(ui, |ui| {
+                for (i, profile) in state.profiles.iter().enumerate() {
+                    let active = state.active_profile.as_deref() == Some(profile.name.as_str());
+                    if ui.selectable_label(active, &profile.name).clicked() {
+                        picked = Some(i);
+                    }
+                }
+            })
+            .response;
+        let hint = if state.profiles.is_empty() {
+            "Save a profile in the Profiles section to switch to it from here"
+        } else {
+            "Switch profiles, stopping whatever is running. * means the settings changed since the profile was loaded or saved"
+        };
+        response.on_hover_text(hint);
+        if let Some(i) = picked {
+            state.apply_profile(i, now);
+        }
+    }
+    
+    // Helper to save the current settings as profiles and switch between them. Each profile is
+    // written to its own file as soon as it's saved, and its file removed when it's deleted.
+    // Returns the export or import asked for, whose dialogs have to wait for the lock to go
+    pub fn show_profiles(&self, ui: &mut egui::Ui, state: &mut AppState, now: Instant) -> Option<FileAction> {
+        let mut file_action = None;
+        egui::CollapsingHeader::new("Profiles")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut selected = None;
+                for profile in &state.profiles {
+                    ui.horizontal(|ui| {
+                        let active = state.active_profile.as_deref() == Some(profile.name.as_str());
+                        let chosen = state.profile_name.trim() == profile.name;
+                        let label = if active { format!("{} (active)", profile.name) } else { profile.name.clone() };
+                        let response = ui.selectable_label(chosen, label).on_hover_text("Click to pick, double-click to load");
+                        if response.double_clicked() {
+                            selected = Some((profile.name.clone(), true));
+                        } else if response.clicked() {
+                            selected = Some((profile.name.clone(), false));
+                        }
+                        ui.weak(format!(
+                            "clicks {} · keys {} · {}",
+                            timing::format_us(profile.click_interval_us),
+                            timing::format_us(profile.key_interval_us),
+                            profile.keys,
+                        ));
+                        if profile.hold_mode {
+                            ui.weak("· hold");
+                        }
+                        if profile.hotkeys.is_some() {
+                            ui.weak("· own hotkeys");
+                        }
+                    });
+                }
+                let mut load = false;
+                if let Some((name, and_load)) = selected {
+                    state.profile_name = name;
+                    load = and_load;
+                }
+                
+                ui.horizontal(|ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut state.profile_name);
+                    ui.checkbox(&mut state.profile_with_hotkeys, "with hotkeys")
+                        .on_hover_text("Store the current hotkey bindings in the profile so switching to it rebinds them");
+                });
+                let index = state.profile_index(&state.profile_name);
+                ui.horizontal(|ui| {
+                    if ui.button("Save").on_hover_text("Save the current settings under this name, replacing a profile of the same name").clicked() {
+                        if let Some(profile) = state.save_profile() {
+                            match profiles::save_profile_file(&profiles::profiles_dir(), &profile) {
+                                Ok(path) => log_info!(state.log, "Wrote {}", path.display()),
+                                Err(e) => log_error!(state.log, "Couldn't save profile '{}': {}", profile.name, e),
+                            }
+                        }
+                    }
+                    load |= ui.add_enabled(index.is_some(), egui::Button::new("Load"))
+                        .on_hover_text("Switch to this profile, stopping whatever is running")
+                        .clicked();
+                    if ui.add_enabled(index.is_some(), egui::Button::new("Delete")).clicked() {
+                        if let Some(profile) = index.and_then(|i| state.delete_profile(i)) {
+                            if let Err(e) = profiles::delete_profile_file(&profiles::profiles_dir(), &profile.name) {
+                                log_error!(state.log, "Couldn't delete profile '{}': {}", profile.name, e);
+                            }
+                        }
+                    }
+                });
+                if load {
+                    if let Some(i) = state.profile_index(&state.profile_name) {
+                        state.apply_profile(i, now);
+                    }
+                }
+                
+                ui.horizontal(|ui| {
+                    if ui.button("Export all…").on_hover_text("Save the settings, hotkeys and every profile to one file").clicked() {
+                        file_action = Some(FileAction::ExportAll);
+                    }
+                    if ui.button("Import all…")
+                        .on_hover_text("Take over the settings and add the profiles from an exported file, asking before replacing a profile")
+                        .clicked()
+                    {
+                        file_action = Some(FileAction::ImportAll);
+                    }
+                });
+            });
+        file_action
+    }
+    
+    // The interval control for `mode`, with its distribution and the quick presets
+    pub fn show_interval_row(&self, ui: &mut egui::Ui, state: &mut AppState, mode: ActiveMode) {
+        let as_cps = state.interval_as_cps;
+        let mut extreme = None;
+        ui.horizontal(|ui| {
+            ui.label(if as_cps { "Rate (CPS):" } else { "Interval (ms):" });
+            let interval = state.interval_mut(&mode);
+            match interval.distribution {
+                Distribution::Fixed => {
+                    extreme = interval_drag_value(ui, &mut interval.us, as_cps).map(|us| (IntervalField::Fixed, us));
+                },
+                Distribution::Uniform => {
+                    let min = interval_drag_value(ui, &mut interval.min_us, as_cps).map(|us| (IntervalField::Min, us));
+                    ui.label("to");
+                    let max = interval_drag_value(ui, &mut interval.max_us, as_cps).map(|us| (IntervalField::Max, us));
+                    extreme = min.or(max);
+                    interval.fix_range();
+                },
+                Distribution::Normal => {
+                    extreme = interval_drag_value(ui, &mut interval.us, as_cps).map(|us| (IntervalField::Fixed, us));
+                    ui.label("±");
+                    micros_drag_value(ui, &mut interval.std_dev_us, 0..=*INTERVAL_RANGE.end())
+                        .on_hover_text("Standard deviation in ms; delays stay within three of it of the mean");
+                },
+            }
+            egui::ComboBox::from_id_source(("interval_distribution", mode.label()))
+                .selected_text(interval.distribution.label())
+                .show_ui(ui, |ui| {
+                    for option in Distribution::ALL {
+                        ui.selectable_value(&mut interval.distribution, option, option.label());
+                    }
+                });
+        });
+        if let Some((field, us)) = extreme {
+            state.extreme_interval = Some((mode.clone(), field, us));
+        }
+        
+        // The OS can't sleep for less than its granularity, so shorter intervals come out longer
+        if let Some(granularity) = state.sleep_granularity.filter(|_| !state.precision_timing) {
+            let shortest = state.interval_for(&mode).delay().bounds().0;
+            if Duration::from_micros(shortest) < granularity {
+                ui.horizontal(|ui| {
+                    ui.colored_label(
+                        egui::Color32::from_rgb(255, 200, 0),
+                        format!("⚠ Intervals below ~{} require precision mode", timing::format_us(granularity.as_micros() as u64)),
+                    );
+                    if ui.small_button("Enable precision timing").clicked() {
+                        state.precision_timing = true;
+                        log_info!(state.log, "Precision timing enabled");
+                    }
+                });
+            }
+        }
+        
+        ui.horizontal_wrapped(|ui| {
+            let (mut picked, mut remove) = (None, None);
+            for (i, &us) in state.interval_presets.iter().enumerate() {
+                let response = ui.small_button(timing::format_us(us))
+                    .on_hover_text("Right-click to remove");
+                if response.clicked() {
+                    picked = Some(us);
+                }
+                if response.secondary_clicked() {
+                    remove = Some(i);
+                }
+            }
+            if let Some(us) = picked {
+                state.interval_mut(&mode).us = us;
+            }
+            if let Some(i) = remove {
+                state.interval_presets.remove(i);
+            }
+            let current = state.interval_for(&mode).us;
+            if !state.interval_presets.contains(&current)
+                && ui.small_button("+").on_hover_text("Save the current interval as a preset").clicked()
+            {
+                state.interval_presets.push(current);
+                state.interval_presets.sort_unstable();
+            }
+        });
+        
+        // Long intervals are easier to type with units than as milliseconds
+        ui.horizontal(|ui| {
+            ui.label("Every:");
+            let entry = match mode {
+                ActiveMode::KeystrokeInjection => &mut state.key_interval_entry,
+                _ => &mut state.click_interval_entry,
+            };
+            let response = ui.add(egui::TextEdit::singleline(entry).hint_text("15m, 1h30m, 90s or ms").desired_width(100.0));
+            let submitted = response.lost_focus() && !entry.trim().is_empty();
+            let parsed = timing::parse_duration(entry).and_then(|us| {
+                if INTERVAL_RANGE.contains(&us) {
+                    Ok(us)
+                } else {
+                    Err(format!("Use between {} and {}", timing::format_us(*INTERVAL_RANGE.start()), timing::format_hms(*INTERVAL_RANGE.end())))
+                }
+            });
+            if submitted {
+                match parsed {
+                    Ok(us) => {
+                        state.interval_mut(&mode).us = us;
+                        state.interval_entry_error = None;
+                        log_info!(state.log, "{} set to every {}", mode.interval_label(), timing::format_hms(us));
+                    },
+                    Err(message) => state.interval_entry_error = Some((mode.clone(), message)),
+                }
+            }
+            match &state.interval_entry_error {
+                Some((error_mode, message)) if *error_mode == mode => {
+                    ui.colored_label(egui::Color32::from_rgb(255, 100, 100), format!("⚠ {}", message));
+                },
+                _ => {
+                    ui.weak(format!("every {}", timing::format_hms(state.interval_for(&mode).us)));
+                },
+            }
+        });
+    }
+    
+    // Helper to show how the schedule behaves, with the achieved rate to check it against
+    // Arming a start at a clock time
+    pub fn show_schedule_settings(&self, ui: &mut egui::Ui, state: &mut AppState) {
+        egui::CollapsingHeader::new("Schedule")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Start");
+                    egui::ComboBox::from_id_source("scheduled_mode")
+                        .selected_text(state.scheduled_mode.label())
+                        .show_ui(ui, |ui| {
+                            for mode in ActiveMode::STARTABLE.into_iter().filter(ActiveMode::is_available) {
+                                let label = mode.label();
+                                ui.selectable_value(&mut state.scheduled_mode, mode, label);
+                            }
+                        });
+                    ui.label("at");
+                    ui.add_enabled(
+                        state.scheduled_start.is_none(),
+                        egui::TextEdit::singleline(&mut state.start_at_entry).hint_text("06:00").desired_width(50.0),
+                    );
+                    match state.scheduled_start {
+                        Some(at) => {
+                            ui.label(format!("armed for {}", at.format("%a %H:%M")));
+                            if ui.button("Cancel").clicked() {
+                                state.cancel_scheduled_start();
+                            }
+                        },
+                        None => {
+                            if ui.button("Arm").clicked() {
+                                state.arm_scheduled_start(Local::now());
+                            }
+                        },
+                    }
+                });
+                if let Some(message) = &state.start_at_error {
+                    ui.colored_label(egui::Color32::from_rgb(255, 100, 100), format!("⚠ {}", message));
+                }
+                
+                ui.separator();
+                ui.checkbox(&mut state.recurrence.enabled, "Repeat on a weekly schedule")
+                    .on_hover_text("Run the mode above at the same time on the checked days. Stopping a run by hand only skips that day's run");
+                ui.add_enabled_ui(state.recurrence.enabled, |ui| {
+                    ui.horizontal(|ui| {
+                        for (day, name) in state.recurrence.days.iter_mut().zip(schedule::WEEKDAY_NAMES) {
+                            ui.checkbox(day, name);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("At");
+                        let response = ui.add(
+                            egui::TextEdit::singleline(&mut state.recurrence_time_entry).hint_text("09:00").desired_width(50.0)
+                        );
+                        let parsed = schedule::parse_clock_time(&state.recurrence_time_entry);
+                        if response.lost_focus() {
+                            if let Ok(time) = parsed {
+                                state.recurrence.start = time;
+                            }
+                        }
+                        ui.label("for");
+                        ui.add(egui::DragValue::new(&mut state.recurrence.run_minutes).clamp_range(1..=24 * 60).suffix(" min"));
+                        if let Err(message) = parsed {
+                            ui.colored_label(egui::Color32::from_rgb(255, 100, 100), "⚠").on_hover_text(message);
+                        }
+                    });
+                    let clock = Local::now().naive_local();
+                    if state.recurrence_running.is_some() {
+                        ui.label("Running this occurrence now");
+                    } else if state.recurrence_skipped.is_some() && state.recurrence_skipped == state.recurrence.current(clock) {
+                        ui.weak("Skipping the current occurrence");
+                    }
+                    match state.recurrence.next(clock) {
+                        Some(next) => ui.label(format!("Next: {}", next.format("%a %d %b %H:%M"))),
+                        None => ui.weak("Check at least one day"),
+                    };
+                });
+            });
+    }
+    
+    pub fn show_timing_settings(&self, ui: &mut egui::Ui, state: &mut AppState) {
+        egui::CollapsingHeader::new("Timing")
+            .default_open(false)
+            .show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("When running late:");
+                    egui::ComboBox::from_id_source("late_policy")
+                        .selected_text(state.late_policy.label())
+                        .show_ui(ui, |ui| {
+                            for option in LatePolicy::ALL {
+                                ui.selectable_value(&mut state.late_policy, option, option.label());
+                            }
+                        });
+                })
+                .response
+                .on_hover_text("Only matters after falling more than a whole interval behind, e.g. when the system was busy");
+                ui.horizontal(|ui| {
+                    ui.label("First action:");
+                    egui::ComboBox::from_id_source("first_action")
+                        .selected_text(state.first_action.label())
+                        .show_ui(ui, |ui| {
+                            for option in FirstAction::ALL {
+                                ui.selectable_value(&mut state.first_action, option, option.label());
+                            }
+                        });
+                })
+                .response
+                .on_hover_text("Whether starting or resuming clicks right away or waits one interval first");
+                ui.horizontal(|ui| {
+                    ui.label("Input backend:");
+                    egui::ComboBox::from_id_source("input_backend")
+                        .selected_text(state.input_backend.label())
+                        .show_ui(ui, |ui| {
+                            for &option in BackendKind::AVAILABLE {
+                                ui.selectable_value(&mut state.input_backend, option, option.label());
+                            }
+                        });
+                    if let Some(in_use) = state.backend_in_use.filter(|&kind| kind != state.input_backend) {
+                        ui.colored_label(egui::Color32::from_rgb(255, 100, 100), format!("⚠ using {}", in_use.label()))
+                            .on_hover_text(format!("Couldn't switch to {}, so input still goes through {}; see the log", state.input_backend.label(), in_use.label()));
+                    }
+                    let idle = !state.benchmark_running && state.active_mode == ActiveMode::None;
+                    if ui.add_enabled(idle, egui::Button::new("Benchmark"))
+                        .on_hover_text("Measure how fast each backend can inject; takes a few seconds and sends harmless mouse releases")
+                        .clicked()
+                    {
+                        state.benchmark_running = true;
+                        log_info!(state.log, "Benchmarking input backends...");
+                        start_backend_benchmark(Arc::clone(&self.state));
+                    }
+                });
+                let stats = state.rate_meter.lock().ok().and_then(|meter| meter.stats());
+                match stats.filter(|_| state.active_mode != ActiveMode::None) {
+                    Some(stats) => ui.label(format!(
+                        "Measured period: {} (target {})",
+                        timing::format_us(stats.mean_period.as_micros() as u64),
+                        timing::format_us(state.interval_for(&state.active_mode).delay().mean()),
+                    )),
+                    None => ui.weak("Measured period: not running"),
+                };
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut state.rate_cap, "Never exceed")
+                        .on_hover_text("A safety cap against typos; turn it off or raise it for benchmarking");
+                    ui.add_enabled(state.rate_cap, egui::DragValue::new(&mut state.max_cps).clamp_range(1.0..=100_000.0).suffix(" CPS"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Warm up over");
+                    ui.add(egui::DragValue::new(&mut state.warm_up_s).clamp_range(0..=600).suffix(" s"))
+                        .on_hover_text("After each start, ease the rate up to the interval's instead of starting at full speed; 0 starts at full speed");
+                    ui.label("from");
+                    ui.add_enabled(state.warm_up_s > 0, egui::DragValue::new(&mut state.warm_up_start_cps).clamp_range(0.1..=1_000.0).suffix(" CPS"));
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut state.idle_activation, "Only run after")
+                        .on_hover_text("Hold actions back until you've left the keyboard and mouse alone this long, and stop them as soon as you touch either");
+                    ui.add_enabled(state.idle_activation, egui::DragValue::new(&mut state.idle_threshold_s).clamp_range(1..=86_400).suffix(" s"));
+                    ui.label("idle");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Count actions as missed when more than");
+                    ui.add(egui::DragValue::new(&mut state.late_tolerance_ms).clamp_range(1..=10_000).suffix(" ms"));
+                    ui.label(format!("late ({} this run)", state.missed_deadlines.total));
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut state.duty_cycle, "Duty cycle:")
+                        .on_hover_text("Alternate bursts of activity with rests; held inputs are released while resting");
+                    ui.label("run");
+                    ui.add(egui::DragValue::new(&mut state.duty_active_s).clamp_range(1..=86_400).suffix(" s"));
+                    ui.label("then rest");
+                    ui.add(egui::DragValue::new(&mut state.duty_rest_s).clamp_range(1..=86_400).suffix(" s"));
+                });
+                ui.checkbox(&mut state.precision_timing, "High-precision timing")
+                    .on_hover_text("Spin for the last 2 ms before each action instead of sleeping. Keeps intervals under ~10 ms regular, but keeps a CPU core busy while running.");
+                if state.precision_timing {
+                    ui.colored_label(egui::Color32::from_rgb(255, 200, 0), "⚠ Uses a full CPU core at short intervals");
+                    ui.checkbox(&mut state.precision_priority, "Raise thread priority")
+                        .on_hover_text("Windows only: run the action thread at high priority so it is woken promptly");
+                }
+            });
+    }
+    
+    // Helper to show each hotkey with a button that captures a new key for it
+    pub fn show_hotkey_editor(&self, ui: &mut egui::Ui, state: &mut AppState, now: Instant) {
+        egui::CollapsingHeader::new("Hotkeys")
+            .default_open(false)
+            .show(ui, |ui| {
+                let suppress = ui.checkbox(&mut state.suppress_hotkeys, "Suppress hotkeys")
+                    .on_hover_text("Swallow hotkey presses so the focused app never sees them. Needs Accessibility permission on macOS and access to /dev/input on Linux, and doesn't work under Wayland.");
+                // The grab listener passes everything through while suppression is off, so it only starts once
+                if suppress.changed() && state.suppress_hotkeys && !state.hotkey_grab_active {
+                    state.hotkey_grab_active = true;
+                    start_grab_thread(Arc::clone(&self.state), Arc::clone(&self.is_running), Arc::clone(&self.hold_released));
+                }
+                
+                for conflict in &state.hotkey_conflicts {
+                    ui.colored_label(egui::Color32::from_rgb(255, 100, 100), format!("⚠ {}", conflict));
+                }
+                
+                for action in HotkeyAction::ALL.into_iter().filter(|action| action.is_available()) {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{}:", action.label()));
+                        if state.capturing_hotkey == Some(action) {
+                            ui.weak("Press a key, combination or mouse button… (Esc to cancel)");
+                            if ui.small_button("Cancel").clicked() {
+                                state.capturing_hotkey = None;
+                            }
+                        } else {
+                            ui.monospace(state.hotkeys.name(action));
+                            if ui.small_button("Rebind").clicked() {
+                                state.capturing_hotkey = Some(action);
+                                state.pending_hotkey = None;
+                            }
+                        }
+                        ui.checkbox(&mut state.hotkeys.get_mut(action).double_tap, "double-tap")
+                            .on_hover_text("Only trigger on two presses in quick succession");
+                        // Only start hotkeys can toggle or be held
+                        if action.mode().is_some() {
+                            let activation = &mut state.hotkeys.get_mut(action).activation;
+                            egui::ComboBox::from_id_source(("hotkey_activation", action.label()))
+                                .selected_text(activation.label())
+                                .show_ui(ui, |ui| {
+                                    for option in Activation::ALL {
+                                        ui.selectable_value(activation, option, option.label());
+                                    }
+                                });
+                        }
+                    });
+                    
+                    if let Some((pending_action, input, modifiers)) = state.pending_hotkey {
+                        if pending_action == action {
+                            let name = Hotkey { input, modifiers, ..*state.hotkeys.get(action) }.name();
+                            ui.colored_label(
+                                egui::Color32::from_rgb(255, 100, 100),
+                                format!("⚠ Bind {} to {}? Every click you make with it will trigger the hotkey.", name, action.label()),
+                            );
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Bind anyway").clicked() {
+                                    state.pending_hotkey = None;
+                                    state.bind_hotkey(action, input, modifiers);
+                                }
+                                if ui.small_button("Cancel").clicked() {
+                                    state.pending_hotkey = None;
+                                }
+                            });
+                        }
+                    }
+                }
+                
+                ui.horizontal(|ui| {
+                    ui.label("One-shot action:");
+                    ui.text_edit_singleline(&mut state.one_shot)
+                        .on_hover_text("A single key or click, written like a Keys field entry (e.g. 'click', 'rclick', 'space')");
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Only in window:");
+                    ui.add(egui::TextEdit::singleline(&mut state.window_filter).hint_text("any window"))
+                        .on_hover_text("Hotkeys only work while the focused window's title contains this text. Stop and panic always work.");
+                    match state.window_capture_at {
+                        Some(at) => {
+                            let left = at.saturating_duration_since(now).as_secs() + 1;
+                            ui.weak(format!("Switch to the target window… {}", left));
+                            ui.ctx().request_repaint_after(Duration::from_millis(200));
+                        },
+                        None => {
+                            if ui.small_button("Use current window").clicked() {
+                                state.window_capture_at = Some(now + WINDOW_CAPTURE_DELAY);
+                            }
+                        },
+                    }
+                });
+                ui.checkbox(&mut state.pause_outside_window, "Pause while another window is focused");
+                ui.horizontal(|ui| {
+                    ui.label("Double-tap window:");
+                    ui.add(egui::DragValue::new(&mut state.double_tap_window_ms).clamp_range(100..=1000).suffix(" ms"));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Re-toggle cooldown:");
+                    ui.add(egui::DragValue::new(&mut state.mode_cooldown_ms).clamp_range(0..=2000).suffix(" ms"))
+                        .on_hover_text("Start and pause hotkeys are ignored for this long after the mode changes. Stop always works.");
+                });
+                let remote = ui.checkbox(&mut state.ipc_enabled, "Remote control")
+                    .on_hover_text(format!(
+                        "Accept commands from scripts on {}: start click, start keys, stop, set interval MS, status. Only your user can connect.",
+                        ipc::endpoint().display(),
+                    ));
+                // A listener still winding down sees the setting back on and carries on
+                if remote.changed() && state.ipc_enabled && !state.ipc_listening {
+                    state.ipc_listening = true;
+                    ipc::start_ipc_listener(Arc::clone(&self.state), Arc::clone(&self.is_running));
+                }
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut state.http_enabled, "HTTP API")
+                        .on_hover_text("Accept requests on 127.0.0.1 only: POST /start?mode=click|keys, POST /stop, POST /interval?ms=N, GET /status and a WebSocket at /events. Each needs the token.");
+                    ui.label("Port:");
+                    ui.add(egui::DragValue::new(&mut state.http_port).clamp_range(1024..=65535));
+                });
+                if state.http_enabled {
+                    ui.horizontal(|ui| {
+                        ui.label("Token:");
+                        ui.monospace(&state.http_token);
+                        if ui.small_button("Copy").clicked() {
+                            ui.output_mut(|o| o.copied_text = state.http_token.clone());
+                        }
+                        if ui.small_button("New token").on_hover_text("Clients using the old token stop working").clicked() {
+                            state.http_token = http_api::random_token();
+                            log_info!(state.log, "Made a new HTTP API token");
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Interval hotkey step:");
+                    ui.add(egui::DragValue::new(&mut state.interval_step));
+                    ui.checkbox(&mut state.interval_step_percent, "%");
+                    let max_step = if state.interval_step_percent { 100 } else { *SAFE_INTERVAL_RANGE.end() / 1_000 };
+                    state.interval_step = state.interval_step.clamp(1, max_step);
+                });
+            });
+    }
+    
+    // Helper to create the list-based step editor, kept in sync with the text field
+    pub fn show_step_editor(&self, ui: &mut egui::Ui, state: &mut AppState) {
+        egui::CollapsingHeader::new("Step editor")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut changed = false;
+                let mut removed = None;
+                let mut moved = None;
+                let step_count = state.steps.len();
+                
+                for (i, step) in state.steps.iter_mut().enumerate() {
+                    let row = ui.horizontal(|ui| {
+                        // Drag handle plus arrow buttons for reordering
+                        ui.dnd_drag_source(egui::Id::new(("step_drag", i)), i, |ui| {
+                            ui.label("☰");
+                        }).response.on_hover_text("Drag to reorder");
+                        if ui.add_enabled(i > 0, egui::Button::new("⏶").small()).clicked() {
+                            moved = Some((i, i - 1));
+                        }
+                        if ui.add_enabled(i + 1 < step_count, egui::Button::new("⏷").small()).clicked() {
+                            moved = Some((i, i + 1));
+                        }
+                        ui.label(format!("{}.", i + 1));
+                        changed |= ui.checkbox(&mut step.enabled, "")
+                            .on_hover_text("Uncheck to skip this step without removing it")
+                            .changed();
+                        
+                        // Disabled steps stay editable but render greyed out
+                        if !step.enabled {
+                            let weak = ui.visuals().weak_text_color();
+                            ui.visuals_mut().override_text_color = Some(weak);
+                        }
+                        
+                        // Step type dropdown; switching type resets the parameters
+                        let type_label = match step.kind {
+                            StepKind::Key(_) => "Key",
+                            StepKind::Click(_) => "Click",
+                            StepKind::Wait(_) => "Wait",
+                        };
+                        egui::ComboBox::from_id_source(("step_type", i))
+                            .width(60.0)
+                            .selected_text(type_label)
+                            .show_ui(ui, |ui| {
+                                if ui.selectable_label(matches!(step.kind, StepKind::Key(_)), "Key").clicked()
+                                    && !matches!(step.kind, StepKind::Key(_))
+                                {
+                                    step.kind = StepKind::Key("w".to_string());
+                                    changed = true;
+                                }
+                                if ui.selectable_label(matches!(step.kind, StepKind::Click(_)), "Click").clicked()
+                                    && !matches!(step.kind, StepKind::Click(_))
+                                {
+                                    step.kind = StepKind::Click(ClickButton::Left);
+                                    changed = true;
+                                }
+                                if ui.selectable_label(matches!(step.kind, StepKind::Wait(_)), "Wait").clicked()
+                                    && !matches!(step.kind, StepKind::Wait(_))
+                                {
+                                    step.kind = StepKind::Wait(500);
+                                    changed = true;
+                                }
+                            });
+                        
+                        // Parameters for the chosen type
+                        match &mut step.kind {
+                            StepKind::Key(key) => {
+                                changed |= ui.add(egui::TextEdit::singleline(key).desired_width(80.0)).changed();
+                            },
+                            StepKind::Click(button) => {
+                                egui::ComboBox::from_id_source(("step_button", i))
+                                    .width(70.0)
+                                    .selected_text(button.label())
+                                    .show_ui(ui, |ui| {
+                                        for option in ClickButton::ALL {
+                                            changed |= ui.selectable_value(button, option, option.label()).changed();
+                                        }
+                                    });
+                            },
+                            StepKind::Wait(ms) => {
+                                changed |= ui.add(egui::DragValue::new(ms).clamp_range(1..=600_000).suffix(" ms")).changed();
+                            },
+                        }
+                        
+                        if !matches!(step.kind, StepKind::Wait(_)) {
+                            changed |= ui.toggle_value(&mut step.handover, "hold")
+                                .on_hover_text("Keep this step held until the next step is pressed")
+                                .changed();
+                        } else if step.handover {
+                            step.handover = false;
+                            changed = true;
+                        }
+                        
+                        if ui.small_button("🗑").on_hover_text("Remove step").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                    
+                    // Dropping a dragged step onto this row moves it here
+                    if let Some(from) = row.response.dnd_release_payload::<usize>() {
+                        moved = Some((*from, i));
+                    }
+                }
+                
+                if let Some((from, to)) = moved {
+                    state.move_step(from, to);
+                    changed = true;
+                }
+                if let Some(i) = removed {
+                    state.steps.remove(i);
+                    state.reset_sequence_position();
+                    changed = true;
+                }
+                if ui.button("Add step").clicked() {
+                    state.steps.push(SequenceStep::new(StepKind::Key("w".to_string())));
+                    state.reset_sequence_position();
+                    changed = true;
+                }
+                if changed {
+                    state.sync_text_from_steps();
+                }
+            });
+    }
+    
+    // Helper to create the log area
+    pub fn show_log_area(&self, ui: &mut egui::Ui, state: &mut AppState) {
+        ui.horizontal(|ui| {
+            ui.label("Show:");
+            egui::ComboBox::from_id_source("log_level")
+                .selected_text(state.log_level.as_str().to_lowercase())
+                .show_ui(ui, |ui| {
+                    for filter in logging::FILTERS {
+                        ui.selectable_value(&mut state.log_level, filter, filter.as_str().to_lowercase());
+                    }
+                })
+                .response
+                .on_hover_text("The least important lines shown; debug adds timing details from the action thread");
+            for category in Category::ALL {
+                let mut shown = state.log_categories.contains(&category);
+                if ui.checkbox(&mut shown, category.label()).changed() {
+                    state.log_categories.retain(|c| *c != category);
+                    if shown {
+                        state.log_categories.push(category);
+                    }
+                }
+            }
+            ui.checkbox(&mut state.log_to_file, "Save to file").on_hover_text(format!(
+                "Also write every line, with the time, to {} (rotated at {} MB, keeping {} old files)",
+                log_file::log_file_path().display(),
+                log_file::MAX_FILE_BYTES / (1024 * 1024),
+                log_file::KEPT_FILES,
+            ));
+            if ui.button("Open config folder")
+                .on_hover_text(format!("Show {} and the log files in the file manager", config::config_path().display()))
+                .clicked()
+            {
+                if let Err(e) = config::open_config_dir() {
+                    log_error!(state.log, "{}", e);
+                }
+            }
+            ui.label("Keep:");
+            let mut max_entries = state.log.max_entries();
+            let response = ui.add(egui::DragValue::new(&mut max_entries).clamp_range(logging::MAX_ENTRIES_RANGE).suffix(" lines"))
+                .on_hover_text("Older lines are dropped from the window once there are this many (the log file keeps everything)");
+            if response.changed() {
+                state.log.set_max_entries(max_entries);
+            }
+            if state.log.trimmed() > 0 {
+                ui.weak(format!("{} older lines trimmed", state.log.trimmed()));
+            }
+        });
+        // Only the rows in view are laid out, so a full log scrolls as fast as an empty one
+        let shown: Vec<_> = state.log
+            .iter()
+            .filter(|entry| entry.level <= state.log_level && state.log_categories.contains(&entry.category))
+            .collect();
+        egui::Frame::none()
+            .fill(egui::Color32::from_rgba_unmultiplied(0, 0, 0, 100))
+            .stroke(egui::Stroke::new(2.0, egui::Color32::GRAY))
+            .show(ui, |ui| {
+                let available_height = 200.0;
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::both()
+                    .max_height(available_height)
+                    .auto_shrink([false, true])
+                    .stick_to_bottom(true)
+                    .show_rows(ui, row_height, shown.len(), |ui, rows| {
+                        ui.set_min_height(available_height);
+                        ui.style_mut().visuals.override_text_color = Some(egui::Color32::WHITE);
+                        for entry in &shown[rows] {
+                            ui.add(egui::Label::new(egui::RichText::new(entry.line()).monospace()).wrap(false));
+                        }
+                    });
+            });
+    }
+}
+
+pub fn is_json_path(path: &std::path::Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+// A microsecond value edited in milliseconds, so fractions of a millisecond can be typed
+pub fn micros_drag_value(ui: &mut egui::Ui, us: &mut u64, range: RangeInclusive<u64>) -> egui::Response {
+    let mut ms = *us as f64 / 1_000.0;
+    let ms_range = *range.start() as f64 / 1_000.0..=*range.end() as f64 / 1_000.0;
+    let response = ui.add(egui::DragValue::new(&mut ms).clamp_range(ms_range).max_decimals(3));
+    if response.changed() {
+        *us = ((ms * 1_000.0).round() as u64).clamp(*range.start(), *range.end());
+    }
+    response
+}
+
+// An interval field in milliseconds or clicks per second. The value is stored in µs either way
+// and clamped to the same INTERVAL_RANGE, so both units allow exactly the same intervals.
+// An edit that leaves SAFE_INTERVAL_RANGE is held back and returned so it can be confirmed
+pub fn interval_drag_value(ui: &mut egui::Ui, us: &mut u64, as_cps: bool) -> Option<u64> {
+    let before = *us;
+    let response = if as_cps {
+        let cps_range = timing::us_to_cps(*INTERVAL_RANGE.end())..=timing::us_to_cps(*INTERVAL_RANGE.start());
+        let mut cps = timing::us_to_cps(*us);
+        let response = ui.add(egui::DragValue::new(&mut cps).clamp_range(cps_range).speed(0.1).max_decimals(2));
+        if response.changed() {
+            *us = timing::cps_to_us(cps).clamp(*INTERVAL_RANGE.start(), *INTERVAL_RANGE.end());
+        }
+        response
+    } else {
+        micros_drag_value(ui, us, INTERVAL_RANGE)
+    };
+    if response.changed() && SAFE_INTERVAL_RANGE.contains(&before) && !SAFE_INTERVAL_RANGE.contains(us) {
+        let requested = *us;
+        *us = requested.clamp(*SAFE_INTERVAL_RANGE.start(), *SAFE_INTERVAL_RANGE.end());
+        return Some(requested);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{MockBackend, MockCall};
+    use crate::scheduler::start_action_thread;
+    use crate::timing::Interval;
+
+    #[test]
+    fn repaints_by_itself_only_while_something_is_going_on() {
+        let mut state = AppState::default();
+        assert_eq!(repaint_interval(&state), None);
+        state.recurrence.enabled = true;
+        assert_eq!(repaint_interval(&state), Some(SCHEDULED_REPAINT_INTERVAL));
+        state.start_clicking(Instant::now());
+        assert_eq!(repaint_interval(&state), Some(ACTIVE_REPAINT_INTERVAL));
+    }
+
+    #[test]
+    fn shutdown_releases_held_input_and_joins_the_action_thread() {
+        let mut state = AppState { hold_mode: true, click_interval: Interval::fixed(60_000_000), ..AppState::default() };
+        state.start_clicking(Instant::now());
+        let state = Arc::new(Mutex::new(state));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let hold_released = Arc::new(AtomicBool::new(false));
+        let backend = MockBackend::default();
+        let thread_backend = backend.clone();
+        let handle = start_action_thread(Arc::clone(&state), Arc::clone(&is_running), Arc::clone(&hold_released), move || Ok(thread_backend.clone()));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend.calls().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        let mut app = AutoClickerApp {
+            state,
+            is_running,
+            hold_released,
+            threads: vec![("action", handle, SHUTDOWN_JOIN_TIMEOUT)],
+            config_writer: ConfigWriter::default(),
+            window_title: String::new(),
+        };
+        app.shutdown_with(|| Ok(MockBackend::default()));
+
+        assert!(app.threads.is_empty());
+        assert!(!app.is_running.load(Ordering::SeqCst));
+        let calls = backend.calls();
+        assert_eq!(calls[0], MockCall::MouseDown(ClickButton::Left));
+        assert!(calls.contains(&MockCall::MouseUp(ClickButton::Left)));
+        assert_eq!(lock_state(&app.state).active_mode, ActiveMode::None);
+    }
+
+    #[test]
+    fn closing_mid_hold_releases_the_key_even_without_waiting_for_the_thread() {
+        let mut state = AppState { key_to_inject: "w".to_string(), hold_mode: true, ..AppState::default() };
+        state.key_interval = Interval::fixed(60_000_000);
+        state.parse_key_sequence();
+        state.start_keystroke_injection(Instant::now());
+        let state = Arc::new(Mutex::new(state));
+        let is_running = Arc::new(AtomicBool::new(true));
+        let hold_released = Arc::new(AtomicBool::new(false));
+        let backend = MockBackend::default();
+        let thread_backend = backend.clone();
+        let handle = start_action_thread(Arc::clone(&state), Arc::clone(&is_running), Arc::clone(&hold_released), move || Ok(thread_backend.clone()));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while backend.calls().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(backend.calls(), [MockCall::KeyDown("w".to_string())]);
+
+        // Don't wait for the thread at all, as if the process were about to exit under it
+        let mut app = AutoClickerApp {
+            state,
+            is_running,
+            hold_released,
+            threads: vec![("action", handle, Duration::ZERO)],
+            config_writer: ConfigWriter::default(),
+            window_title: String::new(),
+        };
+        let leftovers = MockBackend::default();
+        let shutdown_backend = leftovers.clone();
+        app.shutdown_with(move || Ok(shutdown_backend));
+
+        // Whichever side got there first, the key comes back up
+        let key_up = MockCall::KeyUp("w".to_string());
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !backend.calls().contains(&key_up) && !leftovers.calls().contains(&key_up) && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(backend.calls().contains(&key_up) || leftovers.calls().contains(&key_up));
+    }
+}
@@ -0,0 +1,200 @@
+// Injects through a virtual input device created with /dev/uinput.
+//
+// Works without an X server (Wayland, a bare console), since the kernel sees the device like a
+// real keyboard and mouse. Opening /dev/uinput needs permission though, which most distributions
+// only give to root by default. The device has no keyboard layout: keys are sent as evdev codes.
+
+use crate::backend::{keyboard_allowed, mouse_allowed, InputBackend};
+use crate::keys::{map_key_str_to_evdev_key, ClickButton};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::Duration;
+
+const UINPUT_PATH: &str = "/dev/uinput";
+const DEVICE_NAME: &[u8] = b"ez_autoclicker virtual input";
+
+// From linux/uinput.h and linux/input-event-codes.h
+const UI_SET_EVBIT: u64 = 0x4004_5564;
+const UI_SET_KEYBIT: u64 = 0x4004_5565;
+const UI_SET_RELBIT: u64 = 0x4004_5566;
+const UI_DEV_SETUP: u64 = 0x405c_5503;
+const UI_DEV_CREATE: u64 = 0x5501;
+const UI_DEV_DESTROY: u64 = 0x5502;
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const SYN_REPORT: u16 = 0;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const BUS_VIRTUAL: u16 = 0x06;
+// Every ordinary keyboard key sits below this
+const KEY_CODES_END: u16 = 128;
+
+// Desktop environments take a moment to notice a new device; anything sent before then is lost
+const DEVICE_SETTLE: Duration = Duration::from_millis(200);
+
+#[repr(C)]
+struct UinputSetup {
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+    name: [u8; 80],
+    ff_effects_max: u32,
+}
+
+pub struct UinputBackend {
+    device: File,
+}
+
+// What to tell someone whose open of /dev/uinput failed
+pub fn open_error(error: &io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => format!(
+            "no permission to open {}. Add a udev rule like KERNEL==\"uinput\", GROUP=\"input\", MODE=\"0660\", OPTIONS+=\"static_node=uinput\" \
+             (e.g. in /etc/udev/rules.d/99-uinput.rules), add yourself to the input group (sudo usermod -aG input $USER) and log in again",
+            UINPUT_PATH,
+        ),
+        io::ErrorKind::NotFound => format!("{} doesn't exist; load the uinput module with sudo modprobe uinput", UINPUT_PATH),
+        _ => format!("couldn't open {}: {}", UINPUT_PATH, error),
+    }
+}
+
+fn ioctl(device: &File, request: u64, value: libc::c_ulong) -> Result<(), String> {
+    // SAFETY: every request used here takes an integer argument, passed by value
+    if unsafe { libc::ioctl(device.as_raw_fd(), request as _, value) } < 0 {
+        return Err(format!("uinput setup failed: {}", io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+impl UinputBackend {
+    pub fn new() -> Result<Self, String> {
+        let device = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)
+            .map_err(|e| open_error(&e))?;
+
+        ioctl(&device, UI_SET_EVBIT, EV_KEY as libc::c_ulong)?;
+        for code in 1..KEY_CODES_END {
+            ioctl(&device, UI_SET_KEYBIT, code as libc::c_ulong)?;
+        }
+        for button in ClickButton::ALL {
+            ioctl(&device, UI_SET_KEYBIT, button_code(button) as libc::c_ulong)?;
+        }
+        // Relative axes make the device count as a mouse, so its buttons are taken as clicks
+        ioctl(&device, UI_SET_EVBIT, EV_REL as libc::c_ulong)?;
+        ioctl(&device, UI_SET_RELBIT, REL_X as libc::c_ulong)?;
+        ioctl(&device, UI_SET_RELBIT, REL_Y as libc::c_ulong)?;
+
+        let mut setup = UinputSetup { bustype: BUS_VIRTUAL, vendor: 0x1234, product: 0x5678, version: 1, name: [0; 80], ff_effects_max: 0 };
+        setup.name[..DEVICE_NAME.len()].copy_from_slice(DEVICE_NAME);
+        // SAFETY: UI_DEV_SETUP reads one uinput_setup, which UinputSetup matches field for field
+        if unsafe { libc::ioctl(device.as_raw_fd(), UI_DEV_SETUP as _, &setup as *const UinputSetup) } < 0 {
+            return Err(format!("uinput setup failed: {}", io::Error::last_os_error()));
+        }
+        ioctl(&device, UI_DEV_CREATE, 0)?;
+        thread::sleep(DEVICE_SETTLE);
+        Ok(UinputBackend { device })
+    }
+
+    // Write `events` followed by a sync, which is what makes the kernel pass them on
+    fn emit(&mut self, events: &[(u16, u16, i32)]) -> Result<(), String> {
+        let mut bytes = Vec::with_capacity((events.len() + 1) * mem::size_of::<libc::input_event>());
+        for &(kind, code, value) in events.iter().chain(&[(EV_SYN, SYN_REPORT, 0)]) {
+            // SAFETY: input_event is plain data; the timestamp is left zero for the kernel to fill in
+            let mut event: libc::input_event = unsafe { mem::zeroed() };
+            event.type_ = kind;
+            event.code = code;
+            event.value = value;
+            // SAFETY: reads the bytes of a fully initialised repr(C) struct
+            let raw = unsafe {
+                std::slice::from_raw_parts(&event as *const libc::input_event as *const u8, mem::size_of::<libc::input_event>())
+            };
+            bytes.extend_from_slice(raw);
+        }
+        self.device.write_all(&bytes).map_err(|e| format!("uinput write failed: {}", e))
+    }
+
+    fn key_code(key: &str) -> Result<u16, String> {
+        keyboard_allowed()?;
+        map_key_str_to_evdev_key(key).ok_or_else(|| format!("no uinput key code for '{}'", key))
+    }
+}
+
+fn button_code(button: ClickButton) -> u16 {
+    match button {
+        ClickButton::Left => 0x110,
+        ClickButton::Right => 0x111,
+        ClickButton::Middle => 0x112,
+    }
+}
+
+impl InputBackend for UinputBackend {
+    fn mouse_down(&mut self, button: ClickButton) -> Result<(), String> {
+        mouse_allowed()?;
+        self.emit(&[(EV_KEY, button_code(button), 1)])
+    }
+
+    fn mouse_up(&mut self, button: ClickButton) -> Result<(), String> {
+        mouse_allowed()?;
+        self.emit(&[(EV_KEY, button_code(button), 0)])
+    }
+
+    fn mouse_click(&mut self, button: ClickButton) -> Result<(), String> {
+        self.mouse_down(button)?;
+        self.mouse_up(button)
+    }
+
+    fn key_down(&mut self, key: &str) -> Result<(), String> {
+        let code = Self::key_code(key)?;
+        self.emit(&[(EV_KEY, code, 1)])
+    }
+
+    fn key_up(&mut self, key: &str) -> Result<(), String> {
+        let code = Self::key_code(key)?;
+        self.emit(&[(EV_KEY, code, 0)])
+    }
+
+    fn key_click(&mut self, key: &str) -> Result<(), String> {
+        let code = Self::key_code(key)?;
+        self.emit(&[(EV_KEY, code, 1)])?;
+        self.emit(&[(EV_KEY, code, 0)])
+    }
+
+    // A relative device can't be put at a position, only moved by some amount
+    fn move_to(&mut self, _x: i32, _y: i32) -> Result<(), String> {
+        Err("the uinput backend can't move the pointer to a position; use the enigo backend for that".to_string())
+    }
+}
+
+impl Drop for UinputBackend {
+    fn drop(&mut self) {
+        let _ = ioctl(&self.device, UI_DEV_DESTROY, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn explains_permission_problems() {
+        let denied = open_error(&io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(denied.contains("input group"));
+        assert!(denied.contains("udev"));
+        let missing = open_error(&io::Error::from(io::ErrorKind::NotFound));
+        assert!(missing.contains("modprobe uinput"));
+    }
+
+    #[test]
+    fn setup_struct_matches_the_kernel() {
+        assert_eq!(mem::size_of::<UinputSetup>(), 92);
+        assert_eq!(UI_DEV_SETUP >> 16 & 0x3fff, mem::size_of::<UinputSetup>() as u64);
+    }
+}